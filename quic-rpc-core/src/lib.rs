@@ -0,0 +1,63 @@
+//! Service and message definitions for [quic-rpc](https://docs.rs/quic-rpc)
+//!
+//! This crate contains the parts of quic-rpc that are needed to define a service:
+//! the [`Service`] trait, the [`RpcMessage`] requirements and the message traits for
+//! each interaction pattern. It does not depend on std, so the exact same message
+//! definitions can be shared between a host using quic-rpc and embedded firmware
+//! that implements a minimal transport on its own.
+//!
+//! Disable the default `std` feature to use this crate in a `no_std` environment.
+//! Messages are encoded with [serde](https://serde.rs), so any serde format that
+//! works without std, e.g. postcard, can be used on the wire.
+//!
+//! quic-rpc re-exports everything in this crate, so there is no need to depend on
+//! it directly when std is available.
+#![cfg_attr(not(feature = "std"), no_std)]
+#![deny(missing_docs)]
+#![deny(rustdoc::broken_intra_doc_links)]
+use core::fmt::Debug;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+pub mod message;
+pub mod pattern;
+
+/// Requirements for a RPC message
+///
+/// Even when just using the mem transport, we require messages to be Serializable and Deserializable.
+/// Likewise, even when using the quinn transport, we require messages to be Send.
+///
+/// This does not seem like a big restriction. If you want a pure memory channel without the possibility
+/// to also use the quinn transport, you might want to use a mpsc channel directly.
+pub trait RpcMessage: Debug + Serialize + DeserializeOwned + Send + Sync + Unpin + 'static {}
+
+impl<T> RpcMessage for T where
+    T: Debug + Serialize + DeserializeOwned + Send + Sync + Unpin + 'static
+{
+}
+
+/// A service
+///
+/// A service has request and response message types. These types have to be the
+/// union of all possible request and response types for all interactions with
+/// the service.
+///
+/// Usually you will define an enum for the request and response
+/// type, and use the [derive_more](https://crates.io/crates/derive_more) crate to
+/// define the conversions between the enum and the actual request and response types.
+///
+/// To make a message type usable as a request for a service, implement [message::Msg]
+/// for it. This is how you define the interaction patterns for each request type.
+///
+/// Depending on the interaction type, you might need to implement traits that further
+/// define details of the interaction.
+///
+/// A message type can be used for multiple services. E.g. you might have a
+/// Status request that is understood by multiple services and returns a
+/// standard status response.
+pub trait Service: Send + Sync + Debug + Clone + 'static {
+    /// Type of request messages
+    type Req: RpcMessage;
+    /// Type of response messages
+    type Res: RpcMessage;
+}
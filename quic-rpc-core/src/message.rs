@@ -0,0 +1,65 @@
+//! Traits to define the behaviour of messages for services
+use core::fmt::Debug;
+
+use crate::Service;
+
+/// Declares the interaction pattern for a message and a service.
+///
+/// For each server and each message, only one interaction pattern can be defined.
+pub trait Msg<S: Service>: Into<S::Req> + TryFrom<S::Req> + Send + 'static {
+    /// The interaction pattern for this message with this service.
+    type Pattern: InteractionPattern;
+}
+
+/// Trait defining interaction pattern.
+///
+/// Currently there are 4 patterns:
+/// - [Rpc](crate::pattern::Rpc): 1 request, 1 response
+/// - [ClientStreaming](crate::pattern::ClientStreaming): 1 request, stream of updates, 1 response
+/// - [ServerStreaming](crate::pattern::ServerStreaming): 1 request, stream of responses
+/// - [BidiStreaming](crate::pattern::BidiStreaming): 1 request, stream of updates, stream of responses
+///
+/// You could define your own interaction patterns such as OneWay.
+pub trait InteractionPattern: Debug + Clone + Send + Sync + 'static {}
+
+/// Priority of a message relative to the other channels on the same connection
+///
+/// Transports that multiplex channels over one connection, such as the quinn
+/// transport, can use this to schedule the data of the channels. Data of channels with
+/// a higher priority is sent first, so bulk transfers don't delay latency sensitive
+/// calls. The priority of a channel is the priority of the first message sent on it,
+/// so implement this for the request enum of a service on the client side and for the
+/// response enum on the server side.
+pub trait Priority {
+    /// The priority, higher is sent first. The default is 0.
+    fn priority(&self) -> i32 {
+        0
+    }
+}
+
+/// Deadline of a request, after which nobody is waiting for its response anymore
+///
+/// Implement this for the request enum of a service whose requests carry a deadline.
+/// A server can then drop requests that expired while they were waiting to be
+/// handled, instead of doing work whose result is thrown away. The deadline is in
+/// milliseconds since the unix epoch, so it can be sent over the wire and compared
+/// on another host, given that the clocks are roughly in sync.
+pub trait Deadline {
+    /// The deadline of the request, if it has one. The default is no deadline.
+    fn deadline(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Messages that only signal that the other side is still alive
+///
+/// Implement this for the response type of a server streaming call whose server
+/// sends heartbeats while it has nothing else to say. Clients can then drop the
+/// heartbeats, while still counting them as a sign of life. The default is that no
+/// message is a heartbeat.
+pub trait Heartbeat {
+    /// True if this message is a heartbeat and carries no data
+    fn is_heartbeat(&self) -> bool {
+        false
+    }
+}
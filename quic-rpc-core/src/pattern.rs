@@ -0,0 +1,121 @@
+//! Predefined interaction patterns and the message traits that go with them
+use core::{fmt::Debug, result};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{InteractionPattern, Msg},
+    Service,
+};
+
+/// Rpc interaction pattern
+///
+/// There is only one request and one response.
+#[derive(Debug, Clone, Copy)]
+pub struct Rpc;
+impl InteractionPattern for Rpc {}
+
+/// Defines the response type for a rpc message.
+///
+/// Since this is the most common interaction pattern, this also implements [Msg] for you
+/// automatically, with the interaction pattern set to [Rpc]. This is to reduce boilerplate
+/// when defining rpc messages.
+pub trait RpcMsg<S: Service>: Msg<S, Pattern = Rpc> {
+    /// The type for the response
+    ///
+    /// For requests that can produce errors, this can be set to [Result<T, E>](core::result::Result).
+    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
+}
+
+/// We can only do this for one trait, so we do it for RpcMsg since it is the most common
+impl<T: RpcMsg<S>, S: Service> Msg<S> for T {
+    type Pattern = Rpc;
+}
+
+/// Server streaming interaction pattern
+///
+/// After the initial request, the server can send a stream of responses.
+#[derive(Debug, Clone, Copy)]
+pub struct ServerStreaming;
+impl InteractionPattern for ServerStreaming {}
+
+/// Defines response type for a server streaming message.
+pub trait ServerStreamingMsg<S: Service>: Msg<S, Pattern = ServerStreaming> {
+    /// The type for the response
+    ///
+    /// For requests that can produce errors, this can be set to [Result<T, E>](core::result::Result).
+    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
+}
+
+/// Client streaming interaction pattern
+///
+/// After the initial request, the client can send updates, but there is only
+/// one response.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientStreaming;
+impl InteractionPattern for ClientStreaming {}
+
+/// Defines update type and response type for a client streaming message.
+pub trait ClientStreamingMsg<S: Service>: Msg<S, Pattern = ClientStreaming> {
+    /// The type for request updates
+    ///
+    /// For a request that does not support updates, this can be safely set to any type, including
+    /// the message type itself. Any update for such a request will result in an error.
+    type Update: Into<S::Req> + TryFrom<S::Req> + Send + 'static;
+
+    /// The type for the response
+    ///
+    /// For requests that can produce errors, this can be set to [Result<T, E>](core::result::Result).
+    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
+}
+
+/// Bidirectional streaming interaction pattern
+///
+/// After the initial request, the client can send updates and the server can
+/// send responses.
+#[derive(Debug, Clone, Copy)]
+pub struct BidiStreaming;
+impl InteractionPattern for BidiStreaming {}
+
+/// Defines update type and response type for a bidi streaming message.
+pub trait BidiStreamingMsg<S: Service>: Msg<S, Pattern = BidiStreaming> {
+    /// The type for request updates
+    ///
+    /// For a request that does not support updates, this can be safely set to any type, including
+    /// the message type itself. Any update for such a request will result in an error.
+    type Update: Into<S::Req> + TryFrom<S::Req> + Send + 'static;
+
+    /// The type for the response
+    ///
+    /// For requests that can produce errors, this can be set to [Result<T, E>](core::result::Result).
+    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
+}
+
+/// A guard message to indicate that the stream has been created.
+///
+/// This is so we can dinstinguish between an error creating the stream and
+/// an error in the first item produced by the stream.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StreamCreated;
+
+/// Fallible server streaming interaction pattern.
+#[derive(Debug, Clone, Copy)]
+pub struct TryServerStreaming;
+
+impl InteractionPattern for TryServerStreaming {}
+
+/// Same as ServerStreamingMsg, but with lazy stream creation and the error type explicitly defined.
+pub trait TryServerStreamingMsg<S: Service>: Msg<S, Pattern = TryServerStreaming>
+where
+    result::Result<Self::Item, Self::ItemError>: Into<S::Res> + TryFrom<S::Res>,
+    result::Result<StreamCreated, Self::CreateError>: Into<S::Res> + TryFrom<S::Res>,
+{
+    /// Error when creating the stream
+    type CreateError: Debug + Send + 'static;
+
+    /// Error for stream items
+    type ItemError: Debug + Send + 'static;
+
+    /// Successful response item
+    type Item: Send + 'static;
+}
@@ -6,7 +6,7 @@ use syn::{
     parse::{Parse, ParseStream},
     parse_macro_input,
     spanned::Spanned,
-    Data, DeriveInput, Fields, Ident, Token, Type,
+    Data, DeriveInput, Fields, FnArg, Ident, ImplItem, ItemImpl, Token, Type,
 };
 
 const SERVER_STREAMING: &str = "server_streaming";
@@ -176,6 +176,415 @@ pub fn rpc_requests(attr: TokenStream, item: TokenStream) -> TokenStream {
     output.into()
 }
 
+/// Derive the conversions needed to nest services.
+///
+/// For every variant with a single unnamed field, this generates `From<Field>` for the
+/// enum and `TryFrom<Enum>` for the field type, with the original value as error type.
+/// This is what [`RpcClient::map`] and [`RpcChannel::map`] need to map between a parent
+/// service and a child service.
+///
+/// To also map to services nested further down, list their message types with
+/// `#[into_service(via(Grandchild, ...))]` on the variant. This requires the field type
+/// to derive `IntoService` as well.
+///
+/// [`RpcClient::map`]: https://docs.rs/quic-rpc/latest/quic_rpc/client/struct.RpcClient.html#method.map
+/// [`RpcChannel::map`]: https://docs.rs/quic-rpc/latest/quic_rpc/server/struct.RpcChannel.html#method.map
+#[proc_macro_derive(IntoService, attributes(into_service))]
+pub fn derive_into_service(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match into_service_impls(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn into_service_impls(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let data_enum = match &input.data {
+        Data::Enum(data_enum) => data_enum,
+        _ => {
+            return Err(syn::Error::new(
+                input.span(),
+                "IntoService can only be applied to enums",
+            ))
+        }
+    };
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    // a catch all arm is only needed if there is more than one variant
+    let others = if data_enum.variants.len() > 1 {
+        quote! { other => ::std::result::Result::Err(other), }
+    } else {
+        quote! {}
+    };
+
+    let mut res = Vec::new();
+    for variant in &data_enum.variants {
+        let field = match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => &fields.unnamed[0].ty,
+            _ => {
+                return Err(syn::Error::new(
+                    variant.span(),
+                    "Each variant must have exactly one unnamed field",
+                ))
+            }
+        };
+        let ident = &variant.ident;
+        res.push(quote! {
+            impl #impl_generics ::std::convert::From<#field> for #name #ty_generics #where_clause {
+                fn from(value: #field) -> Self {
+                    Self::#ident(value)
+                }
+            }
+
+            impl #impl_generics ::std::convert::TryFrom<#name #ty_generics> for #field #where_clause {
+                type Error = #name #ty_generics;
+
+                fn try_from(value: #name #ty_generics) -> ::std::result::Result<Self, Self::Error> {
+                    match value {
+                        #name::#ident(value) => ::std::result::Result::Ok(value),
+                        #others
+                    }
+                }
+            }
+        });
+
+        for attr in &variant.attrs {
+            if !attr.path.is_ident("into_service") {
+                continue;
+            }
+            for nested in attr.parse_args::<ViaArgs>()?.types {
+                res.push(quote! {
+                    impl #impl_generics ::std::convert::From<#nested> for #name #ty_generics #where_clause {
+                        fn from(value: #nested) -> Self {
+                            Self::#ident(<#field as ::std::convert::From<#nested>>::from(value))
+                        }
+                    }
+
+                    impl #impl_generics ::std::convert::TryFrom<#name #ty_generics> for #nested #where_clause {
+                        type Error = #name #ty_generics;
+
+                        fn try_from(value: #name #ty_generics) -> ::std::result::Result<Self, Self::Error> {
+                            match value {
+                                #name::#ident(value) => {
+                                    <#nested as ::std::convert::TryFrom<#field>>::try_from(value)
+                                        .map_err(#name::#ident)
+                                }
+                                #others
+                            }
+                        }
+                    }
+                });
+            }
+        }
+    }
+    Ok(quote! { #(#res)* })
+}
+
+/// Arguments of the `into_service` attribute: `via(A, B, ...)`
+struct ViaArgs {
+    types: Vec<Type>,
+}
+
+impl Parse for ViaArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key: Ident = input.parse()?;
+        if key != "via" {
+            return Err(syn::Error::new(
+                key.span(),
+                "Unknown argument, expected `via`",
+            ));
+        }
+        let content;
+        syn::parenthesized!(content in input);
+        let types = content.parse_terminated::<Type, Token![,]>(Type::parse)?;
+        Ok(ViaArgs {
+            types: types.into_iter().collect(),
+        })
+    }
+}
+
+/// Turn an impl block into a request dispatcher for a service.
+///
+/// Methods annotated with `#[rpc]`, `#[server_streaming]`, `#[client_streaming]`,
+/// `#[bidi_streaming]` or `#[try_server_streaming]` are used as handlers for the
+/// request variant with the same name as their request type. Use `variant = Name`
+/// to override the variant name, and `update = Name` on streaming handlers to name
+/// the update variant.
+///
+/// This generates a `handle_rpc_request(self, req, chan)` method that matches on all
+/// request variants. Since there is no catch-all arm, a request variant without a
+/// handler is a compile error.
+///
+/// With `#[rpc_handlers(MyService, api = MyApi)]`, a trait `MyApi` with one method per
+/// `#[rpc]` handler is generated as well. It is implemented both by the handler type,
+/// calling the handler in process, and by [`RpcClient`], calling the handler over the
+/// connection. Code written against the trait works the same in both cases.
+///
+/// [`RpcClient`]: https://docs.rs/quic-rpc/latest/quic_rpc/client/struct.RpcClient.html
+#[proc_macro_attribute]
+pub fn rpc_handlers(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(item as ItemImpl);
+    let HandlersArgs { service_name, api } = parse_macro_input!(attr as HandlersArgs);
+
+    let mut arms = Vec::new();
+    let mut api_methods = Vec::new();
+    let mut variants = HashSet::new();
+    let mut updates = Vec::new();
+
+    for item in &mut input.items {
+        let method = match item {
+            ImplItem::Method(method) => method,
+            _ => continue,
+        };
+
+        // Extract and remove RPC attributes
+        let mut rpc_attr = Vec::new();
+        method.attrs.retain(|attr| {
+            for ident in IDENTS {
+                if attr.path.is_ident(ident) {
+                    rpc_attr.push((ident, attr.clone()));
+                    return false;
+                }
+            }
+            true
+        });
+
+        // Fail if there are multiple RPC patterns
+        if rpc_attr.len() > 1 {
+            return syn::Error::new(method.span(), "Each method can only have one RPC pattern")
+                .to_compile_error()
+                .into();
+        }
+
+        let Some((pat, attr)) = rpc_attr.pop() else {
+            continue;
+        };
+        let mut args = if attr.tokens.is_empty() {
+            RpcArgs {
+                types: BTreeMap::new(),
+            }
+        } else {
+            match attr.parse_args::<RpcArgs>() {
+                Ok(args) => args,
+                Err(e) => return e.to_compile_error().into(),
+            }
+        };
+        let variant = match args.types.remove("variant") {
+            Some(ty) => type_ident(&ty, attr.span()),
+            None => request_arg_ident(method),
+        };
+        let variant = match variant {
+            Ok(variant) => variant,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        if matches!(pat, CLIENT_STREAMING | BIDI_STREAMING) {
+            if let Some(update) = args.types.remove("update") {
+                match type_ident(&update, attr.span()) {
+                    Ok(update) => updates.push(update),
+                    Err(e) => return e.to_compile_error().into(),
+                }
+            }
+        }
+        if let Err(e) = args.check_empty(attr.span()) {
+            return e.to_compile_error().into();
+        }
+        if !variants.insert(variant.to_string()) {
+            return syn::Error::new(
+                method.span(),
+                "Each request variant can only have one handler",
+            )
+            .to_compile_error()
+            .into();
+        }
+
+        let name = &method.sig.ident;
+        let invoke = Ident::new(
+            match pat {
+                SERVER_STREAMING => "server_streaming",
+                CLIENT_STREAMING => "client_streaming",
+                BIDI_STREAMING => "bidi_streaming",
+                TRY_SERVER_STREAMING => "try_server_streaming",
+                _ => "rpc",
+            },
+            attr.span(),
+        );
+        arms.push(quote! {
+            __Request::#variant(msg) => chan.#invoke(msg, self, Self::#name).await,
+        });
+        if pat == RPC {
+            match method.sig.inputs.iter().nth(1) {
+                Some(FnArg::Typed(arg)) => api_methods.push((name.clone(), (*arg.ty).clone())),
+                _ => unreachable!("checked in request_arg_ident"),
+            }
+        }
+    }
+
+    // update variants are only valid as follow up messages
+    let mut seen = HashSet::new();
+    updates.retain(|update| {
+        !variants.contains(&update.to_string()) && seen.insert(update.to_string())
+    });
+    let updates = updates.iter().map(|update| {
+        quote! {
+            __Request::#update(_) => Err(::quic_rpc::server::RpcServerError::UnexpectedStartMessage),
+        }
+    });
+
+    let dispatch = quote! {
+        /// Dispatch a request to the handler method for its variant.
+        pub async fn handle_rpc_request<C>(
+            self,
+            req: <#service_name as ::quic_rpc::Service>::Req,
+            chan: ::quic_rpc::server::RpcChannel<#service_name, C>,
+        ) -> ::std::result::Result<(), ::quic_rpc::server::RpcServerError<C>>
+        where
+            C: ::quic_rpc::transport::StreamTypes<
+                In = <#service_name as ::quic_rpc::Service>::Req,
+                Out = <#service_name as ::quic_rpc::Service>::Res,
+            >,
+        {
+            type __Request = <#service_name as ::quic_rpc::Service>::Req;
+            match req {
+                #(#arms)*
+                #(#updates)*
+            }
+        }
+    };
+    input.items.push(ImplItem::Verbatim(dispatch));
+
+    let api = api.map(|api| generate_api(&api, &service_name, &input.self_ty, &api_methods));
+
+    quote! {
+        #input
+
+        #api
+    }
+    .into()
+}
+
+/// Generate the api trait and its impls for the handler and for the client
+fn generate_api(
+    api: &Ident,
+    service_name: &Type,
+    handler: &Type,
+    methods: &[(Ident, Type)],
+) -> TokenStream2 {
+    let decls = methods.iter().map(|(name, request)| {
+        quote! {
+            #[allow(missing_docs)]
+            fn #name(
+                &self,
+                req: #request,
+            ) -> impl ::std::future::Future<
+                Output = ::std::result::Result<
+                    <#request as ::quic_rpc::message::RpcMsg<#service_name>>::Response,
+                    Self::Error,
+                >,
+            > + Send;
+        }
+    });
+    let local = methods.iter().map(|(name, request)| {
+        quote! {
+            async fn #name(
+                &self,
+                req: #request,
+            ) -> ::std::result::Result<
+                <#request as ::quic_rpc::message::RpcMsg<#service_name>>::Response,
+                Self::Error,
+            > {
+                ::std::result::Result::Ok(<#handler>::#name(::std::clone::Clone::clone(self), req).await)
+            }
+        }
+    });
+    let remote = methods.iter().map(|(name, request)| {
+        quote! {
+            async fn #name(
+                &self,
+                req: #request,
+            ) -> ::std::result::Result<
+                <#request as ::quic_rpc::message::RpcMsg<#service_name>>::Response,
+                Self::Error,
+            > {
+                self.rpc(req).await
+            }
+        }
+    });
+    quote! {
+        #[doc = concat!("Calls to ", stringify!(#service_name), ", either in process or over a connection.")]
+        pub trait #api {
+            /// Error when a call fails
+            type Error: ::std::fmt::Debug + Send + 'static;
+
+            #(#decls)*
+        }
+
+        impl #api for #handler {
+            type Error = ::std::convert::Infallible;
+
+            #(#local)*
+        }
+
+        impl<C> #api for ::quic_rpc::RpcClient<#service_name, C>
+        where
+            C: ::quic_rpc::Connector<#service_name>,
+        {
+            type Error = ::quic_rpc::pattern::rpc::Error<C>;
+
+            #(#remote)*
+        }
+    }
+}
+
+/// Arguments of the `rpc_handlers` attribute: `Service` or `Service, api = Api`
+struct HandlersArgs {
+    service_name: Type,
+    api: Option<Ident>,
+}
+
+impl Parse for HandlersArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let service_name: Type = input.parse()?;
+        let mut api = None;
+        if input.peek(Token![,]) {
+            let _: Token![,] = input.parse()?;
+            let key: Ident = input.parse()?;
+            if key != "api" {
+                return Err(syn::Error::new(
+                    key.span(),
+                    "Unknown argument, expected `api`",
+                ));
+            }
+            let _: Token![=] = input.parse()?;
+            api = Some(input.parse()?);
+        }
+        Ok(HandlersArgs { service_name, api })
+    }
+}
+
+/// Get the variant name from the type of the request argument of a handler method
+fn request_arg_ident(method: &syn::ImplItemMethod) -> syn::Result<Ident> {
+    match method.sig.inputs.iter().nth(1) {
+        Some(FnArg::Typed(arg)) => type_ident(&arg.ty, arg.span()),
+        _ => Err(syn::Error::new(
+            method.sig.span(),
+            "Handler methods must take self and the request as arguments",
+        )),
+    }
+}
+
+/// Get the last path segment of a type, e.g. `Foo` for `foo::Foo`
+fn type_ident(ty: &Type, span: Span) -> syn::Result<Ident> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.clone())
+            .ok_or_else(|| syn::Error::new(span, "Expected a type name")),
+        _ => Err(syn::Error::new(span, "Expected a type name")),
+    }
+}
+
 struct RpcArgs {
     types: BTreeMap<String, Type>,
 }
@@ -0,0 +1,38 @@
+use quic_rpc_derive::rpc_handlers;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct A;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct B;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, derive_more::From, derive_more::TryInto)]
+enum Request {
+    A(A),
+    B(B),
+}
+
+#[derive(Debug, Clone)]
+struct Service;
+
+impl quic_rpc::Service for Service {
+    type Req = Request;
+    type Res = Request;
+}
+
+impl quic_rpc::message::RpcMsg<Service> for A {
+    type Response = A;
+}
+
+#[derive(Debug, Clone)]
+struct Handler;
+
+#[rpc_handlers(Service)]
+impl Handler {
+    #[rpc]
+    async fn a(self, req: A) -> A {
+        req
+    }
+}
+
+fn main() {}
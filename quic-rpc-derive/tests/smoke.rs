@@ -1,4 +1,4 @@
-use quic_rpc_derive::rpc_requests;
+use quic_rpc_derive::{rpc_handlers, rpc_requests, IntoService};
 use serde::{Deserialize, Serialize};
 
 #[test]
@@ -67,6 +67,152 @@ fn simple() {
     let _ = Service;
 }
 
+#[test]
+fn handlers() {
+    use futures_lite::{stream, Stream};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct RpcRequest;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct ServerStreamingRequest;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct ClientStreamingRequest;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Update;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Response1;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Response2;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Response3;
+
+    #[rpc_requests(Service)]
+    #[derive(Debug, Serialize, Deserialize, derive_more::From, derive_more::TryInto)]
+    enum Request {
+        #[rpc(response = Response1)]
+        Rpc(RpcRequest),
+        #[server_streaming(response = Response2)]
+        ServerStreaming(ServerStreamingRequest),
+        #[client_streaming(update = Update, response = Response3)]
+        ClientStreaming(ClientStreamingRequest),
+        Update(Update),
+    }
+
+    #[derive(Debug, Serialize, Deserialize, derive_more::From, derive_more::TryInto)]
+    enum Response {
+        Response1(Response1),
+        Response2(Response2),
+        Response3(Response3),
+    }
+
+    #[derive(Debug, Clone)]
+    struct Service;
+
+    impl quic_rpc::Service for Service {
+        type Req = Request;
+        type Res = Response;
+    }
+
+    #[derive(Debug, Clone)]
+    struct Handler;
+
+    #[rpc_handlers(Service, api = Api)]
+    impl Handler {
+        #[rpc(variant = Rpc)]
+        async fn call(self, _req: RpcRequest) -> Response1 {
+            Response1
+        }
+
+        #[server_streaming(variant = ServerStreaming)]
+        fn server_streaming(self, _req: ServerStreamingRequest) -> impl Stream<Item = Response2> {
+            stream::once(Response2)
+        }
+
+        #[client_streaming(variant = ClientStreaming, update = Update)]
+        async fn client_streaming(
+            self,
+            _req: ClientStreamingRequest,
+            _updates: impl Stream<Item = Update>,
+        ) -> Response3 {
+            Response3
+        }
+    }
+
+    let _ = Handler::handle_rpc_request::<quic_rpc::server::BoxedChannelTypes<Service>>;
+
+    // the api can be used both in process and over a connection
+    fn assert_api<T: Api>() {}
+    assert_api::<Handler>();
+    assert_api::<quic_rpc::RpcClient<Service>>();
+}
+
+#[test]
+fn into_service() {
+    mod calc {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        pub struct Add(pub u64, pub u64);
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq, super::IntoService)]
+        pub enum Request {
+            Add(Add),
+        }
+    }
+
+    mod clock {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        pub struct Tick;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq, super::IntoService)]
+        pub enum Request {
+            Tick(Tick),
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, IntoService)]
+    enum IrohRequest {
+        Calc(calc::Request),
+        Clock(clock::Request),
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq, IntoService)]
+    enum AppRequest {
+        #[into_service(via(calc::Request, clock::Request))]
+        Iroh(IrohRequest),
+        Ping(u64),
+    }
+
+    let req = AppRequest::from(calc::Request::Add(calc::Add(1, 2)));
+    assert_eq!(
+        req,
+        AppRequest::Iroh(IrohRequest::Calc(calc::Request::Add(calc::Add(1, 2))))
+    );
+    assert_eq!(
+        calc::Request::try_from(req),
+        Ok(calc::Request::Add(calc::Add(1, 2)))
+    );
+    let req = AppRequest::from(clock::Request::Tick(clock::Tick));
+    assert_eq!(
+        calc::Request::try_from(req),
+        Err(AppRequest::Iroh(IrohRequest::Clock(clock::Request::Tick(
+            clock::Tick
+        ))))
+    );
+    assert_eq!(
+        IrohRequest::try_from(AppRequest::Ping(1)),
+        Err(AppRequest::Ping(1))
+    );
+}
+
 /// Use
 ///
 /// TRYBUILD=overwrite cargo test --test smoke
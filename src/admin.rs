@@ -0,0 +1,260 @@
+//! Built-in admin service to inspect a running server
+//!
+//! An [`Introspection`] keeps track of requests that are currently being handled and
+//! connections that are currently active. Register requests and connections with it
+//! while serving them, and serve the [`AdminService`] on a separate listener (or nested
+//! in your own service) so operators can see what a live server is doing.
+//!
+//! Tracking is explicit: the returned [`RequestGuard`] and [`ConnectionGuard`] remove
+//! the entry when dropped.
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use derive_more::{From, TryInto};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{variant_name, RpcMsg},
+    server::{RpcChannel, RpcServerError},
+    transport::StreamTypes,
+    Service,
+};
+
+/// The admin service
+#[derive(Debug, Clone, Copy)]
+pub struct AdminService;
+
+impl Service for AdminService {
+    type Req = AdminRequest;
+    type Res = AdminResponse;
+}
+
+/// Requests of the [`AdminService`]
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+pub enum AdminRequest {
+    InFlight(InFlightRequest),
+    Connections(ConnectionsRequest),
+}
+
+/// Responses of the [`AdminService`]
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+pub enum AdminResponse {
+    InFlight(InFlightResponse),
+    Connections(ConnectionsResponse),
+}
+
+/// List the requests that are currently being handled
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InFlightRequest;
+
+/// Response to [`InFlightRequest`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InFlightResponse(pub Vec<RequestInfo>);
+
+impl RpcMsg<AdminService> for InFlightRequest {
+    type Response = InFlightResponse;
+}
+
+/// List the connections that are currently active
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectionsRequest;
+
+/// Response to [`ConnectionsRequest`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectionsResponse(pub Vec<ConnectionInfo>);
+
+impl RpcMsg<AdminService> for ConnectionsRequest {
+    type Response = ConnectionsResponse;
+}
+
+/// A request that is currently being handled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestInfo {
+    /// Unique id of the request within the [`Introspection`]
+    pub id: u64,
+    /// Name of the request variant
+    pub variant: String,
+    /// The peer that sent the request, if known
+    pub peer: Option<String>,
+    /// How long the request has been running
+    pub duration: Duration,
+    /// Number of streaming items sent so far
+    pub items_sent: u64,
+}
+
+/// A connection that is currently active
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    /// Unique id of the connection within the [`Introspection`]
+    pub id: u64,
+    /// The remote peer
+    pub peer: String,
+    /// How long the connection has been open
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+struct RequestEntry {
+    variant: String,
+    peer: Option<String>,
+    started: Instant,
+    items_sent: Arc<AtomicU64>,
+}
+
+#[derive(Debug)]
+struct ConnectionEntry {
+    peer: String,
+    started: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    next_id: AtomicU64,
+    requests: Mutex<BTreeMap<u64, RequestEntry>>,
+    connections: Mutex<BTreeMap<u64, ConnectionEntry>>,
+}
+
+/// Registry of in-flight requests and active connections
+///
+/// This is cheap to clone, all clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct Introspection(Arc<Inner>);
+
+impl Introspection {
+    /// Create a new, empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a request that is about to be handled
+    ///
+    /// The variant name is taken from the `Debug` representation of the request.
+    pub fn request(&self, req: &impl Debug, peer: Option<String>) -> RequestGuard {
+        let id = self.0.next_id.fetch_add(1, Ordering::Relaxed);
+        let items_sent = Arc::new(AtomicU64::new(0));
+        let entry = RequestEntry {
+            variant: variant_name(req),
+            peer,
+            started: Instant::now(),
+            items_sent: items_sent.clone(),
+        };
+        self.0.requests.lock().unwrap().insert(id, entry);
+        RequestGuard {
+            inner: self.0.clone(),
+            id,
+            items_sent,
+        }
+    }
+
+    /// Register a connection that was just accepted
+    pub fn connection(&self, peer: impl Into<String>) -> ConnectionGuard {
+        let id = self.0.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = ConnectionEntry {
+            peer: peer.into(),
+            started: Instant::now(),
+        };
+        self.0.connections.lock().unwrap().insert(id, entry);
+        ConnectionGuard {
+            inner: self.0.clone(),
+            id,
+        }
+    }
+
+    /// The requests that are currently being handled, oldest first
+    pub fn in_flight(&self) -> Vec<RequestInfo> {
+        let now = Instant::now();
+        let requests = self.0.requests.lock().unwrap();
+        requests
+            .iter()
+            .map(|(id, entry)| RequestInfo {
+                id: *id,
+                variant: entry.variant.clone(),
+                peer: entry.peer.clone(),
+                duration: now.duration_since(entry.started),
+                items_sent: entry.items_sent.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// The connections that are currently active, oldest first
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        let now = Instant::now();
+        let connections = self.0.connections.lock().unwrap();
+        connections
+            .iter()
+            .map(|(id, entry)| ConnectionInfo {
+                id: *id,
+                peer: entry.peer.clone(),
+                duration: now.duration_since(entry.started),
+            })
+            .collect()
+    }
+
+    /// Handle a request of the [`AdminService`]
+    pub async fn handle_rpc_request<C>(
+        self,
+        req: AdminRequest,
+        chan: RpcChannel<AdminService, C>,
+    ) -> Result<(), RpcServerError<C>>
+    where
+        C: StreamTypes<In = AdminRequest, Out = AdminResponse>,
+    {
+        match req {
+            AdminRequest::InFlight(msg) => {
+                chan.rpc(msg, self, |this, _| async move {
+                    InFlightResponse(this.in_flight())
+                })
+                .await
+            }
+            AdminRequest::Connections(msg) => {
+                chan.rpc(msg, self, |this, _| async move {
+                    ConnectionsResponse(this.connections())
+                })
+                .await
+            }
+        }
+    }
+}
+
+/// Keeps a request registered with an [`Introspection`] until dropped
+#[derive(Debug)]
+pub struct RequestGuard {
+    inner: Arc<Inner>,
+    id: u64,
+    items_sent: Arc<AtomicU64>,
+}
+
+impl RequestGuard {
+    /// Record that a streaming item was sent for this request
+    pub fn item_sent(&self) {
+        self.items_sent.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        self.inner.requests.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Keeps a connection registered with an [`Introspection`] until dropped
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    inner: Arc<Inner>,
+    id: u64,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.inner.connections.lock().unwrap().remove(&self.id);
+    }
+}
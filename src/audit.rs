@@ -0,0 +1,248 @@
+//! Audit log of the requests a server handles
+//!
+//! An [`AuditLog`] set with [`RpcServer::audit`](crate::RpcServer::audit) emits an
+//! [`AuditRecord`] for every request [`RpcServer::serve`](crate::RpcServer::serve)
+//! handles, once the handler finishes: who sent it, which variant it was, when it
+//! arrived, whether handling it succeeded and how long it took.
+//!
+//! Records go to an [`AuditSink`]. There are sinks for [`tracing`](TracingSink), for
+//! files or anything else that implements `Write` ([`WriterSink`]) and for
+//! [channels](ChannelSink), and any `Fn(AuditRecord)` is a sink as well.
+//!
+//! By default a record contains the `Debug` output of the request. Requests with
+//! sensitive fields, e.g. passwords or tokens, should be redacted with
+//! [`AuditLog::redact`] before they end up in a log.
+//!
+//! Requests handled outside of `serve` can be recorded with [`AuditLog::start`].
+use std::{
+    fmt,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{message::variant_name, transport::connections::ConnectionHandle, Service};
+
+/// How handling a request ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Outcome {
+    /// The handler completed
+    Ok,
+    /// The handler failed, with the error
+    Failed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => f.write_str("ok"),
+            Self::Failed(cause) => write!(f, "failed: {cause}"),
+        }
+    }
+}
+
+/// A request that was handled
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// The peer that sent the request, if the transport has connections
+    pub peer: Option<String>,
+    /// Name of the request variant
+    pub variant: String,
+    /// The request, after redaction
+    pub request: String,
+    /// When the request was read
+    pub received: SystemTime,
+    /// How long the handler took
+    pub duration: Duration,
+    /// How handling the request ended
+    pub outcome: Outcome,
+}
+
+/// One line, `<received in ms since the epoch> <peer> <variant> <duration> <outcome> <request>`
+impl fmt::Display for AuditRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let received = self
+            .received
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        write!(
+            f,
+            "{received} {} {} {}ms {} {}",
+            self.peer.as_deref().unwrap_or("-"),
+            self.variant,
+            self.duration.as_millis(),
+            self.outcome,
+            self.request,
+        )
+    }
+}
+
+/// Where an [`AuditLog`] sends its records
+///
+/// Sinks are called from the tasks that handle requests, so they should not block
+/// for long.
+pub trait AuditSink: Send + Sync + 'static {
+    /// Store or forward a record
+    fn record(&self, record: AuditRecord);
+}
+
+impl fmt::Debug for dyn AuditSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuditSink")
+    }
+}
+
+impl<F: Fn(AuditRecord) + Send + Sync + 'static> AuditSink for F {
+    fn record(&self, record: AuditRecord) {
+        self(record)
+    }
+}
+
+/// Emits records as `tracing` events at info level, with the target `quic_rpc::audit`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingSink;
+
+impl AuditSink for TracingSink {
+    fn record(&self, record: AuditRecord) {
+        tracing::info!(
+            target: "quic_rpc::audit",
+            peer = record.peer.as_deref().unwrap_or("-"),
+            variant = %record.variant,
+            duration_ms = record.duration.as_millis() as u64,
+            outcome = %record.outcome,
+            request = %record.request,
+            "request handled"
+        );
+    }
+}
+
+/// Writes one line per record, e.g. to a file
+///
+/// Failed writes are logged and otherwise ignored, so a full disk doesn't take the
+/// server down.
+#[derive(Debug)]
+pub struct WriterSink<W>(Mutex<W>);
+
+impl<W: Write + Send + 'static> WriterSink<W> {
+    /// Write records to `writer`, flushing it after each record
+    pub fn new(writer: W) -> Self {
+        Self(Mutex::new(writer))
+    }
+}
+
+impl<W: Write + Send + 'static> AuditSink for WriterSink<W> {
+    fn record(&self, record: AuditRecord) {
+        let mut writer = self.0.lock().unwrap();
+        if let Err(cause) = writeln!(writer, "{record}").and_then(|()| writer.flush()) {
+            tracing::warn!("failed to write audit record: {cause}");
+        }
+    }
+}
+
+/// Sends records to a channel, to process them elsewhere
+///
+/// Records are dropped with a warning if the channel is full, so a slow consumer
+/// doesn't hold up the server.
+#[derive(Debug, Clone)]
+pub struct ChannelSink(mpsc::Sender<AuditRecord>);
+
+impl ChannelSink {
+    /// Send records to `sender`
+    pub fn new(sender: mpsc::Sender<AuditRecord>) -> Self {
+        Self(sender)
+    }
+}
+
+impl AuditSink for ChannelSink {
+    fn record(&self, record: AuditRecord) {
+        if let Err(mpsc::error::TrySendError::Full(_)) = self.0.try_send(record) {
+            tracing::warn!("audit channel is full, dropping record");
+        }
+    }
+}
+
+type Redact<S> = Arc<dyn Fn(&<S as Service>::Req) -> String + Send + Sync>;
+
+/// Records handled requests to a sink, see the [module docs](self)
+///
+/// This is cheap to clone, all clones share the sink.
+pub struct AuditLog<S: Service> {
+    sink: Arc<dyn AuditSink>,
+    redact: Redact<S>,
+}
+
+impl<S: Service> fmt::Debug for AuditLog<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog").finish_non_exhaustive()
+    }
+}
+
+impl<S: Service> Clone for AuditLog<S> {
+    fn clone(&self) -> Self {
+        Self {
+            sink: self.sink.clone(),
+            redact: self.redact.clone(),
+        }
+    }
+}
+
+impl<S: Service> AuditLog<S> {
+    /// Send records to `sink`
+    pub fn new(sink: impl AuditSink) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            redact: Arc::new(|req| format!("{req:?}")),
+        }
+    }
+
+    /// Describe requests with `redact` instead of their `Debug` output
+    ///
+    /// Use this to leave out or mask sensitive fields, e.g. by matching on the
+    /// variants that have them and falling back to `Debug` for the others.
+    pub fn redact(mut self, redact: impl Fn(&S::Req) -> String + Send + Sync + 'static) -> Self {
+        self.redact = Arc::new(redact);
+        self
+    }
+
+    /// Start recording a request, the record is emitted by [`AuditEntry::finish`]
+    pub fn start(&self, req: &S::Req, connection: Option<&ConnectionHandle>) -> AuditEntry {
+        AuditEntry {
+            sink: self.sink.clone(),
+            peer: connection.map(|connection| connection.peer().to_owned()),
+            variant: variant_name(req),
+            request: (self.redact)(req),
+            received: SystemTime::now(),
+            started: Instant::now(),
+        }
+    }
+}
+
+/// A request that is being handled, see [`AuditLog::start`]
+#[derive(Debug)]
+#[must_use = "the record is only emitted by finish"]
+pub struct AuditEntry {
+    sink: Arc<dyn AuditSink>,
+    peer: Option<String>,
+    variant: String,
+    request: String,
+    received: SystemTime,
+    started: Instant,
+}
+
+impl AuditEntry {
+    /// Emit the record, with the outcome of handling the request
+    pub fn finish(self, outcome: Outcome) {
+        self.sink.record(AuditRecord {
+            peer: self.peer,
+            variant: self.variant,
+            request: self.request,
+            received: self.received,
+            duration: self.started.elapsed(),
+            outcome,
+        });
+    }
+}
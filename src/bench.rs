@@ -0,0 +1,356 @@
+//! Built-in echo service and load generator for benchmarks
+//!
+//! The [`EchoService`] echoes payloads back using the rpc, server streaming and bidi
+//! streaming patterns. Serve it with [`serve`] on the transport you want to measure,
+//! and drive it with [`run`] using a [`LoadConfig`].
+//!
+//! Since the service does no work besides echoing, the measured numbers are the
+//! overhead of the transport and the framing. Using the same service and load
+//! generator for every transport makes the numbers comparable, e.g. to compare
+//! transports or to track performance regressions in CI.
+use std::{
+    result,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use derive_more::{From, TryInto};
+use futures_lite::{Stream, StreamExt};
+use futures_util::SinkExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{BidiStreaming, BidiStreamingMsg, Msg, RpcMsg, ServerStreaming, ServerStreamingMsg},
+    server::{RpcChannel, RpcServerError},
+    transport::StreamTypes,
+    Connector, Listener, RpcClient, RpcServer, Service,
+};
+
+/// The echo service
+#[derive(Debug, Clone, Copy)]
+pub struct EchoService;
+
+impl Service for EchoService {
+    type Req = EchoRequest;
+    type Res = EchoResponse;
+}
+
+/// Requests of the [`EchoService`]
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+pub enum EchoRequest {
+    Echo(Echo),
+    EchoStream(EchoStream),
+    EchoBidi(EchoBidi),
+    EchoBidiUpdate(EchoBidiUpdate),
+}
+
+/// Responses of the [`EchoService`]
+#[allow(missing_docs)]
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+pub enum EchoResponse {
+    Payload(Payload),
+}
+
+/// Payload sent back by all requests of the [`EchoService`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Payload(pub Vec<u8>);
+
+/// Echo a payload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Echo(pub Vec<u8>);
+
+impl RpcMsg<EchoService> for Echo {
+    type Response = Payload;
+}
+
+/// Send back `count` copies of a payload
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EchoStream {
+    /// The payload to send back
+    pub payload: Vec<u8>,
+    /// Number of copies
+    pub count: u64,
+}
+
+impl Msg<EchoService> for EchoStream {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<EchoService> for EchoStream {
+    type Response = Payload;
+}
+
+/// Echo each update
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EchoBidi;
+
+/// Update for [`EchoBidi`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EchoBidiUpdate(pub Vec<u8>);
+
+impl Msg<EchoService> for EchoBidi {
+    type Pattern = BidiStreaming;
+}
+
+impl BidiStreamingMsg<EchoService> for EchoBidi {
+    type Update = EchoBidiUpdate;
+    type Response = Payload;
+}
+
+impl EchoService {
+    async fn echo(self, req: Echo) -> Payload {
+        Payload(req.0)
+    }
+
+    fn echo_stream(self, req: EchoStream) -> impl Stream<Item = Payload> {
+        let EchoStream { payload, count } = req;
+        futures_lite::stream::repeat(payload)
+            .take(count as usize)
+            .map(Payload)
+    }
+
+    fn echo_bidi(
+        self,
+        _req: EchoBidi,
+        updates: impl Stream<Item = EchoBidiUpdate>,
+    ) -> impl Stream<Item = Payload> {
+        updates.map(|EchoBidiUpdate(payload)| Payload(payload))
+    }
+
+    /// Handle a request of the [`EchoService`]
+    pub async fn handle_rpc_request<C>(
+        self,
+        req: EchoRequest,
+        chan: RpcChannel<EchoService, C>,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        C: StreamTypes<In = EchoRequest, Out = EchoResponse>,
+    {
+        match req {
+            EchoRequest::Echo(msg) => chan.rpc(msg, self, Self::echo).await,
+            EchoRequest::EchoStream(msg) => {
+                chan.server_streaming(msg, self, Self::echo_stream).await
+            }
+            EchoRequest::EchoBidi(msg) => chan.bidi_streaming(msg, self, Self::echo_bidi).await,
+            EchoRequest::EchoBidiUpdate(_) => Err(RpcServerError::UnexpectedStartMessage),
+        }
+    }
+}
+
+/// Serve the [`EchoService`], handling each request on its own task
+///
+/// This runs until accepting a channel fails, e.g. because all clients are gone.
+pub async fn serve<C: Listener<EchoService>>(
+    server: RpcServer<EchoService, C>,
+) -> result::Result<(), RpcServerError<C>> {
+    loop {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        tokio::spawn(async move {
+            if let Err(cause) = EchoService.handle_rpc_request(req, chan).await {
+                tracing::warn!("echo request failed: {cause:?}");
+            }
+        });
+    }
+}
+
+/// The interaction pattern used by the load generator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// One [`Echo`] rpc per request
+    Rpc,
+    /// One [`EchoStream`] per request, receiving [`LoadConfig::items`] responses
+    ServerStreaming,
+    /// One [`EchoBidi`] per request, sending and receiving [`LoadConfig::items`] messages
+    BidiStreaming,
+}
+
+/// Configuration for [`run`]
+#[derive(Debug, Clone)]
+pub struct LoadConfig {
+    pattern: Pattern,
+    payload_size: usize,
+    items: u64,
+    requests: u64,
+    concurrency: usize,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            pattern: Pattern::Rpc,
+            payload_size: 0,
+            items: 1,
+            requests: 1000,
+            concurrency: 1,
+        }
+    }
+}
+
+impl LoadConfig {
+    /// Create a config for the given pattern, with defaults for everything else
+    pub fn new(pattern: Pattern) -> Self {
+        Self {
+            pattern,
+            ..Default::default()
+        }
+    }
+
+    /// Size of each payload in bytes, defaults to 0
+    pub fn payload_size(mut self, value: usize) -> Self {
+        self.payload_size = value;
+        self
+    }
+
+    /// Number of streamed items per request, defaults to 1
+    ///
+    /// This is ignored for [`Pattern::Rpc`].
+    pub fn items(mut self, value: u64) -> Self {
+        self.items = value;
+        self
+    }
+
+    /// Total number of requests, defaults to 1000
+    pub fn requests(mut self, value: u64) -> Self {
+        self.requests = value;
+        self
+    }
+
+    /// Number of requests in flight at the same time, defaults to 1
+    pub fn concurrency(mut self, value: usize) -> Self {
+        self.concurrency = value.max(1);
+        self
+    }
+}
+
+/// Result of a load run
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Wall clock time of the whole run
+    pub elapsed: Duration,
+    /// Number of payload bytes received from the server
+    pub bytes: u64,
+    /// Latency of each request, sorted
+    pub latencies: Vec<Duration>,
+}
+
+impl Report {
+    /// Number of completed requests
+    pub fn requests(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// Completed requests per second
+    pub fn requests_per_second(&self) -> f64 {
+        self.requests() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Received payload bytes per second
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Latency at the given quantile, e.g. `0.99` for the 99th percentile
+    ///
+    /// Returns `None` if no request completed.
+    pub fn latency(&self, quantile: f64) -> Option<Duration> {
+        let last = self.latencies.len().checked_sub(1)?;
+        let index = (quantile.clamp(0.0, 1.0) * last as f64).round() as usize;
+        Some(self.latencies[index])
+    }
+}
+
+/// Run a load against an [`EchoService`]
+///
+/// Spawns [`LoadConfig::concurrency`] tasks that send requests until
+/// [`LoadConfig::requests`] requests were sent. Fails on the first failed request, or
+/// if a response does not match the payload that was sent.
+pub async fn run<C: Connector<EchoService>>(
+    client: RpcClient<EchoService, C>,
+    config: LoadConfig,
+) -> anyhow::Result<Report> {
+    let config = Arc::new(config);
+    let next = Arc::new(AtomicU64::new(0));
+    let t0 = Instant::now();
+    let tasks = (0..config.concurrency)
+        .map(|_| {
+            let client = client.clone();
+            let config = config.clone();
+            let next = next.clone();
+            tokio::spawn(async move {
+                let payload = vec![0xAB; config.payload_size];
+                let mut latencies = Vec::new();
+                let mut bytes = 0;
+                while next.fetch_add(1, Ordering::Relaxed) < config.requests {
+                    let t0 = Instant::now();
+                    bytes += one_request(&client, &config, &payload).await?;
+                    latencies.push(t0.elapsed());
+                }
+                anyhow::Ok((latencies, bytes))
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut report = Report {
+        elapsed: Duration::ZERO,
+        bytes: 0,
+        latencies: Vec::with_capacity(config.requests as usize),
+    };
+    for task in tasks {
+        let (latencies, bytes) = task.await??;
+        report.latencies.extend(latencies);
+        report.bytes += bytes;
+    }
+    report.elapsed = t0.elapsed();
+    report.latencies.sort();
+    Ok(report)
+}
+
+/// Perform a single request, returning the number of payload bytes received
+async fn one_request<C: Connector<EchoService>>(
+    client: &RpcClient<EchoService, C>,
+    config: &LoadConfig,
+    payload: &[u8],
+) -> anyhow::Result<u64> {
+    let check = |res: Payload| {
+        anyhow::ensure!(res.0 == payload, "echoed payload does not match");
+        Ok(res.0.len() as u64)
+    };
+    match config.pattern {
+        Pattern::Rpc => check(client.rpc(Echo(payload.to_vec())).await?),
+        Pattern::ServerStreaming => {
+            let msg = EchoStream {
+                payload: payload.to_vec(),
+                count: config.items,
+            };
+            let mut stream = client.server_streaming(msg).await?;
+            let mut bytes = 0;
+            let mut received = 0;
+            while let Some(res) = stream.next().await {
+                bytes += check(res?)?;
+                received += 1;
+            }
+            anyhow::ensure!(received == config.items, "server stream ended early");
+            Ok(bytes)
+        }
+        Pattern::BidiStreaming => {
+            let (mut send, mut recv) = client.bidi(EchoBidi).await?;
+            let mut bytes = 0;
+            for _ in 0..config.items {
+                send.send(EchoBidiUpdate(payload.to_vec()))
+                    .await
+                    .map_err(Into::<anyhow::Error>::into)?;
+                let res = recv
+                    .next()
+                    .await
+                    .ok_or_else(|| anyhow::anyhow!("bidi stream ended early"))??;
+                bytes += check(res)?;
+            }
+            drop(send);
+            anyhow::ensure!(recv.next().await.is_none(), "unexpected bidi response");
+            Ok(bytes)
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Byte payloads that are carried outside of the serde encoding
+//!
+//! A [`Blob`] is a field type for large binary payloads. With the framed transports
+//! (quinn and iroh-net), the bytes of all blobs in a message are not bincode encoded.
+//! Instead, only their lengths are part of the encoded message, and the raw bytes are
+//! appended to the frame after it. On the receiving side each blob is a slice of the
+//! received frame, so handlers get the payload without it being copied.
+//!
+//! Sections are appended in reverse order, so a frame without blobs is exactly the
+//! bincode encoding of the message. Both sides must enable the `zero-copy` feature.
+//!
+//! With all other transports and serializers, a blob is encoded like a byte array.
+use std::{cell::RefCell, fmt, ops::Deref};
+
+use bytes::Bytes;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// A binary payload that is not copied by the framed transports
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Blob(Bytes);
+
+impl Blob {
+    /// Create a new blob
+    pub fn new(bytes: impl Into<Bytes>) -> Self {
+        Self(bytes.into())
+    }
+
+    /// Get the underlying bytes
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl fmt::Debug for Blob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Blob({} bytes)", self.0.len())
+    }
+}
+
+impl Deref for Blob {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Blob {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Bytes> for Blob {
+    fn from(value: Bytes) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Vec<u8>> for Blob {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value.into())
+    }
+}
+
+impl From<Blob> for Bytes {
+    fn from(value: Blob) -> Self {
+        value.0
+    }
+}
+
+struct DecodeContext {
+    frame: Bytes,
+    /// End of the next section, sections are taken from the end of the frame
+    end: usize,
+}
+
+thread_local! {
+    static ENCODE: RefCell<Option<Vec<Bytes>>> = const { RefCell::new(None) };
+    static DECODE: RefCell<Option<DecodeContext>> = const { RefCell::new(None) };
+}
+
+/// Run an encoding function, collecting the bytes of all blobs as separate sections
+///
+/// The returned sections are in the order they have to be appended to the frame.
+pub(crate) fn encode_sections<T>(f: impl FnOnce() -> T) -> (T, Vec<Bytes>) {
+    let outer = ENCODE.with(|cx| cx.replace(Some(Vec::new())));
+    let res = f();
+    let mut sections = ENCODE.with(|cx| cx.replace(outer)).unwrap_or_default();
+    sections.reverse();
+    (res, sections)
+}
+
+/// Run a decoding function, taking the bytes of all blobs from the end of `frame`
+///
+/// Also returns the start of the sections, which must be the end of the encoded message.
+pub(crate) fn decode_sections<T>(frame: Bytes, f: impl FnOnce() -> T) -> (T, usize) {
+    let end = frame.len();
+    let outer = DECODE.with(|cx| cx.replace(Some(DecodeContext { frame, end })));
+    let res = f();
+    let cx = DECODE.with(|cx| cx.replace(outer));
+    (res, cx.map(|cx| cx.end).unwrap_or_default())
+}
+
+impl Serialize for Blob {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let in_frame = ENCODE.with(|cx| match cx.borrow_mut().as_mut() {
+            Some(sections) => {
+                sections.push(self.0.clone());
+                true
+            }
+            None => false,
+        });
+        if in_frame {
+            serializer.serialize_u64(self.0.len() as u64)
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Blob {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let in_frame = DECODE.with(|cx| cx.borrow().is_some());
+        if !in_frame {
+            return deserializer.deserialize_byte_buf(BlobVisitor);
+        }
+        let len = u64::deserialize(deserializer)?;
+        DECODE.with(|cx| {
+            let mut cx = cx.borrow_mut();
+            let cx = cx.as_mut().expect("checked above");
+            let len = usize::try_from(len).ok().filter(|len| *len <= cx.end);
+            let len = len.ok_or_else(|| de::Error::custom("blob section out of bounds"))?;
+            let start = cx.end - len;
+            let section = cx.frame.slice(start..cx.end);
+            cx.end = start;
+            Ok(Blob(section))
+        })
+    }
+}
+
+struct BlobVisitor;
+
+impl<'de> de::Visitor<'de> for BlobVisitor {
+    type Value = Blob;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a byte array")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Blob, E> {
+        Ok(Blob(Bytes::copy_from_slice(v)))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Blob, E> {
+        Ok(Blob(v.into()))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Blob, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(b) = seq.next_element()? {
+            bytes.push(b);
+        }
+        Ok(Blob(bytes.into()))
+    }
+}
@@ -0,0 +1,379 @@
+//! Serve a quic-rpc service to gRPC clients
+//!
+//! The [`GrpcBridge`] is a gRPC server, built on the http2 server of [hyper], that
+//! forwards every call to a [`Connector`]. It implements the following service:
+//!
+//! ```proto
+//! syntax = "proto3";
+//!
+//! package quic_rpc;
+//!
+//! // A quic-rpc message, encoded with `to_payload`
+//! message Payload {
+//!   bytes data = 1;
+//! }
+//!
+//! service Bridge {
+//!   rpc Unary(Payload) returns (Payload);
+//!   rpc ServerStreaming(Payload) returns (stream Payload);
+//!   rpc ClientStreaming(stream Payload) returns (Payload);
+//!   rpc BidiStreaming(stream Payload) returns (stream Payload);
+//! }
+//! ```
+//!
+//! Each call opens one channel on the connector. The request payloads are decoded
+//! into `S::Req` and sent on the channel, and everything the service sends back is
+//! encoded into response payloads. So for client streaming and bidi streaming the
+//! first payload is the request, followed by the updates, just like on a native
+//! channel.
+//!
+//! The method only determines when the channel is closed. For `Unary` and
+//! `ServerStreaming` the send side is kept open until the service is done, since
+//! closing it would cancel the request. For `ClientStreaming` and `BidiStreaming`
+//! the send side is closed as soon as the gRPC client closes its stream.
+//!
+//! Errors are reported as gRPC status:
+//!
+//! - an unknown method is `UNIMPLEMENTED`
+//! - a payload that can not be decoded is `INVALID_ARGUMENT`
+//! - failing to open or send on the channel is `UNAVAILABLE`
+//! - failing to receive from the channel is `INTERNAL`
+//!
+//! Compressed messages are not supported.
+//!
+//! [hyper]: https://crates.io/crates/hyper/
+use std::{convert::Infallible, fmt, marker::PhantomData, net::SocketAddr, sync::Arc};
+
+use bincode::Options;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_lite::StreamExt;
+use futures_util::SinkExt;
+use hyper::{
+    body::Sender,
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::oneshot;
+use tracing::trace;
+
+use crate::{Connector, Service};
+
+/// Path prefix of all methods of the bridge service
+const PREFIX: &str = "/quic_rpc.Bridge/";
+
+const OK: u32 = 0;
+const INVALID_ARGUMENT: u32 = 3;
+const UNIMPLEMENTED: u32 = 12;
+const INTERNAL: u32 = 13;
+const UNAVAILABLE: u32 = 14;
+
+/// Encode a message into the `data` field of a `Payload`
+///
+/// This uses bincode with fixint encoding, the same encoding the framed transports use.
+pub fn to_payload<T: Serialize>(msg: &T) -> bincode::Result<Vec<u8>> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .serialize(msg)
+}
+
+/// Decode a message from the `data` field of a `Payload`
+pub fn from_payload<T: DeserializeOwned>(data: &[u8]) -> bincode::Result<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .deserialize(data)
+}
+
+/// A gRPC server that forwards all calls to a quic-rpc service
+pub struct GrpcBridge<S, C> {
+    connector: C,
+    _p: PhantomData<S>,
+}
+
+impl<S, C: Clone> Clone for GrpcBridge<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            connector: self.connector.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S, C: fmt::Debug> fmt::Debug for GrpcBridge<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GrpcBridge")
+            .field("connector", &self.connector)
+            .finish()
+    }
+}
+
+impl<S: Service, C: Connector<S>> GrpcBridge<S, C> {
+    /// Create a new bridge that forwards calls to the given connector
+    pub fn new(connector: C) -> Self {
+        Self {
+            connector,
+            _p: PhantomData,
+        }
+    }
+
+    /// Serve gRPC on the given address
+    ///
+    /// This runs until the http2 server fails.
+    pub async fn serve(self, addr: SocketAddr) -> hyper::Result<()> {
+        let bridge = Arc::new(self);
+        let service = make_service_fn(move |_| {
+            let bridge = bridge.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let bridge = bridge.clone();
+                    async move { Ok::<_, Infallible>(bridge.handle(req).await) }
+                }))
+            }
+        });
+        Server::bind(&addr).http2_only(true).serve(service).await
+    }
+
+    /// Handle a single gRPC call
+    ///
+    /// Use this to serve the bridge from your own hyper server, e.g. next to other
+    /// services. The request must come in over http2.
+    pub async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        let close_on_end = match req.uri().path().strip_prefix(PREFIX) {
+            Some("Unary" | "ServerStreaming") => false,
+            Some("ClientStreaming" | "BidiStreaming") => true,
+            _ => {
+                let status = Status::new(UNIMPLEMENTED, format!("unknown method {}", req.uri()));
+                return status.into_response();
+            }
+        };
+        let (send, recv) = match self.connector.open().await {
+            Ok(channel) => channel,
+            Err(cause) => return Status::new(UNAVAILABLE, cause.to_string()).into_response(),
+        };
+        let (abort_tx, abort_rx) = oneshot::channel();
+        let (done_tx, done_rx) = oneshot::channel();
+        let (body_tx, body) = Body::channel();
+        tokio::spawn(forward_requests::<S, C>(
+            req.into_body(),
+            send,
+            close_on_end,
+            abort_tx,
+            done_rx,
+        ));
+        tokio::spawn(forward_responses::<S, C>(recv, body_tx, abort_rx, done_tx));
+        Response::builder()
+            .header(CONTENT_TYPE, "application/grpc")
+            .body(body)
+            .expect("valid response")
+    }
+}
+
+/// Forward the request payloads from the gRPC client to the channel
+///
+/// If the payloads can not be forwarded, the status is sent to `abort`. Otherwise the
+/// channel is closed when the client is done, or when the responses are done if
+/// `close_on_end` is false.
+async fn forward_requests<S: Service, C: Connector<S>>(
+    mut body: Body,
+    mut send: C::SendSink,
+    close_on_end: bool,
+    abort: oneshot::Sender<Status>,
+    done: oneshot::Receiver<()>,
+) {
+    let res = async {
+        let mut buf = BytesMut::new();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|cause| Status::new(UNAVAILABLE, cause.to_string()))?;
+            buf.extend_from_slice(&chunk);
+            while let Some(data) = next_frame(&mut buf)? {
+                let msg = decode_payload(&data)
+                    .ok_or_else(|| Status::new(INVALID_ARGUMENT, "invalid payload message"))?;
+                let msg = from_payload::<S::Req>(msg)
+                    .map_err(|cause| Status::new(INVALID_ARGUMENT, cause.to_string()))?;
+                send.send(msg)
+                    .await
+                    .map_err(|cause| Status::new(UNAVAILABLE, cause.to_string()))?;
+            }
+        }
+        if !buf.is_empty() {
+            return Err(Status::new(INTERNAL, "truncated message"));
+        }
+        Ok(())
+    }
+    .await;
+    match res {
+        Ok(()) if !close_on_end => {
+            // closing the send side would cancel the request, so wait for the responses
+            done.await.ok();
+        }
+        Ok(()) => {}
+        Err(status) => {
+            trace!("aborting grpc call: {}", status.message);
+            abort.send(status).ok();
+        }
+    }
+}
+
+/// Forward the responses from the channel to the gRPC client, followed by the status
+async fn forward_responses<S: Service, C: Connector<S>>(
+    mut recv: C::RecvStream,
+    mut body: Sender,
+    mut abort: oneshot::Receiver<Status>,
+    _done: oneshot::Sender<()>,
+) {
+    let mut requests_done = false;
+    let status = loop {
+        let item = tokio::select! {
+            status = &mut abort, if !requests_done => match status {
+                Ok(status) => break status,
+                Err(_) => {
+                    requests_done = true;
+                    continue;
+                }
+            },
+            item = recv.next() => item,
+        };
+        let msg = match item {
+            Some(Ok(msg)) => msg,
+            Some(Err(cause)) => break Status::new(INTERNAL, cause.to_string()),
+            None => break Status::new(OK, ""),
+        };
+        let data = match to_payload(&msg) {
+            Ok(data) => data,
+            Err(cause) => break Status::new(INTERNAL, cause.to_string()),
+        };
+        if body.send_data(encode_frame(&data)).await.is_err() {
+            // the client is gone, dropping the channel cancels the request
+            return;
+        }
+    };
+    body.send_trailers(status.into_headers()).await.ok();
+}
+
+/// A gRPC status
+struct Status {
+    code: u32,
+    message: String,
+}
+
+impl Status {
+    fn new(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn into_headers(self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("grpc-status", HeaderValue::from(self.code));
+        if !self.message.is_empty() {
+            let message = percent_encode(&self.message);
+            if let Ok(value) = HeaderValue::from_str(&message) {
+                headers.insert("grpc-message", value);
+            }
+        }
+        headers
+    }
+
+    /// A trailers-only response
+    fn into_response(self) -> Response<Body> {
+        let mut res = Response::new(Body::empty());
+        res.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+        res.headers_mut().extend(self.into_headers());
+        res
+    }
+}
+
+/// Percent encode a grpc-message, as required by the gRPC spec
+fn percent_encode(message: &str) -> String {
+    let mut res = String::with_capacity(message.len());
+    for b in message.bytes() {
+        if (0x20..0x7f).contains(&b) && b != b'%' {
+            res.push(b as char);
+        } else {
+            res.push_str(&format!("%{b:02X}"));
+        }
+    }
+    res
+}
+
+/// Take the next length prefixed message from the buffer, if it is complete
+fn next_frame(buf: &mut BytesMut) -> Result<Option<Bytes>, Status> {
+    if buf.len() < 5 {
+        return Ok(None);
+    }
+    if buf[0] != 0 {
+        return Err(Status::new(UNIMPLEMENTED, "compressed messages"));
+    }
+    let len = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize;
+    if buf.len() < 5 + len {
+        return Ok(None);
+    }
+    buf.advance(5);
+    Ok(Some(buf.split_to(len).freeze()))
+}
+
+/// Encode a `Payload` message with the given data into a length prefixed message
+fn encode_frame(data: &[u8]) -> Bytes {
+    let mut msg = Vec::with_capacity(data.len() + 11);
+    // field 1, length delimited
+    msg.push(0x0A);
+    put_varint(&mut msg, data.len() as u64);
+    msg.extend_from_slice(data);
+    let mut frame = BytesMut::with_capacity(msg.len() + 5);
+    frame.put_u8(0);
+    frame.put_u32(msg.len() as u32);
+    frame.extend_from_slice(&msg);
+    frame.freeze()
+}
+
+/// Get the `data` field of a `Payload` message, skipping unknown fields
+fn decode_payload(mut msg: &[u8]) -> Option<&[u8]> {
+    let mut data: &[u8] = &[];
+    while !msg.is_empty() {
+        let key = get_varint(&mut msg)?;
+        let len = match key & 7 {
+            0 => {
+                get_varint(&mut msg)?;
+                0
+            }
+            1 => 8,
+            2 => usize::try_from(get_varint(&mut msg)?).ok()?,
+            5 => 4,
+            _ => return None,
+        };
+        if msg.len() < len {
+            return None;
+        }
+        let (field, rest) = msg.split_at(len);
+        if key == 0x0A {
+            data = field;
+        }
+        msg = rest;
+    }
+    Some(data)
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn get_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for i in 0..10 {
+        let (&b, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= u64::from(b & 0x7f) << (i * 7);
+        if b < 0x80 {
+            return Some(value);
+        }
+    }
+    None
+}
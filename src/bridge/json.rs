@@ -0,0 +1,36 @@
+//! Mapping between the variants of the service enums and json values
+use serde_json::{Map, Value};
+
+use crate::Service;
+
+/// Error when building a request from json
+#[derive(Debug)]
+pub(crate) enum RequestError {
+    /// There is no request variant with this name
+    UnknownMethod(serde_json::Error),
+    /// The content does not match the request variant
+    InvalidParams(serde_json::Error),
+}
+
+/// Build a request from the name of its variant and its content
+pub(crate) fn to_request<S: Service>(
+    method: String,
+    params: Value,
+) -> Result<S::Req, RequestError> {
+    let req = Value::Object(Map::from_iter([(method, params)]));
+    serde_json::from_value(req).map_err(|cause| {
+        if cause.to_string().starts_with("unknown variant") {
+            RequestError::UnknownMethod(cause)
+        } else {
+            RequestError::InvalidParams(cause)
+        }
+    })
+}
+
+/// Get the content of the variant of a response
+pub(crate) fn from_response<S: Service>(res: &S::Res) -> serde_json::Result<Value> {
+    Ok(match serde_json::to_value(res)? {
+        Value::Object(map) if map.len() == 1 => map.into_iter().next().expect("one entry").1,
+        value => value,
+    })
+}
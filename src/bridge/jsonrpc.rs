@@ -0,0 +1,220 @@
+//! Serve a quic-rpc service to JSON-RPC 2.0 clients
+//!
+//! The [`JsonRpcBridge`] maps the `method` of a JSON-RPC call to the variant of the
+//! request enum with the same name, and the `params` to the content of that variant.
+//! So for a service with a request enum like
+//!
+//! ```ignore
+//! enum ComputeRequest {
+//!     Sqr(Sqr),
+//!     ...
+//! }
+//! ```
+//!
+//! the call `{"jsonrpc": "2.0", "method": "Sqr", "params": 4, "id": 1}` sends
+//! `ComputeRequest::Sqr(Sqr(4))`. The result is the content of the response variant,
+//! e.g. `{"jsonrpc": "2.0", "result": 16, "id": 1}`.
+//!
+//! By default a method is expected to be an rpc, so the result is the first response.
+//! Methods registered with [`JsonRpcBridge::server_streaming`] collect all responses
+//! into an array instead. Client streaming and bidi streaming requests can not be
+//! expressed in JSON-RPC and are not supported.
+//!
+//! Batches and notifications are supported. Use [`JsonRpcBridge::serve`] to serve
+//! JSON-RPC over HTTP, or [`JsonRpcBridge::handle_message`] to serve it over another
+//! message based transport such as websockets.
+use std::{
+    collections::HashSet, convert::Infallible, fmt, marker::PhantomData, net::SocketAddr, sync::Arc,
+};
+
+use futures_lite::StreamExt;
+use futures_util::SinkExt;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde_json::{json, Value};
+
+use super::json::{from_response, to_request, RequestError};
+use crate::{Connector, Service};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// A JSON-RPC 2.0 server that forwards all calls to a quic-rpc service
+pub struct JsonRpcBridge<S, C> {
+    connector: C,
+    streaming: HashSet<String>,
+    _p: PhantomData<S>,
+}
+
+impl<S, C: Clone> Clone for JsonRpcBridge<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            connector: self.connector.clone(),
+            streaming: self.streaming.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S, C: fmt::Debug> fmt::Debug for JsonRpcBridge<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonRpcBridge")
+            .field("connector", &self.connector)
+            .field("streaming", &self.streaming)
+            .finish()
+    }
+}
+
+impl<S: Service, C: Connector<S>> JsonRpcBridge<S, C> {
+    /// Create a new bridge that forwards calls to the given connector
+    pub fn new(connector: C) -> Self {
+        Self {
+            connector,
+            streaming: HashSet::new(),
+            _p: PhantomData,
+        }
+    }
+
+    /// Mark a method as server streaming
+    ///
+    /// The result of calls to this method is an array of all responses.
+    pub fn server_streaming(mut self, method: impl Into<String>) -> Self {
+        self.streaming.insert(method.into());
+        self
+    }
+
+    /// Serve JSON-RPC over HTTP on the given address
+    ///
+    /// Calls are sent as the body of `POST` requests. This runs until the http server
+    /// fails.
+    pub async fn serve(self, addr: SocketAddr) -> hyper::Result<()> {
+        let bridge = Arc::new(self);
+        let service = make_service_fn(move |_| {
+            let bridge = bridge.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let bridge = bridge.clone();
+                    async move { Ok::<_, Infallible>(bridge.handle(req).await) }
+                }))
+            }
+        });
+        Server::bind(&addr).serve(service).await
+    }
+
+    /// Handle a single HTTP request
+    ///
+    /// Use this to serve the bridge from your own hyper server, e.g. next to other
+    /// services.
+    pub async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        let status = |status| {
+            let mut res = Response::new(Body::empty());
+            *res.status_mut() = status;
+            res
+        };
+        if req.method() != Method::POST {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        let body = match hyper::body::to_bytes(req.into_body()).await {
+            Ok(body) => body,
+            Err(_) => return status(StatusCode::BAD_REQUEST),
+        };
+        match self.handle_message(&body).await {
+            Some(msg) => Response::builder()
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(msg))
+                .expect("valid response"),
+            None => status(StatusCode::NO_CONTENT),
+        }
+    }
+
+    /// Handle a single JSON-RPC message, which is either a call or a batch
+    ///
+    /// Returns the serialized response, or `None` if the message only contained
+    /// notifications.
+    pub async fn handle_message(&self, msg: &[u8]) -> Option<String> {
+        let res = match serde_json::from_slice(msg) {
+            Ok(Value::Array(batch)) if !batch.is_empty() => {
+                let calls = batch.into_iter().map(|call| self.handle_call(call));
+                let res = futures_util::future::join_all(calls)
+                    .await
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>();
+                if res.is_empty() {
+                    return None;
+                }
+                Value::Array(res)
+            }
+            Ok(Value::Array(_)) => error(Value::Null, INVALID_REQUEST, "empty batch"),
+            Ok(call) => self.handle_call(call).await?,
+            Err(cause) => error(Value::Null, PARSE_ERROR, cause),
+        };
+        Some(res.to_string())
+    }
+
+    /// Handle a single call, returning `None` for notifications
+    async fn handle_call(&self, call: Value) -> Option<Value> {
+        let Value::Object(mut call) = call else {
+            return Some(error(Value::Null, INVALID_REQUEST, "call is not an object"));
+        };
+        let id = call.remove("id");
+        let res = match (call.remove("jsonrpc"), call.remove("method")) {
+            (Some(Value::String(version)), Some(Value::String(method))) if version == "2.0" => {
+                let params = call.remove("params").unwrap_or(Value::Null);
+                self.call(method, params).await
+            }
+            _ => Err((INVALID_REQUEST, "invalid call".to_string())),
+        };
+        match (id, res) {
+            (Some(id), Ok(result)) => Some(json!({ "jsonrpc": "2.0", "result": result, "id": id })),
+            (Some(id), Err((code, message))) => Some(error(id, code, message)),
+            // invalid calls are answered even if they look like a notification
+            (None, Err((INVALID_REQUEST, message))) => {
+                Some(error(Value::Null, INVALID_REQUEST, message))
+            }
+            (None, _) => None,
+        }
+    }
+
+    async fn call(&self, method: String, params: Value) -> Result<Value, (i64, String)> {
+        let streaming = self.streaming.contains(&method);
+        let req = to_request::<S>(method, params).map_err(|cause| match cause {
+            RequestError::UnknownMethod(cause) => (METHOD_NOT_FOUND, cause.to_string()),
+            RequestError::InvalidParams(cause) => (INVALID_PARAMS, cause.to_string()),
+        })?;
+        let internal = |cause: &dyn fmt::Display| (INTERNAL_ERROR, cause.to_string());
+        let (mut send, mut recv) = self.connector.open().await.map_err(|e| internal(&e))?;
+        send.send(req).await.map_err(|e| internal(&e))?;
+        // the send side is kept open until we are done, closing it would cancel the request
+        let mut results = Vec::new();
+        while let Some(res) = recv.next().await {
+            let res = res.map_err(|e| internal(&e))?;
+            results.push(from_response::<S>(&res).map_err(|e| internal(&e))?);
+            if !streaming {
+                break;
+            }
+        }
+        drop(send);
+        if streaming {
+            Ok(Value::Array(results))
+        } else {
+            results
+                .pop()
+                .ok_or_else(|| (INTERNAL_ERROR, "no response".to_string()))
+        }
+    }
+}
+
+fn error(id: Value, code: i64, message: impl fmt::Display) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "error": { "code": code, "message": message.to_string() },
+        "id": id,
+    })
+}
@@ -0,0 +1,14 @@
+//! Bridges that expose a quic-rpc service over other protocols
+//!
+//! A bridge holds a [`Connector`](crate::Connector) to the actual service and translates
+//! incoming calls of a foreign protocol into channels on that connector. This allows
+//! existing clients and infrastructure to talk to a quic-rpc backend, e.g. during a
+//! migration.
+#[cfg(feature = "grpc-bridge")]
+pub mod grpc;
+#[cfg(any(feature = "jsonrpc-bridge", feature = "rest-gateway"))]
+mod json;
+#[cfg(feature = "jsonrpc-bridge")]
+pub mod jsonrpc;
+#[cfg(feature = "rest-gateway")]
+pub mod rest;
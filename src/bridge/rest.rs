@@ -0,0 +1,198 @@
+//! Serve a quic-rpc service as HTTP endpoints with json bodies
+//!
+//! The [`RestGateway`] mounts every request variant at `POST /{service}/{method}`, where
+//! `method` is the name of the variant. The json body is the content of the variant,
+//! and an empty body is the same as `null`. So for a service with a request enum like
+//!
+//! ```ignore
+//! enum ComputeRequest {
+//!     Sqr(Sqr),
+//!     ...
+//! }
+//! ```
+//!
+//! `curl -d 4 http://localhost:3000/compute/Sqr` sends `ComputeRequest::Sqr(Sqr(4))`
+//! and returns the content of the response variant, e.g. `16`.
+//!
+//! Methods registered with [`RestGateway::server_streaming`] stream their responses,
+//! as server sent events if the request accepts `text/event-stream` and as
+//! newline delimited json otherwise. Client streaming and bidi streaming requests are
+//! not supported.
+//!
+//! Errors are reported with the status code:
+//!
+//! - an unknown method is `404 Not Found`
+//! - a body that does not match the request is `400 Bad Request`
+//! - failing to talk to the service is `502 Bad Gateway`
+//!
+//! Once a stream has started, an error is sent as a final `{"error": ...}` line, or as
+//! an `error` event.
+use std::{
+    collections::HashSet, convert::Infallible, fmt, marker::PhantomData, net::SocketAddr, sync::Arc,
+};
+
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, Sse},
+        IntoResponse, Response,
+    },
+    routing::post,
+    Json, Router,
+};
+use futures_lite::StreamExt;
+use futures_util::SinkExt;
+use serde_json::{json, Value};
+
+use super::json::{from_response, to_request, RequestError};
+use crate::{Connector, Service};
+
+/// An HTTP gateway that forwards requests to a quic-rpc service
+pub struct RestGateway<S, C> {
+    service: String,
+    connector: C,
+    streaming: HashSet<String>,
+    _p: PhantomData<S>,
+}
+
+impl<S, C: Clone> Clone for RestGateway<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            service: self.service.clone(),
+            connector: self.connector.clone(),
+            streaming: self.streaming.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S, C: fmt::Debug> fmt::Debug for RestGateway<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RestGateway")
+            .field("service", &self.service)
+            .field("connector", &self.connector)
+            .field("streaming", &self.streaming)
+            .finish()
+    }
+}
+
+impl<S: Service, C: Connector<S>> RestGateway<S, C> {
+    /// Create a new gateway that mounts the service under `/{service}`
+    pub fn new(service: impl Into<String>, connector: C) -> Self {
+        Self {
+            service: service.into(),
+            connector,
+            streaming: HashSet::new(),
+            _p: PhantomData,
+        }
+    }
+
+    /// Mark a method as server streaming
+    pub fn server_streaming(mut self, method: impl Into<String>) -> Self {
+        self.streaming.insert(method.into());
+        self
+    }
+
+    /// Get a router with the endpoints of the service
+    ///
+    /// Merge this with the routers of other services to serve them together.
+    pub fn router(self) -> Router {
+        let path = format!("/{}/:method", self.service);
+        Router::new()
+            .route(&path, post(Self::handle))
+            .with_state(Arc::new(self))
+    }
+
+    async fn handle(
+        State(gateway): State<Arc<Self>>,
+        Path(method): Path<String>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        let params = if body.is_empty() {
+            Value::Null
+        } else {
+            match serde_json::from_slice(&body) {
+                Ok(params) => params,
+                Err(cause) => return error(StatusCode::BAD_REQUEST, cause),
+            }
+        };
+        let streaming = gateway.streaming.contains(&method);
+        let req = match to_request::<S>(method, params) {
+            Ok(req) => req,
+            Err(RequestError::UnknownMethod(cause)) => return error(StatusCode::NOT_FOUND, cause),
+            Err(RequestError::InvalidParams(cause)) => {
+                return error(StatusCode::BAD_REQUEST, cause)
+            }
+        };
+        let (mut send, mut recv) = match gateway.connector.open().await {
+            Ok(channel) => channel,
+            Err(cause) => return error(StatusCode::BAD_GATEWAY, cause),
+        };
+        if let Err(cause) = send.send(req).await {
+            return error(StatusCode::BAD_GATEWAY, cause);
+        }
+        if !streaming {
+            // the send side is kept open until we are done, closing it would cancel the request
+            let res = recv.next().await;
+            drop(send);
+            return match res {
+                Some(Ok(res)) => match from_response::<S>(&res) {
+                    Ok(res) => Json(res).into_response(),
+                    Err(cause) => error(StatusCode::BAD_GATEWAY, cause),
+                },
+                Some(Err(cause)) => error(StatusCode::BAD_GATEWAY, cause),
+                None => error(StatusCode::BAD_GATEWAY, "no response"),
+            };
+        }
+        // keep the send side alive for as long as the response stream
+        let items = futures_lite::stream::unfold(Some((send, recv)), |state| async move {
+            let (send, mut recv) = state?;
+            let item = match recv.next().await? {
+                Ok(res) => from_response::<S>(&res).map_err(|cause| cause.to_string()),
+                Err(cause) => Err(cause.to_string()),
+            };
+            let next = item.is_ok().then_some((send, recv));
+            Some((item, next))
+        });
+        let sse = headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/event-stream"));
+        if sse {
+            Sse::new(items.map(sse_event)).into_response()
+        } else {
+            let body = StreamBody::new(items.map(ndjson_line));
+            ([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+        }
+    }
+
+    /// Serve the endpoints of the service on the given address
+    ///
+    /// This runs until the http server fails.
+    pub async fn serve(self, addr: SocketAddr) -> hyper::Result<()> {
+        axum::Server::bind(&addr)
+            .serve(self.router().into_make_service())
+            .await
+    }
+}
+
+fn sse_event(item: Result<Value, String>) -> Result<Event, Infallible> {
+    Ok(match item {
+        Ok(value) => Event::default().data(value.to_string()),
+        Err(cause) => Event::default().event("error").data(cause),
+    })
+}
+
+fn ndjson_line(item: Result<Value, String>) -> Result<Bytes, Infallible> {
+    let value = item.unwrap_or_else(|cause| json!({ "error": cause }));
+    let mut line = value.to_string();
+    line.push('\n');
+    Ok(line.into())
+}
+
+fn error(status: StatusCode, cause: impl fmt::Display) -> Response {
+    (status, Json(json!({ "error": cause.to_string() }))).into_response()
+}
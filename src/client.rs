@@ -1,14 +1,23 @@
 //! Client side api
 //!
 //! The main entry point is [RpcClient].
+#[cfg(feature = "tokio-runtime")]
 use crate::{
-    transport::{boxed::BoxableConnector, mapped::MappedConnector, StreamTypes},
+    message::Heartbeat,
+    runtime::{Sleep, Timer, Tokio},
+};
+use crate::{
+    transport::{
+        boxed::BoxableConnector, capabilities::Negotiated, mapped::MappedConnector, StreamTypes,
+    },
     Connector, Service,
 };
-use futures_lite::Stream;
+use futures_lite::{Stream, StreamExt};
 use futures_sink::Sink;
 
 use pin_project::pin_project;
+#[cfg(feature = "tokio-runtime")]
+use std::{fmt, sync::Arc, time::Duration};
 use std::{
     fmt::Debug,
     marker::PhantomData,
@@ -66,6 +75,42 @@ where
     pub fn new(sink: C::SendSink) -> Self {
         Self(sink, PhantomData)
     }
+
+    /// Write all updates sent so far to the transport immediately
+    ///
+    /// Transports can coalesce small frames into fewer writes, e.g. the quinn transport
+    /// with a flush interval. Sending an update then only schedules a write, and this
+    /// forces it to happen now.
+    pub async fn flush(&mut self) -> Result<(), C::SendError> {
+        // the first flush may only schedule a write, flushing again without
+        // sending anything in between writes immediately
+        futures_util::SinkExt::flush(self).await?;
+        futures_util::SinkExt::flush(self).await
+    }
+
+    /// Send an update without flushing it
+    ///
+    /// The update is buffered by the transport until the sink is flushed, either
+    /// explicitly with [`UpdateSink::flush`] or by closing it. Use this to upload many
+    /// small updates without a write for each of them.
+    pub async fn feed(&mut self, update: T) -> Result<(), C::SendError> {
+        futures_util::SinkExt::feed(self, update).await
+    }
+
+    /// Send all updates of a stream, flushing once at the end
+    pub async fn send_all(&mut self, updates: impl Stream<Item = T>) -> Result<(), C::SendError> {
+        futures_lite::pin!(updates);
+        while let Some(update) = updates.next().await {
+            self.feed(update).await?;
+        }
+        futures_util::SinkExt::flush(self).await
+    }
+
+    /// Send a last update and close the sink, which ends the updates
+    pub async fn close_with(mut self, update: T) -> Result<(), C::SendError> {
+        self.feed(update).await?;
+        futures_util::SinkExt::close(&mut self).await
+    }
 }
 
 impl<C, T> Sink<T> for UpdateSink<C, T>
@@ -122,6 +167,13 @@ where
         self.source
     }
 
+    /// The capabilities negotiated with the server, if the connector knows them
+    ///
+    /// See [`capabilities`](crate::transport::capabilities) for details.
+    pub fn capabilities(&self) -> Option<Negotiated> {
+        self.source.capabilities()
+    }
+
     /// Map this channel's service into an inner service.
     ///
     /// This method is available if the required bounds are upheld:
@@ -159,6 +211,113 @@ where
     }
 }
 
+/// A stream of responses that drops heartbeats and reports when it goes quiet
+///
+/// Wrap the responses of a server streaming call to tell a subscription that is
+/// quiet apart from one that is dead. Items for which [`Heartbeat::is_heartbeat`]
+/// returns true are not yielded, but like every other item they restart the idle
+/// window. If nothing arrives within the window, the stream yields
+/// [`IdleError::Idle`] and starts the next window, so the consumer can decide to
+/// keep waiting or to give up.
+#[cfg(feature = "tokio-runtime")]
+#[pin_project]
+pub struct IdleTimeout<St> {
+    #[pin]
+    stream: St,
+    window: Duration,
+    timer: Arc<dyn Timer>,
+    sleep: Sleep,
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl<St: Debug> Debug for IdleTimeout<St> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdleTimeout")
+            .field("stream", &self.stream)
+            .field("window", &self.window)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl<St> IdleTimeout<St> {
+    /// Wrap a stream, reporting when nothing arrives for `window`
+    pub fn new(stream: St, window: Duration) -> Self {
+        Self {
+            stream,
+            window,
+            timer: Arc::new(Tokio),
+            sleep: Tokio.sleep(window),
+        }
+    }
+
+    /// Measure the idle window using the given timer
+    pub fn with_timer(mut self, timer: impl Timer) -> Self {
+        self.sleep = timer.sleep(self.window);
+        self.timer = Arc::new(timer);
+        self
+    }
+
+    /// Get back the wrapped stream
+    pub fn into_inner(self) -> St {
+        self.stream
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl<St, T, E> Stream for IdleTimeout<St>
+where
+    St: Stream<Item = Result<T, E>>,
+    T: Heartbeat,
+{
+    type Item = Result<T, IdleError<E>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    *this.sleep = this.timer.sleep(*this.window);
+                    match item {
+                        Ok(item) if item.is_heartbeat() => continue,
+                        Ok(item) => return Poll::Ready(Some(Ok(item))),
+                        Err(cause) => return Poll::Ready(Some(Err(IdleError::Item(cause)))),
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => {}
+            }
+            return match this.sleep.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    *this.sleep = this.timer.sleep(*this.window);
+                    Poll::Ready(Some(Err(IdleError::Idle(*this.window))))
+                }
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
+
+/// Error of an [`IdleTimeout`] stream
+#[cfg(feature = "tokio-runtime")]
+#[derive(Debug)]
+pub enum IdleError<E> {
+    /// Neither an item nor a heartbeat arrived for the given duration
+    Idle(Duration),
+    /// The wrapped stream failed
+    Item(E),
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl<E: Debug> fmt::Display for IdleError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl<E: Debug> std::error::Error for IdleError<E> {}
+
 /// Wrap a stream with an additional item that is kept alive until the stream is dropped
 #[pin_project]
 pub(crate) struct DeferDrop<S: Stream, X>(#[pin] pub S, pub X);
@@ -0,0 +1,691 @@
+//! Code generation from a small service description language
+//!
+//! This is meant to be used from a build script, so that multiple crates can share
+//! one source of truth for the messages and services.
+//!
+//! # Example
+//!
+//! ```text
+//! // messages are plain rust types
+//! message Sqr(u64);
+//! message SqrResponse(u128);
+//! message Sum;
+//! message SumUpdate(u64);
+//! message SumResponse { total: u128 }
+//!
+//! // `service Compute` generates `ComputeService`, `ComputeRequest`,
+//! // `ComputeResponse` and `ComputeClient`
+//! service Compute {
+//!     rpc sqr(Sqr) -> SqrResponse;
+//!     client_streaming sum(Sum, SumUpdate) -> SumResponse;
+//! }
+//! ```
+//!
+//! In `build.rs`:
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! let out_dir = std::env::var("OUT_DIR").unwrap();
+//! quic_rpc::codegen::compile("compute.rpc", format!("{out_dir}/compute.rs"))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! And then `include!(concat!(env!("OUT_DIR"), "/compute.rs"));` in the crate.
+//!
+//! The generated code depends on `serde` with the `derive` feature and `quic-rpc`.
+//!
+//! # TypeScript
+//!
+//! [generate_typescript] emits TypeScript types matching the JSON encoding of the
+//! messages, plus a thin client for the rpc and server streaming methods of each
+//! service, so web frontends don't have to mirror every message by hand.
+//!
+//! # Runtime
+//!
+//! [Schema] keeps the parsed description around, so tools can inspect services
+//! without generating code. With the `dyn-client` feature, `DynClient` uses it to
+//! call any method with JSON values.
+use std::{fmt, io, path::Path};
+
+/// Error when parsing a service description
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// Line of the error, starting at 1
+    pub line: usize,
+    /// What went wrong
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Read a service description from `input` and write the generated code to `output`.
+///
+/// This also tells cargo to rerun the build script when the input changes.
+pub fn compile(input: impl AsRef<Path>, output: impl AsRef<Path>) -> io::Result<()> {
+    let input = input.as_ref();
+    println!("cargo:rerun-if-changed={}", input.display());
+    let idl = std::fs::read_to_string(input)?;
+    let code = generate(&idl).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    std::fs::write(output, code)
+}
+
+/// Generate rust code from a service description.
+pub fn generate(idl: &str) -> Result<String, Error> {
+    let items = Parser::new(idl)?.parse()?;
+    let mut out = String::from("// Generated by quic_rpc::codegen. Do not edit.\n");
+    for item in &items {
+        match item {
+            Item::Message(message) => generate_message(&mut out, message),
+            Item::Service(service) => generate_service(&mut out, service),
+        }
+    }
+    Ok(out)
+}
+
+/// Generate TypeScript definitions and clients from a service description.
+///
+/// The types match the default serde JSON encoding of the generated rust types.
+/// Integers are mapped to `number`, so 64 and 128 bit values can lose precision.
+///
+/// The client for each service is generic over a transport, which has to send
+/// a request and deliver the JSON encoded responses.
+pub fn generate_typescript(idl: &str) -> Result<String, Error> {
+    let items = Parser::new(idl)?.parse()?;
+    let mut out = String::from("// Generated by quic_rpc::codegen. Do not edit.\n");
+    for item in &items {
+        match item {
+            Item::Message(message) => typescript_message(&mut out, message),
+            Item::Service(service) => typescript_service(&mut out, service),
+        }
+    }
+    Ok(out)
+}
+
+/// A parsed service description
+#[derive(Debug)]
+pub struct Schema {
+    items: Vec<Item>,
+}
+
+impl Schema {
+    /// Parse a service description.
+    pub fn parse(idl: &str) -> Result<Self, Error> {
+        let items = Parser::new(idl)?.parse()?;
+        Ok(Self { items })
+    }
+
+    /// Names of the services, in order of definition
+    pub fn services(&self) -> impl Iterator<Item = &str> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Service(service) => Some(service.name.as_str()),
+            Item::Message(_) => None,
+        })
+    }
+
+    /// Names of the methods of a service, empty if there is no such service
+    pub fn methods(&self, service: &str) -> impl Iterator<Item = &str> {
+        self.service(service)
+            .into_iter()
+            .flat_map(|service| service.methods.iter().map(|m| m.name.as_str()))
+    }
+
+    pub(crate) fn service(&self, name: &str) -> Option<&Service> {
+        self.items.iter().find_map(|item| match item {
+            Item::Service(service) if service.name == name => Some(service),
+            _ => None,
+        })
+    }
+
+    #[cfg(feature = "dyn-client")]
+    pub(crate) fn message(&self, name: &str) -> Option<&Message> {
+        self.items.iter().find_map(|item| match item {
+            Item::Message(message) if message.name == name => Some(message),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Fields {
+    Unit,
+    Tuple(Vec<String>),
+    Named(Vec<(String, String)>),
+}
+
+#[derive(Debug)]
+pub(crate) struct Message {
+    pub(crate) name: String,
+    pub(crate) fields: Fields,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pattern {
+    Rpc,
+    ServerStreaming,
+    ClientStreaming,
+    BidiStreaming,
+}
+
+#[derive(Debug)]
+pub(crate) struct Method {
+    pub(crate) pattern: Pattern,
+    pub(crate) name: String,
+    pub(crate) request: String,
+    pub(crate) update: Option<String>,
+    pub(crate) response: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct Service {
+    pub(crate) name: String,
+    pub(crate) methods: Vec<Method>,
+}
+
+#[derive(Debug)]
+enum Item {
+    Message(Message),
+    Service(Service),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Punct(char),
+    Arrow,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Result<Self, Error> {
+        let mut tokens = Vec::new();
+        for (i, line) in input.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.split("//").next().unwrap_or_default();
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                if c.is_whitespace() {
+                    continue;
+                } else if c.is_alphanumeric() || c == '_' {
+                    let mut ident = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_alphanumeric() || c == '_') {
+                            break;
+                        }
+                        ident.push(c);
+                        chars.next();
+                    }
+                    tokens.push((Token::Ident(ident), line_no));
+                } else if c == '-' && chars.peek() == Some(&'>') {
+                    chars.next();
+                    tokens.push((Token::Arrow, line_no));
+                } else if "{}()<>[],;:&'".contains(c) {
+                    tokens.push((Token::Punct(c), line_no));
+                } else {
+                    return Err(Error {
+                        line: line_no,
+                        message: format!("unexpected character {c:?}"),
+                    });
+                }
+            }
+        }
+        Ok(Self { tokens, pos: 0 })
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map(|(_, line)| *line)
+            .unwrap_or(1)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, Error> {
+        Err(Error {
+            line: self.line(),
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            self.error(format!("expected `{c}`"))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.pos += 1;
+                Ok(ident)
+            }
+            _ => self.error("expected identifier"),
+        }
+    }
+
+    /// Parse a rust type up to the next `,`, `;` or closing delimiter at nesting level 0
+    fn ty(&mut self) -> Result<String, Error> {
+        let mut ty = String::new();
+        let mut depth = 0usize;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Punct(',' | ';' | ')' | '}' | '>' | ']') if depth == 0 => break,
+                Token::Punct('(' | '<' | '[') => depth += 1,
+                Token::Punct(')' | '>' | ']') => depth -= 1,
+                _ => {}
+            }
+            let token = self.next().expect("peeked");
+            match token {
+                Token::Ident(ident) => {
+                    if ty.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+                        ty.push(' ');
+                    }
+                    ty.push_str(&ident);
+                }
+                Token::Punct(c) => ty.push(c),
+                Token::Arrow => ty.push_str("->"),
+            }
+        }
+        if ty.is_empty() {
+            return self.error("expected type");
+        }
+        Ok(ty)
+    }
+
+    fn parse(mut self) -> Result<Vec<Item>, Error> {
+        let mut items = Vec::new();
+        while self.peek().is_some() {
+            let keyword = self.ident()?;
+            match keyword.as_str() {
+                "message" => items.push(Item::Message(self.message()?)),
+                "service" => items.push(Item::Service(self.service()?)),
+                _ => {
+                    return self.error(format!("expected `message` or `service`, got `{keyword}`"))
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    fn message(&mut self) -> Result<Message, Error> {
+        let name = self.ident()?;
+        let fields = if self.eat(';') {
+            Fields::Unit
+        } else if self.eat('(') {
+            let mut fields = Vec::new();
+            while !self.eat(')') {
+                fields.push(self.ty()?);
+                if !self.eat(',') {
+                    self.expect(')')?;
+                    break;
+                }
+            }
+            self.expect(';')?;
+            Fields::Tuple(fields)
+        } else if self.eat('{') {
+            let mut fields = Vec::new();
+            while !self.eat('}') {
+                let field = self.ident()?;
+                self.expect(':')?;
+                fields.push((field, self.ty()?));
+                if !self.eat(',') {
+                    self.expect('}')?;
+                    break;
+                }
+            }
+            self.eat(';');
+            Fields::Named(fields)
+        } else {
+            return self.error("expected `;`, `(` or `{`");
+        };
+        Ok(Message { name, fields })
+    }
+
+    fn service(&mut self) -> Result<Service, Error> {
+        let name = self.ident()?;
+        self.expect('{')?;
+        let mut methods = Vec::new();
+        while !self.eat('}') {
+            let pattern = match self.ident()?.as_str() {
+                "rpc" => Pattern::Rpc,
+                "server_streaming" => Pattern::ServerStreaming,
+                "client_streaming" => Pattern::ClientStreaming,
+                "bidi_streaming" => Pattern::BidiStreaming,
+                other => return self.error(format!("unknown interaction pattern `{other}`")),
+            };
+            let name = self.ident()?;
+            self.expect('(')?;
+            let request = self.ident()?;
+            let update = match pattern {
+                Pattern::ClientStreaming | Pattern::BidiStreaming => {
+                    self.expect(',')?;
+                    Some(self.ident()?)
+                }
+                Pattern::Rpc | Pattern::ServerStreaming => None,
+            };
+            self.expect(')')?;
+            if self.next() != Some(Token::Arrow) {
+                return self.error("expected `->`");
+            }
+            let response = self.ident()?;
+            self.expect(';')?;
+            methods.push(Method {
+                pattern,
+                name,
+                request,
+                update,
+                response,
+            });
+        }
+        Ok(Service { name, methods })
+    }
+}
+
+fn generate_message(out: &mut String, message: &Message) {
+    out.push_str("\n#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]\n");
+    match &message.fields {
+        Fields::Unit => out.push_str(&format!("pub struct {};\n", message.name)),
+        Fields::Tuple(fields) => {
+            let fields = fields
+                .iter()
+                .map(|ty| format!("pub {ty}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            out.push_str(&format!("pub struct {}({fields});\n", message.name));
+        }
+        Fields::Named(fields) => {
+            out.push_str(&format!("pub struct {} {{\n", message.name));
+            for (name, ty) in fields {
+                out.push_str(&format!("    pub {name}: {ty},\n"));
+            }
+            out.push_str("}\n");
+        }
+    }
+}
+
+/// Remove duplicate types, keeping the first occurrence
+fn dedup<'a>(types: &[&'a str]) -> Vec<&'a str> {
+    let mut res: Vec<&str> = Vec::new();
+    for ty in types {
+        if !res.contains(ty) {
+            res.push(ty);
+        }
+    }
+    res
+}
+
+/// Get the request and response types of a service
+pub(crate) fn service_types(service: &Service) -> (Vec<&str>, Vec<&str>) {
+    let mut requests = Vec::new();
+    let mut responses = Vec::new();
+    for m in &service.methods {
+        requests.push(m.request.as_str());
+        if let Some(update) = &m.update {
+            requests.push(update.as_str());
+        }
+        responses.push(m.response.as_str());
+    }
+    (dedup(&requests), dedup(&responses))
+}
+
+/// Generate an enum with one variant per type, and the conversions to and from it
+fn generate_enum(out: &mut String, doc: &str, name: &str, variants: &[&str]) {
+    out.push_str(&format!("\n#[doc = \"{doc}\"]\n"));
+    out.push_str("#[allow(clippy::enum_variant_names)]\n");
+    out.push_str("#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]\n");
+    out.push_str(&format!("pub enum {name} {{\n"));
+    for v in variants {
+        out.push_str(&format!("    {v}({v}),\n"));
+    }
+    out.push_str("}\n");
+    for v in variants {
+        out.push_str(&format!(
+            "\nimpl From<{v}> for {name} {{\n    fn from(value: {v}) -> Self {{\n        Self::{v}(value)\n    }}\n}}\n"
+        ));
+        let others = if variants.len() > 1 {
+            "            other => Err(other),\n"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "\nimpl TryFrom<{name}> for {v} {{\n    type Error = {name};\n\n    fn try_from(value: {name}) -> Result<Self, Self::Error> {{\n        match value {{\n            {name}::{v}(value) => Ok(value),\n{others}        }}\n    }}\n}}\n"
+        ));
+    }
+}
+
+fn generate_service(out: &mut String, service: &Service) {
+    let name = &service.name;
+    let service_ty = format!("{name}Service");
+    let request_ty = format!("{name}Request");
+    let response_ty = format!("{name}Response");
+    let client_ty = format!("{name}Client");
+
+    let (requests, responses) = service_types(service);
+    generate_enum(
+        out,
+        &format!("Request messages for {service_ty}"),
+        &request_ty,
+        &requests,
+    );
+    generate_enum(
+        out,
+        &format!("Response messages for {service_ty}"),
+        &response_ty,
+        &responses,
+    );
+
+    out.push_str(&format!(
+        "\n/// RPC service {name}\n#[derive(Debug, Clone, Copy)]\npub struct {service_ty};\n\nimpl ::quic_rpc::Service for {service_ty} {{\n    type Req = {request_ty};\n    type Res = {response_ty};\n}}\n"
+    ));
+
+    for m in &service.methods {
+        let (request, response) = (&m.request, &m.response);
+        match m.pattern {
+            Pattern::Rpc => out.push_str(&format!(
+                "\nimpl ::quic_rpc::message::RpcMsg<{service_ty}> for {request} {{\n    type Response = {response};\n}}\n"
+            )),
+            Pattern::ServerStreaming => out.push_str(&format!(
+                "\nimpl ::quic_rpc::message::Msg<{service_ty}> for {request} {{\n    type Pattern = ::quic_rpc::message::ServerStreaming;\n}}\n\nimpl ::quic_rpc::message::ServerStreamingMsg<{service_ty}> for {request} {{\n    type Response = {response};\n}}\n"
+            )),
+            Pattern::ClientStreaming | Pattern::BidiStreaming => {
+                let pattern = if m.pattern == Pattern::ClientStreaming {
+                    "ClientStreaming"
+                } else {
+                    "BidiStreaming"
+                };
+                let update = m.update.as_deref().unwrap_or_default();
+                out.push_str(&format!(
+                    "\nimpl ::quic_rpc::message::Msg<{service_ty}> for {request} {{\n    type Pattern = ::quic_rpc::message::{pattern};\n}}\n\nimpl ::quic_rpc::message::{pattern}Msg<{service_ty}> for {request} {{\n    type Update = {update};\n    type Response = {response};\n}}\n"
+                ));
+            }
+        }
+    }
+
+    out.push_str(&format!(
+        "\n/// Client for {service_ty}\n#[derive(Debug, Clone)]\npub struct {client_ty}<C = ::quic_rpc::client::BoxedConnector<{service_ty}>> {{\n    /// The underlying rpc client\n    pub rpc: ::quic_rpc::RpcClient<{service_ty}, C>,\n}}\n\nimpl<C: ::quic_rpc::Connector<{service_ty}>> {client_ty}<C> {{\n    /// Create a new client from an rpc client\n    pub fn new(rpc: ::quic_rpc::RpcClient<{service_ty}, C>) -> Self {{\n        Self {{ rpc }}\n    }}\n"
+    ));
+    for m in &service.methods {
+        let (method, request, response) = (&m.name, &m.request, &m.response);
+        let update = m.update.as_deref().unwrap_or_default();
+        let (ret, call) = match m.pattern {
+            Pattern::Rpc => (
+                format!("Result<{response}, ::quic_rpc::pattern::rpc::Error<C>>"),
+                "rpc",
+            ),
+            Pattern::ServerStreaming => (
+                format!("Result<::quic_rpc::client::BoxStreamSync<'static, Result<{response}, ::quic_rpc::pattern::server_streaming::ItemError<C>>>, ::quic_rpc::pattern::server_streaming::Error<C>>"),
+                "server_streaming",
+            ),
+            Pattern::ClientStreaming => (
+                format!("Result<(::quic_rpc::client::UpdateSink<C, {update}>, ::std::pin::Pin<Box<dyn ::std::future::Future<Output = Result<{response}, ::quic_rpc::pattern::client_streaming::ItemError<C>>> + Send + 'static>>), ::quic_rpc::pattern::client_streaming::Error<C>>"),
+                "client_streaming",
+            ),
+            Pattern::BidiStreaming => (
+                format!("Result<(::quic_rpc::client::UpdateSink<C, {update}>, ::quic_rpc::client::BoxStreamSync<'static, Result<{response}, ::quic_rpc::pattern::bidi_streaming::ItemError<C>>>), ::quic_rpc::pattern::bidi_streaming::Error<C>>"),
+                "bidi",
+            ),
+        };
+        out.push_str(&format!(
+            "\n    /// Call `{method}` on the service\n    pub async fn {method}(&self, req: {request}) -> {ret} {{\n        self.rpc.{call}(req).await\n    }}\n"
+        ));
+    }
+    out.push_str("}\n");
+}
+
+/// Convert a rust type to the TypeScript type of its JSON encoding
+fn typescript_type(ty: &str) -> String {
+    let ty = ty.trim();
+    if let Some(inner) = ty.strip_prefix('(').and_then(|ty| ty.strip_suffix(')')) {
+        return match split_top_level(inner).as_slice() {
+            [] => "null".to_string(),
+            items => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|ty| typescript_type(ty))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+    }
+    if let Some(inner) = ty.strip_prefix('[').and_then(|ty| ty.strip_suffix(']')) {
+        let elem = inner.split(';').next().unwrap_or_default();
+        return format!("{}[]", typescript_type(elem));
+    }
+    let (path, args) = match ty.find('<') {
+        Some(i) if ty.ends_with('>') => (&ty[..i], split_top_level(&ty[i + 1..ty.len() - 1])),
+        _ => (ty, Vec::new()),
+    };
+    let name = path.rsplit("::").next().unwrap_or(path);
+    match (name, args.as_slice()) {
+        (
+            "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32" | "i64" | "i128"
+            | "isize" | "f32" | "f64",
+            [],
+        ) => "number".to_string(),
+        ("bool", []) => "boolean".to_string(),
+        ("String" | "str" | "char", []) => "string".to_string(),
+        ("Vec" | "VecDeque" | "HashSet" | "BTreeSet", [elem]) => {
+            format!("{}[]", typescript_type(elem))
+        }
+        ("Box" | "Arc" | "Rc", [inner]) => typescript_type(inner),
+        ("Option", [inner]) => format!("{} | null", typescript_type(inner)),
+        ("HashMap" | "BTreeMap", [_, value]) => {
+            format!("Record<string, {}>", typescript_type(value))
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Split a comma separated list of types, ignoring commas in nested types
+pub(crate) fn split_top_level(s: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '<' | '[' => depth += 1,
+            ')' | '>' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                res.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !s[start..].trim().is_empty() {
+        res.push(&s[start..]);
+    }
+    res
+}
+
+fn typescript_message(out: &mut String, message: &Message) {
+    let name = &message.name;
+    match &message.fields {
+        Fields::Unit => out.push_str(&format!("\nexport type {name} = null;\n")),
+        Fields::Tuple(fields) if fields.len() == 1 => out.push_str(&format!(
+            "\nexport type {name} = {};\n",
+            typescript_type(&fields[0])
+        )),
+        Fields::Tuple(fields) => out.push_str(&format!(
+            "\nexport type {name} = [{}];\n",
+            fields
+                .iter()
+                .map(|ty| typescript_type(ty))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        Fields::Named(fields) => {
+            out.push_str(&format!("\nexport interface {name} {{\n"));
+            for (field, ty) in fields {
+                out.push_str(&format!("  {field}: {};\n", typescript_type(ty)));
+            }
+            out.push_str("}\n");
+        }
+    }
+}
+
+fn typescript_service(out: &mut String, service: &Service) {
+    let name = &service.name;
+    let (requests, responses) = service_types(service);
+    for (ty, variants) in [("Request", &requests), ("Response", &responses)] {
+        let variants = variants
+            .iter()
+            .map(|v| format!("\n  | {{ {v}: {v} }}"))
+            .collect::<String>();
+        out.push_str(&format!("\nexport type {name}{ty} ={variants};\n"));
+    }
+
+    out.push_str(&format!(
+        "\n/** Transport used by {name}Client, sending JSON encoded messages. */\nexport interface {name}Transport {{\n  rpc(req: {name}Request): Promise<{name}Response>;\n  serverStreaming(req: {name}Request): AsyncIterable<{name}Response>;\n}}\n"
+    ));
+    out.push_str(&format!(
+        "\n/** Client for {name}Service. */\nexport class {name}Client {{\n  constructor(private readonly transport: {name}Transport) {{}}\n"
+    ));
+    for m in &service.methods {
+        let (method, request, response) = (&m.name, &m.request, &m.response);
+        match m.pattern {
+            Pattern::Rpc => out.push_str(&format!(
+                "\n  async {method}(req: {request}): Promise<{response}> {{\n    const res = await this.transport.rpc({{ {request}: req }});\n    if (!(\"{response}\" in res)) {{\n      throw new Error(\"unexpected response\");\n    }}\n    return res.{response};\n  }}\n"
+            )),
+            Pattern::ServerStreaming => out.push_str(&format!(
+                "\n  async *{method}(req: {request}): AsyncIterable<{response}> {{\n    for await (const res of this.transport.serverStreaming({{ {request}: req }})) {{\n      if (!(\"{response}\" in res)) {{\n        throw new Error(\"unexpected response\");\n      }}\n      yield res.{response};\n    }}\n  }}\n"
+            )),
+            // streaming updates are not supported by the thin client
+            Pattern::ClientStreaming | Pattern::BidiStreaming => {}
+        }
+    }
+    out.push_str("}\n");
+}
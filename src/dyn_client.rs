@@ -0,0 +1,716 @@
+//! Untyped client for tooling
+//!
+//! [`DynClient`] calls the methods of a service described by a [`Schema`], with
+//! requests, updates and responses as JSON values. This allows generic tools such as
+//! a `rpc-call` command line or a debugging UI to talk to any service, without being
+//! compiled against the crate that defines it.
+//!
+//! The values use the same JSON encoding as serde_json for the types generated by
+//! [`codegen`](crate::codegen): `null` for a message without fields, the inner value
+//! for a message with a single unnamed field, an array for several unnamed fields and
+//! an object for named fields. 128 bit integers that don't fit into 64 bits are
+//! represented as strings.
+//!
+//! The client speaks the wire format of the quinn transport directly on a
+//! [`quinn::Connection`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn example(connection: quinn::Connection) -> anyhow::Result<()> {
+//! use quic_rpc::{codegen::Schema, dyn_client::DynClient};
+//! use serde_json::json;
+//!
+//! let schema = Schema::parse(&std::fs::read_to_string("compute.rpc")?)?;
+//! let client = DynClient::new(schema, "Compute", connection)?;
+//! assert_eq!(client.rpc("sqr", json!(4)).await?, json!(16));
+//! # Ok(())
+//! # }
+//! ```
+use std::{fmt, io, sync::Arc};
+
+use bincode::Options;
+use futures_lite::{Stream, StreamExt};
+use futures_util::SinkExt;
+use serde::{
+    de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor},
+    ser::{self, Error as _, SerializeMap, SerializeSeq, SerializeStruct, SerializeTuple},
+    ser::{SerializeTupleStruct, Serializer},
+    Serialize,
+};
+use serde_json::{Map, Value};
+use tokio_util::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+
+use crate::codegen::{service_types, split_top_level, Fields, Schema};
+
+/// Same as the quinn transport
+const MAX_FRAME_LENGTH: usize = 1024 * 1024 * 16;
+
+fn bincode_options() -> impl Options {
+    bincode::DefaultOptions::new().with_fixint_encoding()
+}
+
+/// Error of a [`DynClient`] call
+#[derive(Debug)]
+pub enum Error {
+    /// The schema does not contain the service
+    UnknownService(String),
+    /// The service does not have the method
+    UnknownMethod(String),
+    /// Updates were sent to a method that does not take any
+    NoUpdates,
+    /// A value does not match its type in the schema
+    Encode(bincode::Error),
+    /// A response does not match its type in the schema
+    Decode(bincode::Error),
+    /// The server sent a response of another type than the method's response
+    UnexpectedResponse(u32),
+    /// The server closed the channel without a response
+    EarlyClose,
+    /// Opening the channel failed
+    Open(quinn::ConnectionError),
+    /// Sending or receiving a frame failed
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Client for a service described by a [`Schema`]
+///
+/// This is cheap to clone.
+#[derive(Debug, Clone)]
+pub struct DynClient {
+    schema: Arc<Schema>,
+    service: String,
+    connection: quinn::Connection,
+}
+
+impl DynClient {
+    /// Create a client for `service` in `schema`, talking to a server on `connection`.
+    pub fn new(
+        schema: impl Into<Arc<Schema>>,
+        service: impl Into<String>,
+        connection: quinn::Connection,
+    ) -> Result<Self, Error> {
+        let schema = schema.into();
+        let service = service.into();
+        if schema.service(&service).is_none() {
+            return Err(Error::UnknownService(service));
+        }
+        Ok(Self {
+            schema,
+            service,
+            connection,
+        })
+    }
+
+    /// The schema of the service
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Open a channel for `method` and send the initial request.
+    ///
+    /// This works for all interaction patterns. Like with the typed client, the
+    /// update side of the channel stays open until the channel is dropped, since the
+    /// server cancels rpc and server streaming requests when it is closed.
+    pub async fn open(&self, method: &str, request: Value) -> Result<DynChannel, Error> {
+        let service = self.schema.service(&self.service).expect("checked in new");
+        let m = service
+            .methods
+            .iter()
+            .find(|m| m.name == method)
+            .ok_or_else(|| Error::UnknownMethod(method.to_string()))?;
+        // the variant index of each type in the generated request and response enums
+        let (requests, responses) = service_types(service);
+        let variant = |types: &[&str], ty: &String| {
+            let index = types
+                .iter()
+                .position(|t| t == ty)
+                .expect("type of the service");
+            (index as u32, Type::Named(ty.clone()))
+        };
+        let frame = encode(&self.schema, &variant(&requests, &m.request), &request)?;
+        let (send, recv) = self.connection.open_bi().await.map_err(Error::Open)?;
+        let mut chan = DynChannel {
+            schema: self.schema.clone(),
+            update: m.update.as_ref().map(|ty| variant(&requests, ty)),
+            response: variant(&responses, &m.response),
+            send: FramedWrite::new(send, codec()),
+            recv: FramedRead::new(recv, codec()),
+        };
+        chan.send.send(frame.into()).await.map_err(Error::Io)?;
+        Ok(chan)
+    }
+
+    /// Call an rpc method, or any method that sends a single response.
+    pub async fn rpc(&self, method: &str, request: Value) -> Result<Value, Error> {
+        let mut chan = self.open(method, request).await?;
+        chan.recv().await.unwrap_or(Err(Error::EarlyClose))
+    }
+
+    /// Call a server streaming method.
+    pub async fn server_streaming(
+        &self,
+        method: &str,
+        request: Value,
+    ) -> Result<impl Stream<Item = Result<Value, Error>> + Send + 'static, Error> {
+        Ok(self.open(method, request).await?.into_stream())
+    }
+}
+
+/// A channel opened by [`DynClient::open`]
+pub struct DynChannel {
+    schema: Arc<Schema>,
+    update: Option<(u32, Type)>,
+    response: (u32, Type),
+    send: FramedWrite<quinn::SendStream, LengthDelimitedCodec>,
+    recv: FramedRead<quinn::RecvStream, LengthDelimitedCodec>,
+}
+
+impl fmt::Debug for DynChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynChannel")
+            .field("update", &self.update)
+            .field("response", &self.response)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DynChannel {
+    /// Send an update, for client streaming and bidi streaming methods.
+    pub async fn send(&mut self, update: Value) -> Result<(), Error> {
+        let ty = self.update.as_ref().ok_or(Error::NoUpdates)?;
+        let frame = encode(&self.schema, ty, &update)?;
+        self.send.send(frame.into()).await.map_err(Error::Io)
+    }
+
+    /// Signal the server that there will be no more updates.
+    pub async fn finish(&mut self) -> Result<(), Error> {
+        self.send.close().await.map_err(Error::Io)
+    }
+
+    /// Receive the next response, or `None` if the server is done.
+    pub async fn recv(&mut self) -> Option<Result<Value, Error>> {
+        Some(match self.recv.next().await? {
+            Ok(frame) => decode(&self.schema, &self.response, &frame),
+            Err(cause) => Err(Error::Io(cause)),
+        })
+    }
+
+    /// Turn the channel into a stream of responses.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Value, Error>> + Send + 'static {
+        futures_lite::stream::unfold(self, |mut chan| async move {
+            let item = chan.recv().await?;
+            Some((item, chan))
+        })
+    }
+}
+
+fn codec() -> LengthDelimitedCodec {
+    LengthDelimitedCodec::builder()
+        .max_frame_length(MAX_FRAME_LENGTH)
+        .new_codec()
+}
+
+/// Encode a value as the enum variant `index` of a request, like bincode does
+fn encode(schema: &Schema, (index, ty): &(u32, Type), value: &Value) -> Result<Vec<u8>, Error> {
+    let mut frame = index.to_le_bytes().to_vec();
+    bincode_options()
+        .serialize_into(&mut frame, &Typed { schema, ty, value })
+        .map_err(Error::Encode)?;
+    Ok(frame)
+}
+
+/// Decode a response, which has to be the enum variant `index`
+fn decode(schema: &Schema, (index, ty): &(u32, Type), frame: &[u8]) -> Result<Value, Error> {
+    if frame.len() < 4 {
+        return Err(Error::Decode(de::Error::custom("frame too short")));
+    }
+    let (variant, payload) = frame.split_at(4);
+    let variant = u32::from_le_bytes(variant.try_into().unwrap());
+    if variant != *index {
+        return Err(Error::UnexpectedResponse(variant));
+    }
+    bincode_options()
+        .deserialize_seed(Seed { schema, ty }, payload)
+        .map_err(Error::Decode)
+}
+
+/// A rust type, as far as the wire encoding is concerned
+#[derive(Debug, Clone)]
+enum Type {
+    Unit,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    Char,
+    String,
+    Seq(Box<Type>),
+    Array(Box<Type>, usize),
+    Option(Box<Type>),
+    Tuple(Vec<Type>),
+    Map(Box<Type>, Box<Type>),
+    /// A message of the schema
+    Named(String),
+}
+
+impl Type {
+    /// Parse the same type syntax as [`generate_typescript`](crate::codegen::generate_typescript)
+    fn parse(ty: &str) -> Result<Self, String> {
+        let ty = ty.trim();
+        if let Some(inner) = ty.strip_prefix('(').and_then(|ty| ty.strip_suffix(')')) {
+            let items = split_top_level(inner);
+            if items.is_empty() {
+                return Ok(Self::Unit);
+            }
+            return Ok(Self::Tuple(
+                items
+                    .into_iter()
+                    .map(Self::parse)
+                    .collect::<Result<_, _>>()?,
+            ));
+        }
+        if let Some(inner) = ty.strip_prefix('[').and_then(|ty| ty.strip_suffix(']')) {
+            if let Some((elem, len)) = inner.split_once(';') {
+                let len = len
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("unsupported array length in `{ty}`"))?;
+                return Ok(Self::Array(Box::new(Self::parse(elem)?), len));
+            }
+        }
+        let (path, args) = match ty.find('<') {
+            Some(i) if ty.ends_with('>') => (&ty[..i], split_top_level(&ty[i + 1..ty.len() - 1])),
+            _ => (ty, Vec::new()),
+        };
+        let name = path.rsplit("::").next().unwrap_or(path).trim();
+        let arg = |i: usize| Self::parse(args[i]).map(Box::new);
+        Ok(match (name, args.len()) {
+            ("bool", 0) => Self::Bool,
+            ("u8", 0) => Self::U8,
+            ("u16", 0) => Self::U16,
+            ("u32", 0) => Self::U32,
+            // bincode encodes usize and isize as 64 bits with fixint encoding
+            ("u64" | "usize", 0) => Self::U64,
+            ("u128", 0) => Self::U128,
+            ("i8", 0) => Self::I8,
+            ("i16", 0) => Self::I16,
+            ("i32", 0) => Self::I32,
+            ("i64" | "isize", 0) => Self::I64,
+            ("i128", 0) => Self::I128,
+            ("f32", 0) => Self::F32,
+            ("f64", 0) => Self::F64,
+            ("char", 0) => Self::Char,
+            ("String", 0) => Self::String,
+            ("Vec" | "VecDeque" | "HashSet" | "BTreeSet", 1) => Self::Seq(arg(0)?),
+            ("Box" | "Arc" | "Rc", 1) => Self::parse(args[0])?,
+            ("Option", 1) => Self::Option(arg(0)?),
+            ("HashMap" | "BTreeMap", 2) => Self::Map(arg(0)?, arg(1)?),
+            (name, 0) if name.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                Self::Named(name.to_string())
+            }
+            _ => return Err(format!("unsupported type `{ty}`")),
+        })
+    }
+
+    /// Resolve the field types of a message
+    fn fields(schema: &Schema, name: &str) -> Result<MessageFields, String> {
+        let message = schema
+            .message(name)
+            .ok_or_else(|| format!("unknown type `{name}`"))?;
+        Ok(match &message.fields {
+            Fields::Unit => MessageFields::Unit,
+            Fields::Tuple(types) => MessageFields::Tuple(
+                types
+                    .iter()
+                    .map(|ty| Self::parse(ty))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Fields::Named(fields) => MessageFields::Named(
+                fields
+                    .iter()
+                    .map(|(name, ty)| Ok((name.clone(), Self::parse(ty)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+        })
+    }
+}
+
+enum MessageFields {
+    Unit,
+    Tuple(Vec<Type>),
+    Named(Vec<(String, Type)>),
+}
+
+/// A JSON value that serializes like a value of the rust type
+struct Typed<'a> {
+    schema: &'a Schema,
+    ty: &'a Type,
+    value: &'a Value,
+}
+
+impl<'a> Typed<'a> {
+    fn with(&self, ty: &'a Type, value: &'a Value) -> Self {
+        Self {
+            schema: self.schema,
+            ty,
+            value,
+        }
+    }
+
+    fn mismatch<E: ser::Error>(&self) -> E {
+        E::custom(format!("expected {:?}, got `{}`", self.ty, self.value))
+    }
+
+    fn int<T: TryFrom<i128>, E: ser::Error>(&self) -> Result<T, E> {
+        let value = match self.value {
+            Value::Number(n) => n
+                .as_i64()
+                .map(i128::from)
+                .or_else(|| n.as_u64().map(i128::from)),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        };
+        value
+            .and_then(|value| T::try_from(value).ok())
+            .ok_or_else(|| self.mismatch())
+    }
+
+    fn array<E: ser::Error>(&self, len: Option<usize>) -> Result<&'a Vec<Value>, E> {
+        match self.value {
+            Value::Array(items) if len.map_or(true, |len| len == items.len()) => Ok(items),
+            _ => Err(self.mismatch()),
+        }
+    }
+
+    fn float<E: ser::Error>(&self) -> Result<f64, E> {
+        self.value.as_f64().ok_or_else(|| self.mismatch())
+    }
+}
+
+impl Serialize for Typed<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.ty {
+            Type::Unit if self.value.is_null() => s.serialize_unit(),
+            Type::Bool => s.serialize_bool(self.value.as_bool().ok_or_else(|| self.mismatch())?),
+            Type::U8 => s.serialize_u8(self.int()?),
+            Type::U16 => s.serialize_u16(self.int()?),
+            Type::U32 => s.serialize_u32(self.int()?),
+            Type::U64 => s.serialize_u64(self.int()?),
+            Type::U128 => match self.value {
+                // does not fit into an i128
+                Value::String(v) if v.parse::<u128>().is_ok() => {
+                    s.serialize_u128(v.parse().unwrap())
+                }
+                _ => s.serialize_u128(self.int()?),
+            },
+            Type::I8 => s.serialize_i8(self.int()?),
+            Type::I16 => s.serialize_i16(self.int()?),
+            Type::I32 => s.serialize_i32(self.int()?),
+            Type::I64 => s.serialize_i64(self.int()?),
+            Type::I128 => s.serialize_i128(self.int()?),
+            Type::F32 => s.serialize_f32(self.float()? as f32),
+            Type::F64 => s.serialize_f64(self.float()?),
+            Type::Char => {
+                let mut chars = self.value.as_str().unwrap_or_default().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => s.serialize_char(c),
+                    _ => Err(self.mismatch()),
+                }
+            }
+            Type::String => s.serialize_str(self.value.as_str().ok_or_else(|| self.mismatch())?),
+            Type::Seq(ty) => {
+                let items = self.array(None)?;
+                let mut seq = s.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&self.with(ty, item))?;
+                }
+                seq.end()
+            }
+            Type::Array(ty, len) => {
+                let items = self.array(Some(*len))?;
+                let mut tuple = s.serialize_tuple(*len)?;
+                for item in items {
+                    tuple.serialize_element(&self.with(ty, item))?;
+                }
+                tuple.end()
+            }
+            Type::Option(_) if self.value.is_null() => s.serialize_none(),
+            Type::Option(ty) => s.serialize_some(&self.with(ty, self.value)),
+            Type::Tuple(types) => {
+                let items = self.array(Some(types.len()))?;
+                let mut tuple = s.serialize_tuple(types.len())?;
+                for (ty, item) in types.iter().zip(items) {
+                    tuple.serialize_element(&self.with(ty, item))?;
+                }
+                tuple.end()
+            }
+            Type::Map(key_ty, value_ty) => {
+                let entries = self.value.as_object().ok_or_else(|| self.mismatch())?;
+                let mut map = s.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    // json object keys are always strings, even for numbers
+                    let key = match **key_ty {
+                        Type::String | Type::Char => Value::String(key.clone()),
+                        _ => {
+                            serde_json::from_str(key).unwrap_or_else(|_| Value::String(key.clone()))
+                        }
+                    };
+                    map.serialize_entry(&self.with(key_ty, &key), &self.with(value_ty, value))?;
+                }
+                map.end()
+            }
+            Type::Named(name) => match Type::fields(self.schema, name).map_err(S::Error::custom)? {
+                MessageFields::Unit if self.value.is_null() => s.serialize_unit_struct(""),
+                MessageFields::Unit => Err(self.mismatch()),
+                MessageFields::Tuple(types) if types.len() == 1 => {
+                    s.serialize_newtype_struct("", &self.with(&types[0], self.value))
+                }
+                MessageFields::Tuple(types) => {
+                    let items = self.array(Some(types.len()))?;
+                    let mut tuple = s.serialize_tuple_struct("", types.len())?;
+                    for (ty, item) in types.iter().zip(items) {
+                        tuple.serialize_field(&self.with(ty, item))?;
+                    }
+                    tuple.end()
+                }
+                MessageFields::Named(fields) => {
+                    let object = self.value.as_object().ok_or_else(|| self.mismatch())?;
+                    // the field names are not encoded
+                    let mut st = s.serialize_struct("", fields.len())?;
+                    for (name, ty) in &fields {
+                        let value = match (object.get(name), ty) {
+                            (Some(value), _) => value,
+                            // serde treats missing optional fields as none
+                            (None, Type::Option(_)) => &Value::Null,
+                            (None, _) => {
+                                return Err(S::Error::custom(format!("missing field `{name}`")))
+                            }
+                        };
+                        st.serialize_field("", &self.with(ty, value))?;
+                    }
+                    st.end()
+                }
+            },
+            Type::Unit => Err(self.mismatch()),
+        }
+    }
+}
+
+/// Deserializes a value of the rust type into a JSON value
+#[derive(Clone, Copy)]
+struct Seed<'a> {
+    schema: &'a Schema,
+    ty: &'a Type,
+}
+
+impl<'a> Seed<'a> {
+    fn with(self, ty: &'a Type) -> Self {
+        Self {
+            schema: self.schema,
+            ty,
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Seed<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        match self.ty {
+            Type::Unit => d.deserialize_unit(Primitive),
+            Type::Bool => d.deserialize_bool(Primitive),
+            Type::U8 => d.deserialize_u8(Primitive),
+            Type::U16 => d.deserialize_u16(Primitive),
+            Type::U32 => d.deserialize_u32(Primitive),
+            Type::U64 => d.deserialize_u64(Primitive),
+            Type::U128 => d.deserialize_u128(Primitive),
+            Type::I8 => d.deserialize_i8(Primitive),
+            Type::I16 => d.deserialize_i16(Primitive),
+            Type::I32 => d.deserialize_i32(Primitive),
+            Type::I64 => d.deserialize_i64(Primitive),
+            Type::I128 => d.deserialize_i128(Primitive),
+            Type::F32 => d.deserialize_f32(Primitive),
+            Type::F64 => d.deserialize_f64(Primitive),
+            Type::Char => d.deserialize_char(Primitive),
+            Type::String => d.deserialize_string(Primitive),
+            Type::Seq(ty) => d.deserialize_seq(Items::Repeat(self.with(ty))),
+            Type::Array(ty, len) => d.deserialize_tuple(*len, Items::Repeat(self.with(ty))),
+            Type::Option(ty) => d.deserialize_option(Items::Repeat(self.with(ty))),
+            Type::Tuple(types) => d.deserialize_tuple(types.len(), Items::Each(self, types)),
+            Type::Map(key, value) => d.deserialize_map(Entries(self.with(key), self.with(value))),
+            Type::Named(name) => {
+                match Type::fields(self.schema, name).map_err(<D::Error as de::Error>::custom)? {
+                    MessageFields::Unit => d.deserialize_unit_struct("", Primitive),
+                    MessageFields::Tuple(types) if types.len() == 1 => {
+                        d.deserialize_newtype_struct("", Items::Repeat(self.with(&types[0])))
+                    }
+                    MessageFields::Tuple(types) => {
+                        d.deserialize_tuple_struct("", types.len(), Items::Each(self, &types))
+                    }
+                    MessageFields::Named(fields) => {
+                        // bincode encodes structs as tuples
+                        d.deserialize_tuple(fields.len(), Items::Named(self, &fields))
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Visitor for values without nested types
+struct Primitive;
+
+impl<'de> Visitor<'de> for Primitive {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a primitive value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<Value, E> {
+        Ok(i64::try_from(v).map_or_else(|_| v.to_string().into(), Value::from))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<Value, E> {
+        Ok(u64::try_from(v).map_or_else(|_| v.to_string().into(), Value::from))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_char<E: de::Error>(self, v: char) -> Result<Value, E> {
+        Ok(v.to_string().into())
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(v.into())
+    }
+}
+
+/// Visitor for sequences, options, tuples and structs
+enum Items<'a> {
+    /// Any number of items of one type, or a single one for options and newtypes
+    Repeat(Seed<'a>),
+    /// One item of each type
+    Each(Seed<'a>, &'a [Type]),
+    /// One item for each field
+    Named(Seed<'a>, &'a [(String, Type)]),
+}
+
+impl<'de> Visitor<'de> for Items<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        self.visit_newtype_struct(d)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        match self {
+            Self::Repeat(seed) => seed.deserialize(d),
+            _ => Err(de::Error::custom("unexpected newtype")),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let missing = |i| de::Error::invalid_length(i, &"more items");
+        match self {
+            Self::Repeat(seed) => {
+                let mut items = Vec::new();
+                while let Some(item) = seq.next_element_seed(seed)? {
+                    items.push(item);
+                }
+                Ok(Value::Array(items))
+            }
+            Self::Each(seed, types) => {
+                let mut items = Vec::new();
+                for (i, ty) in types.iter().enumerate() {
+                    items.push(
+                        seq.next_element_seed(seed.with(ty))?
+                            .ok_or_else(|| missing(i))?,
+                    );
+                }
+                Ok(Value::Array(items))
+            }
+            Self::Named(seed, fields) => {
+                let mut object = Map::new();
+                for (i, (name, ty)) in fields.iter().enumerate() {
+                    let value = seq
+                        .next_element_seed(seed.with(ty))?
+                        .ok_or_else(|| missing(i))?;
+                    object.insert(name.clone(), value);
+                }
+                Ok(Value::Object(object))
+            }
+        }
+    }
+}
+
+/// Visitor for maps, with the keys turned into strings like serde_json does
+struct Entries<'a>(Seed<'a>, Seed<'a>);
+
+impl<'de> Visitor<'de> for Entries<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut object = Map::new();
+        while let Some((key, value)) = map.next_entry_seed(self.0, self.1)? {
+            let key = match key {
+                Value::String(key) => key,
+                key => key.to_string(),
+            };
+            object.insert(key, value);
+        }
+        Ok(Value::Object(object))
+    }
+}
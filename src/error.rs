@@ -0,0 +1,601 @@
+//! A unified error type for all interaction patterns and transports
+//!
+//! Every interaction pattern has its own error types, which are generic over the
+//! transport errors. This is precise, but makes it hard to handle errors of different
+//! calls in one place. All of them convert into [`Error`], which sorts the error into
+//! one of a few categories, independent of the transport. Use [`Error::kind`] to
+//! match on the category.
+//!
+//! ```
+//! # use quic_rpc::error::{Error, ErrorKind};
+//! fn should_reconnect(error: &Error) -> bool {
+//!     matches!(error.kind(), ErrorKind::Connect | ErrorKind::Shutdown)
+//! }
+//! ```
+//!
+//! When the server closes a connection on purpose, e.g. with
+//! [`ConnectionHandle::close_with`](crate::transport::connections::ConnectionHandle::close_with),
+//! the client gets [`Error::Closed`] with the [`CloseReason`], so it can tell whether
+//! to back off, authenticate again or give up. Only transports with connections, i.e.
+//! quinn and iroh-net, carry close reasons.
+//!
+//! To make errors actionable in logs, attach a [`Context`] with the request and an id
+//! using [`ResultExt::context`]:
+//!
+//! ```
+//! # use quic_rpc::error::{CallError, Context, ResultExt};
+//! # use quic_rpc::{message::RpcMsg, RpcClient, Service};
+//! # async fn example<S: Service, C: quic_rpc::Connector<S>, M: RpcMsg<S> + std::fmt::Debug>(
+//! #     client: RpcClient<S, C>,
+//! #     msg: M,
+//! # ) -> Result<M::Response, CallError> {
+//! let context = Context::new(&msg);
+//! let res = client.rpc(msg).await.context(context)?;
+//! # Ok(res)
+//! # }
+//! ```
+use std::{
+    convert::Infallible,
+    error, fmt, io,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{
+    message::variant_name,
+    pattern::{bidi_streaming, client_streaming, rpc, server_streaming, try_server_streaming},
+    server::RpcServerError,
+    transport::{self, ConnectionErrors},
+    RpcError,
+};
+
+/// A boxed error, used as the cause of an [`Error`]
+pub type BoxError = Box<dyn error::Error + Send + Sync + 'static>;
+
+/// The category of an [`Error`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Unable to open or accept a channel
+    Connect,
+    /// Unable to send a message
+    Send,
+    /// Unable to receive a message
+    Recv,
+    /// A message could not be encoded or decoded, or was not the expected type
+    Decode,
+    /// The call took too long
+    Deadline,
+    /// The call was cancelled by the other side
+    Cancelled,
+    /// The handler returned an error
+    Application,
+    /// The connection was closed locally, or the other side is gone
+    Shutdown,
+    /// The other side closed the connection on purpose, see [`CloseReason`]
+    Closed,
+}
+
+impl ErrorKind {
+    /// Whether the connection to the other side is gone
+    ///
+    /// Reconnecting might help, e.g. by opening a new connector.
+    pub fn is_connection_lost(self) -> bool {
+        matches!(self, Self::Connect | Self::Shutdown | Self::Closed)
+    }
+
+    /// Whether the error might go away by itself, e.g. a timeout or a broken stream
+    pub fn is_temporary(self) -> bool {
+        matches!(
+            self,
+            Self::Connect | Self::Send | Self::Recv | Self::Deadline
+        )
+    }
+
+    /// Whether retrying the call might succeed
+    ///
+    /// This is true for temporary errors and lost connections. Errors in the messages
+    /// themselves and application errors will happen again. Note that the request
+    /// might have been handled already when sending or receiving fails, so only retry
+    /// calls that are safe to repeat.
+    pub fn is_retryable(self) -> bool {
+        self.is_temporary() || self.is_connection_lost()
+    }
+}
+
+/// A unified error for all interaction patterns and transports
+///
+/// `E` is the type of application errors, for calls that distinguish them from
+/// transport errors.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error<E = Infallible> {
+    /// Unable to open or accept a channel
+    Connect(BoxError),
+    /// Unable to send a message
+    Send(BoxError),
+    /// Unable to receive a message
+    Recv(BoxError),
+    /// A message could not be encoded or decoded, or was not the expected type
+    Decode(BoxError),
+    /// The call took too long
+    Deadline(BoxError),
+    /// The call was cancelled by the other side
+    Cancelled,
+    /// The handler returned an error
+    Application(E),
+    /// The connection was closed locally, or the other side is gone
+    Shutdown(BoxError),
+    /// The other side closed the connection on purpose
+    Closed(CloseReason),
+}
+
+impl<E> Error<E> {
+    /// The category of this error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Connect(_) => ErrorKind::Connect,
+            Self::Send(_) => ErrorKind::Send,
+            Self::Recv(_) => ErrorKind::Recv,
+            Self::Decode(_) => ErrorKind::Decode,
+            Self::Deadline(_) => ErrorKind::Deadline,
+            Self::Cancelled => ErrorKind::Cancelled,
+            Self::Application(_) => ErrorKind::Application,
+            Self::Shutdown(_) => ErrorKind::Shutdown,
+            Self::Closed(_) => ErrorKind::Closed,
+        }
+    }
+
+    /// Whether the connection to the other side is gone, see [`ErrorKind::is_connection_lost`]
+    pub fn is_connection_lost(&self) -> bool {
+        self.kind().is_connection_lost()
+    }
+
+    /// Whether the error might go away by itself, see [`ErrorKind::is_temporary`]
+    pub fn is_temporary(&self) -> bool {
+        self.kind().is_temporary()
+    }
+
+    /// Whether retrying the call might succeed, see [`ErrorKind::is_retryable`]
+    ///
+    /// For connections that were closed on purpose, this depends on the
+    /// [`CloseCode`].
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Closed(reason) => reason.code.is_retryable(),
+            _ => self.kind().is_retryable(),
+        }
+    }
+
+    /// The reason the other side closed the connection, if it did so on purpose
+    pub fn close_reason(&self) -> Option<&CloseReason> {
+        match self {
+            Self::Closed(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Create an error from a transport error
+    ///
+    /// The transports know more specific categories for some of their errors, e.g. a
+    /// receive error can be a decode error. Otherwise the error gets the category
+    /// `kind`, which must be one of the transport categories.
+    pub fn transport(kind: ErrorKind, cause: impl RpcError) -> Self {
+        let cause: anyhow::Error = cause.into();
+        if let Some(reason) = cause.chain().find_map(transport_close_reason) {
+            return Self::Closed(reason);
+        }
+        let kind = cause.chain().find_map(transport_error_kind).unwrap_or(kind);
+        let cause = BoxError::from(cause);
+        match kind {
+            ErrorKind::Connect => Self::Connect(cause),
+            ErrorKind::Send => Self::Send(cause),
+            ErrorKind::Decode => Self::Decode(cause),
+            ErrorKind::Deadline => Self::Deadline(cause),
+            ErrorKind::Shutdown => Self::Shutdown(cause),
+            _ => Self::Recv(cause),
+        }
+    }
+
+    fn unexpected_message() -> Self {
+        Self::Decode("unexpected message".into())
+    }
+
+    /// Attach the context of the call this error belongs to
+    pub fn with_context(self, context: Context) -> CallError<E> {
+        CallError {
+            context,
+            error: self,
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect(cause) => write!(f, "failed to connect: {cause}"),
+            Self::Send(cause) => write!(f, "failed to send: {cause}"),
+            Self::Recv(cause) => write!(f, "failed to receive: {cause}"),
+            Self::Decode(cause) => write!(f, "failed to decode: {cause}"),
+            Self::Deadline(cause) => write!(f, "deadline exceeded: {cause}"),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::Application(cause) => write!(f, "application error: {cause:?}"),
+            Self::Shutdown(cause) => write!(f, "shut down: {cause}"),
+            Self::Closed(reason) => write!(f, "closed by peer: {reason}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> error::Error for Error<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Connect(cause)
+            | Self::Send(cause)
+            | Self::Recv(cause)
+            | Self::Decode(cause)
+            | Self::Deadline(cause)
+            | Self::Shutdown(cause) => Some(cause.as_ref()),
+            Self::Closed(reason) => Some(reason),
+            Self::Cancelled | Self::Application(_) => None,
+        }
+    }
+}
+
+/// Why a connection was closed on purpose, as an application error code
+///
+/// The codes are sent as the application error code of the QUIC connection close,
+/// so peers that don't use this type see them as plain numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseCode {
+    /// The server is shutting down, reconnect later or to another server
+    Shutdown,
+    /// The client is not allowed to connect, authenticate again before reconnecting
+    Unauthorized,
+    /// The client violated the protocol, reconnecting won't help
+    ProtocolError,
+    /// The server is overloaded, back off before reconnecting
+    Overloaded,
+    /// Any other code, specific to the application
+    ///
+    /// Codes 0 to 3 are the codes above, they are never returned as `Other`.
+    Other(u32),
+}
+
+impl CloseCode {
+    /// Whether reconnecting without changing anything might succeed
+    ///
+    /// This is true for a shutdown or an overloaded server, ideally with some backoff.
+    /// Application specific codes are not retryable, since their meaning is unknown.
+    pub fn is_retryable(self) -> bool {
+        matches!(self, Self::Shutdown | Self::Overloaded)
+    }
+}
+
+impl From<u32> for CloseCode {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::Shutdown,
+            1 => Self::Unauthorized,
+            2 => Self::ProtocolError,
+            3 => Self::Overloaded,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl From<CloseCode> for u32 {
+    fn from(value: CloseCode) -> Self {
+        match value {
+            CloseCode::Shutdown => 0,
+            CloseCode::Unauthorized => 1,
+            CloseCode::ProtocolError => 2,
+            CloseCode::Overloaded => 3,
+            CloseCode::Other(code) => code,
+        }
+    }
+}
+
+/// The code and reason the other side closed a connection with
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CloseReason {
+    /// What kind of close this is
+    pub code: CloseCode,
+    /// A human readable reason, empty if none was given
+    pub reason: String,
+}
+
+impl CloseReason {
+    /// Find the close reason in an error or its sources, if the transport reports one
+    pub fn find(cause: &(dyn error::Error + 'static)) -> Option<Self> {
+        let mut cause = Some(cause);
+        while let Some(current) = cause {
+            if let Some(reason) = transport_close_reason(current) {
+                return Some(reason);
+            }
+            cause = current.source();
+        }
+        None
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            CloseCode::Other(code) => write!(f, "code {code}")?,
+            code => write!(f, "{code:?}")?,
+        }
+        if !self.reason.is_empty() {
+            write!(f, ": {}", self.reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for CloseReason {}
+
+/// Ask the transports whether an error is about a connection the peer closed on purpose
+fn transport_close_reason(cause: &(dyn error::Error + 'static)) -> Option<CloseReason> {
+    #[cfg(feature = "quinn-transport")]
+    if let Some(reason) = transport::quinn::close_reason(cause) {
+        return Some(reason);
+    }
+    #[cfg(all(feature = "iroh-net-transport", not(feature = "quinn-transport")))]
+    if let Some(reason) = transport::iroh_net::close_reason(cause) {
+        return Some(reason);
+    }
+    let _ = cause;
+    None
+}
+
+/// Ask the transports for the category of an error
+fn transport_error_kind(cause: &(dyn error::Error + 'static)) -> Option<ErrorKind> {
+    #[cfg(feature = "flume-transport")]
+    if let Some(kind) = transport::flume::error_kind(cause) {
+        return Some(kind);
+    }
+    #[cfg(feature = "hyper-transport")]
+    if let Some(kind) = transport::hyper::error_kind(cause) {
+        return Some(kind);
+    }
+    #[cfg(feature = "longpoll-transport")]
+    if let Some(kind) = transport::longpoll::error_kind(cause) {
+        return Some(kind);
+    }
+    #[cfg(feature = "quinn-transport")]
+    if let Some(kind) = transport::quinn::error_kind(cause) {
+        return Some(kind);
+    }
+    #[cfg(all(feature = "iroh-net-transport", not(feature = "quinn-transport")))]
+    if let Some(kind) = transport::iroh_net::error_kind(cause) {
+        return Some(kind);
+    }
+    if cause.is::<transport::mapped::UnexpectedVariant>() {
+        return Some(ErrorKind::Decode);
+    }
+    // the framed transports report errors as io errors
+    match cause.downcast_ref::<io::Error>()?.kind() {
+        io::ErrorKind::InvalidData => Some(ErrorKind::Decode),
+        io::ErrorKind::TimedOut => Some(ErrorKind::Deadline),
+        _ => None,
+    }
+}
+
+impl<C: ConnectionErrors, E> From<rpc::Error<C>> for Error<E> {
+    fn from(value: rpc::Error<C>) -> Self {
+        match value {
+            rpc::Error::Open(cause) => Self::transport(ErrorKind::Connect, cause),
+            rpc::Error::Send(cause) => Self::transport(ErrorKind::Send, cause),
+            rpc::Error::EarlyClose => Self::Cancelled,
+            rpc::Error::RecvError(cause) => Self::transport(ErrorKind::Recv, cause),
+            rpc::Error::DowncastError => Self::unexpected_message(),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E> From<server_streaming::Error<C>> for Error<E> {
+    fn from(value: server_streaming::Error<C>) -> Self {
+        match value {
+            server_streaming::Error::Open(cause) => Self::transport(ErrorKind::Connect, cause),
+            server_streaming::Error::Send(cause) => Self::transport(ErrorKind::Send, cause),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E> From<server_streaming::ItemError<C>> for Error<E> {
+    fn from(value: server_streaming::ItemError<C>) -> Self {
+        match value {
+            server_streaming::ItemError::RecvError(cause) => {
+                Self::transport(ErrorKind::Recv, cause)
+            }
+            server_streaming::ItemError::DowncastError => Self::unexpected_message(),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E> From<client_streaming::Error<C>> for Error<E> {
+    fn from(value: client_streaming::Error<C>) -> Self {
+        match value {
+            client_streaming::Error::Open(cause) => Self::transport(ErrorKind::Connect, cause),
+            client_streaming::Error::Send(cause) => Self::transport(ErrorKind::Send, cause),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E> From<client_streaming::ItemError<C>> for Error<E> {
+    fn from(value: client_streaming::ItemError<C>) -> Self {
+        match value {
+            client_streaming::ItemError::EarlyClose => Self::Cancelled,
+            client_streaming::ItemError::RecvError(cause) => {
+                Self::transport(ErrorKind::Recv, cause)
+            }
+            client_streaming::ItemError::DowncastError => Self::unexpected_message(),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E> From<bidi_streaming::Error<C>> for Error<E> {
+    fn from(value: bidi_streaming::Error<C>) -> Self {
+        match value {
+            bidi_streaming::Error::Open(cause) => Self::transport(ErrorKind::Connect, cause),
+            bidi_streaming::Error::Send(cause) => Self::transport(ErrorKind::Send, cause),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E> From<bidi_streaming::ItemError<C>> for Error<E> {
+    fn from(value: bidi_streaming::ItemError<C>) -> Self {
+        match value {
+            bidi_streaming::ItemError::RecvError(cause) => Self::transport(ErrorKind::Recv, cause),
+            bidi_streaming::ItemError::DowncastError => Self::unexpected_message(),
+        }
+    }
+}
+
+impl<C: transport::Connector, E: fmt::Debug> From<try_server_streaming::Error<C, E>> for Error<E> {
+    fn from(value: try_server_streaming::Error<C, E>) -> Self {
+        match value {
+            try_server_streaming::Error::Open(cause) => Self::transport(ErrorKind::Connect, cause),
+            try_server_streaming::Error::Send(cause) => Self::transport(ErrorKind::Send, cause),
+            try_server_streaming::Error::Recv(cause) => Self::transport(ErrorKind::Recv, cause),
+            try_server_streaming::Error::EarlyClose => Self::Cancelled,
+            try_server_streaming::Error::Downcast => Self::unexpected_message(),
+            try_server_streaming::Error::Application(cause) => Self::Application(cause),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E: fmt::Debug> From<try_server_streaming::ItemError<C, E>> for Error<E> {
+    fn from(value: try_server_streaming::ItemError<C, E>) -> Self {
+        match value {
+            try_server_streaming::ItemError::Recv(cause) => Self::transport(ErrorKind::Recv, cause),
+            try_server_streaming::ItemError::Downcast => Self::unexpected_message(),
+            try_server_streaming::ItemError::Application(cause) => Self::Application(cause),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E> From<RpcServerError<C>> for Error<E> {
+    fn from(value: RpcServerError<C>) -> Self {
+        match value {
+            RpcServerError::Accept(cause) => Self::transport(ErrorKind::Connect, cause),
+            RpcServerError::EarlyClose | RpcServerError::Cancelled => Self::Cancelled,
+            RpcServerError::UnexpectedStartMessage | RpcServerError::UnexpectedUpdateMessage => {
+                Self::unexpected_message()
+            }
+            RpcServerError::RecvError(cause) => Self::transport(ErrorKind::Recv, cause),
+            RpcServerError::SendError(cause) => Self::transport(ErrorKind::Send, cause),
+            RpcServerError::DeadlineExceeded => Self::Deadline("deadline exceeded".into()),
+        }
+    }
+}
+
+/// The call an error belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    request: String,
+    id: u64,
+}
+
+impl Context {
+    /// Context for a call with the given request, with a new process wide unique id
+    ///
+    /// The request is only used for its name, which is taken from its `Debug`
+    /// representation. So both a message like `Sqr(2)` and a request enum like
+    /// `ComputeRequest::Sqr(Sqr(2))` work.
+    pub fn new(request: &impl fmt::Debug) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self::with_id(request, NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Context for a call with the given request and id, e.g. a correlation id that
+    /// is part of the request
+    pub fn with_id(request: &impl fmt::Debug, id: u64) -> Self {
+        Self {
+            request: variant_name(request),
+            id,
+        }
+    }
+
+    /// The name of the request
+    pub fn request(&self) -> &str {
+        &self.request
+    }
+
+    /// The id of the call
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} #{}", self.request, self.id)
+    }
+}
+
+/// An [`Error`] with the [`Context`] of the call it belongs to
+#[derive(Debug)]
+pub struct CallError<E = Infallible> {
+    context: Context,
+    error: Error<E>,
+}
+
+impl<E> CallError<E> {
+    /// The call this error belongs to
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// The error without the context
+    pub fn error(&self) -> &Error<E> {
+        &self.error
+    }
+
+    /// Get the error without the context
+    pub fn into_error(self) -> Error<E> {
+        self.error
+    }
+
+    /// The category of the error
+    pub fn kind(&self) -> ErrorKind {
+        self.error.kind()
+    }
+
+    /// Whether the connection to the other side is gone, see [`ErrorKind::is_connection_lost`]
+    pub fn is_connection_lost(&self) -> bool {
+        self.error.is_connection_lost()
+    }
+
+    /// Whether the error might go away by itself, see [`ErrorKind::is_temporary`]
+    pub fn is_temporary(&self) -> bool {
+        self.error.is_temporary()
+    }
+
+    /// Whether retrying the call might succeed, see [`ErrorKind::is_retryable`]
+    pub fn is_retryable(&self) -> bool {
+        self.error.is_retryable()
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for CallError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.context, self.error)
+    }
+}
+
+impl<E: fmt::Debug + 'static> error::Error for CallError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Attach a [`Context`] to the error of a call
+pub trait ResultExt<T, E> {
+    /// Convert the error into an [`Error`] and attach the context of the call
+    fn context(self, context: Context) -> Result<T, CallError<E>>;
+}
+
+impl<T, X: Into<Error<E>>, E> ResultExt<T, E> for Result<T, X> {
+    fn context(self, context: Context) -> Result<T, CallError<E>> {
+        self.map_err(|error| error.into().with_context(context))
+    }
+}
@@ -0,0 +1,179 @@
+//! Byte stream adapters for streaming interactions
+//!
+//! [`SinkWriter`] turns a sink of messages, e.g. the [`UpdateSink`](crate::client::UpdateSink)
+//! of a bidi call, into an [`AsyncWrite`]. [`StreamReader`] turns a stream of messages,
+//! e.g. the responses of a bidi call, into an [`AsyncRead`]. Each message carries a
+//! chunk of bytes, so existing code written against the IO traits, like `tokio::io::copy`,
+//! compression or archive libraries, can run over an rpc call.
+//!
+//! The chunk message types just need to convert from and into `Vec<u8>`:
+//!
+//! ```ignore
+//! #[derive(Debug, Serialize, Deserialize)]
+//! struct Chunk(Vec<u8>);
+//!
+//! impl From<Vec<u8>> for Chunk { ... }
+//! impl From<Chunk> for Vec<u8> { ... }
+//!
+//! let (updates, responses) = client.bidi(Upload).await?;
+//! let mut writer = SinkWriter::new(updates);
+//! let mut reader = StreamReader::new(responses);
+//! ```
+//!
+//! Every write sends one message, so wrap the writer in a `BufWriter` when writing
+//! many small pieces.
+use std::{
+    io,
+    marker::PhantomData,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_lite::Stream;
+use futures_sink::Sink;
+use pin_project::pin_project;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Default maximum number of bytes per message written by a [`SinkWriter`]
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// An [`AsyncWrite`] that sends the written bytes as messages of type `T`
+///
+/// Shutting the writer down closes the sink, which signals the end of the updates
+/// to the server.
+#[pin_project]
+#[derive(Debug)]
+pub struct SinkWriter<S, T> {
+    #[pin]
+    sink: S,
+    max_chunk_size: usize,
+    _p: PhantomData<fn(T)>,
+}
+
+impl<S, T> SinkWriter<S, T>
+where
+    S: Sink<T>,
+    T: From<Vec<u8>>,
+{
+    /// Wrap a sink of chunk messages
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+            _p: PhantomData,
+        }
+    }
+
+    /// Set the maximum number of bytes per message
+    ///
+    /// Larger writes are split into several messages. This must be below the
+    /// maximum frame size of the transport.
+    pub fn with_max_chunk_size(mut self, max_chunk_size: usize) -> Self {
+        self.max_chunk_size = max_chunk_size.max(1);
+        self
+    }
+
+    /// Get back the wrapped sink
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<S, T> AsyncWrite for SinkWriter<S, T>
+where
+    S: Sink<T>,
+    S::Error: std::error::Error + Send + Sync + 'static,
+    T: From<Vec<u8>>,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let mut this = self.project();
+        ready!(this.sink.as_mut().poll_ready(cx)).map_err(io::Error::other)?;
+        let n = buf.len().min(*this.max_chunk_size);
+        this.sink
+            .start_send(T::from(buf[..n].to_vec()))
+            .map_err(io::Error::other)?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().sink.poll_flush(cx).map_err(io::Error::other)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().sink.poll_close(cx).map_err(io::Error::other)
+    }
+}
+
+/// An [`AsyncRead`] that reads the bytes of a stream of messages of type `T`
+///
+/// The reader is at its end when the stream ends. An error in the stream fails the
+/// read with [`io::ErrorKind::Other`].
+#[pin_project]
+#[derive(Debug)]
+pub struct StreamReader<S, T> {
+    #[pin]
+    stream: S,
+    chunk: Vec<u8>,
+    pos: usize,
+    _p: PhantomData<fn() -> T>,
+}
+
+impl<S, T, E> StreamReader<S, T>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: Into<Vec<u8>>,
+{
+    /// Wrap a stream of chunk messages
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            chunk: Vec::new(),
+            pos: 0,
+            _p: PhantomData,
+        }
+    }
+
+    /// Get back the wrapped stream
+    ///
+    /// Bytes of the current message that have not been read yet are lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S, T, E> AsyncRead for StreamReader<S, T>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: Into<Vec<u8>>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+        // skip empty messages, they don't mean the end of the stream
+        while *this.pos == this.chunk.len() {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(Ok(chunk)) => {
+                    *this.chunk = chunk.into();
+                    *this.pos = 0;
+                }
+                Some(Err(cause)) => return Poll::Ready(Err(io::Error::other(cause))),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+        let n = buf.remaining().min(this.chunk.len() - *this.pos);
+        buf.put_slice(&this.chunk[*this.pos..*this.pos + n]);
+        *this.pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
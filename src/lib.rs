@@ -91,33 +91,56 @@
 //! ```
 #![deny(missing_docs)]
 #![deny(rustdoc::broken_intra_doc_links)]
-use serde::{de::DeserializeOwned, Serialize};
 use std::fmt::{Debug, Display};
+#[cfg(feature = "admin")]
+pub mod admin;
+#[cfg(feature = "audit")]
+pub mod audit;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "zero-copy")]
+pub mod blob;
+#[cfg(any(
+    feature = "grpc-bridge",
+    feature = "jsonrpc-bridge",
+    feature = "rest-gateway"
+))]
+pub mod bridge;
 pub mod client;
+#[cfg(feature = "codegen")]
+pub mod codegen;
+#[cfg(feature = "dyn-client")]
+pub mod dyn_client;
+pub mod error;
+pub mod io;
 pub mod message;
+#[cfg(feature = "offline-queue")]
+pub mod offline;
+#[cfg(feature = "ping")]
+pub mod ping;
+#[cfg(feature = "relay")]
+pub mod relay;
+#[cfg(feature = "reliable")]
+pub mod reliable;
+#[cfg(feature = "replay-protection")]
+pub mod replay;
+pub mod runtime;
 pub mod server;
+#[cfg(feature = "request-signing")]
+pub mod signing;
+#[cfg(feature = "test-utils")]
+pub mod test;
 pub mod transport;
+#[cfg(feature = "zero-copy")]
+pub use blob::Blob;
 pub use client::RpcClient;
+pub use quic_rpc_core::{RpcMessage, Service};
 pub use server::RpcServer;
 #[cfg(feature = "macros")]
 mod macros;
 
 pub mod pattern;
 
-/// Requirements for a RPC message
-///
-/// Even when just using the mem transport, we require messages to be Serializable and Deserializable.
-/// Likewise, even when using the quinn transport, we require messages to be Send.
-///
-/// This does not seem like a big restriction. If you want a pure memory channel without the possibility
-/// to also use the quinn transport, you might want to use a mpsc channel directly.
-pub trait RpcMessage: Debug + Serialize + DeserializeOwned + Send + Sync + Unpin + 'static {}
-
-impl<T> RpcMessage for T where
-    T: Debug + Serialize + DeserializeOwned + Send + Sync + Unpin + 'static
-{
-}
-
 /// Requirements for an internal error
 ///
 /// All errors have to be Send, Sync and 'static so they can be sent across threads.
@@ -133,32 +156,6 @@ pub trait RpcError: Debug + Display + Into<anyhow::Error> + Send + Sync + Unpin
 impl<T> RpcError for T where T: Debug + Display + Into<anyhow::Error> + Send + Sync + Unpin + 'static
 {}
 
-/// A service
-///
-/// A service has request and response message types. These types have to be the
-/// union of all possible request and response types for all interactions with
-/// the service.
-///
-/// Usually you will define an enum for the request and response
-/// type, and use the [derive_more](https://crates.io/crates/derive_more) crate to
-/// define the conversions between the enum and the actual request and response types.
-///
-/// To make a message type usable as a request for a service, implement [message::Msg]
-/// for it. This is how you define the interaction patterns for each request type.
-///
-/// Depending on the interaction type, you might need to implement traits that further
-/// define details of the interaction.
-///
-/// A message type can be used for multiple services. E.g. you might have a
-/// Status request that is understood by multiple services and returns a
-/// standard status response.
-pub trait Service: Send + Sync + Debug + Clone + 'static {
-    /// Type of request messages
-    type Req: RpcMessage;
-    /// Type of response messages
-    type Res: RpcMessage;
-}
-
 /// A connector to a specific service
 ///
 /// This is just a trait alias for a [`transport::Connector`] with the right types. It is used
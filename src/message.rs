@@ -1,29 +1,38 @@
 //! Service definition
 //!
 //! Traits to define the behaviour of messages for services
-use crate::Service;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 
 pub use crate::pattern::bidi_streaming::{BidiStreaming, BidiStreamingMsg};
 pub use crate::pattern::client_streaming::{ClientStreaming, ClientStreamingMsg};
 pub use crate::pattern::rpc::{Rpc, RpcMsg};
 pub use crate::pattern::server_streaming::{ServerStreaming, ServerStreamingMsg};
 
-/// Declares the interaction pattern for a message and a service.
-///
-/// For each server and each message, only one interaction pattern can be defined.
-pub trait Msg<S: Service>: Into<S::Req> + TryFrom<S::Req> + Send + 'static {
-    /// The interaction pattern for this message with this service.
-    type Pattern: InteractionPattern;
-}
+pub use quic_rpc_core::message::{Deadline, Heartbeat, InteractionPattern, Msg, Priority};
 
-/// Trait defining interaction pattern.
-///
-/// Currently there are 4 patterns:
-/// - [Rpc]: 1 request, 1 response
-/// - [ClientStreaming]: 1 request, stream of updates, 1 response
-/// - [ServerStreaming]: 1 request, stream of responses
-/// - [BidiStreaming]: 1 request, stream of updates, stream of responses
+/// Get the variant name of a message enum from its `Debug` representation
 ///
-/// You could define your own interaction patterns such as OneWay.
-pub trait InteractionPattern: Debug + Clone + Send + Sync + 'static {}
+/// Formatting stops right after the name, so this is cheap even for large messages.
+pub(crate) fn variant_name(value: &impl Debug) -> String {
+    /// Collects the leading identifier and fails once it ends, to stop formatting
+    struct Name(String);
+
+    impl fmt::Write for Name {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            match s.find(|c: char| !(c.is_alphanumeric() || c == '_')) {
+                Some(end) => {
+                    self.0.push_str(&s[..end]);
+                    Err(fmt::Error)
+                }
+                None => {
+                    self.0.push_str(s);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    let mut name = Name(String::new());
+    fmt::write(&mut name, format_args!("{value:?}")).ok();
+    name.0
+}
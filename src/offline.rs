@@ -0,0 +1,315 @@
+//! Durable queue for requests issued while offline
+//!
+//! An [`OfflineQueue`] wraps an [`RpcClient`] for clients that are only connected
+//! some of the time. Requests that don't need a response, either because they are
+//! fire-and-forget or because they are idempotent and can simply be sent again, are
+//! handed to [`OfflineQueue::submit`]. If the server can't be reached, the request is
+//! persisted to a file and sent later by [`OfflineQueue::flush`], in the order the
+//! requests were submitted. The quinn and iroh-net connectors reconnect on their
+//! own, so [`OfflineQueue::run`] just retries flushing periodically.
+//!
+//! The queue survives restarts of the client, [`OfflineQueue::open`] loads the
+//! requests that were still pending. Use [`QueueLimits`] to bound how many requests
+//! are kept and for how long.
+//!
+//! Requests are sent as rpc calls and the responses are discarded. A request that
+//! was sent but whose response got lost is sent again, so only queue requests that
+//! are safe to repeat.
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    path::{Path, PathBuf},
+    result,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_lite::StreamExt;
+use futures_util::SinkExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::RpcMsg,
+    pattern::rpc,
+    runtime::{Timer, Tokio},
+    transport::ConnectionErrors,
+    Connector, RpcClient, Service,
+};
+
+/// Limits for the requests kept by an [`OfflineQueue`], unlimited by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueLimits {
+    max_requests: Option<usize>,
+    max_bytes: Option<usize>,
+    max_age: Option<Duration>,
+}
+
+impl QueueLimits {
+    /// Refuse new requests once `max` requests are queued
+    pub fn max_requests(mut self, max: usize) -> Self {
+        self.max_requests = Some(max);
+        self
+    }
+
+    /// Refuse new requests once the queued requests take up `max` bytes, encoded
+    pub fn max_bytes(mut self, max: usize) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    /// Drop requests that could not be sent within `max_age` of being queued
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+}
+
+/// A queued request, encoded so it can be sent again after a failed attempt
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// Milliseconds since the unix epoch, so the age survives restarts
+    queued_at: u64,
+    request: Vec<u8>,
+}
+
+/// A client that persists requests it can't send right away
+pub struct OfflineQueue<S, C> {
+    client: RpcClient<S, C>,
+    path: PathBuf,
+    limits: QueueLimits,
+    entries: Mutex<VecDeque<Entry>>,
+    flushing: tokio::sync::Mutex<()>,
+    timer: Arc<dyn Timer>,
+}
+
+impl<S: Service, C: fmt::Debug> fmt::Debug for OfflineQueue<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OfflineQueue")
+            .field("client", &self.client)
+            .field("path", &self.path)
+            .field("limits", &self.limits)
+            .field("len", &self.entries.lock().unwrap().len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: Service, C: Connector<S>> OfflineQueue<S, C> {
+    /// Create a queue that persists requests to the file at `path`
+    ///
+    /// Requests that are still in the file from a previous run are loaded, except
+    /// for those that are older than the maximum age.
+    pub fn open(
+        client: RpcClient<S, C>,
+        path: impl Into<PathBuf>,
+        limits: QueueLimits,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let entries = match std::fs::read(&path) {
+            Ok(data) => bincode::deserialize(&data)
+                .map_err(|cause| io::Error::new(io::ErrorKind::InvalidData, cause))?,
+            Err(cause) if cause.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+            Err(cause) => return Err(cause),
+        };
+        let this = Self {
+            client,
+            path,
+            limits,
+            entries: Mutex::new(entries),
+            flushing: Default::default(),
+            timer: Arc::new(Tokio),
+        };
+        let mut entries = this.entries.lock().unwrap();
+        if this.expire(&mut entries) {
+            persist(&this.path, &entries)?;
+        }
+        drop(entries);
+        Ok(this)
+    }
+
+    /// Wait between flush attempts of [`OfflineQueue::run`] using the given timer
+    pub fn with_timer(mut self, timer: impl Timer) -> Self {
+        self.timer = Arc::new(timer);
+        self
+    }
+
+    /// The file the requests are persisted to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of queued requests
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// True if no requests are queued
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Send a request now, or queue it if that is not possible
+    ///
+    /// Requests are sent right away only if nothing is queued, so they reach the
+    /// server in order. The response is discarded.
+    pub async fn submit<M: RpcMsg<S>>(&self, msg: M) -> result::Result<(), Error<C>> {
+        let req: S::Req = msg.into();
+        let request = bincode::serialize(&req).map_err(Error::Encode)?;
+        if self.is_empty() {
+            let _flushing = self.flushing.lock().await;
+            if self.is_empty() {
+                match self.send(req).await {
+                    Ok(()) => return Ok(()),
+                    Err(cause) => tracing::debug!("queueing request, sending failed: {cause}"),
+                }
+            }
+        }
+        self.push(request)
+    }
+
+    /// Queue a request without trying to send it
+    pub fn enqueue<M: RpcMsg<S>>(&self, msg: M) -> result::Result<(), Error<C>> {
+        let req: S::Req = msg.into();
+        let request = bincode::serialize(&req).map_err(Error::Encode)?;
+        self.push(request)
+    }
+
+    /// Send the queued requests in order
+    ///
+    /// Stops at the first request that fails, which stays at the front of the
+    /// queue. Returns the number of requests that were sent.
+    pub async fn flush(&self) -> result::Result<usize, Error<C>> {
+        let _flushing = self.flushing.lock().await;
+        let mut sent = 0;
+        loop {
+            let req = {
+                let mut entries = self.entries.lock().unwrap();
+                if self.expire(&mut entries) {
+                    persist(&self.path, &entries).map_err(Error::Io)?;
+                }
+                let Some(entry) = entries.front() else {
+                    return Ok(sent);
+                };
+                bincode::deserialize::<S::Req>(&entry.request)
+            };
+            match req {
+                Ok(req) => {
+                    self.send(req).await.map_err(Error::Rpc)?;
+                    sent += 1;
+                }
+                // e.g. the request type changed since the request was queued
+                Err(cause) => tracing::warn!("dropping queued request: {cause}"),
+            }
+            // entries are only removed while holding the flush lock, so the front
+            // is still the same one
+            let mut entries = self.entries.lock().unwrap();
+            entries.pop_front();
+            persist(&self.path, &entries).map_err(Error::Io)?;
+        }
+    }
+
+    /// Flush the queue whenever it is not empty, waiting `interval` between attempts
+    ///
+    /// This never returns, spawn it as a task or race it against a shutdown signal.
+    pub async fn run(&self, interval: Duration) {
+        loop {
+            if !self.is_empty() {
+                match self.flush().await {
+                    Ok(n) => tracing::debug!("flushed {n} queued requests"),
+                    Err(cause) => tracing::debug!("flushing queued requests failed: {cause}"),
+                }
+            }
+            self.timer.sleep(interval).await;
+        }
+    }
+
+    async fn send(&self, req: S::Req) -> result::Result<(), rpc::Error<C>> {
+        let source = &self.client.source;
+        let (mut send, mut recv) = source.open_rpc().await.map_err(rpc::Error::Open)?;
+        send.send(req).await.map_err(rpc::Error::Send)?;
+        recv.next()
+            .await
+            .ok_or(rpc::Error::EarlyClose)?
+            .map_err(rpc::Error::RecvError)?;
+        Ok(())
+    }
+
+    fn push(&self, request: Vec<u8>) -> result::Result<(), Error<C>> {
+        let mut entries = self.entries.lock().unwrap();
+        // don't pull the front away from under a running flush
+        if let Ok(_flushing) = self.flushing.try_lock() {
+            self.expire(&mut entries);
+        }
+        if self
+            .limits
+            .max_requests
+            .is_some_and(|max| entries.len() >= max)
+        {
+            return Err(Error::Full);
+        }
+        if let Some(max) = self.limits.max_bytes {
+            let bytes: usize = entries.iter().map(|entry| entry.request.len()).sum();
+            if bytes + request.len() > max {
+                return Err(Error::Full);
+            }
+        }
+        entries.push_back(Entry {
+            queued_at: now_millis(),
+            request,
+        });
+        if let Err(cause) = persist(&self.path, &entries) {
+            entries.pop_back();
+            return Err(Error::Io(cause));
+        }
+        Ok(())
+    }
+
+    /// Drop the requests that are too old, returns true if any were dropped
+    fn expire(&self, entries: &mut VecDeque<Entry>) -> bool {
+        let Some(max_age) = self.limits.max_age else {
+            return false;
+        };
+        let cutoff = now_millis().saturating_sub(max_age.as_millis() as u64);
+        let len = entries.len();
+        entries.retain(|entry| entry.queued_at >= cutoff);
+        if entries.len() < len {
+            tracing::debug!("dropped {} expired requests", len - entries.len());
+        }
+        entries.len() < len
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Write the queue to a temporary file and move it into place
+fn persist(path: &Path, entries: &VecDeque<Entry>) -> io::Result<()> {
+    let data = bincode::serialize(entries).map_err(io::Error::other)?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// Error of an [`OfflineQueue`]
+#[derive(Debug)]
+pub enum Error<C: ConnectionErrors> {
+    /// The request can't be queued because the queue is at its limit
+    Full,
+    /// The request could not be encoded
+    Encode(bincode::Error),
+    /// The queue could not be persisted
+    Io(io::Error),
+    /// Sending a queued request failed
+    Rpc(rpc::Error<C>),
+}
+
+impl<C: ConnectionErrors> fmt::Display for Error<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl<C: ConnectionErrors> std::error::Error for Error<C> {}
@@ -3,10 +3,11 @@
 use futures_lite::{Stream, StreamExt};
 use futures_util::{FutureExt, SinkExt};
 
+#[cfg(feature = "tokio-runtime")]
+use crate::server::{drive_while, BlockingUpdates};
 use crate::{
     client::{BoxStreamSync, UpdateSink},
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError, UpdateStream},
+    server::{race2, unless_closed, RpcChannel, RpcServerError, UpdateStream},
     transport::{ConnectionErrors, Connector, StreamTypes},
     RpcClient, Service,
 };
@@ -17,27 +18,7 @@ use std::{
     result,
 };
 
-/// Bidirectional streaming interaction pattern
-///
-/// After the initial request, the client can send updates and the server can
-/// send responses.
-#[derive(Debug, Clone, Copy)]
-pub struct BidiStreaming;
-impl InteractionPattern for BidiStreaming {}
-
-/// Defines update type and response type for a bidi streaming message.
-pub trait BidiStreamingMsg<S: Service>: Msg<S, Pattern = BidiStreaming> {
-    /// The type for request updates
-    ///
-    /// For a request that does not support updates, this can be safely set to any type, including
-    /// the message type itself. Any update for such a request will result in an error.
-    type Update: Into<S::Req> + TryFrom<S::Req> + Send + 'static;
-
-    /// The type for the response
-    ///
-    /// For requests that can produce errors, this can be set to [Result<T, E>](std::result::Result).
-    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
-}
+pub use quic_rpc_core::pattern::{BidiStreaming, BidiStreamingMsg};
 
 /// Server error when accepting a bidi request
 #[derive(Debug)]
@@ -131,7 +112,8 @@ where
         let responses = f(target, req, updates);
         race2(read_error.map(Err), async move {
             tokio::pin!(responses);
-            while let Some(response) = responses.next().await {
+            // stop producing responses once the client stops receiving them
+            while let Some(response) = unless_closed::<C, _>(&mut send, responses.next()).await? {
                 // turn into a S::Res so we can send it
                 let response = response.into();
                 // send it and return the error if any
@@ -143,4 +125,29 @@ where
         })
         .await
     }
+    /// handle the message M using a blocking function on the target object
+    ///
+    /// The function runs on the blocking pool of the tokio runtime. It gets the updates
+    /// as a blocking iterator, and each item of the returned iterator is sent as a
+    /// response.
+    #[cfg(feature = "tokio-runtime")]
+    pub async fn bidi_streaming_blocking<M, F, I, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: BidiStreamingMsg<S>,
+        F: FnOnce(T, M, BlockingUpdates<M::Update>) -> I + Send + 'static,
+        I: IntoIterator<Item = M::Response>,
+        T: Send + 'static,
+    {
+        self.bidi_streaming(req, target, |target, req, updates| {
+            let (updates, forward) = BlockingUpdates::new(updates);
+            let responses = crate::runtime::spawn_blocking_iter(move || f(target, req, updates));
+            drive_while(responses, forward)
+        })
+        .await
+    }
 }
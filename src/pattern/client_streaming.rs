@@ -3,10 +3,11 @@
 use futures_lite::{future::Boxed, Future, StreamExt};
 use futures_util::{FutureExt, SinkExt, TryFutureExt};
 
+#[cfg(feature = "tokio-runtime")]
+use crate::server::BlockingUpdates;
 use crate::{
     client::UpdateSink,
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError, UpdateStream},
+    server::{race2, unless_closed, RpcChannel, RpcServerError, UpdateStream},
     transport::{ConnectionErrors, StreamTypes},
     Connector, RpcClient, Service,
 };
@@ -17,27 +18,7 @@ use std::{
     result,
 };
 
-/// Client streaming interaction pattern
-///
-/// After the initial request, the client can send updates, but there is only
-/// one response.
-#[derive(Debug, Clone, Copy)]
-pub struct ClientStreaming;
-impl InteractionPattern for ClientStreaming {}
-
-/// Defines update type and response type for a client streaming message.
-pub trait ClientStreamingMsg<S: Service>: Msg<S, Pattern = ClientStreaming> {
-    /// The type for request updates
-    ///
-    /// For a request that does not support updates, this can be safely set to any type, including
-    /// the message type itself. Any update for such a request will result in an error.
-    type Update: Into<S::Req> + TryFrom<S::Req> + Send + 'static;
-
-    /// The type for the response
-    ///
-    /// For requests that can produce errors, this can be set to [Result<T, E>](std::result::Result).
-    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
-}
+pub use quic_rpc_core::pattern::{ClientStreaming, ClientStreamingMsg};
 
 /// Server error when accepting a client streaming request
 #[derive(Debug)]
@@ -134,8 +115,8 @@ where
         let Self { mut send, recv, .. } = self;
         let (updates, read_error) = UpdateStream::new(recv);
         race2(read_error.map(Err), async move {
-            // get the response
-            let res = f(target, req, updates).await;
+            // get the response, unless the client stops waiting for it
+            let res = unless_closed::<C, _>(&mut send, f(target, req, updates)).await?;
             // turn into a S::Res so we can send it
             let res = res.into();
             // send it and return the error if any
@@ -143,4 +124,35 @@ where
         })
         .await
     }
+    /// handle the message M using a blocking function on the target object
+    ///
+    /// The function runs on the blocking pool of the tokio runtime. It gets the updates
+    /// as a blocking iterator.
+    #[cfg(feature = "tokio-runtime")]
+    pub async fn client_streaming_blocking<M, F, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ClientStreamingMsg<S>,
+        F: FnOnce(T, M, BlockingUpdates<M::Update>) -> M::Response + Send + 'static,
+        T: Send + 'static,
+    {
+        self.client_streaming(req, target, |target, req, updates| async move {
+            let (updates, forward) = BlockingUpdates::new(updates);
+            let res = crate::runtime::spawn_blocking(move || f(target, req, updates));
+            // keep forwarding until all updates are in, but don't wait for more
+            race2(
+                async {
+                    forward.await;
+                    std::future::pending().await
+                },
+                res,
+            )
+            .await
+        })
+        .await
+    }
 }
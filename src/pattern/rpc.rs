@@ -4,8 +4,7 @@ use futures_lite::{Future, StreamExt};
 use futures_util::{FutureExt, SinkExt};
 
 use crate::{
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError},
+    server::{race2, unless_closed, RpcChannel, RpcServerError},
     transport::{ConnectionErrors, StreamTypes},
     Connector, RpcClient, Service,
 };
@@ -16,29 +15,8 @@ use std::{
     result,
 };
 
-/// Rpc interaction pattern
-///
-/// There is only one request and one response.
-#[derive(Debug, Clone, Copy)]
-pub struct Rpc;
-impl InteractionPattern for Rpc {}
+pub use quic_rpc_core::pattern::{Rpc, RpcMsg};
 
-/// Defines the response type for a rpc message.
-///
-/// Since this is the most common interaction pattern, this also implements [Msg] for you
-/// automatically, with the interaction pattern set to [Rpc]. This is to reduce boilerplate
-/// when defining rpc messages.
-pub trait RpcMsg<S: Service>: Msg<S, Pattern = Rpc> {
-    /// The type for the response
-    ///
-    /// For requests that can produce errors, this can be set to [Result<T, E>](std::result::Result).
-    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
-}
-
-/// We can only do this for one trait, so we do it for RpcMsg since it is the most common
-impl<T: RpcMsg<S>, S: Service> Msg<S> for T {
-    type Pattern = Rpc;
-}
 /// Client error. All client DSL methods return a `Result` with this error type.
 #[derive(Debug)]
 pub enum Error<C: ConnectionErrors> {
@@ -73,7 +51,7 @@ where
         M: RpcMsg<S>,
     {
         let msg = msg.into();
-        let (mut send, mut recv) = self.source.open().await.map_err(Error::Open)?;
+        let (mut send, mut recv) = self.source.open_rpc().await.map_err(Error::Open)?;
         send.send(msg).await.map_err(Error::<C>::Send)?;
         let res = recv
             .next()
@@ -109,14 +87,15 @@ where
         let Self {
             mut send, mut recv, ..
         } = self;
-        // cancel if we get an update, no matter what it is
-        let cancel = recv
-            .next()
-            .map(|_| RpcServerError::UnexpectedUpdateMessage::<C>);
+        // cancel if we get an update, no matter what it is, or if the client is gone
+        let cancel = recv.next().map(|update| match update {
+            Some(_) => RpcServerError::UnexpectedUpdateMessage::<C>,
+            None => RpcServerError::Cancelled,
+        });
         // race the computation and the cancellation
         race2(cancel.map(Err), async move {
-            // get the response
-            let res = f(target, req).await;
+            // get the response, unless the client stops waiting for it
+            let res = unless_closed::<C, _>(&mut send, f(target, req)).await?;
             // turn into a S::Res so we can send it
             let res = res.into();
             // send it and return the error if any
@@ -151,4 +130,26 @@ where
         };
         self.rpc(req, target, fut).await
     }
+
+    /// handle the message of type `M` using a blocking function on the target object
+    ///
+    /// The function runs on the blocking pool of the tokio runtime, so it can do CPU heavy
+    /// work or synchronous IO without stalling other tasks.
+    #[cfg(feature = "tokio-runtime")]
+    pub async fn rpc_blocking<M, F, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: RpcMsg<S>,
+        F: FnOnce(T, M) -> M::Response + Send + 'static,
+        T: Send + 'static,
+    {
+        self.rpc(req, target, |target, req| {
+            crate::runtime::spawn_blocking(move || f(target, req))
+        })
+        .await
+    }
 }
@@ -5,8 +5,7 @@ use futures_util::{FutureExt, SinkExt, TryFutureExt};
 
 use crate::{
     client::{BoxStreamSync, DeferDrop},
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError},
+    server::{race2, unless_closed, RpcChannel, RpcServerError},
     transport::{ConnectionErrors, Connector, StreamTypes},
     RpcClient, Service,
 };
@@ -14,23 +13,13 @@ use crate::{
 use std::{
     error,
     fmt::{self, Debug},
+    pin::Pin,
     result,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
 };
 
-/// Server streaming interaction pattern
-///
-/// After the initial request, the server can send a stream of responses.
-#[derive(Debug, Clone, Copy)]
-pub struct ServerStreaming;
-impl InteractionPattern for ServerStreaming {}
-
-/// Defines response type for a server streaming message.
-pub trait ServerStreamingMsg<S: Service>: Msg<S, Pattern = ServerStreaming> {
-    /// The type for the response
-    ///
-    /// For requests that can produce errors, this can be set to [Result<T, E>](std::result::Result).
-    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
-}
+pub use quic_rpc_core::pattern::{ServerStreaming, ServerStreamingMsg};
 
 /// Server error when accepting a server streaming request
 #[derive(Debug)]
@@ -66,6 +55,68 @@ impl<S: ConnectionErrors> fmt::Display for ItemError<S> {
 
 impl<S: ConnectionErrors> error::Error for ItemError<S> {}
 
+/// Source of the ids of [`Subscription`]s, unique within the process
+static NEXT_SUBSCRIPTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Handle for the responses of a server streaming request
+///
+/// The handle is a stream of the responses. Dropping it unsubscribes right away:
+/// the channel is torn down in both directions, so the server cancels the handler
+/// instead of producing responses until the buffers are full. Use
+/// [`Subscription::close`] to wait until the unsubscribe was sent.
+pub struct Subscription<C: StreamTypes, T> {
+    id: u64,
+    recv: Option<BoxStreamSync<'static, result::Result<T, ItemError<C>>>>,
+    send: Option<C::SendSink>,
+}
+
+impl<C: StreamTypes, T> Subscription<C, T> {
+    /// Id of the subscription, unique within the process, e.g. for logging
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Unsubscribe, and wait until the server was told
+    ///
+    /// Responses that were not received yet are discarded.
+    pub async fn close(mut self) -> result::Result<(), C::SendError> {
+        // stop receiving first, so the server does not block on sending a response
+        self.recv.take();
+        match self.send.take() {
+            Some(mut send) => send.close().await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl<C: StreamTypes, T> Debug for Subscription<C, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: StreamTypes, T> Stream for Subscription<C, T> {
+    type Item = result::Result<T, ItemError<C>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.recv.as_mut() {
+            Some(recv) => recv.as_mut().poll_next(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+impl<C: StreamTypes, T> Drop for Subscription<C, T> {
+    fn drop(&mut self) {
+        // dropping the receive side tells the server that nobody listens anymore,
+        // dropping the send side ends the request
+        self.recv.take();
+        self.send.take();
+    }
+}
+
 impl<S, C> RpcClient<S, C>
 where
     C: crate::Connector<S>,
@@ -90,6 +141,31 @@ where
         let recv = Box::pin(DeferDrop(recv, send));
         Ok(recv)
     }
+
+    /// Server streaming call that returns a [`Subscription`] handle
+    ///
+    /// Like [`RpcClient::server_streaming`], but the subscription can be closed
+    /// explicitly, and dropping it unsubscribes.
+    pub async fn subscribe<M>(
+        &self,
+        msg: M,
+    ) -> result::Result<Subscription<C, M::Response>, Error<C>>
+    where
+        M: ServerStreamingMsg<S>,
+    {
+        let msg = msg.into();
+        let (mut send, recv) = self.source.open().await.map_err(Error::Open)?;
+        send.send(msg).map_err(Error::<C>::Send).await?;
+        let recv = recv.map(move |x| match x {
+            Ok(msg) => M::Response::try_from(msg).map_err(|_| ItemError::DowncastError),
+            Err(e) => Err(ItemError::RecvError(e)),
+        });
+        Ok(Subscription {
+            id: NEXT_SUBSCRIPTION_ID.fetch_add(1, Ordering::Relaxed),
+            recv: Some(Box::pin(recv)),
+            send: Some(send),
+        })
+    }
 }
 
 impl<S, C> RpcChannel<S, C>
@@ -115,16 +191,18 @@ where
         let Self {
             mut send, mut recv, ..
         } = self;
-        // cancel if we get an update, no matter what it is
-        let cancel = recv
-            .next()
-            .map(|_| RpcServerError::UnexpectedUpdateMessage::<C>);
+        // cancel if we get an update, no matter what it is, or if the client unsubscribes
+        let cancel = recv.next().map(|update| match update {
+            Some(_) => RpcServerError::UnexpectedUpdateMessage::<C>,
+            None => RpcServerError::Cancelled,
+        });
         // race the computation and the cancellation
         race2(cancel.map(Err), async move {
             // get the response
             let responses = f(target, req);
             tokio::pin!(responses);
-            while let Some(response) = responses.next().await {
+            // stop producing responses once the client stops receiving them
+            while let Some(response) = unless_closed::<C, _>(&mut send, responses.next()).await? {
                 // turn into a S::Res so we can send it
                 let response = response.into();
                 // send it and return the error if any
@@ -136,4 +214,28 @@ where
         })
         .await
     }
+
+    /// handle the message M using a blocking function on the target object
+    ///
+    /// The function runs on the blocking pool of the tokio runtime, and each item of the
+    /// returned iterator is sent as a response. Iteration stops early if the client
+    /// goes away.
+    #[cfg(feature = "tokio-runtime")]
+    pub async fn server_streaming_blocking<M, F, I, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ServerStreamingMsg<S>,
+        F: FnOnce(T, M) -> I + Send + 'static,
+        I: IntoIterator<Item = M::Response>,
+        T: Send + 'static,
+    {
+        self.server_streaming(req, target, |target, req| {
+            crate::runtime::spawn_blocking_iter(move || f(target, req))
+        })
+        .await
+    }
 }
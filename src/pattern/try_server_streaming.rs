@@ -2,12 +2,10 @@
 
 use futures_lite::{Future, Stream, StreamExt};
 use futures_util::{FutureExt, SinkExt, TryFutureExt};
-use serde::{Deserialize, Serialize};
 
 use crate::{
     client::{BoxStreamSync, DeferDrop},
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError},
+    server::{race2, unless_closed, RpcChannel, RpcServerError},
     transport::{self, ConnectionErrors, StreamTypes},
     Connector, RpcClient, Service,
 };
@@ -18,34 +16,7 @@ use std::{
     result,
 };
 
-/// A guard message to indicate that the stream has been created.
-///
-/// This is so we can dinstinguish between an error creating the stream and
-/// an error in the first item produced by the stream.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct StreamCreated;
-
-/// Fallible server streaming interaction pattern.
-#[derive(Debug, Clone, Copy)]
-pub struct TryServerStreaming;
-
-impl InteractionPattern for TryServerStreaming {}
-
-/// Same as ServerStreamingMsg, but with lazy stream creation and the error type explicitly defined.
-pub trait TryServerStreamingMsg<S: Service>: Msg<S, Pattern = TryServerStreaming>
-where
-    result::Result<Self::Item, Self::ItemError>: Into<S::Res> + TryFrom<S::Res>,
-    result::Result<StreamCreated, Self::CreateError>: Into<S::Res> + TryFrom<S::Res>,
-{
-    /// Error when creating the stream
-    type CreateError: Debug + Send + 'static;
-
-    /// Error for stream items
-    type ItemError: Debug + Send + 'static;
-
-    /// Successful response item
-    type Item: Send + 'static;
-}
+pub use quic_rpc_core::pattern::{StreamCreated, TryServerStreaming, TryServerStreamingMsg};
 
 /// Server error when accepting a server streaming request
 ///
@@ -126,10 +97,11 @@ where
         let Self {
             mut send, mut recv, ..
         } = self;
-        // cancel if we get an update, no matter what it is
-        let cancel = recv
-            .next()
-            .map(|_| RpcServerError::UnexpectedUpdateMessage::<C>);
+        // cancel if we get an update, no matter what it is, or if the client unsubscribes
+        let cancel = recv.next().map(|update| match update {
+            Some(_) => RpcServerError::UnexpectedUpdateMessage::<C>,
+            None => RpcServerError::Cancelled,
+        });
         // race the computation and the cancellation
         race2(cancel.map(Err), async move {
             // get the response
@@ -154,7 +126,8 @@ where
                 }
             };
             tokio::pin!(responses);
-            while let Some(response) = responses.next().await {
+            // stop producing responses once the client stops receiving them
+            while let Some(response) = unless_closed::<C, _>(&mut send, responses.next()).await? {
                 // turn into a S::Res so we can send it
                 let response = response.into();
                 // send it and return the error if any
@@ -0,0 +1,75 @@
+//! Built-in ping service to measure round-trip time
+//!
+//! [`RpcClient::ping`] sends a [`PingRequest`] and measures how long it takes until the
+//! response arrives. Unlike a transport level ping, this goes through the same
+//! connection, framing and server accept loop as real requests, so it reflects the
+//! latency that calls actually see. This is useful to display the connection quality
+//! or to decide when to fail over to another server.
+//!
+//! The [`PingService`] can be served on its own, or nested in another service by adding
+//! a variant for [`PingRequest`] and [`PingResponse`] to its request and response enums:
+//!
+//! ```ignore
+//! // server
+//! MyRequest::Ping(req) => PingService.handle_rpc_request(req, chan.map()).await,
+//! // client
+//! let rtt = client.clone().map::<PingService>().ping().await?;
+//! ```
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::RpcMsg,
+    pattern::rpc,
+    server::{RpcChannel, RpcServerError},
+    transport::StreamTypes,
+    Connector, RpcClient, Service,
+};
+
+/// The ping service
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PingService;
+
+impl Service for PingService {
+    type Req = PingRequest;
+    type Res = PingResponse;
+}
+
+/// Ask the server to respond immediately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PingRequest;
+
+/// Response to [`PingRequest`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PingResponse;
+
+impl RpcMsg<PingService> for PingRequest {
+    type Response = PingResponse;
+}
+
+impl PingService {
+    /// Handle a request of the [`PingService`]
+    pub async fn handle_rpc_request<C>(
+        self,
+        req: PingRequest,
+        chan: RpcChannel<PingService, C>,
+    ) -> Result<(), RpcServerError<C>>
+    where
+        C: StreamTypes<In = PingRequest, Out = PingResponse>,
+    {
+        chan.rpc(req, self, |_, _| async { PingResponse }).await
+    }
+}
+
+impl<C: Connector<PingService>> RpcClient<PingService, C> {
+    /// Measure the round-trip time of a request to the server
+    ///
+    /// This includes opening a channel, which is cheap for the multiplexing transports
+    /// once the connection is established.
+    pub async fn ping(&self) -> Result<Duration, rpc::Error<C>> {
+        let start = Instant::now();
+        self.rpc(PingRequest).await?;
+        Ok(start.elapsed())
+    }
+}
@@ -0,0 +1,395 @@
+//! Relay channels from one transport to an upstream server on another transport
+//!
+//! A [`Relay`] accepts channels on a [`Listener`] and opens a matching channel on a
+//! [`Connector`] for each of them. Messages are forwarded in both directions until the
+//! upstream server is done with the channel. This can be used to expose a server to
+//! clients that can only use a different transport, or to hop across network
+//! boundaries.
+//!
+//! The relay decodes and re-encodes every message, so the two transports can use
+//! completely different framing. It does not look at the messages though, so it does
+//! not need to know the interaction pattern. If the client closes its side of the
+//! channel, the relay closes its side of the upstream channel, so cancellation works
+//! just like on a direct connection.
+//!
+//! Each channel is relayed on its own task, spawned using a [`Spawner`]. By default
+//! this is tokio, use [`Relay::with_spawner`] to run the relay on another executor.
+//!
+//! # Shadow traffic
+//!
+//! A relay can mirror a fraction of the channels to a second server with
+//! [`Relay::with_shadow`], e.g. to validate a new server version against production
+//! traffic. The client only ever sees the responses of the upstream server, the
+//! responses of the shadow server are discarded. With [`Shadow::on_divergence`], the
+//! responses of both servers are compared once a channel is done, and every mismatch
+//! is reported.
+use std::{
+    error, fmt,
+    marker::PhantomData,
+    result,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use futures_lite::StreamExt;
+use futures_util::SinkExt;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    runtime::{Spawner, Tokio},
+    transport::{
+        boxed::{BoxableConnector, BoxedConnector},
+        ConnectionErrors,
+    },
+    Connector, Listener, Service,
+};
+
+/// Relays all channels accepted on a listener to an upstream connector
+pub struct Relay<S: Service, L, C> {
+    listener: L,
+    upstream: C,
+    spawner: Arc<dyn Spawner>,
+    shadow: Option<Arc<Shadow<S>>>,
+    _p: PhantomData<S>,
+}
+
+impl<S: Service, L: fmt::Debug, C: fmt::Debug> fmt::Debug for Relay<S, L, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Relay")
+            .field("listener", &self.listener)
+            .field("upstream", &self.upstream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: Service, L: Listener<S>, C: Connector<S>> Relay<S, L, C> {
+    /// Create a new relay from a listener for the clients and a connector to the server
+    pub fn new(listener: L, upstream: C) -> Self {
+        Self {
+            listener,
+            upstream,
+            spawner: Arc::new(Tokio),
+            shadow: None,
+            _p: PhantomData,
+        }
+    }
+
+    /// Spawn the tasks relaying the channels using the given spawner
+    pub fn with_spawner(mut self, spawner: impl Spawner) -> Self {
+        self.spawner = Arc::new(spawner);
+        self
+    }
+
+    /// Mirror a fraction of the channels to a shadow server
+    pub fn with_shadow(mut self, shadow: Shadow<S>) -> Self {
+        self.shadow = Some(Arc::new(shadow));
+        self
+    }
+
+    /// Accept channels and relay each of them on its own task
+    ///
+    /// This runs until accepting a channel fails. Errors of individual channels are
+    /// logged and don't stop the relay.
+    pub async fn run(self) -> result::Result<(), L::AcceptError> {
+        loop {
+            let (send, recv) = self.listener.accept().await?;
+            let upstream = self.upstream.clone();
+            let mirror = match &self.shadow {
+                Some(shadow) if shadow.sample() => {
+                    Some(Mirror::spawn(shadow.clone(), self.spawner.as_ref()))
+                }
+                _ => None,
+            };
+            self.spawner.spawn(Box::pin(async move {
+                let res = relay_channel_inner::<S, L, C>((send, recv), &upstream, mirror).await;
+                if let Err(cause) = res {
+                    tracing::debug!("relaying channel failed: {cause}");
+                }
+            }));
+        }
+    }
+}
+
+/// Relay a single accepted channel to a new channel on the upstream connector
+///
+/// Returns when the upstream server has closed its side of the channel, or when
+/// forwarding a message fails.
+pub async fn relay_channel<S, L, C>(
+    downstream: (L::SendSink, L::RecvStream),
+    upstream: &C,
+) -> result::Result<(), RelayError<L, C>>
+where
+    S: Service,
+    L: Listener<S>,
+    C: Connector<S>,
+{
+    relay_channel_inner::<S, L, C>(downstream, upstream, None).await
+}
+
+async fn relay_channel_inner<S, L, C>(
+    downstream: (L::SendSink, L::RecvStream),
+    upstream: &C,
+    mirror: Option<Mirror<S>>,
+) -> result::Result<(), RelayError<L, C>>
+where
+    S: Service,
+    L: Listener<S>,
+    C: Connector<S>,
+{
+    let (mut down_send, mut down_recv) = downstream;
+    let (mut up_send, mut up_recv) = upstream.open().await.map_err(RelayError::Open)?;
+    let (mirror_requests, mirror_responses) = match mirror {
+        Some(Mirror {
+            shadow,
+            requests,
+            responses,
+        }) => (
+            Some((shadow.clone_req, requests)),
+            shadow
+                .compare
+                .as_ref()
+                .map(|compare| (compare.clone_res, responses)),
+        ),
+        None => (None, None),
+    };
+    let requests = async move {
+        while let Some(msg) = down_recv.next().await {
+            let msg = msg.map_err(RelayError::RecvRequest)?;
+            if let Some((clone, mirror)) = &mirror_requests {
+                mirror.send(clone(&msg)).ok();
+            }
+            up_send.send(msg).await.map_err(RelayError::SendRequest)?;
+        }
+        // the client is done, so tell the server and the shadow server
+        drop(mirror_requests);
+        up_send.close().await.map_err(RelayError::SendRequest)?;
+        drop(up_send);
+        // the channel is done once the server is done
+        std::future::pending().await
+    };
+    let responses = async {
+        let mut recorded = Vec::new();
+        while let Some(msg) = up_recv.next().await {
+            let msg = msg.map_err(RelayError::RecvResponse)?;
+            if let Some((clone, _)) = &mirror_responses {
+                recorded.push(clone(&msg));
+            }
+            down_send
+                .send(msg)
+                .await
+                .map_err(RelayError::SendResponse)?;
+        }
+        if let Some((_, mirror)) = mirror_responses {
+            mirror.send(recorded).ok();
+        }
+        down_send.close().await.map_err(RelayError::SendResponse)
+    };
+    tokio::select! {
+        res = requests => res,
+        res = responses => res,
+    }
+}
+
+/// Mirrors a fraction of the relayed channels to a shadow server
+///
+/// The requests of a mirrored channel are sent to both servers. The relay does not
+/// wait for the shadow server, so a slow or failing shadow server does not affect
+/// the clients.
+pub struct Shadow<S: Service> {
+    connector: BoxedConnector<S::Res, S::Req>,
+    fraction: f64,
+    channels: AtomicU64,
+    clone_req: CloneFn<S::Req>,
+    compare: Option<Compare<S>>,
+}
+
+type CloneFn<T> = fn(&T) -> T;
+type EqFn<T> = fn(&[T], &[T]) -> bool;
+
+struct Compare<S: Service> {
+    clone_res: CloneFn<S::Res>,
+    eq: EqFn<S::Res>,
+    report: Box<dyn Fn(Divergence<S>) + Send + Sync>,
+}
+
+impl<S: Service> fmt::Debug for Shadow<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shadow")
+            .field("connector", &self.connector)
+            .field("fraction", &self.fraction)
+            .field("compare", &self.compare.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: Service> Shadow<S> {
+    /// Mirror `fraction` of the channels, between 0 and 1, to a shadow server
+    ///
+    /// The channels are picked evenly, e.g. with a fraction of 0.25 every fourth
+    /// channel is mirrored.
+    pub fn new(connector: impl BoxableConnector<S::Res, S::Req>, fraction: f64) -> Self
+    where
+        S::Req: Clone,
+    {
+        Self {
+            connector: BoxedConnector::new(connector),
+            fraction: fraction.clamp(0.0, 1.0),
+            channels: AtomicU64::new(0),
+            clone_req: S::Req::clone,
+            compare: None,
+        }
+    }
+
+    /// Compare the responses of both servers and report the channels where they differ
+    ///
+    /// A channel where the shadow server failed is reported as well. Channels where
+    /// relaying to the upstream server failed are not compared.
+    pub fn on_divergence(mut self, report: impl Fn(Divergence<S>) + Send + Sync + 'static) -> Self
+    where
+        S::Res: Clone + PartialEq,
+    {
+        self.compare = Some(Compare {
+            clone_res: S::Res::clone,
+            eq: <[S::Res]>::eq,
+            report: Box::new(report),
+        });
+        self
+    }
+
+    /// Decide whether the next channel is mirrored
+    fn sample(&self) -> bool {
+        let n = self.channels.fetch_add(1, Ordering::Relaxed);
+        // mirror channel n if the scaled count crosses an integer
+        (((n + 1) as f64) * self.fraction).floor() > ((n as f64) * self.fraction).floor()
+    }
+}
+
+/// A channel where the shadow server responded differently than the upstream server
+pub struct Divergence<S: Service> {
+    /// The requests sent by the client
+    pub requests: Vec<S::Req>,
+    /// The responses of the upstream server, as seen by the client
+    pub upstream: Vec<S::Res>,
+    /// The responses of the shadow server, or why the shadow channel failed
+    pub shadow: result::Result<Vec<S::Res>, anyhow::Error>,
+}
+
+impl<S: Service> fmt::Debug for Divergence<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Divergence")
+            .field("requests", &self.requests)
+            .field("upstream", &self.upstream)
+            .field("shadow", &self.shadow)
+            .finish()
+    }
+}
+
+/// The relay side of a mirrored channel
+struct Mirror<S: Service> {
+    shadow: Arc<Shadow<S>>,
+    requests: mpsc::UnboundedSender<S::Req>,
+    responses: oneshot::Sender<Vec<S::Res>>,
+}
+
+impl<S: Service> Mirror<S> {
+    /// Spawn a task that runs the shadow channel
+    fn spawn(shadow: Arc<Shadow<S>>, spawner: &dyn Spawner) -> Self {
+        let (requests, recv_req) = mpsc::unbounded_channel();
+        let (responses, recv_res) = oneshot::channel::<Vec<S::Res>>();
+        let this = Self {
+            shadow: shadow.clone(),
+            requests,
+            responses,
+        };
+        spawner.spawn(Box::pin(async move {
+            let mut sent = Vec::new();
+            let record = shadow.compare.as_ref().map(|_| &mut sent);
+            let res = shadow_channel(&shadow, recv_req, record).await;
+            if let Err(cause) = &res {
+                tracing::debug!("shadow channel failed: {cause}");
+            }
+            let Some(compare) = &shadow.compare else {
+                return;
+            };
+            // the upstream channel failed, nothing to compare
+            let Ok(upstream) = recv_res.await else {
+                return;
+            };
+            if !matches!(&res, Ok(responses) if (compare.eq)(responses, &upstream)) {
+                (compare.report)(Divergence {
+                    requests: sent,
+                    upstream,
+                    shadow: res,
+                });
+            }
+        }));
+        this
+    }
+}
+
+/// Forward the mirrored requests to the shadow server and collect its responses
+async fn shadow_channel<S: Service>(
+    shadow: &Shadow<S>,
+    mut requests: mpsc::UnboundedReceiver<S::Req>,
+    mut record: Option<&mut Vec<S::Req>>,
+) -> anyhow::Result<Vec<S::Res>> {
+    let (mut send, mut recv) = crate::transport::Connector::open(&shadow.connector).await?;
+    let requests = async move {
+        while let Some(msg) = requests.recv().await {
+            if let Some(sent) = &mut record {
+                sent.push((shadow.clone_req)(&msg));
+            }
+            send.send(msg).await?;
+        }
+        send.close().await?;
+        drop(send);
+        std::future::pending().await
+    };
+    let responses = async {
+        let mut responses = Vec::new();
+        while let Some(msg) = recv.next().await {
+            responses.push(msg?);
+        }
+        anyhow::Ok(responses)
+    };
+    tokio::select! {
+        res = requests => res,
+        res = responses => res,
+    }
+}
+
+/// Error when relaying a channel
+pub enum RelayError<L: ConnectionErrors, C: ConnectionErrors> {
+    /// Unable to open the upstream channel
+    Open(C::OpenError),
+    /// Error receiving a message from the client
+    RecvRequest(L::RecvError),
+    /// Error sending a message to the server
+    SendRequest(C::SendError),
+    /// Error receiving a message from the server
+    RecvResponse(C::RecvError),
+    /// Error sending a message to the client
+    SendResponse(L::SendError),
+}
+
+impl<L: ConnectionErrors, C: ConnectionErrors> fmt::Debug for RelayError<L, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open(arg0) => f.debug_tuple("Open").field(arg0).finish(),
+            Self::RecvRequest(arg0) => f.debug_tuple("RecvRequest").field(arg0).finish(),
+            Self::SendRequest(arg0) => f.debug_tuple("SendRequest").field(arg0).finish(),
+            Self::RecvResponse(arg0) => f.debug_tuple("RecvResponse").field(arg0).finish(),
+            Self::SendResponse(arg0) => f.debug_tuple("SendResponse").field(arg0).finish(),
+        }
+    }
+}
+
+impl<L: ConnectionErrors, C: ConnectionErrors> fmt::Display for RelayError<L, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl<L: ConnectionErrors, C: ConnectionErrors> error::Error for RelayError<L, C> {}
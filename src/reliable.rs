@@ -0,0 +1,441 @@
+//! Bidi sessions that survive reconnects
+//!
+//! A bidi call ends when its channel breaks, and updates that were in flight are
+//! lost. For protocols that can't afford that, e.g. sync protocols, this module adds
+//! a thin reliability layer on top of the bidi pattern:
+//!
+//! - the client numbers its updates and keeps them until the server acks them
+//! - the server acks each update once the handler asked for the next one
+//! - after a reconnect the client replays the updates that were not acked, and the
+//!   server skips the ones it already handled
+//!
+//! The bidi message declares the wrapped update and response types:
+//!
+//! ```ignore
+//! impl BidiStreamingMsg<SyncService> for Sync {
+//!     type Update = reliable::Update<SyncUpdate>;
+//!     type Response = reliable::Response<SyncResponse>;
+//! }
+//!
+//! // client
+//! let mut session = ReliableBidi::connect(client, Sync).await?;
+//! session.send(SyncUpdate(..)).await?;
+//! let response = session.recv().await;
+//!
+//! // server, sessions is shared between all channels
+//! chan.bidi_streaming(req, sessions.clone(), |sessions, req, updates| {
+//!     sessions.handle(updates, |updates| sync(req, updates))
+//! })
+//! ```
+//!
+//! Updates are handled at least once: an update that is being handled when the
+//! connection drops is handled again after the reconnect. Responses are not replayed,
+//! responses that were in flight when the connection dropped are lost.
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, VecDeque},
+    fmt,
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    result,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use futures_lite::{Stream, StreamExt};
+use futures_util::SinkExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    client::{BoxStreamSync, UpdateSink},
+    message::BidiStreamingMsg,
+    pattern::bidi_streaming,
+    transport::ConnectionErrors,
+    Connector, RpcClient, Service,
+};
+
+/// An update of a reliable bidi session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Update<T> {
+    /// The first update on every channel of a session
+    Hello {
+        /// Identifies the session across reconnects
+        session: u64,
+    },
+    /// An update of the session
+    Item {
+        /// Sequence number of the update, starting at 0
+        seq: u64,
+        /// The update
+        update: T,
+    },
+    /// The client is done with the session
+    Close,
+}
+
+/// A response of a reliable bidi session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Response<T> {
+    /// All updates up to and including this sequence number were handled
+    Ack(u64),
+    /// A response of the session
+    Item(T),
+    /// The handler is done, no more responses follow
+    End,
+}
+
+type Channel<C, U, R> = (
+    UpdateSink<C, Update<U>>,
+    BoxStreamSync<'static, result::Result<Response<R>, bidi_streaming::ItemError<C>>>,
+);
+
+/// Client side of a reliable bidi session
+///
+/// The session reconnects on its own when sending or receiving fails, using the
+/// same request message. Each call reconnects at most once, if that fails too the
+/// error is returned and the next call tries again.
+pub struct ReliableBidi<S: Service, C: Connector<S>, M, U, R> {
+    client: RpcClient<S, C>,
+    request: M,
+    session: u64,
+    next_seq: u64,
+    unacked: VecDeque<(u64, U)>,
+    channel: Option<Channel<C, U, R>>,
+}
+
+impl<S, C, M, U, R> fmt::Debug for ReliableBidi<S, C, M, U, R>
+where
+    S: Service,
+    C: Connector<S>,
+    M: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReliableBidi")
+            .field("client", &self.client)
+            .field("request", &self.request)
+            .field("session", &self.session)
+            .field("next_seq", &self.next_seq)
+            .field("unacked", &self.unacked.len())
+            .field("connected", &self.channel.is_some())
+            .finish()
+    }
+}
+
+impl<S, C, M, U, R> ReliableBidi<S, C, M, U, R>
+where
+    S: Service,
+    C: Connector<S>,
+    M: BidiStreamingMsg<S, Update = Update<U>, Response = Response<R>> + Clone,
+    Update<U>: Into<S::Req>,
+    U: Clone,
+{
+    /// Start a new session with the given request
+    pub async fn connect(client: RpcClient<S, C>, request: M) -> result::Result<Self, Error<C>> {
+        let mut this = Self {
+            client,
+            request,
+            session: new_session_id(),
+            next_seq: 0,
+            unacked: VecDeque::new(),
+            channel: None,
+        };
+        this.reconnect().await?;
+        Ok(this)
+    }
+
+    /// The id of the session
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    /// Number of updates that were sent but not acked yet
+    pub fn unacked(&self) -> usize {
+        self.unacked.len()
+    }
+
+    /// Open a new channel for the session and replay the updates that were not acked
+    pub async fn reconnect(&mut self) -> result::Result<(), Error<C>> {
+        self.channel = None;
+        let (mut send, recv) = self
+            .client
+            .bidi(self.request.clone())
+            .await
+            .map_err(Error::Open)?;
+        send.feed(Update::Hello {
+            session: self.session,
+        })
+        .await
+        .map_err(Error::Send)?;
+        for (seq, update) in &self.unacked {
+            send.feed(Update::Item {
+                seq: *seq,
+                update: update.clone(),
+            })
+            .await
+            .map_err(Error::Send)?;
+        }
+        SinkExt::flush(&mut send).await.map_err(Error::Send)?;
+        self.channel = Some((send, recv));
+        Ok(())
+    }
+
+    /// Send an update
+    ///
+    /// The update is kept until the server acks it, which happens while receiving.
+    pub async fn send(&mut self, update: U) -> result::Result<(), Error<C>> {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.unacked.push_back((seq, update.clone()));
+        if let Some((send, _)) = &mut self.channel {
+            if send.send(Update::Item { seq, update }).await.is_ok() {
+                return Ok(());
+            }
+        }
+        // replays the update along with the other unacked ones
+        self.reconnect().await
+    }
+
+    /// Receive the next response, or `None` once the handler on the server is done
+    pub async fn recv(&mut self) -> Option<result::Result<R, Error<C>>> {
+        let mut reconnected = false;
+        loop {
+            let item = match &mut self.channel {
+                Some((_, recv)) => recv.next().await,
+                None => None,
+            };
+            let cause = match item {
+                Some(Ok(Response::Ack(seq))) => {
+                    while self.unacked.front().is_some_and(|(s, _)| *s <= seq) {
+                        self.unacked.pop_front();
+                    }
+                    continue;
+                }
+                Some(Ok(Response::Item(response))) => return Some(Ok(response)),
+                Some(Ok(Response::End)) => return None,
+                Some(Err(cause)) => Error::Recv(cause),
+                None => Error::EarlyClose,
+            };
+            if reconnected {
+                self.channel = None;
+                return Some(Err(cause));
+            }
+            tracing::debug!("reconnecting reliable session {}: {cause}", self.session);
+            if let Err(cause) = self.reconnect().await {
+                return Some(Err(cause));
+            }
+            reconnected = true;
+        }
+    }
+
+    /// End the session
+    ///
+    /// This waits for the server to handle the updates that were sent before, and
+    /// drops the responses that are still coming.
+    pub async fn close(mut self) -> result::Result<(), Error<C>> {
+        if self.channel.is_none() {
+            self.reconnect().await?;
+        }
+        let (send, recv) = self.channel.as_mut().expect("connected above");
+        send.feed(Update::Close).await.map_err(Error::Send)?;
+        SinkExt::close(send).await.map_err(Error::Send)?;
+        // dropping the responses early would cancel the handler
+        loop {
+            match recv.next().await {
+                Some(Ok(Response::End)) | None => return Ok(()),
+                Some(Ok(_)) => {}
+                Some(Err(cause)) => return Err(Error::Recv(cause)),
+            }
+        }
+    }
+}
+
+/// A session id that is unlikely to collide with the ones of other clients
+fn new_session_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.finish()
+}
+
+/// Error of a [`ReliableBidi`] session
+#[derive(Debug)]
+pub enum Error<C: ConnectionErrors> {
+    /// Opening a channel for the session failed
+    Open(bidi_streaming::Error<C>),
+    /// Sending an update failed
+    Send(C::SendError),
+    /// Receiving a response failed
+    Recv(bidi_streaming::ItemError<C>),
+    /// The channel ended without the handler being done
+    EarlyClose,
+}
+
+impl<C: ConnectionErrors> fmt::Display for Error<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl<C: ConnectionErrors> std::error::Error for Error<C> {}
+
+/// Server side state of the reliable sessions of a service
+///
+/// This remembers which updates of each session were handled, so it must be shared
+/// by all channels of the service. It is cheap to clone, all clones share the state.
+/// A session is forgotten when the client closes it.
+#[derive(Debug, Clone, Default)]
+pub struct Sessions {
+    /// The next sequence number to handle, per session
+    next_seq: Arc<Mutex<BTreeMap<u64, u64>>>,
+}
+
+impl Sessions {
+    /// Create an empty set of sessions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of sessions that are not closed
+    pub fn len(&self) -> usize {
+        self.next_seq.lock().unwrap().len()
+    }
+
+    /// True if there are no open sessions
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run the handler of a session on the updates of one channel
+    ///
+    /// `f` gets the updates without the ones that were already handled on earlier
+    /// channels of the session, and returns the responses. The returned stream has
+    /// the responses and the acks, use it as the responses of the bidi call.
+    pub fn handle<U, R, St>(
+        &self,
+        updates: impl Stream<Item = Update<U>> + Send + 'static,
+        f: impl FnOnce(Updates<U>) -> St,
+    ) -> impl Stream<Item = Response<R>> + Send + 'static
+    where
+        U: Send + 'static,
+        St: Stream<Item = R> + Send + 'static,
+    {
+        let (acks_tx, mut acks) = mpsc::unbounded_channel();
+        let updates = Updates {
+            inner: Box::pin(updates),
+            sessions: self.clone(),
+            session: None,
+            handling: None,
+            acks: acks_tx,
+        };
+        let mut responses = Box::pin(f(updates));
+        let mut done = false;
+        futures_lite::stream::poll_fn(move |cx| {
+            if done {
+                return Poll::Ready(None);
+            }
+            // once the updates are dropped there are no more acks, the responses
+            // tell whether the handler is done
+            if let Poll::Ready(Some(seq)) = acks.poll_recv(cx) {
+                return Poll::Ready(Some(Response::Ack(seq)));
+            }
+            match responses.as_mut().poll_next(cx) {
+                Poll::Ready(Some(response)) => Poll::Ready(Some(Response::Item(response))),
+                Poll::Ready(None) => {
+                    done = true;
+                    Poll::Ready(Some(Response::End))
+                }
+                Poll::Pending => Poll::Pending,
+            }
+        })
+    }
+}
+
+/// The updates of a reliable session, as seen by the handler
+///
+/// An update counts as handled once the handler asks for the next one.
+pub struct Updates<U> {
+    inner: Pin<Box<dyn Stream<Item = Update<U>> + Send>>,
+    sessions: Sessions,
+    session: Option<u64>,
+    /// Sequence number of the update the handler is working on
+    handling: Option<u64>,
+    acks: mpsc::UnboundedSender<u64>,
+}
+
+impl<U> fmt::Debug for Updates<U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Updates")
+            .field("session", &self.session)
+            .field("handling", &self.handling)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<U> Updates<U> {
+    /// The id of the session, once the client said hello
+    pub fn session(&self) -> Option<u64> {
+        self.session
+    }
+}
+
+impl<U> Stream for Updates<U> {
+    type Item = U;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<U>> {
+        let this = &mut *self;
+        if let Some(seq) = this.handling.take() {
+            if let Some(session) = this.session {
+                this.sessions
+                    .next_seq
+                    .lock()
+                    .unwrap()
+                    .insert(session, seq + 1);
+            }
+            this.acks.send(seq).ok();
+        }
+        loop {
+            match std::task::ready!(this.inner.poll_next(cx)) {
+                Some(Update::Hello { session }) => {
+                    this.sessions
+                        .next_seq
+                        .lock()
+                        .unwrap()
+                        .entry(session)
+                        .or_insert(0);
+                    this.session = Some(session);
+                }
+                Some(Update::Item { seq, update }) => {
+                    let next = match this.session {
+                        Some(session) => this
+                            .sessions
+                            .next_seq
+                            .lock()
+                            .unwrap()
+                            .get(&session)
+                            .copied(),
+                        None => None,
+                    };
+                    if next.is_some_and(|next| seq < next) {
+                        // handled on an earlier channel, the ack got lost
+                        this.acks.send(seq).ok();
+                        continue;
+                    }
+                    this.handling = Some(seq);
+                    return Poll::Ready(Some(update));
+                }
+                Some(Update::Close) => {
+                    if let Some(session) = this.session.take() {
+                        this.sessions.next_seq.lock().unwrap().remove(&session);
+                    }
+                    return Poll::Ready(None);
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
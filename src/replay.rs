@@ -0,0 +1,182 @@
+//! At-most-once requests with replay protection
+//!
+//! Requests that pass through relays or persistent queues can be delivered twice, and
+//! anyone who can record a request can send it again later. For security sensitive
+//! commands, e.g. payments, the client can wrap the request in a [`Guarded`], which
+//! carries a random nonce and the time the request was created. The server checks it
+//! with a [`ReplayWindow`]:
+//!
+//! - requests that are older than the window, or too far in the future, are rejected
+//!   with [`ReplayError::Expired`]
+//! - requests with a nonce that was already seen within the window are rejected with
+//!   [`ReplayError::Replayed`]
+//!
+//! The message declares the guarded request and a result as the response:
+//!
+//! ```ignore
+//! impl RpcMsg<BankService> for Guarded<Transfer> {
+//!     type Response = Result<TransferResponse, ReplayError>;
+//! }
+//!
+//! // client
+//! let res = client.rpc(Guarded::new(Transfer { .. })).await??;
+//!
+//! // server, the window is shared between all channels
+//! chan.rpc(req, window.clone(), |window, req| async move {
+//!     window.handle(req, |transfer| bank.transfer(transfer)).await
+//! })
+//! ```
+//!
+//! The window only covers requests that arrive within it, so a request that was
+//! queued for longer than the window is rejected as expired. Clocks of clients and
+//! servers must agree to within the window. Note that the request itself is not
+//! authenticated, use a secure transport so a replayed request can't be modified.
+use std::{
+    collections::{hash_map::RandomState, BTreeSet, HashSet},
+    fmt,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A request with a nonce and the time it was created, see the [module docs](self)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Guarded<T> {
+    /// Random value that is unique per request
+    pub nonce: u128,
+    /// When the request was created, in milliseconds since the unix epoch
+    pub timestamp: u64,
+    /// The request
+    pub request: T,
+}
+
+impl<T> Guarded<T> {
+    /// Guard a request with a new nonce and the current time
+    ///
+    /// Create the guarded request once, and send that same value again when
+    /// retrying, so the server handles it at most once.
+    pub fn new(request: T) -> Self {
+        Self {
+            nonce: new_nonce(),
+            timestamp: now_millis(),
+            request,
+        }
+    }
+}
+
+/// Why a [`ReplayWindow`] rejected a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplayError {
+    /// The nonce of the request was already seen
+    Replayed,
+    /// The timestamp of the request is outside of the window
+    Expired,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+#[derive(Debug, Default)]
+struct Seen {
+    nonces: HashSet<u128>,
+    /// The same nonces, ordered by timestamp to forget them once they expire
+    by_time: BTreeSet<(u64, u128)>,
+}
+
+/// Server side record of the nonces of recent requests
+///
+/// This must be shared by all channels of the service. It is cheap to clone, all
+/// clones share the state. Only the nonces of requests within the window are kept.
+#[derive(Debug, Clone)]
+pub struct ReplayWindow {
+    window: Duration,
+    seen: Arc<Mutex<Seen>>,
+}
+
+impl ReplayWindow {
+    /// Accept requests whose timestamp is at most `window` away from now
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Default::default(),
+        }
+    }
+
+    /// Number of nonces currently remembered
+    pub fn len(&self) -> usize {
+        self.seen.lock().unwrap().nonces.len()
+    }
+
+    /// True if no nonces are remembered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check a request and remember its nonce, returning the inner request
+    pub fn check<T>(&self, req: Guarded<T>) -> Result<T, ReplayError> {
+        let window = self.window.as_millis() as u64;
+        let now = now_millis();
+        if req.timestamp.abs_diff(now) > window {
+            return Err(ReplayError::Expired);
+        }
+        let mut seen = self.seen.lock().unwrap();
+        // forget nonces that are expired, requests with them are rejected anyway
+        let horizon = now.saturating_sub(window);
+        while let Some(&(timestamp, nonce)) = seen.by_time.first() {
+            if timestamp >= horizon {
+                break;
+            }
+            seen.by_time.pop_first();
+            seen.nonces.remove(&nonce);
+        }
+        if !seen.nonces.insert(req.nonce) {
+            return Err(ReplayError::Replayed);
+        }
+        seen.by_time.insert((req.timestamp, req.nonce));
+        Ok(req.request)
+    }
+
+    /// Check a request and run `f` on the inner request if it is accepted
+    pub async fn handle<T, R, F, Fut>(&self, req: Guarded<T>, f: F) -> Result<R, ReplayError>
+    where
+        F: FnOnce(T) -> Fut,
+        Fut: Future<Output = R>,
+    {
+        let req = self.check(req)?;
+        Ok(f(req).await)
+    }
+}
+
+/// A nonce that is unlikely to collide with the ones of other requests
+fn new_nonce() -> u128 {
+    let state = RandomState::new();
+    let mut nonce = 0u128;
+    for _ in 0..2 {
+        let mut hasher = state.build_hasher();
+        hasher.write_u128(nonce);
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        nonce = (nonce << 64) | hasher.finish() as u128;
+    }
+    nonce
+}
+
+/// Milliseconds since the unix epoch, the unit of [`Guarded::timestamp`]
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -0,0 +1,163 @@
+//! Abstraction over the async executor
+//!
+//! [`RpcClient`](crate::RpcClient), [`RpcServer`](crate::RpcServer) and the flume
+//! transport only use executor independent primitives, so they work on any executor,
+//! e.g. smol or async-std. Components that need to spawn tasks or wait for some time
+//! take a [`Spawner`] or a [`Timer`] instead of calling into tokio directly. They
+//! default to `Tokio`, which is available with the `tokio-runtime` feature.
+//!
+//! Both traits are implemented for closures, so plugging in another executor is a
+//! one-liner:
+//!
+//! ```ignore
+//! let spawner = |future| smol::spawn(future).detach();
+//! let timer = |duration| -> Sleep {
+//!     Box::pin(async move {
+//!         smol::Timer::after(duration).await;
+//!     })
+//! };
+//! ```
+//!
+//! The quinn, iroh-net and hyper transports are built on tokio and need a tokio
+//! runtime regardless.
+use std::{future::Future, pin::Pin, time::Duration};
+
+/// A boxed future that can be sent to another task
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// A boxed future returned by [`Timer::sleep`]
+///
+/// This is `Sync` so it can be stored in streams that are shared between threads.
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + Sync + 'static>>;
+
+/// Spawns a future as a detached task
+pub trait Spawner: Send + Sync + 'static {
+    /// Spawn the future, running it to completion in the background
+    fn spawn(&self, future: BoxFuture<()>);
+}
+
+impl<F: Fn(BoxFuture<()>) + Send + Sync + 'static> Spawner for F {
+    fn spawn(&self, future: BoxFuture<()>) {
+        self(future)
+    }
+}
+
+/// Creates futures that complete after some time
+pub trait Timer: Send + Sync + 'static {
+    /// A future that completes after the given duration
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+impl<F: Fn(Duration) -> Sleep + Send + Sync + 'static> Timer for F {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        self(duration)
+    }
+}
+
+/// The tokio runtime
+///
+/// Spawning and sleeping must be done from within a tokio runtime.
+#[cfg(feature = "tokio-runtime")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tokio;
+
+#[cfg(feature = "tokio-runtime")]
+impl Spawner for Tokio {
+    fn spawn(&self, future: BoxFuture<()>) {
+        tokio::spawn(future);
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl Timer for Tokio {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Spawn on a specific tokio runtime, e.g. one dedicated to handling requests
+#[cfg(feature = "tokio-runtime")]
+impl Spawner for tokio::runtime::Handle {
+    fn spawn(&self, future: BoxFuture<()>) {
+        tokio::runtime::Handle::spawn(self, future);
+    }
+}
+
+/// Runs each future on the blocking thread pool of a tokio runtime
+///
+/// Each future gets a thread of its own, so it can block, e.g. on CPU heavy work or
+/// synchronous IO, without stalling the tasks of the runtime. The number of threads
+/// is limited by the runtime's `max_blocking_threads`.
+#[cfg(feature = "tokio-runtime")]
+#[derive(Debug, Clone)]
+pub struct BlockingPool(tokio::runtime::Handle);
+
+#[cfg(feature = "tokio-runtime")]
+impl BlockingPool {
+    /// Use the blocking pool of the given runtime
+    pub fn new(handle: tokio::runtime::Handle) -> Self {
+        Self(handle)
+    }
+
+    /// Use the blocking pool of the current runtime
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn current() -> Self {
+        Self(tokio::runtime::Handle::current())
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl Spawner for BlockingPool {
+    fn spawn(&self, future: BoxFuture<()>) {
+        let handle = self.0.clone();
+        self.0.spawn_blocking(move || handle.block_on(future));
+    }
+}
+
+/// Run a blocking function on the blocking pool of the current runtime
+///
+/// A panic of the function is resumed on the calling task.
+#[cfg(feature = "tokio-runtime")]
+pub(crate) async fn spawn_blocking<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(cause) if cause.is_panic() => std::panic::resume_unwind(cause.into_panic()),
+        // the runtime is shutting down, so the caller is about to be dropped
+        Err(_) => std::future::pending().await,
+    }
+}
+
+/// Run a blocking function returning an iterator on the blocking pool of the current
+/// runtime, and yield the items of the iterator
+///
+/// The iterator is advanced one item ahead of the stream, and stops when the stream is
+/// dropped. A panic of the function or the iterator is resumed when polling the stream.
+#[cfg(feature = "tokio-runtime")]
+pub(crate) fn spawn_blocking_iter<I>(
+    f: impl FnOnce() -> I + Send + 'static,
+) -> impl futures_lite::Stream<Item = I::Item> + Send + 'static
+where
+    I: IntoIterator,
+    I::Item: Send + 'static,
+{
+    let (send, recv) = tokio::sync::mpsc::channel(1);
+    let task = tokio::task::spawn_blocking(move || {
+        for item in f() {
+            if send.blocking_send(item).is_err() {
+                break;
+            }
+        }
+    });
+    futures_lite::stream::unfold((recv, task), |(mut recv, task)| async move {
+        if let Some(item) = recv.recv().await {
+            return Some((item, (recv, task)));
+        }
+        if let Err(cause) = task.await {
+            if cause.is_panic() {
+                std::panic::resume_unwind(cause.into_panic());
+            }
+        }
+        None
+    })
+}
@@ -2,26 +2,34 @@
 //!
 //! The main entry point is [RpcServer]
 use crate::{
+    message::{variant_name, Deadline},
+    runtime::Spawner,
     transport::{
         self,
         boxed::BoxableListener,
-        mapped::{ErrorOrMapError, MappedRecvStream, MappedSendSink, MappedStreamTypes},
+        connections::ConnectionHandle,
+        mapped::{
+            ErrorOrMapError, MappedListener, MappedRecvStream, MappedSendSink, MappedStreamTypes,
+        },
         ConnectionErrors, StreamTypes,
     },
     Listener, RpcMessage, Service,
 };
 use futures_lite::{Future, Stream, StreamExt};
-use futures_util::{SinkExt, TryStreamExt};
+use futures_util::{stream::FuturesUnordered, SinkExt, TryStreamExt};
 use pin_project::pin_project;
 use std::{
+    collections::BTreeMap,
     error,
     fmt::{self, Debug},
     marker::PhantomData,
     pin::Pin,
     result,
+    sync::Arc,
     task::{self, Poll},
+    time::{SystemTime, UNIX_EPOCH},
 };
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
 
 /// Stream types on the server side
 ///
@@ -49,20 +57,51 @@ pub type BoxedListener<S> =
 ///
 /// `S` is the service type.
 /// `C` is the channel type.
-#[derive(Debug)]
-pub struct RpcServer<S, C = BoxedListener<S>> {
+pub struct RpcServer<S: Service, C = BoxedListener<S>> {
     /// The channel on which new requests arrive.
     ///
     /// Each new request is a receiver and channel pair on which messages for this request
     /// are received and responses sent.
     source: C,
+    /// Where [`RpcServer::serve`] runs the handlers, if not on its own task
+    spawner: Option<Arc<dyn Spawner>>,
+    /// Answers requests whose deadline passed, see [`RpcServer::drop_expired`]
+    expired: Option<ExpiredFn<S>>,
+    /// Caps the handlers of [`RpcServer::serve`], see [`RpcServer::concurrency_limits`]
+    limiter: Option<Arc<Limiter>>,
+    /// Records the requests [`RpcServer::serve`] handles, see [`RpcServer::audit`]
+    #[cfg(feature = "audit")]
+    audit: Option<crate::audit::AuditLog<S>>,
     _p: PhantomData<S>,
 }
 
-impl<S, C: Clone> Clone for RpcServer<S, C> {
+/// Passes on requests that are still in time, and answers the others
+type ExpiredFn<S> = Arc<
+    dyn Fn(<S as Service>::Req) -> result::Result<<S as Service>::Req, <S as Service>::Res>
+        + Send
+        + Sync,
+>;
+
+impl<S: Service, C: Debug> Debug for RpcServer<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcServer")
+            .field("source", &self.source)
+            .field("spawner", &self.spawner.is_some())
+            .field("drop_expired", &self.expired.is_some())
+            .field("limiter", &self.limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: Service, C: Clone> Clone for RpcServer<S, C> {
     fn clone(&self) -> Self {
         Self {
             source: self.source.clone(),
+            spawner: self.spawner.clone(),
+            expired: self.expired.clone(),
+            limiter: self.limiter.clone(),
+            #[cfg(feature = "audit")]
+            audit: self.audit.clone(),
             _p: PhantomData,
         }
     }
@@ -76,6 +115,93 @@ impl<S: Service, C: Listener<S>> RpcServer<S, C> {
     pub fn new(source: C) -> Self {
         Self {
             source,
+            spawner: None,
+            expired: None,
+            limiter: None,
+            #[cfg(feature = "audit")]
+            audit: None,
+            _p: PhantomData,
+        }
+    }
+
+    /// Run the handlers of [`RpcServer::serve`] on the given spawner
+    ///
+    /// Use a tokio `Handle` to isolate the handlers on a runtime of their
+    /// own, or a `BlockingPool` from the [runtime](crate::runtime) module for handlers
+    /// that block.
+    pub fn with_spawner(mut self, spawner: impl Spawner) -> Self {
+        self.spawner = Some(Arc::new(spawner));
+        self
+    }
+
+    /// Answer requests whose [`Deadline`] passed without handling them
+    ///
+    /// The deadline is checked when the request is read by [`Accepting::read_first`],
+    /// i.e. when the server gets around to handling it. If it passed, `response` is
+    /// sent to the client instead of handing the request to the handler, and
+    /// `read_first` fails with [`RpcServerError::DeadlineExceeded`]. This keeps a
+    /// server that is recovering from overload from working on requests nobody is
+    /// waiting for anymore.
+    ///
+    /// `response` gets the expired request, so it can pick the response variant the
+    /// client expects for it.
+    pub fn drop_expired(
+        mut self,
+        response: impl Fn(S::Req) -> S::Res + Send + Sync + 'static,
+    ) -> Self
+    where
+        S::Req: Deadline,
+    {
+        self.expired = Some(Arc::new(move |req: S::Req| match req.deadline() {
+            Some(deadline) if deadline < now_millis() => Err(response(req)),
+            _ => Ok(req),
+        }));
+        self
+    }
+
+    /// Limit how many handlers [`RpcServer::serve`] runs at the same time
+    ///
+    /// Once the total limit is reached, no new channels are accepted until a handler
+    /// finishes. Requests of a variant that is at its own limit wait before their
+    /// handler starts, while requests of other variants go ahead. Clones of the
+    /// server share the limits.
+    pub fn concurrency_limits(mut self, limits: ConcurrencyLimits) -> Self {
+        self.limiter = Some(Arc::new(Limiter::new(limits)));
+        self
+    }
+
+    /// Record every request [`RpcServer::serve`] handles to an audit log
+    ///
+    /// See the [audit](crate::audit) module. Clones of the server share the log.
+    #[cfg(feature = "audit")]
+    pub fn audit(mut self, log: crate::audit::AuditLog<S>) -> Self {
+        self.audit = Some(log);
+        self
+    }
+
+    /// Map this server's service into an inner service.
+    ///
+    /// Unlike [`RpcChannel::map`], this maps every channel the server accepts, so
+    /// the whole listener can be handed to code that only knows the inner service.
+    /// The first request of a channel that is not a request of the inner service
+    /// fails to read with [`RpcServerError::UnexpectedUpdateMessage`].
+    ///
+    /// [`RpcServer::drop_expired`], [`RpcServer::concurrency_limits`] and the audit log
+    /// do not carry over, since they are tied to the message types of the outer
+    /// service.
+    pub fn map<SNext>(self) -> RpcServer<SNext, MappedListener<SNext::Req, SNext::Res, C>>
+    where
+        SNext: Service,
+        SNext::Req: TryFrom<S::Req>,
+        S::Res: From<SNext::Res>,
+    {
+        RpcServer {
+            source: self.source.map(),
+            spawner: self.spawner,
+            expired: None,
+            limiter: None,
+            #[cfg(feature = "audit")]
+            audit: None,
             _p: PhantomData,
         }
     }
@@ -88,7 +214,77 @@ impl<S: Service, C: Listener<S>> RpcServer<S, C> {
     where
         C: BoxableListener<S::Req, S::Res>,
     {
-        RpcServer::new(self.source.boxed())
+        RpcServer {
+            source: self.source.boxed(),
+            spawner: self.spawner,
+            expired: self.expired,
+            limiter: self.limiter,
+            #[cfg(feature = "audit")]
+            audit: self.audit,
+            _p: PhantomData,
+        }
+    }
+}
+
+/// Limits on the handlers [`RpcServer::serve`] runs at the same time, unlimited by default
+///
+/// Variants are the variants of the request enum of the service, by the name its
+/// `Debug` impl prints, e.g. `"Export"` for `Request::Export(ExportRequest)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConcurrencyLimits {
+    max_in_flight: Option<usize>,
+    variants: BTreeMap<String, usize>,
+}
+
+impl ConcurrencyLimits {
+    /// Handle at most `max` requests at the same time, in total
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    /// Handle at most `max` requests of the given variant at the same time
+    pub fn max_variant(mut self, variant: impl Into<String>, max: usize) -> Self {
+        self.variants.insert(variant.into(), max);
+        self
+    }
+}
+
+/// Hands out permits according to [`ConcurrencyLimits`]
+#[derive(Debug, Default)]
+struct Limiter {
+    in_flight: Option<Arc<Semaphore>>,
+    variants: BTreeMap<String, Arc<Semaphore>>,
+}
+
+impl Limiter {
+    fn new(limits: ConcurrencyLimits) -> Self {
+        Self {
+            in_flight: limits
+                .max_in_flight
+                .map(|max| Arc::new(Semaphore::new(max))),
+            variants: limits
+                .variants
+                .into_iter()
+                .map(|(variant, max)| (variant, Arc::new(Semaphore::new(max))))
+                .collect(),
+        }
+    }
+
+    /// Wait for a permit to handle another request
+    async fn in_flight(&self) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self.in_flight.clone()?;
+        // the semaphore is never closed
+        semaphore.acquire_owned().await.ok()
+    }
+
+    /// Wait for a permit to handle a request of the variant of `req`
+    async fn variant(&self, req: &impl Debug) -> Option<OwnedSemaphorePermit> {
+        if self.variants.is_empty() {
+            return None;
+        }
+        let semaphore = self.variants.get(&variant_name(req))?.clone();
+        semaphore.acquire_owned().await.ok()
     }
 }
 
@@ -134,12 +330,21 @@ where
         C::SendError: Into<anyhow::Error> + Send + Sync + 'static,
         C::RecvError: Into<anyhow::Error> + Send + Sync + 'static,
     {
-        let send =
-            transport::boxed::SendSink::boxed(Box::new(self.send.sink_map_err(|e| e.into())));
-        let recv = transport::boxed::RecvStream::boxed(Box::new(self.recv.map_err(|e| e.into())));
+        let connection = self.connection();
+        let send = transport::boxed::SendSink::boxed(self.send.sink_map_err(|e| e.into()));
+        let recv = transport::boxed::RecvStream::boxed(self.recv.map_err(|e| e.into()))
+            .with_connection(connection);
         RpcChannel::new(send, recv)
     }
 
+    /// The connection this channel belongs to, if the transport has connections
+    ///
+    /// Use this to log who a request comes from, or to decide whether to serve it
+    /// based on the remote address or the negotiated ALPN protocol.
+    pub fn connection(&self) -> Option<ConnectionHandle> {
+        C::connection(&self.recv)
+    }
+
     /// Map this channel's service into an inner service.
     ///
     /// This method is available if the required bounds are upheld:
@@ -166,10 +371,19 @@ where
 pub struct Accepting<S: Service, C: Listener<S>> {
     send: C::SendSink,
     recv: C::RecvStream,
+    expired: Option<ExpiredFn<S>>,
     _p: PhantomData<S>,
 }
 
 impl<S: Service, C: Listener<S>> Accepting<S, C> {
+    /// The connection the channel belongs to, if the transport has connections
+    ///
+    /// This is available before the first request is read, so connections can be
+    /// turned away without reading anything.
+    pub fn connection(&self) -> Option<ConnectionHandle> {
+        C::connection(&self.recv)
+    }
+
     /// Read the first message from the client.
     ///
     /// The return value is a tuple of `(request, channel)`.  Here `request` is the
@@ -180,7 +394,12 @@ impl<S: Service, C: Listener<S>> Accepting<S, C> {
     /// Often sink and stream will wrap an an underlying byte stream. In this case you can
     /// call into_inner() on them to get it back to perform byte level reads and writes.
     pub async fn read_first(self) -> result::Result<(S::Req, RpcChannel<S, C>), RpcServerError<C>> {
-        let Accepting { send, mut recv, .. } = self;
+        let Accepting {
+            mut send,
+            mut recv,
+            expired,
+            ..
+        } = self;
         // get the first message from the client. This will tell us what it wants to do.
         let request: S::Req = recv
             .next()
@@ -189,6 +408,18 @@ impl<S: Service, C: Listener<S>> Accepting<S, C> {
             .ok_or(RpcServerError::EarlyClose)?
             // recv error
             .map_err(RpcServerError::RecvError)?;
+        let request = match expired {
+            Some(expired) => match expired(request) {
+                Ok(request) => request,
+                Err(response) => {
+                    send.send(response)
+                        .await
+                        .map_err(RpcServerError::SendError)?;
+                    return Err(RpcServerError::DeadlineExceeded);
+                }
+            },
+            None => request,
+        };
         Ok((request, RpcChannel::<S, C>::new(send, recv)))
     }
 }
@@ -201,14 +432,103 @@ impl<S: Service, C: Listener<S>> RpcServer<S, C> {
         Ok(Accepting {
             send,
             recv,
+            expired: self.expired.clone(),
             _p: PhantomData,
         })
     }
 
+    /// The connections the listener currently serves channels on
+    ///
+    /// Use [`ConnectionHandle::close`] to evict a client. Transports without
+    /// connections, like the flume transport, return an empty list.
+    pub fn connections(&self) -> Vec<ConnectionHandle> {
+        self.source.connections()
+    }
+
     /// Get the underlying service endpoint
     pub fn into_inner(self) -> C {
         self.source
     }
+
+    /// Accept channels and handle each of them concurrently
+    ///
+    /// For each channel, the first request is read and passed to `handler` together
+    /// with a clone of `target`. By default the handlers run as part of the returned
+    /// future, on whatever executor polls it. With [`RpcServer::with_spawner`] each
+    /// handler runs as a task of its own on the spawner instead.
+    ///
+    /// With [`RpcServer::concurrency_limits`], the number of handlers running at the
+    /// same time is capped.
+    ///
+    /// This runs until accepting a channel fails. Errors of individual channels are
+    /// logged and don't stop the server.
+    pub async fn serve<T, F, Fut>(
+        &self,
+        target: T,
+        handler: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        T: Clone + Send + 'static,
+        F: Fn(RpcChannel<S, C>, S::Req, T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = result::Result<(), RpcServerError<C>>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let limiter = self.limiter.clone().unwrap_or_default();
+        let mut running = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                (accepting, permit) = async {
+                    let permit = limiter.in_flight().await;
+                    (self.accept().await, permit)
+                } => {
+                    let accepting = accepting?;
+                    let target = target.clone();
+                    let handler = handler.clone();
+                    let limiter = limiter.clone();
+                    #[cfg(feature = "audit")]
+                    let audit = self.audit.clone();
+                    let task = async move {
+                        let _permit = permit;
+                        let res = match accepting.read_first().await {
+                            Ok((req, chan)) => {
+                                #[cfg(feature = "audit")]
+                                let entry = audit
+                                    .as_ref()
+                                    .map(|audit| audit.start(&req, chan.connection().as_ref()));
+                                let _permit = limiter.variant(&req).await;
+                                let res = handler(chan, req, target).await;
+                                #[cfg(feature = "audit")]
+                                if let Some(entry) = entry {
+                                    entry.finish(match &res {
+                                        Ok(()) => crate::audit::Outcome::Ok,
+                                        Err(cause) => crate::audit::Outcome::Failed(cause.to_string()),
+                                    });
+                                }
+                                res
+                            }
+                            Err(cause) => Err(cause),
+                        };
+                        if let Err(cause) = res {
+                            tracing::debug!("handling channel failed: {cause}");
+                        }
+                    };
+                    match &self.spawner {
+                        Some(spawner) => spawner.spawn(Box::pin(task)),
+                        None => running.push(task),
+                    }
+                }
+                Some(()) = running.next(), if !running.is_empty() => {}
+            }
+        }
+    }
+}
+
+/// Milliseconds since the unix epoch, the unit of [`Deadline`]
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
 }
 
 impl<S: Service, C: Listener<S>> AsRef<C> for RpcServer<S, C> {
@@ -281,6 +601,59 @@ where
     }
 }
 
+/// The updates of a streaming call, for a handler running on the blocking pool
+///
+/// Iterating blocks the thread until the next update arrives, so this must not be
+/// used from async code. The iterator ends when the client is done sending updates.
+#[cfg(feature = "tokio-runtime")]
+#[derive(Debug)]
+pub struct BlockingUpdates<T>(tokio::sync::mpsc::Receiver<T>);
+
+#[cfg(feature = "tokio-runtime")]
+impl<T> BlockingUpdates<T> {
+    /// Feed the updates of `stream` to the iterator, as long as the returned future is polled
+    pub(crate) fn new(stream: impl Stream<Item = T>) -> (Self, impl Future<Output = ()>) {
+        let (send, recv) = tokio::sync::mpsc::channel(1);
+        let forward = async move {
+            tokio::pin!(stream);
+            while let Some(update) = stream.next().await {
+                if send.send(update).await.is_err() {
+                    break;
+                }
+            }
+        };
+        (Self(recv), forward)
+    }
+}
+
+#[cfg(feature = "tokio-runtime")]
+impl<T> Iterator for BlockingUpdates<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.blocking_recv()
+    }
+}
+
+/// Poll `future` alongside `stream`, until the stream ends
+#[cfg(feature = "tokio-runtime")]
+pub(crate) fn drive_while<St, Fut>(stream: St, future: Fut) -> impl Stream<Item = St::Item>
+where
+    St: Stream,
+    Fut: Future<Output = ()>,
+{
+    let mut stream = Box::pin(stream);
+    let mut future = Some(Box::pin(future));
+    futures_lite::stream::poll_fn(move |cx| {
+        if let Some(fut) = &mut future {
+            if fut.as_mut().poll(cx).is_ready() {
+                future = None;
+            }
+        }
+        stream.as_mut().poll_next(cx)
+    })
+}
+
 /// Server error. All server DSL methods return a `Result` with this error type.
 pub enum RpcServerError<C: ConnectionErrors> {
     /// Unable to open a new channel
@@ -295,6 +668,10 @@ pub enum RpcServerError<C: ConnectionErrors> {
     SendError(C::SendError),
     /// Got an unexpected update message, e.g. a request message or a non-matching update message
     UnexpectedUpdateMessage,
+    /// The client stopped receiving responses, so the handler was cancelled
+    Cancelled,
+    /// The deadline of the request passed before it was handled
+    DeadlineExceeded,
 }
 
 impl<In: RpcMessage, Out: RpcMessage, C: ConnectionErrors>
@@ -309,9 +686,11 @@ impl<In: RpcMessage, Out: RpcMessage, C: ConnectionErrors>
             RpcServerError::SendError(x) => RpcServerError::SendError(x),
             RpcServerError::Accept(x) => RpcServerError::Accept(x),
             RpcServerError::RecvError(ErrorOrMapError::Inner(x)) => RpcServerError::RecvError(x),
-            RpcServerError::RecvError(ErrorOrMapError::Conversion) => {
+            RpcServerError::RecvError(ErrorOrMapError::UnexpectedVariant(_)) => {
                 RpcServerError::UnexpectedUpdateMessage
             }
+            RpcServerError::Cancelled => RpcServerError::Cancelled,
+            RpcServerError::DeadlineExceeded => RpcServerError::DeadlineExceeded,
         }
     }
 }
@@ -332,6 +711,8 @@ impl<C: ConnectionErrors> RpcServerError<C> {
             RpcServerError::SendError(x) => RpcServerError::SendError(x.into()),
             RpcServerError::Accept(x) => RpcServerError::Accept(x.into()),
             RpcServerError::RecvError(x) => RpcServerError::RecvError(x.into()),
+            RpcServerError::Cancelled => RpcServerError::Cancelled,
+            RpcServerError::DeadlineExceeded => RpcServerError::DeadlineExceeded,
         }
     }
 }
@@ -345,6 +726,8 @@ impl<C: ConnectionErrors> fmt::Debug for RpcServerError<C> {
             Self::SendError(arg0) => f.debug_tuple("SendError").field(arg0).finish(),
             Self::UnexpectedStartMessage => f.debug_tuple("UnexpectedStartMessage").finish(),
             Self::UnexpectedUpdateMessage => f.debug_tuple("UnexpectedStartMessage").finish(),
+            Self::Cancelled => f.debug_tuple("Cancelled").finish(),
+            Self::DeadlineExceeded => f.debug_tuple("DeadlineExceeded").finish(),
         }
     }
 }
@@ -379,6 +762,21 @@ pub(crate) async fn race2<T, A: Future<Output = T>, B: Future<Output = T>>(f1: A
     }
 }
 
+/// Run `f`, unless the client stops receiving on `send` first
+///
+/// Nobody would see the result then, so the handler is cancelled.
+pub(crate) async fn unless_closed<C: StreamTypes, T>(
+    send: &mut C::SendSink,
+    f: impl Future<Output = T>,
+) -> result::Result<T, RpcServerError<C>> {
+    let closed = futures_lite::future::poll_fn(|cx| C::poll_closed(send, cx));
+    race2(async { Ok(f.await) }, async {
+        closed.await;
+        Err(RpcServerError::Cancelled)
+    })
+    .await
+}
+
 /// Run a server loop, invoking a handler callback for each request.
 ///
 /// Requests will be handled sequentially.
@@ -0,0 +1,277 @@
+//! Signed requests for audit trails
+//!
+//! Administrative RPCs often need a record of who issued them that holds up later,
+//! not just a log line of the server. The client signs such a request with a
+//! [`RequestSigner`], which wraps it in a [`Signed`] that carries the encoded request,
+//! the ed25519 public key of the client, the time the request was signed and the
+//! signature over all of them. The server checks it with a [`SignatureVerifier`]:
+//!
+//! - requests signed with a key that is not trusted are rejected with
+//!   [`SignatureError::UntrustedKey`]
+//! - requests with a signature that doesn't match are rejected with
+//!   [`SignatureError::InvalidSignature`]
+//!
+//! For each accepted request, the verifier passes a [`SignatureRecord`] to its record
+//! callback before the request is handled. The record contains the exact bytes that
+//! were signed, so it can be verified again later by anyone with the public key, see
+//! [`SignatureRecord::verify`].
+//!
+//! The message declares the signed request and a result as the response:
+//!
+//! ```ignore
+//! impl RpcMsg<AdminService> for Signed<Shutdown> {
+//!     type Response = Result<(), SignatureError>;
+//! }
+//!
+//! // client
+//! client.rpc(signer.sign(&Shutdown)?).await??;
+//!
+//! // server
+//! chan.rpc(req, verifier.clone(), |verifier, req| async move {
+//!     verifier.handle(req, |shutdown| admin.shutdown(shutdown)).await
+//! })
+//! ```
+//!
+//! A signature does not prevent a recorded request from being sent again, combine it
+//! with the `replay` module by signing a `Guarded` request if that matters.
+use std::{
+    collections::HashSet,
+    fmt,
+    future::Future,
+    io,
+    marker::PhantomData,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Prefix of the signed bytes, so signatures can't be reused in another protocol
+const DOMAIN: &[u8] = b"quic-rpc signed request v1";
+
+/// An ed25519 public key
+pub type PublicKey = [u8; 32];
+
+/// A request signed by a [`RequestSigner`], see the [module docs](self)
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Signed<T> {
+    key: PublicKey,
+    timestamp: u64,
+    payload: Vec<u8>,
+    signature: Vec<u8>,
+    #[serde(skip)]
+    _request: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for Signed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signed")
+            .field("key", &hex::encode(self.key))
+            .field("timestamp", &self.timestamp)
+            .field("len", &self.payload.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Clone for Signed<T> {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key,
+            timestamp: self.timestamp,
+            payload: self.payload.clone(),
+            signature: self.signature.clone(),
+            _request: PhantomData,
+        }
+    }
+}
+
+/// The bytes that are signed
+fn message(key: &PublicKey, timestamp: u64, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(DOMAIN.len() + 40 + payload.len());
+    message.extend_from_slice(DOMAIN);
+    message.extend_from_slice(key);
+    message.extend_from_slice(&timestamp.to_be_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Signs requests with an ed25519 key
+///
+/// This is cheap to clone, all clones use the same key.
+#[derive(Clone)]
+pub struct RequestSigner(Arc<Ed25519KeyPair>);
+
+impl fmt::Debug for RequestSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RequestSigner")
+            .field(&hex::encode(self.public_key()))
+            .finish()
+    }
+}
+
+impl RequestSigner {
+    /// Generate a new key, encoded as pkcs8 so it can be stored
+    pub fn generate_pkcs8() -> io::Result<Vec<u8>> {
+        let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| io::Error::other("failed to generate key"))?;
+        Ok(document.as_ref().to_vec())
+    }
+
+    /// Sign with a pkcs8 encoded ed25519 key
+    pub fn from_pkcs8(pkcs8: &[u8]) -> io::Result<Self> {
+        let key = Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|cause| io::Error::new(io::ErrorKind::InvalidInput, cause.to_string()))?;
+        Ok(Self(Arc::new(key)))
+    }
+
+    /// The public key the server needs to trust
+    pub fn public_key(&self) -> PublicKey {
+        self.0
+            .public_key()
+            .as_ref()
+            .try_into()
+            .expect("ed25519 public keys are 32 bytes")
+    }
+
+    /// Sign a request, with the current time
+    pub fn sign<T: Serialize>(&self, request: &T) -> Result<Signed<T>, bincode::Error> {
+        let key = self.public_key();
+        let timestamp = now_millis();
+        let payload = bincode::serialize(request)?;
+        let signature = self.0.sign(&message(&key, timestamp, &payload));
+        Ok(Signed {
+            key,
+            timestamp,
+            payload,
+            signature: signature.as_ref().to_vec(),
+            _request: PhantomData,
+        })
+    }
+}
+
+/// Why a [`SignatureVerifier`] rejected a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureError {
+    /// The request was signed with a key that is not trusted
+    UntrustedKey,
+    /// The signature does not match the request
+    InvalidSignature,
+    /// The signature is valid, but the request could not be decoded
+    Malformed,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// Proof that a client issued a request, for an audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureRecord {
+    /// The key the request was signed with
+    pub key: PublicKey,
+    /// When the client signed the request, in milliseconds since the unix epoch
+    pub timestamp: u64,
+    /// The encoded request
+    pub payload: Vec<u8>,
+    /// The ed25519 signature
+    pub signature: Vec<u8>,
+}
+
+impl SignatureRecord {
+    /// Check the signature again, e.g. when reading an audit log
+    pub fn verify(&self) -> bool {
+        UnparsedPublicKey::new(&ED25519, &self.key)
+            .verify(
+                &message(&self.key, self.timestamp, &self.payload),
+                &self.signature,
+            )
+            .is_ok()
+    }
+
+    /// Decode the request
+    pub fn request<T: DeserializeOwned>(&self) -> Result<T, bincode::Error> {
+        bincode::deserialize(&self.payload)
+    }
+}
+
+type Record = Arc<dyn Fn(SignatureRecord) + Send + Sync>;
+
+/// Server side check of signed requests, see the [module docs](self)
+///
+/// This is cheap to clone, all clones share the trusted keys and record callback.
+#[derive(Clone)]
+pub struct SignatureVerifier {
+    keys: Arc<HashSet<PublicKey>>,
+    record: Record,
+}
+
+impl fmt::Debug for SignatureVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignatureVerifier")
+            .field("keys", &self.keys.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl SignatureVerifier {
+    /// Pass a record of each accepted request to `record`, nothing is trusted yet
+    pub fn new(record: impl Fn(SignatureRecord) + Send + Sync + 'static) -> Self {
+        Self {
+            keys: Default::default(),
+            record: Arc::new(record),
+        }
+    }
+
+    /// Accept requests signed with `key`
+    pub fn trust(mut self, key: PublicKey) -> Self {
+        Arc::make_mut(&mut self.keys).insert(key);
+        self
+    }
+
+    /// Check a request and record it, returning the decoded request
+    pub fn check<T: DeserializeOwned>(&self, req: Signed<T>) -> Result<T, SignatureError> {
+        if !self.keys.contains(&req.key) {
+            return Err(SignatureError::UntrustedKey);
+        }
+        let record = SignatureRecord {
+            key: req.key,
+            timestamp: req.timestamp,
+            payload: req.payload,
+            signature: req.signature,
+        };
+        if !record.verify() {
+            return Err(SignatureError::InvalidSignature);
+        }
+        let request = record.request().map_err(|_| SignatureError::Malformed)?;
+        (self.record)(record);
+        Ok(request)
+    }
+
+    /// Check a request and run `f` on the decoded request if it is accepted
+    pub async fn handle<T, R, F, Fut>(&self, req: Signed<T>, f: F) -> Result<R, SignatureError>
+    where
+        T: DeserializeOwned,
+        F: FnOnce(T) -> Fut,
+        Fut: Future<Output = R>,
+    {
+        let req = self.check(req)?;
+        Ok(f(req).await)
+    }
+}
+
+/// Milliseconds since the unix epoch, the unit of [`SignatureRecord::timestamp`]
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -0,0 +1,108 @@
+//! Utilities for testing services
+//!
+//! [`pair`] wires up an in-memory server and client for a service, so tests don't
+//! have to repeat the boilerplate of creating a channel and spawning a serve loop.
+//!
+//! [`roundtrip`] checks that messages survive the wire encodings of the network
+//! transports.
+use std::{future::Future, sync::Arc};
+
+use tokio::task::{JoinHandle, JoinSet};
+
+pub mod roundtrip;
+
+use crate::{
+    server::{RpcChannel, RpcServerError},
+    transport::flume::{self, FlumeConnector, FlumeListener},
+    RpcClient, RpcServer, Service,
+};
+
+/// The listener used by [`pair`]
+pub type TestListener<S> = FlumeListener<<S as Service>::Req, <S as Service>::Res>;
+
+/// The connector used by [`pair`]
+pub type TestConnector<S> = FlumeConnector<<S as Service>::Res, <S as Service>::Req>;
+
+/// Create a connected client and server for a service
+///
+/// Every request is handled by calling `handler` on its own task. The serve loop and
+/// all handler tasks are stopped when the returned [`ShutdownGuard`] is dropped, or
+/// when the client and all its clones are dropped.
+///
+/// Handler errors are logged, since in a test they usually show up as a client error
+/// anyway.
+///
+/// This must be called from within a tokio runtime.
+pub fn pair<S, F, Fut>(handler: F) -> (RpcClient<S, TestConnector<S>>, ShutdownGuard)
+where
+    S: Service,
+    F: Fn(S::Req, RpcChannel<S, TestListener<S>>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), RpcServerError<TestListener<S>>>> + Send + 'static,
+{
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<S, _>::new(listener);
+    let handler = Arc::new(handler);
+    let task = tokio::spawn(async move {
+        let mut tasks = JoinSet::new();
+        loop {
+            tokio::select! {
+                accepting = server.accept() => {
+                    let Ok(accepting) = accepting else {
+                        // all clients are gone
+                        break;
+                    };
+                    let handler = handler.clone();
+                    tasks.spawn(async move {
+                        let (req, chan) = accepting.read_first().await?;
+                        handler(req, chan).await
+                    });
+                }
+                Some(res) = tasks.join_next() => log_result(res),
+            }
+        }
+        while let Some(res) = tasks.join_next().await {
+            log_result(res);
+        }
+    });
+    (RpcClient::new(connector), ShutdownGuard(task))
+}
+
+fn log_result<E: std::fmt::Debug>(res: Result<Result<(), E>, tokio::task::JoinError>) {
+    match res {
+        Ok(Ok(())) => {}
+        Ok(Err(cause)) => tracing::warn!("handler failed: {cause:?}"),
+        Err(cause) if cause.is_panic() => std::panic::resume_unwind(cause.into_panic()),
+        Err(_) => {}
+    }
+}
+
+/// Stops the server created by [`pair`] when dropped
+#[derive(Debug)]
+pub struct ShutdownGuard(JoinHandle<()>);
+
+impl ShutdownGuard {
+    /// Stop the server and wait for it to finish
+    ///
+    /// If a handler panicked, the panic is propagated.
+    pub async fn shutdown(self) {
+        self.0.abort();
+        self.join().await
+    }
+
+    /// Wait for the server to finish on its own, after all clients were dropped
+    ///
+    /// If a handler panicked, the panic is propagated.
+    pub async fn join(mut self) {
+        if let Err(cause) = (&mut self.0).await {
+            if cause.is_panic() {
+                std::panic::resume_unwind(cause.into_panic());
+            }
+        }
+    }
+}
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
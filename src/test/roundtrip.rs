@@ -0,0 +1,181 @@
+//! Round trip checks for message types
+//!
+//! Messages are only serialized when they go over a network transport, so an
+//! asymmetric serde implementation (e.g. a custom `Deserialize` that does not accept
+//! what `Serialize` produces, or `skip_serializing_if` on a non self describing
+//! format) is easy to miss when testing with the memory transport.
+//!
+//! The functions in this module encode a value with each wire encoding used by the
+//! built in transports, decode it again, and check that encoding the decoded value
+//! gives the same bytes. No `PartialEq` implementation is required.
+//!
+//! With the `proptest` feature, `check_service` runs these checks on arbitrary
+//! requests and responses of a service.
+use std::fmt;
+
+use bincode::Options;
+
+use crate::{RpcMessage, Service};
+
+/// Maximum frame length of the framed transports (quinn and iroh-net)
+pub const MAX_FRAME_LENGTH: usize = 1024 * 1024 * 16;
+
+/// A wire encoding used by the built in transports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// Bincode with fixint encoding, used by the quinn and iroh-net transports
+    FramedBincode,
+    /// Bincode with the legacy default options, used by the hyper transport
+    Bincode,
+}
+
+impl Codec {
+    /// All codecs
+    pub const ALL: [Codec; 2] = [Codec::FramedBincode, Codec::Bincode];
+
+    fn encode<T: RpcMessage>(self, value: &T) -> bincode::Result<Vec<u8>> {
+        match self {
+            Codec::FramedBincode => bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .serialize(value),
+            Codec::Bincode => bincode::serialize(value),
+        }
+    }
+
+    fn decode<T: RpcMessage>(self, bytes: &[u8]) -> bincode::Result<T> {
+        match self {
+            Codec::FramedBincode => bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .deserialize(bytes),
+            Codec::Bincode => bincode::deserialize(bytes),
+        }
+    }
+}
+
+/// Error of a failed round trip
+#[derive(Debug)]
+pub enum RoundtripError {
+    /// The value could not be encoded
+    Encode {
+        /// The codec that failed
+        codec: Codec,
+        /// The value that failed to encode
+        value: String,
+        /// The underlying error
+        cause: bincode::Error,
+    },
+    /// The encoded value could not be decoded
+    Decode {
+        /// The codec that failed
+        codec: Codec,
+        /// The value that failed to decode
+        value: String,
+        /// The underlying error
+        cause: bincode::Error,
+    },
+    /// Encoding the decoded value gave different bytes
+    Mismatch {
+        /// The codec that failed
+        codec: Codec,
+        /// The original value
+        value: String,
+        /// The decoded value
+        decoded: String,
+    },
+    /// The encoded value does not fit in a frame of the framed transports
+    FrameTooLarge {
+        /// The value that is too large
+        value: String,
+        /// The encoded size
+        len: usize,
+    },
+}
+
+impl fmt::Display for RoundtripError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for RoundtripError {}
+
+/// Round trip a single value through a single codec
+pub fn roundtrip_with<T: RpcMessage>(codec: Codec, value: &T) -> Result<(), RoundtripError> {
+    let encoded = codec
+        .encode(value)
+        .map_err(|cause| RoundtripError::Encode {
+            codec,
+            value: format!("{value:?}"),
+            cause,
+        })?;
+    if codec == Codec::FramedBincode && encoded.len() > MAX_FRAME_LENGTH {
+        return Err(RoundtripError::FrameTooLarge {
+            value: format!("{value:?}"),
+            len: encoded.len(),
+        });
+    }
+    let decoded: T = codec
+        .decode(&encoded)
+        .map_err(|cause| RoundtripError::Decode {
+            codec,
+            value: format!("{value:?}"),
+            cause,
+        })?;
+    let reencoded = codec.encode(&decoded).ok();
+    if reencoded.as_ref() != Some(&encoded) {
+        return Err(RoundtripError::Mismatch {
+            codec,
+            value: format!("{value:?}"),
+            decoded: format!("{decoded:?}"),
+        });
+    }
+    Ok(())
+}
+
+/// Round trip a single value through all codecs
+pub fn roundtrip<T: RpcMessage>(value: &T) -> Result<(), RoundtripError> {
+    Codec::ALL
+        .into_iter()
+        .try_for_each(|codec| roundtrip_with(codec, value))
+}
+
+/// Round trip a request of a service through all codecs
+pub fn request<S: Service>(req: &S::Req) -> Result<(), RoundtripError> {
+    roundtrip(req)
+}
+
+/// Round trip a response of a service through all codecs
+pub fn response<S: Service>(res: &S::Res) -> Result<(), RoundtripError> {
+    roundtrip(res)
+}
+
+/// Round trip arbitrary requests and responses of a service through all codecs
+///
+/// This uses the default proptest configuration, and panics with the minimal
+/// failing value if a round trip fails.
+#[cfg(feature = "proptest")]
+pub fn check_service<S>()
+where
+    S: Service,
+    S::Req: proptest::arbitrary::Arbitrary,
+    S::Res: proptest::arbitrary::Arbitrary,
+{
+    use proptest::{
+        arbitrary::any,
+        test_runner::{TestCaseError, TestRunner},
+    };
+
+    let mut runner = TestRunner::default();
+    let result = runner.run(&any::<S::Req>(), |req| {
+        request::<S>(&req).map_err(|e| TestCaseError::fail(e.to_string()))
+    });
+    if let Err(cause) = result {
+        panic!("request round trip failed: {cause}");
+    }
+    let result = runner.run(&any::<S::Res>(), |res| {
+        response::<S>(&res).map_err(|e| TestCaseError::fail(e.to_string()))
+    });
+    if let Err(cause) = result {
+        panic!("response round trip failed: {cause}");
+    }
+}
@@ -14,20 +14,27 @@ use std::future::Future;
 
 use crate::RpcMessage;
 
-use super::{ConnectionErrors, StreamTypes};
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+use super::util::{FramedBincodeRead, FramedBincodeWrite};
+use super::{
+    capabilities::Negotiated, connections::ConnectionHandle, ConnectionErrors, StreamTypes,
+};
+
 type BoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + Sync + 'a>>;
 
 enum SendSinkInner<T: RpcMessage> {
     #[cfg(feature = "flume-transport")]
-    Direct(::flume::r#async::SendSink<'static, T>),
+    Direct(super::flume::SendSink<T>),
+    /// The framing shared by the quinn and iroh-net transports
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    Framed(FramedBincodeWrite<quinn::SendStream, T>),
     Boxed(Pin<Box<dyn Sink<T, Error = anyhow::Error> + Send + Sync + 'static>>),
 }
 
 /// A sink that can be used to send messages to the remote end of a channel.
 ///
-/// For local channels, this is a thin wrapper around a flume send sink.
-/// For network channels, this contains a boxed sink, since it is reasonable
-/// to assume that in that case the additional overhead of boxing is negligible.
+/// For the built in transports, this is a thin wrapper around the concrete sink of
+/// the transport. Other transports use a boxed sink.
 #[pin_project]
 pub struct SendSink<T: RpcMessage>(SendSinkInner<T>);
 
@@ -39,9 +46,29 @@ impl<T: RpcMessage> SendSink<T> {
 
     /// Create a new send sink from a direct flume send sink
     #[cfg(feature = "flume-transport")]
-    pub(crate) fn direct(sink: ::flume::r#async::SendSink<'static, T>) -> Self {
+    pub(crate) fn direct(sink: super::flume::SendSink<T>) -> Self {
         Self(SendSinkInner::Direct(sink))
     }
+
+    /// Create a new send sink from the framing of a quinn or iroh-net send sink
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub(crate) fn framed(sink: FramedBincodeWrite<quinn::SendStream, T>) -> Self {
+        Self(SendSinkInner::Framed(sink))
+    }
+
+    /// Poll whether the remote side stopped receiving, never resolves for boxed sinks
+    fn poll_closed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.0 {
+            #[cfg(feature = "flume-transport")]
+            SendSinkInner::Direct(sink) => sink.poll_closed(cx),
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            SendSinkInner::Framed(sink) => sink.poll_stopped(cx),
+            SendSinkInner::Boxed(_) => {
+                let _ = cx;
+                Poll::Pending
+            }
+        }
+    }
 }
 
 impl<T: RpcMessage> Sink<T> for SendSink<T> {
@@ -54,6 +81,8 @@ impl<T: RpcMessage> Sink<T> for SendSink<T> {
         match self.project().0 {
             #[cfg(feature = "flume-transport")]
             SendSinkInner::Direct(sink) => sink.poll_ready_unpin(cx).map_err(anyhow::Error::from),
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            SendSinkInner::Framed(sink) => sink.poll_ready_unpin(cx).map_err(anyhow::Error::from),
             SendSinkInner::Boxed(sink) => sink.poll_ready_unpin(cx).map_err(anyhow::Error::from),
         }
     }
@@ -62,7 +91,9 @@ impl<T: RpcMessage> Sink<T> for SendSink<T> {
         match self.project().0 {
             #[cfg(feature = "flume-transport")]
             SendSinkInner::Direct(sink) => sink.start_send_unpin(item).map_err(anyhow::Error::from),
-            SendSinkInner::Boxed(sink) => sink.start_send_unpin(item).map_err(anyhow::Error::from),
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            SendSinkInner::Framed(sink) => sink.start_send_unpin(item).map_err(anyhow::Error::from),
+            SendSinkInner::Boxed(sink) => sink.start_send_unpin(item),
         }
     }
 
@@ -73,6 +104,8 @@ impl<T: RpcMessage> Sink<T> for SendSink<T> {
         match self.project().0 {
             #[cfg(feature = "flume-transport")]
             SendSinkInner::Direct(sink) => sink.poll_flush_unpin(cx).map_err(anyhow::Error::from),
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            SendSinkInner::Framed(sink) => sink.poll_flush_unpin(cx).map_err(anyhow::Error::from),
             SendSinkInner::Boxed(sink) => sink.poll_flush_unpin(cx).map_err(anyhow::Error::from),
         }
     }
@@ -84,6 +117,8 @@ impl<T: RpcMessage> Sink<T> for SendSink<T> {
         match self.project().0 {
             #[cfg(feature = "flume-transport")]
             SendSinkInner::Direct(sink) => sink.poll_close_unpin(cx).map_err(anyhow::Error::from),
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            SendSinkInner::Framed(sink) => sink.poll_close_unpin(cx).map_err(anyhow::Error::from),
             SendSinkInner::Boxed(sink) => sink.poll_close_unpin(cx).map_err(anyhow::Error::from),
         }
     }
@@ -91,29 +126,45 @@ impl<T: RpcMessage> Sink<T> for SendSink<T> {
 
 enum RecvStreamInner<T: RpcMessage> {
     #[cfg(feature = "flume-transport")]
-    Direct(::flume::r#async::RecvStream<'static, T>),
+    Direct(super::flume::RecvStream<T>),
+    /// The framing shared by the quinn and iroh-net transports
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    Framed(FramedBincodeRead<quinn::RecvStream, T>),
     Boxed(Pin<Box<dyn Stream<Item = Result<T, anyhow::Error>> + Send + Sync + 'static>>),
 }
 
 /// A stream that can be used to receive messages from the remote end of a channel.
 ///
-/// For local channels, this is a thin wrapper around a flume receive stream.
-/// For network channels, this contains a boxed stream, since it is reasonable
+/// For the built in transports, this is a thin wrapper around the concrete stream of
+/// the transport. Other transports use a boxed stream.
 #[pin_project]
-pub struct RecvStream<T: RpcMessage>(RecvStreamInner<T>);
+pub struct RecvStream<T: RpcMessage>(RecvStreamInner<T>, Option<ConnectionHandle>);
 
 impl<T: RpcMessage> RecvStream<T> {
     /// Create a new receive stream from a boxed stream
     pub fn boxed(
         stream: impl Stream<Item = Result<T, anyhow::Error>> + Send + Sync + 'static,
     ) -> Self {
-        Self(RecvStreamInner::Boxed(Box::pin(stream)))
+        Self(RecvStreamInner::Boxed(Box::pin(stream)), None)
+    }
+
+    /// Set the connection the channel belongs to, see [`StreamTypes::connection`]
+    pub fn with_connection(mut self, connection: Option<ConnectionHandle>) -> Self {
+        self.1 = connection;
+        self
     }
 
     /// Create a new receive stream from a direct flume receive stream
     #[cfg(feature = "flume-transport")]
-    pub(crate) fn direct(stream: ::flume::r#async::RecvStream<'static, T>) -> Self {
-        Self(RecvStreamInner::Direct(stream))
+    pub(crate) fn direct(stream: super::flume::RecvStream<T>) -> Self {
+        Self(RecvStreamInner::Direct(stream), None)
+    }
+
+    /// Create a new receive stream from the framing of a quinn or iroh-net receive stream
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub(crate) fn framed(stream: FramedBincodeRead<quinn::RecvStream, T>) -> Self {
+        let connection = stream.connection().cloned();
+        Self(RecvStreamInner::Framed(stream), connection)
     }
 }
 
@@ -123,11 +174,13 @@ impl<T: RpcMessage> Stream for RecvStream<T> {
     fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         match self.project().0 {
             #[cfg(feature = "flume-transport")]
-            RecvStreamInner::Direct(stream) => match stream.poll_next_unpin(cx) {
-                Poll::Ready(Some(item)) => Poll::Ready(Some(Ok(item))),
-                Poll::Ready(None) => Poll::Ready(None),
-                Poll::Pending => Poll::Pending,
-            },
+            RecvStreamInner::Direct(stream) => stream
+                .poll_next_unpin(cx)
+                .map(|item| item.map(|res| res.map_err(anyhow::Error::from))),
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            RecvStreamInner::Framed(stream) => stream
+                .poll_next_unpin(cx)
+                .map(|item| item.map(|res| res.map_err(anyhow::Error::from))),
             RecvStreamInner::Boxed(stream) => stream.poll_next_unpin(cx),
         }
     }
@@ -167,7 +220,7 @@ impl<'a, In: RpcMessage, Out: RpcMessage> Future for OpenFuture<'a, In, Out> {
             #[cfg(feature = "flume-transport")]
             OpenFutureInner::Direct(f) => f
                 .poll(cx)
-                .map_ok(|(send, recv)| (SendSink::direct(send.0), RecvStream::direct(recv.0)))
+                .map_ok(|(send, recv)| (SendSink::direct(send), RecvStream::direct(recv)))
                 .map_err(|e| e.into()),
             OpenFutureInner::Boxed(f) => f.poll(cx),
         }
@@ -208,7 +261,7 @@ impl<'a, In: RpcMessage, Out: RpcMessage> Future for AcceptFuture<'a, In, Out> {
             #[cfg(feature = "flume-transport")]
             AcceptFutureInner::Direct(f) => f
                 .poll(cx)
-                .map_ok(|(send, recv)| (SendSink::direct(send.0), RecvStream::direct(recv.0)))
+                .map_ok(|(send, recv)| (SendSink::direct(send), RecvStream::direct(recv)))
                 .map_err(|e| e.into()),
             AcceptFutureInner::Boxed(f) => f.poll(cx),
         }
@@ -221,7 +274,17 @@ pub trait BoxableConnector<In: RpcMessage, Out: RpcMessage>: Debug + Send + Sync
     fn clone_box(&self) -> Box<dyn BoxableConnector<In, Out>>;
 
     /// Open a channel to the remote che
-    fn open_boxed(&self) -> OpenFuture<In, Out>;
+    fn open_boxed(&self) -> OpenFuture<'_, In, Out>;
+
+    /// Open a channel for a single request and a single response
+    fn open_rpc_boxed(&self) -> OpenFuture<'_, In, Out> {
+        self.open_boxed()
+    }
+
+    /// The capabilities negotiated with the remote
+    fn capabilities(&self) -> Option<Negotiated> {
+        None
+    }
 }
 
 /// A boxed connector
@@ -246,6 +309,12 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for BoxedConnector<In, Out> {
     type Out = Out;
     type RecvStream = RecvStream<In>;
     type SendSink = SendSink<Out>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        send.poll_closed(cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        recv.1.clone()
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for BoxedConnector<In, Out> {
@@ -259,6 +328,14 @@ impl<In: RpcMessage, Out: RpcMessage> super::Connector for BoxedConnector<In, Ou
     async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
         self.0.open_boxed().await
     }
+
+    async fn open_rpc(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        self.0.open_rpc_boxed().await
+    }
+
+    fn capabilities(&self) -> Option<Negotiated> {
+        self.0.capabilities()
+    }
 }
 
 /// Stream types for boxed streams
@@ -287,6 +364,12 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for BoxedStreamTypes<In, Out>
     type Out = Out;
     type RecvStream = RecvStream<In>;
     type SendSink = SendSink<Out>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        send.poll_closed(cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        recv.1.clone()
+    }
 }
 
 /// A boxable listener
@@ -295,10 +378,15 @@ pub trait BoxableListener<In: RpcMessage, Out: RpcMessage>: Debug + Send + Sync
     fn clone_box(&self) -> Box<dyn BoxableListener<In, Out>>;
 
     /// Accept a channel from a remote client
-    fn accept_bi_boxed(&self) -> AcceptFuture<In, Out>;
+    fn accept_bi_boxed(&self) -> AcceptFuture<'_, In, Out>;
 
     /// Get the local address
     fn local_addr(&self) -> &[super::LocalAddr];
+
+    /// Get the connections the listener currently serves channels on
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        Vec::new()
+    }
 }
 
 /// A boxed listener
@@ -323,6 +411,12 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for BoxedListener<In, Out> {
     type Out = Out;
     type RecvStream = RecvStream<In>;
     type SendSink = SendSink<Out>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        send.poll_closed(cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        recv.1.clone()
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for BoxedListener<In, Out> {
@@ -343,14 +437,46 @@ impl<In: RpcMessage, Out: RpcMessage> super::Listener for BoxedListener<In, Out>
     fn local_addr(&self) -> &[super::LocalAddr] {
         self.0.local_addr()
     }
+
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        self.0.connections()
+    }
 }
 impl<In: RpcMessage, Out: RpcMessage> BoxableConnector<In, Out> for BoxedConnector<In, Out> {
     fn clone_box(&self) -> Box<dyn BoxableConnector<In, Out>> {
-        Box::new(self.clone())
+        self.0.clone_box()
     }
 
-    fn open_boxed(&self) -> OpenFuture<In, Out> {
-        OpenFuture::boxed(crate::transport::Connector::open(self))
+    // forward to the inner connector instead of boxing the future again
+    fn open_boxed(&self) -> OpenFuture<'_, In, Out> {
+        self.0.open_boxed()
+    }
+
+    fn open_rpc_boxed(&self) -> OpenFuture<'_, In, Out> {
+        self.0.open_rpc_boxed()
+    }
+
+    fn capabilities(&self) -> Option<Negotiated> {
+        self.0.capabilities()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out> for BoxedListener<In, Out> {
+    fn clone_box(&self) -> Box<dyn BoxableListener<In, Out>> {
+        self.0.clone_box()
+    }
+
+    // forward to the inner listener instead of boxing the future again
+    fn accept_bi_boxed(&self) -> AcceptFuture<'_, In, Out> {
+        self.0.accept_bi_boxed()
+    }
+
+    fn local_addr(&self) -> &[super::LocalAddr] {
+        self.0.local_addr()
+    }
+
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        self.0.connections()
     }
 }
 
@@ -362,16 +488,15 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableConnector<In, Out>
         Box::new(self.clone())
     }
 
-    fn open_boxed(&self) -> OpenFuture<In, Out> {
-        let f = Box::pin(async move {
+    fn open_boxed(&self) -> OpenFuture<'_, In, Out> {
+        OpenFuture::boxed(async move {
             let (send, recv) = super::Connector::open(self).await?;
-            // map the error types to anyhow
-            let send = send.sink_map_err(anyhow::Error::from);
-            let recv = recv.map_err(anyhow::Error::from);
-            // return the boxed streams
-            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
-        });
-        OpenFuture::boxed(f)
+            anyhow::Ok((SendSink::framed(send.0), RecvStream::framed(recv.0)))
+        })
+    }
+
+    fn capabilities(&self) -> Option<Negotiated> {
+        super::Connector::capabilities(self)
     }
 }
 
@@ -383,19 +508,20 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out>
         Box::new(self.clone())
     }
 
-    fn accept_bi_boxed(&self) -> AcceptFuture<In, Out> {
-        let f = async move {
+    fn accept_bi_boxed(&self) -> AcceptFuture<'_, In, Out> {
+        AcceptFuture::boxed(async move {
             let (send, recv) = super::Listener::accept(self).await?;
-            let send = send.sink_map_err(anyhow::Error::from);
-            let recv = recv.map_err(anyhow::Error::from);
-            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
-        };
-        AcceptFuture::boxed(f)
+            anyhow::Ok((SendSink::framed(send.0), RecvStream::framed(recv.0)))
+        })
     }
 
     fn local_addr(&self) -> &[super::LocalAddr] {
         super::Listener::local_addr(self)
     }
+
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        super::Listener::connections(self)
+    }
 }
 
 #[cfg(feature = "iroh-net-transport")]
@@ -406,16 +532,15 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableConnector<In, Out>
         Box::new(self.clone())
     }
 
-    fn open_boxed(&self) -> OpenFuture<In, Out> {
-        let f = Box::pin(async move {
+    fn open_boxed(&self) -> OpenFuture<'_, In, Out> {
+        OpenFuture::boxed(async move {
             let (send, recv) = super::Connector::open(self).await?;
-            // map the error types to anyhow
-            let send = send.sink_map_err(anyhow::Error::from);
-            let recv = recv.map_err(anyhow::Error::from);
-            // return the boxed streams
-            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
-        });
-        OpenFuture::boxed(f)
+            anyhow::Ok((SendSink::framed(send.0), RecvStream::framed(recv.0)))
+        })
+    }
+
+    fn capabilities(&self) -> Option<Negotiated> {
+        super::Connector::capabilities(self)
     }
 }
 
@@ -427,19 +552,20 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out>
         Box::new(self.clone())
     }
 
-    fn accept_bi_boxed(&self) -> AcceptFuture<In, Out> {
-        let f = async move {
+    fn accept_bi_boxed(&self) -> AcceptFuture<'_, In, Out> {
+        AcceptFuture::boxed(async move {
             let (send, recv) = super::Listener::accept(self).await?;
-            let send = send.sink_map_err(anyhow::Error::from);
-            let recv = recv.map_err(anyhow::Error::from);
-            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
-        };
-        AcceptFuture::boxed(f)
+            anyhow::Ok((SendSink::framed(send.0), RecvStream::framed(recv.0)))
+        })
     }
 
     fn local_addr(&self) -> &[super::LocalAddr] {
         super::Listener::local_addr(self)
     }
+
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        super::Listener::connections(self)
+    }
 }
 
 #[cfg(feature = "flume-transport")]
@@ -450,7 +576,7 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableConnector<In, Out>
         Box::new(self.clone())
     }
 
-    fn open_boxed(&self) -> OpenFuture<In, Out> {
+    fn open_boxed(&self) -> OpenFuture<'_, In, Out> {
         OpenFuture::direct(super::Connector::open(self))
     }
 }
@@ -463,7 +589,7 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out>
         Box::new(self.clone())
     }
 
-    fn accept_bi_boxed(&self) -> AcceptFuture<In, Out> {
+    fn accept_bi_boxed(&self) -> AcceptFuture<'_, In, Out> {
         AcceptFuture::direct(super::Listener::accept(self))
     }
 
@@ -472,6 +598,46 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out>
     }
 }
 
+#[cfg(feature = "hyper-transport")]
+impl<In: RpcMessage, Out: RpcMessage> BoxableConnector<In, Out>
+    for super::hyper::HyperConnector<In, Out>
+{
+    fn clone_box(&self) -> Box<dyn BoxableConnector<In, Out>> {
+        Box::new(self.clone())
+    }
+
+    fn open_boxed(&self) -> OpenFuture<'_, In, Out> {
+        OpenFuture::boxed(async move {
+            let (send, recv) = super::Connector::open(self).await?;
+            let send = send.sink_map_err(anyhow::Error::from);
+            let recv = recv.map_err(anyhow::Error::from);
+            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
+        })
+    }
+}
+
+#[cfg(feature = "hyper-transport")]
+impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out>
+    for super::hyper::HyperListener<In, Out>
+{
+    fn clone_box(&self) -> Box<dyn BoxableListener<In, Out>> {
+        Box::new(self.clone())
+    }
+
+    fn accept_bi_boxed(&self) -> AcceptFuture<'_, In, Out> {
+        AcceptFuture::boxed(async move {
+            let (send, recv) = super::Listener::accept(self).await?;
+            let send = send.sink_map_err(anyhow::Error::from);
+            let recv = recv.map_err(anyhow::Error::from);
+            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
+        })
+    }
+
+    fn local_addr(&self) -> &[super::LocalAddr] {
+        super::Listener::local_addr(self)
+    }
+}
+
 impl<In, Out, C> BoxableConnector<In, Out> for super::mapped::MappedConnector<In, Out, C>
 where
     In: RpcMessage,
@@ -487,31 +653,61 @@ where
         Box::new(self.clone())
     }
 
-    fn open_boxed(&self) -> OpenFuture<In, Out> {
-        let f = Box::pin(async move {
+    fn open_boxed(&self) -> OpenFuture<'_, In, Out> {
+        OpenFuture::boxed(async move {
             let (send, recv) = super::Connector::open(self).await.map_err(|e| e.into())?;
             // map the error types to anyhow
             let send = send.sink_map_err(|e| e.into());
             let recv = recv.map_err(|e| e.into());
             // return the boxed streams
             anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
-        });
-        OpenFuture::boxed(f)
+        })
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::Service;
+impl<In, Out, L> BoxableListener<In, Out> for super::mapped::MappedListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: super::Listener,
+    L::Out: From<Out>,
+    In: TryFrom<L::In>,
+    L::SendError: Into<anyhow::Error>,
+    L::RecvError: Into<anyhow::Error>,
+    L::AcceptError: Into<anyhow::Error>,
+{
+    fn clone_box(&self) -> Box<dyn BoxableListener<In, Out>> {
+        Box::new(self.clone())
+    }
 
-    #[derive(Debug, Clone)]
-    struct FooService;
+    fn accept_bi_boxed(&self) -> AcceptFuture<'_, In, Out> {
+        // the inner accept future is only Send, the mutex makes it Sync
+        let accept = std::sync::Mutex::new(Box::pin(super::Listener::accept(self)));
+        AcceptFuture::boxed(async move {
+            let accept =
+                futures_lite::future::poll_fn(|cx| accept.lock().unwrap().as_mut().poll(cx));
+            let (send, recv) = accept.await.map_err(|e| e.into())?;
+            let connection = <Self as StreamTypes>::connection(&recv);
+            // map the error types to anyhow
+            let send = send.sink_map_err(|e| e.into());
+            let recv = recv.map_err(|e| e.into());
+            // return the boxed streams
+            let recv = RecvStream::boxed(recv).with_connection(connection);
+            anyhow::Ok((SendSink::boxed(send), recv))
+        })
+    }
 
-    impl Service for FooService {
-        type Req = u64;
-        type Res = u64;
+    fn local_addr(&self) -> &[super::LocalAddr] {
+        super::Listener::local_addr(self)
     }
 
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        super::Listener::connections(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
     #[cfg(feature = "flume-transport")]
     #[tokio::test]
     async fn box_smoke() {
@@ -0,0 +1,120 @@
+//! Capabilities that clients and servers agree on when connecting
+//!
+//! Clients and servers that are deployed independently don't always support the
+//! same things, e.g. a newer client may know a compression algorithm or a protocol
+//! version an older server doesn't. Both sides describe what they support with
+//! [`Capabilities`], which are exchanged as part of the connection handshake of the
+//! quinn and iroh-net transports, see the `handshake` module. The server picks what
+//! both sides support and sends the [`Negotiated`] set back to the client.
+//!
+//! Clients query the negotiated set with
+//! [`RpcClient::capabilities`](crate::RpcClient::capabilities), handlers with
+//! `ConnectionHandle::capabilities` on the connection of their channel. Nothing is
+//! enforced by quic-rpc itself, it is up to the application to only use what was
+//! negotiated.
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+/// What one side of a connection supports, nothing by default
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    versions: BTreeSet<u32>,
+    compression: Vec<String>,
+    max_message_size: Option<u64>,
+    features: BTreeSet<String>,
+}
+
+impl Capabilities {
+    /// Support a protocol version
+    pub fn version(mut self, version: u32) -> Self {
+        self.versions.insert(version);
+        self
+    }
+
+    /// Support a compression algorithm
+    ///
+    /// Algorithms are matched by name. Clients add them in the order of their
+    /// preference, the server picks the first one it supports as well.
+    pub fn compression(mut self, algorithm: impl Into<String>) -> Self {
+        let algorithm = algorithm.into();
+        if !self.compression.contains(&algorithm) {
+            self.compression.push(algorithm);
+        }
+        self
+    }
+
+    /// Accept messages of up to `bytes`, unlimited by default
+    pub fn max_message_size(mut self, bytes: u64) -> Self {
+        self.max_message_size = Some(bytes);
+        self
+    }
+
+    /// Support an optional feature, e.g. `"batching"`
+    pub fn feature(mut self, name: impl Into<String>) -> Self {
+        self.features.insert(name.into());
+        self
+    }
+
+    /// Agree on what both these server capabilities and the `client` support
+    ///
+    /// This picks the highest common version, the compression algorithm the client
+    /// prefers most among the ones the server supports, the smaller message size
+    /// limit, and the common features.
+    pub fn negotiate(&self, client: &Capabilities) -> Negotiated {
+        let max_message_size = match (self.max_message_size, client.max_message_size) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Negotiated {
+            version: self.versions.intersection(&client.versions).max().copied(),
+            compression: client
+                .compression
+                .iter()
+                .find(|algorithm| self.compression.contains(algorithm))
+                .cloned(),
+            max_message_size,
+            features: self
+                .features
+                .intersection(&client.features)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// What both sides of a connection support, see [`Capabilities::negotiate`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Negotiated {
+    version: Option<u32>,
+    compression: Option<String>,
+    max_message_size: Option<u64>,
+    features: BTreeSet<String>,
+}
+
+impl Negotiated {
+    /// The protocol version to use, if there is a common one
+    pub fn version(&self) -> Option<u32> {
+        self.version
+    }
+
+    /// The compression algorithm to use, if there is a common one
+    pub fn compression(&self) -> Option<&str> {
+        self.compression.as_deref()
+    }
+
+    /// The maximum size of a message, if either side has a limit
+    pub fn max_message_size(&self) -> Option<u64> {
+        self.max_message_size
+    }
+
+    /// Whether both sides support a feature
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.contains(feature)
+    }
+
+    /// The features both sides support
+    pub fn features(&self) -> impl Iterator<Item = &str> {
+        self.features.iter().map(String::as_str)
+    }
+}
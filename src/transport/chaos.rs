@@ -0,0 +1,288 @@
+//! Transport wrapper that injects faults
+//!
+//! A [`ChaosConnection`] wraps any [`Connector`] and injects latency, stream resets,
+//! dropped frames and reordering of received frames according to a [`ChaosConfig`].
+//!
+//! All random decisions are made by a pseudo random number generator seeded from
+//! [`ChaosConfig::seed`], so a test run can be reproduced exactly as long as channels
+//! are opened in the same order.
+//!
+//! Latency is injected using a [`Timer`], tokio by default. Use
+//! [`ChaosConnection::with_timer`] to run on another executor.
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Display},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use futures_lite::Stream;
+use pin_project::pin_project;
+
+use super::{connections::ConnectionHandle, rng::Rng, ConnectionErrors, Connector, StreamTypes};
+use crate::runtime::{Sleep, Timer, Tokio};
+
+/// Configuration for a [`ChaosConnection`]
+///
+/// The default configuration does not inject any faults.
+#[derive(Debug, Clone, Default)]
+pub struct ChaosConfig {
+    seed: u64,
+    latency: Duration,
+    jitter: Duration,
+    reset_probability: f64,
+    drop_probability: f64,
+    reorder_probability: f64,
+}
+
+impl ChaosConfig {
+    /// Set the seed for the random number generator
+    pub fn seed(mut self, value: u64) -> Self {
+        self.seed = value;
+        self
+    }
+
+    /// Delay opening channels and delivering received frames
+    ///
+    /// Each delay is `latency` plus a random value up to `jitter`.
+    pub fn latency(mut self, latency: Duration, jitter: Duration) -> Self {
+        self.latency = latency;
+        self.jitter = jitter;
+        self
+    }
+
+    /// Probability that receiving a frame fails with [`ChaosError::Reset`]
+    ///
+    /// After a reset, the receive stream ends.
+    pub fn reset_probability(mut self, value: f64) -> Self {
+        self.reset_probability = value;
+        self
+    }
+
+    /// Probability that a received frame is silently dropped
+    ///
+    /// Sent frames are never dropped, so requests always reach the server.
+    pub fn drop_probability(mut self, value: f64) -> Self {
+        self.drop_probability = value;
+        self
+    }
+
+    /// Probability that a received frame is held back and delivered after the next one
+    ///
+    /// This only has an effect for patterns with multiple responses.
+    pub fn reorder_probability(mut self, value: f64) -> Self {
+        self.reorder_probability = value;
+        self
+    }
+
+    fn delay(&self, rng: &mut Rng) -> Duration {
+        if self.jitter.is_zero() {
+            self.latency
+        } else {
+            self.latency + self.jitter.mul_f64(rng.next_f64())
+        }
+    }
+}
+
+/// A connection that injects faults into channels opened on an inner connection
+#[derive(Clone)]
+pub struct ChaosConnection<C> {
+    inner: C,
+    config: Arc<ChaosConfig>,
+    rng: Arc<Mutex<Rng>>,
+    timer: Arc<dyn Timer>,
+}
+
+impl<C: Debug> Debug for ChaosConnection<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChaosConnection")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: Connector> ChaosConnection<C> {
+    /// Wrap a connection, injecting faults according to the given config
+    pub fn new(inner: C, config: ChaosConfig) -> Self {
+        Self {
+            inner,
+            rng: Arc::new(Mutex::new(Rng::new(config.seed))),
+            config: Arc::new(config),
+            timer: Arc::new(Tokio),
+        }
+    }
+
+    /// Inject latency using the given timer
+    pub fn with_timer(mut self, timer: impl Timer) -> Self {
+        self.timer = Arc::new(timer);
+        self
+    }
+
+    /// Get the inner connection
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+/// Error of a [`ChaosConnection`]
+#[derive(Debug)]
+pub enum ChaosError<E> {
+    /// Error from the inner connection
+    Inner(E),
+    /// Injected stream reset
+    Reset,
+}
+
+impl<E: Debug + Display> std::error::Error for ChaosError<E> {}
+
+impl<E: Display> Display for ChaosError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChaosError::Inner(e) => write!(f, "Inner error: {}", e),
+            ChaosError::Reset => write!(f, "Injected stream reset"),
+        }
+    }
+}
+
+impl<C: ConnectionErrors> ConnectionErrors for ChaosConnection<C> {
+    type SendError = C::SendError;
+    type RecvError = ChaosError<C::RecvError>;
+    type OpenError = C::OpenError;
+    type AcceptError = C::AcceptError;
+}
+
+impl<C: StreamTypes> StreamTypes for ChaosConnection<C> {
+    type In = C::In;
+    type Out = C::Out;
+    type SendSink = C::SendSink;
+    type RecvStream = ChaosRecvStream<C::RecvStream, C::In>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        C::poll_closed(send, cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        C::connection(&recv.inner)
+    }
+}
+
+impl<C: Connector> Connector for ChaosConnection<C> {
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        self.wrap(self.inner.open()).await
+    }
+
+    async fn open_rpc(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        self.wrap(self.inner.open_rpc()).await
+    }
+}
+
+impl<C: Connector> ChaosConnection<C> {
+    async fn wrap(
+        &self,
+        open: impl Future<Output = Result<(C::SendSink, C::RecvStream), C::OpenError>>,
+    ) -> Result<(C::SendSink, ChaosRecvStream<C::RecvStream, C::In>), C::OpenError> {
+        // fork a generator for each channel, so the decisions don't depend on scheduling
+        let mut rng = Rng::new(self.rng.lock().unwrap().next_u64());
+        let delay = self.config.delay(&mut rng);
+        if !delay.is_zero() {
+            self.timer.sleep(delay).await;
+        }
+        let (send, recv) = open.await?;
+        let recv = ChaosRecvStream {
+            inner: recv,
+            config: self.config.clone(),
+            timer: self.timer.clone(),
+            rng,
+            ready: VecDeque::new(),
+            held: None,
+            sleep: None,
+            reset: false,
+        };
+        Ok((send, recv))
+    }
+}
+
+/// Receive side of a [`ChaosConnection`] channel
+#[pin_project]
+pub struct ChaosRecvStream<S, In> {
+    #[pin]
+    inner: S,
+    config: Arc<ChaosConfig>,
+    timer: Arc<dyn Timer>,
+    rng: Rng,
+    /// Frames that are ready to be delivered
+    ready: VecDeque<In>,
+    /// Frame held back for reordering
+    held: Option<In>,
+    /// Latency for the frame at the front of `ready`
+    sleep: Option<Sleep>,
+    reset: bool,
+}
+
+impl<S: Debug, In: Debug> Debug for ChaosRecvStream<S, In> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChaosRecvStream")
+            .field("inner", &self.inner)
+            .field("ready", &self.ready)
+            .field("held", &self.held)
+            .field("reset", &self.reset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, In, E> Stream for ChaosRecvStream<S, In>
+where
+    S: Stream<Item = Result<In, E>>,
+{
+    type Item = Result<In, ChaosError<E>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if *this.reset {
+                return Poll::Ready(None);
+            }
+            if !this.ready.is_empty() {
+                if this.sleep.is_none() {
+                    let delay = this.config.delay(this.rng);
+                    if delay.is_zero() {
+                        return Poll::Ready(this.ready.pop_front().map(Ok));
+                    }
+                    *this.sleep = Some(this.timer.sleep(delay));
+                }
+                if let Some(sleep) = this.sleep.as_mut() {
+                    ready!(sleep.as_mut().poll(cx));
+                }
+                *this.sleep = None;
+                return Poll::Ready(this.ready.pop_front().map(Ok));
+            }
+            match ready!(this.inner.as_mut().poll_next(cx)) {
+                None => {
+                    // deliver a held back frame before ending the stream
+                    match this.held.take() {
+                        Some(item) => this.ready.push_back(item),
+                        None => return Poll::Ready(None),
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(ChaosError::Inner(e)))),
+                Some(Ok(item)) => {
+                    if this.rng.chance(this.config.reset_probability) {
+                        *this.reset = true;
+                        return Poll::Ready(Some(Err(ChaosError::Reset)));
+                    }
+                    if this.rng.chance(this.config.drop_probability) {
+                        continue;
+                    }
+                    if this.held.is_none() && this.rng.chance(this.config.reorder_probability) {
+                        *this.held = Some(item);
+                        continue;
+                    }
+                    this.ready.push_back(item);
+                    this.ready.extend(this.held.take());
+                }
+            }
+        }
+    }
+}
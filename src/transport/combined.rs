@@ -1,5 +1,7 @@
 //! Transport that combines two other transports
-use super::{ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes};
+use super::{
+    connections::ConnectionHandle, ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes,
+};
 use futures_lite::Stream;
 use futures_sink::Sink;
 use pin_project::pin_project;
@@ -206,6 +208,18 @@ impl<A: Connector, B: Connector<In = A::In, Out = A::Out>> StreamTypes for Combi
     type Out = A::Out;
     type RecvStream = self::RecvStream<A, B>;
     type SendSink = self::SendSink<A, B>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        match send {
+            SendSink::A(send) => A::poll_closed(send, cx),
+            SendSink::B(send) => B::poll_closed(send, cx),
+        }
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        match recv {
+            RecvStream::A(recv) => A::connection(recv),
+            RecvStream::B(recv) => B::connection(recv),
+        }
+    }
 }
 
 impl<A: Connector, B: Connector<In = A::In, Out = A::Out>> Connector for CombinedConnector<A, B> {
@@ -236,6 +250,18 @@ impl<A: Listener, B: Listener<In = A::In, Out = A::Out>> StreamTypes for Combine
     type Out = A::Out;
     type RecvStream = self::RecvStream<A, B>;
     type SendSink = self::SendSink<A, B>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        match send {
+            SendSink::A(send) => A::poll_closed(send, cx),
+            SendSink::B(send) => B::poll_closed(send, cx),
+        }
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        match recv {
+            RecvStream::A(recv) => A::connection(recv),
+            RecvStream::B(recv) => B::connection(recv),
+        }
+    }
 }
 
 impl<A: Listener, B: Listener<In = A::In, Out = A::Out>> Listener for CombinedListener<A, B> {
@@ -268,6 +294,12 @@ impl<A: Listener, B: Listener<In = A::In, Out = A::Out>> Listener for CombinedLi
     fn local_addr(&self) -> &[LocalAddr] {
         &self.local_addr
     }
+
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        let a = self.a.iter().flat_map(|a| a.connections());
+        let b = self.b.iter().flat_map(|b| b.connections());
+        a.chain(b).collect()
+    }
 }
 
 #[cfg(test)]
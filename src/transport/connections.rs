@@ -0,0 +1,232 @@
+//! Connections of a listener
+//!
+//! Transports that multiplex channels over connections, such as the quinn and
+//! iroh-net transports, keep track of the connections their listener serves.
+//! [`Listener::connections`](super::Listener::connections) returns a
+//! [`ConnectionHandle`] for each of them, so admin tooling can list peers and evict
+//! a misbehaving client without restarting the whole endpoint.
+//!
+//! Transports without connections, such as the flume transport, don't return any.
+//!
+//! The server can also look up the connection of a single channel, with
+//! [`RpcChannel::connection`](crate::server::RpcChannel::connection), e.g. to log the
+//! remote address or to base security decisions on the negotiated ALPN protocol.
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+use std::{
+    collections::BTreeMap,
+    sync::{atomic::AtomicU64, Mutex},
+};
+use std::{
+    fmt,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+use super::handshake::Handshake;
+use crate::error::CloseCode;
+
+type Close = Box<dyn Fn(u32, &[u8]) + Send + Sync>;
+
+struct Entry {
+    id: u64,
+    peer: String,
+    remote_addr: SocketAddr,
+    alpn: Option<Vec<u8>>,
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    handshake: Option<Handshake>,
+    established: SystemTime,
+    open_streams: AtomicUsize,
+    close: Close,
+}
+
+/// A connection that a listener currently serves channels on
+///
+/// This is cheap to clone. The handle stays usable after the connection is gone,
+/// closing it again has no effect.
+#[derive(Clone)]
+pub struct ConnectionHandle(Arc<Entry>);
+
+impl fmt::Debug for ConnectionHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionHandle")
+            .field("id", &self.0.id)
+            .field("peer", &self.0.peer)
+            .field("remote_addr", &self.0.remote_addr)
+            .field("established", &self.0.established)
+            .field("open_streams", &self.open_streams())
+            .finish()
+    }
+}
+
+impl ConnectionHandle {
+    /// Id of the connection, unique within the listener
+    pub fn id(&self) -> u64 {
+        self.0.id
+    }
+
+    /// The remote peer, e.g. a socket address or a node id
+    pub fn peer(&self) -> &str {
+        &self.0.peer
+    }
+
+    /// The address the connection comes from, as seen by this endpoint
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.0.remote_addr
+    }
+
+    /// The ALPN protocol negotiated in the handshake, if any
+    pub fn alpn(&self) -> Option<&[u8]> {
+        self.0.alpn.as_deref()
+    }
+
+    /// The hello the client sent when connecting, if there was a handshake
+    ///
+    /// Returns `None` if the listener has no
+    /// [`ServerHandshake`](super::handshake::ServerHandshake), or if the hello can't be
+    /// decoded as `T`.
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub fn handshake<T: serde::de::DeserializeOwned>(&self) -> Option<T> {
+        bincode::deserialize(&self.0.handshake.as_ref()?.hello).ok()
+    }
+
+    /// The capabilities negotiated in the handshake, if the client sent its capabilities
+    ///
+    /// See [`capabilities`](super::capabilities) for details.
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub fn capabilities(&self) -> Option<&super::capabilities::Negotiated> {
+        self.0.handshake.as_ref()?.negotiated.as_ref()
+    }
+
+    /// When the connection was established
+    pub fn established(&self) -> SystemTime {
+        self.0.established
+    }
+
+    /// Number of channels on this connection that are currently being served
+    pub fn open_streams(&self) -> usize {
+        self.0.open_streams.load(Ordering::Relaxed)
+    }
+
+    /// Close the connection with an application error code and a reason
+    ///
+    /// All channels on the connection fail, and the peer receives the code and reason.
+    pub fn close(&self, code: u32, reason: &[u8]) {
+        (self.0.close)(code, reason)
+    }
+
+    /// Close the connection with a typed code, see [`CloseCode`]
+    ///
+    /// The client gets the code and reason as [`Error::Closed`](crate::error::Error::Closed).
+    pub fn close_with(&self, code: CloseCode, reason: &str) {
+        self.close(code.into(), reason.as_bytes())
+    }
+}
+
+/// Registry of the connections of a listener
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+#[derive(Clone, Default)]
+pub(crate) struct Connections {
+    next_id: Arc<AtomicU64>,
+    entries: Arc<Mutex<BTreeMap<u64, ConnectionHandle>>>,
+}
+
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+impl fmt::Debug for Connections {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.list()).finish()
+    }
+}
+
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+impl Connections {
+    /// Register a connection with its handshake, until the returned guard is dropped
+    pub fn register(
+        &self,
+        connection: &quinn::Connection,
+        peer: String,
+        handshake: Option<Handshake>,
+    ) -> ConnectionGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let alpn = connection
+            .handshake_data()
+            .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+            .and_then(|data| data.protocol);
+        let handle = ConnectionHandle(Arc::new(Entry {
+            id,
+            peer,
+            remote_addr: connection.remote_address(),
+            alpn,
+            handshake,
+            established: SystemTime::now(),
+            open_streams: AtomicUsize::new(0),
+            close: Box::new({
+                let connection = connection.clone();
+                move |code, reason| connection.close(code.into(), reason)
+            }),
+        }));
+        self.entries.lock().unwrap().insert(id, handle.clone());
+        ConnectionGuard {
+            connections: self.clone(),
+            handle,
+        }
+    }
+
+    /// The registered connections, oldest first
+    pub fn list(&self) -> Vec<ConnectionHandle> {
+        self.entries.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// Keeps a connection registered with [`Connections`] until dropped
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub(crate) struct ConnectionGuard {
+    connections: Connections,
+    handle: ConnectionHandle,
+}
+
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+impl ConnectionGuard {
+    /// Count a channel as open on the connection, until the returned guard is dropped
+    pub fn stream(&self) -> StreamGuard {
+        self.handle.0.open_streams.fetch_add(1, Ordering::Relaxed);
+        StreamGuard(self.handle.clone())
+    }
+}
+
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let id = self.handle.id();
+        self.connections.entries.lock().unwrap().remove(&id);
+    }
+}
+
+/// Keeps a channel counted as open on its connection until dropped
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+#[derive(Debug)]
+pub(crate) struct StreamGuard(ConnectionHandle);
+
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+impl StreamGuard {
+    /// The remote peer of the connection
+    pub fn peer(&self) -> &str {
+        self.0.peer()
+    }
+
+    /// The connection the channel belongs to
+    pub fn connection(&self) -> &ConnectionHandle {
+        &self.0
+    }
+}
+
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.0 .0.open_streams.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,415 @@
+//! Fail over between replicas of a server
+//!
+//! A [`FailoverConnector`] opens channels on one of several connectors to replicas of
+//! the same service, called backends. It sticks to one backend as long as that works:
+//! when opening a channel fails, the backend is marked as unhealthy and channels are
+//! opened on the next healthy backend from then on, even after the previous one
+//! recovered.
+//!
+//! With [`FailoverConnector::health_check`], every backend is probed periodically, so
+//! a dead backend is noticed before a request fails on it. The default probe opens a
+//! channel and drops it right away, use [`FailoverConnector::probe`] to send a real
+//! request instead, e.g. a ping:
+//!
+//! ```ignore
+//! let connector = FailoverConnector::new([replica1, replica2])
+//!     .probe(|backend| async move {
+//!         let client = RpcClient::<MyService, _>::new(backend);
+//!         client.map::<PingService>().ping().await.is_ok()
+//!     })
+//!     .health_check(Duration::from_secs(5));
+//! let mut events = connector.events();
+//! let client = RpcClient::<MyService, _>::new(connector);
+//! ```
+//!
+//! Every switch to another backend is reported as a [`FailoverEvent`]. Subscriptions
+//! made with [`RpcClient::subscribe_with_failover`] are restarted on the new backend
+//! when their backend dies.
+use std::{
+    fmt,
+    future::Future,
+    result,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_lite::{stream::Boxed, StreamExt};
+use futures_util::SinkExt;
+use tokio::{sync::broadcast, task::AbortHandle};
+
+use super::{connections::ConnectionHandle, ConnectionErrors, Connector, StreamTypes};
+use crate::{
+    message::ServerStreamingMsg,
+    pattern::server_streaming::{Error, ItemError},
+    runtime::BoxFuture,
+    RpcClient, Service,
+};
+
+/// Number of events kept for slow receivers, they miss older events
+const EVENT_CAPACITY: usize = 16;
+
+/// Time a subscription waits for the probe of its backend after its stream ended
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Channels are opened on another backend from now on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailoverEvent {
+    /// Index of the backend that was used so far
+    pub from: usize,
+    /// Index of the backend that is used from now on
+    pub to: usize,
+}
+
+type Probe<C> = Arc<dyn Fn(C) -> BoxFuture<bool> + Send + Sync>;
+
+struct Backend<C> {
+    connector: C,
+    healthy: AtomicBool,
+}
+
+struct Shared<C> {
+    backends: Vec<Backend<C>>,
+    current: AtomicUsize,
+    probe: Mutex<Probe<C>>,
+    events: broadcast::Sender<FailoverEvent>,
+    health_check: Mutex<Option<AbortHandle>>,
+}
+
+impl<C> Drop for Shared<C> {
+    fn drop(&mut self) {
+        if let Some(task) = self.health_check.get_mut().unwrap().take() {
+            task.abort();
+        }
+    }
+}
+
+impl<C: Connector> Shared<C> {
+    fn is_healthy(&self, index: usize) -> bool {
+        self.backends[index].healthy.load(Ordering::Relaxed)
+    }
+
+    /// Switch to another backend, unless another task switched already
+    fn switch(&self, from: usize, to: usize) {
+        let switched = self
+            .current
+            .compare_exchange(from, to, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok();
+        if switched {
+            tracing::info!("failing over from backend {from} to backend {to}");
+            self.events.send(FailoverEvent { from, to }).ok();
+        }
+    }
+
+    /// Mark a backend as unhealthy, failing over if it is the current one
+    fn fail(&self, index: usize) {
+        self.backends[index].healthy.store(false, Ordering::Relaxed);
+        if self.current.load(Ordering::Relaxed) != index {
+            return;
+        }
+        let len = self.backends.len();
+        let next = (1..len)
+            .map(|offset| (index + offset) % len)
+            .find(|&next| self.is_healthy(next));
+        if let Some(next) = next {
+            self.switch(index, next);
+        }
+    }
+
+    /// Probe a backend and record the result, returns whether it is healthy
+    async fn check(&self, index: usize, timeout: Duration) -> bool {
+        let probe = self.probe.lock().unwrap().clone();
+        let connector = self.backends[index].connector.clone();
+        let healthy = tokio::time::timeout(timeout, probe(connector))
+            .await
+            .unwrap_or(false);
+        if healthy {
+            self.backends[index].healthy.store(true, Ordering::Relaxed);
+        } else {
+            self.fail(index);
+        }
+        healthy
+    }
+
+    /// Open a channel on the current backend, or on the next one that works
+    ///
+    /// Healthy backends are tried first, the others only if none of them works.
+    async fn open(
+        &self,
+        rpc: bool,
+    ) -> result::Result<(usize, C::SendSink, C::RecvStream), C::OpenError> {
+        let current = self.current.load(Ordering::Relaxed);
+        let len = self.backends.len();
+        let mut order = (0..len)
+            .map(|offset| (current + offset) % len)
+            .collect::<Vec<_>>();
+        order.sort_by_key(|&index| !self.is_healthy(index));
+        let mut error = None;
+        for index in order {
+            let connector = &self.backends[index].connector;
+            let res = if rpc {
+                connector.open_rpc().await
+            } else {
+                connector.open().await
+            };
+            match res {
+                Ok((send, recv)) => {
+                    self.backends[index].healthy.store(true, Ordering::Relaxed);
+                    let current = self.current.load(Ordering::Relaxed);
+                    if index != current {
+                        self.switch(current, index);
+                    }
+                    return Ok((index, send, recv));
+                }
+                Err(cause) => {
+                    tracing::debug!("opening a channel on backend {index} failed: {cause}");
+                    self.backends[index].healthy.store(false, Ordering::Relaxed);
+                    error = Some(cause);
+                }
+            }
+        }
+        Err(error.expect("there is at least one backend"))
+    }
+
+    async fn health_check(shared: Weak<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let Some(shared) = shared.upgrade() else {
+                return;
+            };
+            let checks = (0..shared.backends.len()).map(|index| shared.check(index, interval));
+            futures_util::future::join_all(checks).await;
+        }
+    }
+}
+
+/// A connector that fails over between backends, see the [module docs](self)
+pub struct FailoverConnector<C> {
+    shared: Arc<Shared<C>>,
+}
+
+impl<C> Clone for FailoverConnector<C> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<C> fmt::Debug for FailoverConnector<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let healthy = self
+            .shared
+            .backends
+            .iter()
+            .map(|backend| backend.healthy.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        f.debug_struct("FailoverConnector")
+            .field("current", &self.shared.current.load(Ordering::Relaxed))
+            .field("healthy", &healthy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: Connector> FailoverConnector<C> {
+    /// Fail over between the given backends, starting with the first one
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no backends.
+    pub fn new(backends: impl IntoIterator<Item = C>) -> Self {
+        let backends = backends
+            .into_iter()
+            .map(|connector| Backend {
+                connector,
+                healthy: AtomicBool::new(true),
+            })
+            .collect::<Vec<_>>();
+        assert!(!backends.is_empty(), "failover needs at least one backend");
+        let probe: Probe<C> =
+            Arc::new(|connector: C| Box::pin(async move { connector.open().await.is_ok() }));
+        Self {
+            shared: Arc::new(Shared {
+                backends,
+                current: AtomicUsize::new(0),
+                probe: Mutex::new(probe),
+                events: broadcast::channel(EVENT_CAPACITY).0,
+                health_check: Default::default(),
+            }),
+        }
+    }
+
+    /// Check whether a backend is healthy with the given probe
+    ///
+    /// The probe gets a clone of the connector of the backend, and returns true if
+    /// the backend is healthy.
+    pub fn probe<F, Fut>(self, probe: F) -> Self
+    where
+        F: Fn(C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        *self.shared.probe.lock().unwrap() = Arc::new(move |connector| Box::pin(probe(connector)));
+        self
+    }
+
+    /// Probe every backend each `interval`, failing over if the current one is unhealthy
+    ///
+    /// Probes that take longer than `interval` count as failed. The health check
+    /// stops when the last clone of the connector is dropped.
+    pub fn health_check(self, interval: Duration) -> Self {
+        let task = tokio::spawn(Shared::health_check(Arc::downgrade(&self.shared), interval));
+        let previous = self
+            .shared
+            .health_check
+            .lock()
+            .unwrap()
+            .replace(task.abort_handle());
+        if let Some(previous) = previous {
+            previous.abort();
+        }
+        self
+    }
+
+    /// Index of the backend channels are opened on
+    pub fn current(&self) -> usize {
+        self.shared.current.load(Ordering::Relaxed)
+    }
+
+    /// Whether the backend with the given index passed its last check
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no backend with this index.
+    pub fn is_healthy(&self, index: usize) -> bool {
+        self.shared.is_healthy(index)
+    }
+
+    /// The connector of the backend with the given index
+    pub fn backend(&self, index: usize) -> Option<&C> {
+        let backend = self.shared.backends.get(index)?;
+        Some(&backend.connector)
+    }
+
+    /// Receive an event for every switch to another backend
+    pub fn events(&self) -> broadcast::Receiver<FailoverEvent> {
+        self.shared.events.subscribe()
+    }
+}
+
+impl<C: ConnectionErrors> ConnectionErrors for FailoverConnector<C> {
+    type SendError = C::SendError;
+    type RecvError = C::RecvError;
+    type OpenError = C::OpenError;
+    type AcceptError = C::AcceptError;
+}
+
+impl<C: StreamTypes> StreamTypes for FailoverConnector<C> {
+    type In = C::In;
+    type Out = C::Out;
+    type SendSink = C::SendSink;
+    type RecvStream = C::RecvStream;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        C::poll_closed(send, cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        C::connection(recv)
+    }
+}
+
+impl<C: Connector> Connector for FailoverConnector<C> {
+    async fn open(&self) -> result::Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let (_, send, recv) = self.shared.open(false).await?;
+        Ok((send, recv))
+    }
+
+    async fn open_rpc(
+        &self,
+    ) -> result::Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let (_, send, recv) = self.shared.open(true).await?;
+        Ok((send, recv))
+    }
+}
+
+type Channel<C> = (
+    usize,
+    <C as StreamTypes>::SendSink,
+    <C as StreamTypes>::RecvStream,
+);
+
+impl<S, C> RpcClient<S, FailoverConnector<C>>
+where
+    S: Service,
+    C: crate::Connector<S>,
+{
+    /// Server streaming call that is restarted on another backend when its backend dies
+    ///
+    /// The backend is considered dead if receiving a response fails, or if the
+    /// responses end and the backend fails its probe. The request is then sent
+    /// again, so this is meant for subscriptions that start with the current state.
+    /// Responses that were in flight are lost.
+    ///
+    /// The responses end if no backend can be reached anymore. Unlike the stream
+    /// of [`RpcClient::server_streaming`], the stream is not `Sync`, since it opens
+    /// channels itself.
+    #[allow(clippy::type_complexity)]
+    pub async fn subscribe_with_failover<M>(
+        &self,
+        msg: M,
+    ) -> result::Result<
+        Boxed<result::Result<M::Response, ItemError<FailoverConnector<C>>>>,
+        Error<FailoverConnector<C>>,
+    >
+    where
+        M: ServerStreamingMsg<S> + Clone,
+    {
+        let shared = self.source.shared.clone();
+        let channel = subscribe(&shared, msg.clone()).await?;
+        let state = (shared, msg, Some(channel));
+        let recv = futures_lite::stream::unfold(state, |(shared, msg, mut channel)| async move {
+            loop {
+                let (index, _send, recv) = channel.as_mut()?;
+                let index = *index;
+                let cause = match recv.next().await {
+                    Some(Ok(res)) => {
+                        let res = M::Response::try_from(res).map_err(|_| ItemError::DowncastError);
+                        return Some((res, (shared, msg, channel)));
+                    }
+                    Some(Err(cause)) => {
+                        shared.fail(index);
+                        Some(cause)
+                    }
+                    None if shared.check(index, PROBE_TIMEOUT).await => return None,
+                    None => None,
+                };
+                tracing::debug!("backend {index} died, restarting subscription");
+                channel = subscribe(&shared, msg.clone()).await.ok();
+                if let (None, Some(cause)) = (&channel, cause) {
+                    return Some((Err(ItemError::RecvError(cause)), (shared, msg, channel)));
+                }
+            }
+        });
+        Ok(Box::pin(recv))
+    }
+}
+
+/// Open a channel and send the request of a subscription
+async fn subscribe<S, C, M>(
+    shared: &Shared<C>,
+    msg: M,
+) -> result::Result<Channel<C>, Error<FailoverConnector<C>>>
+where
+    S: Service,
+    C: crate::Connector<S>,
+    M: ServerStreamingMsg<S>,
+{
+    let (index, mut send, recv) = shared.open(false).await.map_err(Error::Open)?;
+    if let Err(cause) = send.send(msg.into()).await {
+        shared.fail(index);
+        return Err(Error::Send(cause));
+    }
+    Ok((index, send, recv))
+}
@@ -0,0 +1,60 @@
+//! Reject requests before they are deserialized
+//!
+//! The framed transports (quinn and iroh-net) normally deserialize the whole first
+//! message of a channel before the server sees it. A [`RequestFilter`] set on the
+//! listener is called with a [`RequestPeek`] of the first frame instead, which only
+//! contains the enum variant index and the size of the frame. If the filter returns
+//! false, the frame is never deserialized and receiving fails with
+//! [`std::io::ErrorKind::PermissionDenied`].
+//!
+//! This allows authorization, rate limiting or size limits per request type without
+//! paying for deserializing large payloads that are rejected anyway.
+use std::{fmt, sync::Arc};
+
+/// What is known about a request before it is deserialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RequestPeek {
+    /// Index of the variant of the request enum, in declaration order
+    ///
+    /// This is only meaningful if the request type of the service is an enum.
+    pub variant: u32,
+    /// Size of the encoded request in bytes
+    pub len: usize,
+}
+
+impl RequestPeek {
+    /// Peek at an encoded request
+    ///
+    /// Bincode encodes the variant index of an enum as a little endian u32 in front of
+    /// the fields. Returns `None` if the frame is too short.
+    pub(crate) fn new(frame: &[u8]) -> Option<Self> {
+        let variant = frame.get(..4)?.try_into().ok()?;
+        Some(Self {
+            variant: u32::from_le_bytes(variant),
+            len: frame.len(),
+        })
+    }
+}
+
+/// Decides whether a request is handled, see the [module docs](self)
+#[derive(Clone)]
+pub struct RequestFilter(Arc<dyn Fn(&RequestPeek) -> bool + Send + Sync>);
+
+impl fmt::Debug for RequestFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestFilter").finish_non_exhaustive()
+    }
+}
+
+impl RequestFilter {
+    /// Create a filter from a function that returns true for requests to handle
+    pub fn new(f: impl Fn(&RequestPeek) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// Check whether a request is handled
+    pub fn accepts(&self, peek: &RequestPeek) -> bool {
+        (self.0)(peek)
+    }
+}
@@ -1,15 +1,33 @@
 //! Memory transport implementation using [flume]
 //!
+//! Messages are moved through the channels as typed values, they are never
+//! serialized. Use this transport when client and server live in the same process,
+//! e.g. together with a network transport in a
+//! [`CombinedListener`](super::combined::CombinedListener) to serve local and remote
+//! clients with the same server.
+//!
+//! With the `flume-simulation` feature, `channel_with_conditions` creates channels
+//! that simulate a bad network link, see `LinkConditions`.
+//!
 //! [flume]: https://docs.rs/flume/
 use futures_lite::{Future, Stream};
 use futures_sink::Sink;
 
 use crate::{
+    error::ErrorKind,
     transport::{ConnectionErrors, Connector, Listener, LocalAddr},
     RpcMessage,
 };
 use core::fmt;
-use std::{error, fmt::Display, marker::PhantomData, pin::Pin, result, task::Poll};
+use std::{
+    error,
+    fmt::Display,
+    marker::PhantomData,
+    pin::Pin,
+    result,
+    task::{ready, Context, Poll},
+};
+use tokio::sync::oneshot;
 
 use super::StreamTypes;
 
@@ -26,7 +44,23 @@ impl fmt::Display for RecvError {
 }
 
 /// Sink for memory channels
-pub struct SendSink<T: RpcMessage>(pub(crate) flume::r#async::SendSink<'static, T>);
+pub struct SendSink<T: RpcMessage>(
+    pub(crate) flume::r#async::SendSink<'static, T>,
+    /// Completes when the [`RecvStream`] of the other side is dropped, then taken
+    pub(crate) Option<oneshot::Receiver<()>>,
+);
+
+impl<T: RpcMessage> SendSink<T> {
+    /// Poll whether the [`RecvStream`] of the other side was dropped
+    pub(crate) fn poll_closed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let Some(closed) = &mut self.1 else {
+            return Poll::Ready(());
+        };
+        ready!(Pin::new(closed).poll(cx)).ok();
+        self.1 = None;
+        Poll::Ready(())
+    }
+}
 
 impl<T: RpcMessage> fmt::Debug for SendSink<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -72,7 +106,12 @@ impl<T: RpcMessage> Sink<T> for SendSink<T> {
 }
 
 /// Stream for memory channels
-pub struct RecvStream<T: RpcMessage>(pub(crate) flume::r#async::RecvStream<'static, T>);
+pub struct RecvStream<T: RpcMessage>(
+    pub(crate) flume::r#async::RecvStream<'static, T>,
+    /// Dropped together with the stream, to tell the [`SendSink`] of the other side
+    #[allow(dead_code)]
+    pub(crate) oneshot::Sender<()>,
+);
 
 impl<T: RpcMessage> fmt::Debug for RecvStream<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -199,6 +238,9 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for FlumeListener<In, Out> {
     type Out = Out;
     type SendSink = SendSink<Out>;
     type RecvStream = RecvStream<In>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        send.poll_closed(cx)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Listener for FlumeListener<In, Out> {
@@ -227,20 +269,33 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for FlumeConnector<In, Out> {
     type Out = Out;
     type SendSink = SendSink<Out>;
     type RecvStream = RecvStream<In>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        send.poll_closed(cx)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Connector for FlumeConnector<In, Out> {
     #[allow(refining_impl_trait)]
     fn open(&self) -> OpenFuture<In, Out> {
-        let (local_send, remote_recv) = flume::bounded::<Out>(128);
-        let (remote_send, local_recv) = flume::bounded::<In>(128);
+        let (local_send, remote_recv) = flume::bounded::<Out>(self.stream_buffer);
+        let (remote_send, local_recv) = flume::bounded::<In>(self.stream_buffer);
+        #[cfg(feature = "flume-simulation")]
+        let (remote_recv, local_recv) = match &self.simulation {
+            Some(simulation) => (
+                simulation.to_remote.relay(remote_recv, self.stream_buffer),
+                simulation.to_local.relay(local_recv, self.stream_buffer),
+            ),
+            None => (remote_recv, local_recv),
+        };
+        let (local_guard, remote_closed) = oneshot::channel();
+        let (remote_guard, local_closed) = oneshot::channel();
         let remote_chan = (
-            SendSink(remote_send.into_sink()),
-            RecvStream(remote_recv.into_stream()),
+            SendSink(remote_send.into_sink(), Some(remote_closed)),
+            RecvStream(remote_recv.into_stream(), remote_guard),
         );
         let local_chan = (
-            SendSink(local_send.into_sink()),
-            RecvStream(local_recv.into_stream()),
+            SendSink(local_send.into_sink(), Some(local_closed)),
+            RecvStream(local_recv.into_stream(), local_guard),
         );
         OpenFuture::new(self.sink.clone().into_send_async(remote_chan), local_chan)
     }
@@ -252,12 +307,32 @@ impl<In: RpcMessage, Out: RpcMessage> Connector for FlumeConnector<In, Out> {
 pub struct FlumeConnector<In: RpcMessage, Out: RpcMessage> {
     #[allow(clippy::type_complexity)]
     sink: flume::Sender<(SendSink<In>, RecvStream<Out>)>,
+    stream_buffer: usize,
+    #[cfg(feature = "flume-simulation")]
+    simulation: Option<std::sync::Arc<simulation::Simulation>>,
+}
+
+/// Default number of messages buffered in each direction of a channel
+const DEFAULT_STREAM_BUFFER: usize = 128;
+
+impl<In: RpcMessage, Out: RpcMessage> FlumeConnector<In, Out> {
+    /// Set the number of messages buffered in each direction of channels opened
+    /// from this connector.
+    ///
+    /// The default is 128. Keep this at a low value to get backpressure.
+    pub fn stream_buffer(mut self, value: usize) -> Self {
+        self.stream_buffer = value;
+        self
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Clone for FlumeConnector<In, Out> {
     fn clone(&self) -> Self {
         Self {
             sink: self.sink.clone(),
+            stream_buffer: self.stream_buffer,
+            #[cfg(feature = "flume-simulation")]
+            simulation: self.simulation.clone(),
         }
     }
 }
@@ -266,6 +341,7 @@ impl<In: RpcMessage, Out: RpcMessage> fmt::Debug for FlumeConnector<In, Out> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("FlumeClientChannel")
             .field("sink", &self.sink)
+            .field("stream_buffer", &self.stream_buffer)
             .finish()
     }
 }
@@ -336,10 +412,181 @@ impl std::error::Error for CreateChannelError {}
 
 /// Create a flume listener and a connected flume connector.
 ///
-/// `buffer` the size of the buffer for each channel. Keep this at a low value to get backpressure
+/// `buffer` the number of channels that can be opened before the listener accepts them.
+/// Use [FlumeConnector::stream_buffer] to set the buffer size of each channel.
 pub fn channel<Req: RpcMessage, Res: RpcMessage>(
     buffer: usize,
 ) -> (FlumeListener<Req, Res>, FlumeConnector<Res, Req>) {
     let (sink, stream) = flume::bounded(buffer);
-    (FlumeListener { stream }, FlumeConnector { sink })
+    let connector = FlumeConnector {
+        sink,
+        stream_buffer: DEFAULT_STREAM_BUFFER,
+        #[cfg(feature = "flume-simulation")]
+        simulation: None,
+    };
+    (FlumeListener { stream }, connector)
+}
+
+/// Create a flume listener and a connected flume connector that simulate a bad link.
+///
+/// `to_server` applies to frames sent by the connector side, `to_client` to frames sent
+/// by the listener side. Frames are relayed by a task per direction and channel, so
+/// channels must be opened from within a tokio runtime.
+#[cfg(feature = "flume-simulation")]
+pub fn channel_with_conditions<Req: RpcMessage, Res: RpcMessage>(
+    buffer: usize,
+    to_server: LinkConditions,
+    to_client: LinkConditions,
+) -> (FlumeListener<Req, Res>, FlumeConnector<Res, Req>) {
+    let (sink, stream) = flume::bounded(buffer);
+    let connector = FlumeConnector {
+        sink,
+        stream_buffer: DEFAULT_STREAM_BUFFER,
+        simulation: Some(std::sync::Arc::new(simulation::Simulation {
+            to_remote: simulation::Link::new(to_server),
+            to_local: simulation::Link::new(to_client),
+        })),
+    };
+    (FlumeListener { stream }, connector)
+}
+
+#[cfg(feature = "flume-simulation")]
+pub use simulation::LinkConditions;
+
+#[cfg(feature = "flume-simulation")]
+mod simulation {
+    use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+    use tokio::time::Instant;
+
+    use crate::{transport::rng::Rng, RpcMessage};
+
+    /// Conditions of one direction of a simulated link
+    ///
+    /// The default conditions describe a perfect link: no latency, unlimited bandwidth
+    /// and no dropped frames.
+    #[derive(Debug, Clone, Default)]
+    pub struct LinkConditions {
+        seed: u64,
+        latency: Duration,
+        bandwidth: Option<u64>,
+        drop_probability: f64,
+    }
+
+    impl LinkConditions {
+        /// Set the seed for the random number generator used to drop frames
+        pub fn seed(mut self, value: u64) -> Self {
+            self.seed = value;
+            self
+        }
+
+        /// Delay each frame by the given latency
+        pub fn latency(mut self, value: Duration) -> Self {
+            self.latency = value;
+            self
+        }
+
+        /// Limit the bandwidth to the given number of bytes per second
+        ///
+        /// The size of a frame is its bincode encoded size. Frames are sent one after
+        /// the other, so a large frame delays the frames behind it.
+        pub fn bandwidth(mut self, bytes_per_second: u64) -> Self {
+            self.bandwidth = Some(bytes_per_second);
+            self
+        }
+
+        /// Probability that a frame is silently dropped
+        pub fn drop_probability(mut self, value: f64) -> Self {
+            self.drop_probability = value;
+            self
+        }
+
+        fn transmit_time<T: RpcMessage>(&self, item: &T) -> Duration {
+            match self.bandwidth {
+                Some(bandwidth) => {
+                    let size = bincode::serialized_size(item).unwrap_or_default();
+                    Duration::from_secs_f64(size as f64 / bandwidth.max(1) as f64)
+                }
+                None => Duration::ZERO,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub(super) struct Simulation {
+        /// Frames sent by the connector side
+        pub to_remote: Link,
+        /// Frames sent by the listener side
+        pub to_local: Link,
+    }
+
+    #[derive(Debug)]
+    pub(super) struct Link {
+        conditions: LinkConditions,
+        rng: Mutex<Rng>,
+    }
+
+    impl Link {
+        pub fn new(conditions: LinkConditions) -> Self {
+            Self {
+                rng: Mutex::new(Rng::new(conditions.seed)),
+                conditions,
+            }
+        }
+
+        /// Insert a relay task in front of the given receiver
+        pub fn relay<T: RpcMessage>(
+            &self,
+            recv: flume::Receiver<T>,
+            buffer: usize,
+        ) -> flume::Receiver<T> {
+            // fork a generator for each channel, so the decisions don't depend on scheduling
+            let rng = Rng::new(self.rng.lock().unwrap().next_u64());
+            let (send, relayed) = flume::bounded(buffer);
+            tokio::spawn(relay(recv, send, self.conditions.clone(), rng, buffer));
+            relayed
+        }
+    }
+
+    async fn relay<T: RpcMessage>(
+        recv: flume::Receiver<T>,
+        send: flume::Sender<T>,
+        conditions: LinkConditions,
+        mut rng: Rng,
+        buffer: usize,
+    ) {
+        // frames in transit, with the time they arrive
+        let mut in_transit = VecDeque::<(Instant, T)>::new();
+        // time when the link is done transmitting the previous frame
+        let mut link_free = Instant::now();
+        let mut open = true;
+        loop {
+            let next = in_transit.front().map(|(at, _)| *at);
+            tokio::select! {
+                item = recv.recv_async(), if open && in_transit.len() < buffer => match item {
+                    Ok(item) => {
+                        if rng.chance(conditions.drop_probability) {
+                            continue;
+                        }
+                        link_free = link_free.max(Instant::now()) + conditions.transmit_time(&item);
+                        in_transit.push_back((link_free + conditions.latency, item));
+                    }
+                    Err(_) => open = false,
+                },
+                _ = tokio::time::sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                    let (_, item) = in_transit.pop_front().expect("checked above");
+                    if send.send_async(item).await.is_err() {
+                        break;
+                    }
+                }
+                else => break,
+            }
+        }
+    }
+}
+
+/// The category of an error of this transport, if it is more specific than the default
+pub(crate) fn error_kind(cause: &(dyn error::Error + 'static)) -> Option<ErrorKind> {
+    let dropped = cause.is::<SendError>() || cause.is::<OpenError>() || cause.is::<AcceptError>();
+    dropped.then_some(ErrorKind::Shutdown)
 }
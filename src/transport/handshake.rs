@@ -0,0 +1,296 @@
+//! Exchange an application defined handshake on each new connection
+//!
+//! Some servers need to know who is connecting before serving any channel, e.g. a
+//! magic value to detect misconfigured clients, a tenant id or an auth blob. The
+//! quinn and iroh-net transports can send a typed hello message on every new
+//! connection, before any request:
+//!
+//! - the connector sends the hello of its [`ClientHandshake`] on each connection it
+//!   establishes, see [`QuinnConnector::handshake`](super::quinn::QuinnConnector::handshake)
+//! - the listener reads the hello with its [`ServerHandshake`] before it accepts any
+//!   channel on the connection, and closes the connection if the check rejects it
+//!
+//! A rejected client gets the [`CloseReason`] of the check as
+//! [`Error::Closed`](crate::error::Error::Closed) when opening a channel. The server
+//! can look at the hello of a connection again with
+//! [`ConnectionHandle::handshake`](super::connections::ConnectionHandle::handshake).
+//!
+//! The handshake also negotiates [`Capabilities`]: if the client sends its
+//! capabilities with [`ClientHandshake::capabilities`], the listener replies with what
+//! both sides support, using the capabilities of its
+//! [`ServerHandshake::capabilities`].
+//!
+//! The hello is sent on a unidirectional stream, so the endpoint of the listener
+//! must allow at least one incoming unidirectional stream per connection, and the
+//! reply is sent on one in the other direction. Both sides must agree on whether
+//! there is a handshake: a listener with a handshake closes connections that don't
+//! send a hello in time.
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::sync::watch;
+
+use super::{
+    capabilities::{Capabilities, Negotiated},
+    util::spawn_named,
+};
+use crate::{
+    error::{CloseCode, CloseReason},
+    runtime::BoxFuture,
+};
+
+/// Maximum size of an encoded hello
+const MAX_HELLO_LEN: usize = 64 * 1024;
+
+/// Maximum size of the encoded capabilities sent with a hello, and of the reply
+const MAX_CAPABILITIES_LEN: usize = 16 * 1024;
+
+/// Time a client has to send its hello after connecting
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the client sends on the unidirectional stream of the handshake
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Hello {
+    payload: Vec<u8>,
+    capabilities: Option<Capabilities>,
+}
+
+/// The hello a connector sends on each new connection
+///
+/// The default sends an empty hello, e.g. to only negotiate capabilities.
+#[derive(Clone, Default)]
+pub struct ClientHandshake(Arc<Hello>);
+
+impl fmt::Debug for ClientHandshake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientHandshake")
+            .field("len", &self.0.payload.len())
+            .field("capabilities", &self.0.capabilities)
+            .finish()
+    }
+}
+
+impl ClientHandshake {
+    /// Send `hello` on each new connection
+    pub fn new<T: Serialize>(hello: &T) -> Result<Self, bincode::Error> {
+        let payload = bincode::serialize(hello)?;
+        if payload.len() > MAX_HELLO_LEN {
+            return Err(Box::new(bincode::ErrorKind::SizeLimit));
+        }
+        Ok(Self(Arc::new(Hello {
+            payload,
+            capabilities: None,
+        })))
+    }
+
+    /// Negotiate capabilities with the server, see [`capabilities`](super::capabilities)
+    pub fn capabilities(self, capabilities: Capabilities) -> Self {
+        Self(Arc::new(Hello {
+            payload: self.0.payload.clone(),
+            capabilities: Some(capabilities),
+        }))
+    }
+}
+
+type Check = Arc<dyn Fn(&[u8]) -> BoxFuture<Result<(), CloseReason>> + Send + Sync>;
+
+/// The check a listener applies to the hello of each new connection
+///
+/// The default accepts any hello.
+#[derive(Clone)]
+pub struct ServerHandshake {
+    check: Check,
+    capabilities: Capabilities,
+}
+
+impl fmt::Debug for ServerHandshake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerHandshake")
+            .field("capabilities", &self.capabilities)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ServerHandshake {
+    fn default() -> Self {
+        Self {
+            check: Arc::new(|_| Box::pin(std::future::ready(Ok(())))),
+            capabilities: Capabilities::default(),
+        }
+    }
+}
+
+/// The result of a successful handshake, kept with the connection
+#[derive(Debug)]
+pub(crate) struct Handshake {
+    /// The encoded hello
+    pub hello: Vec<u8>,
+    /// The negotiated capabilities, if the client sent its capabilities
+    pub negotiated: Option<Negotiated>,
+}
+
+impl ServerHandshake {
+    /// Check the hello of each connection, closing the connection if `check` fails
+    ///
+    /// Hellos that can't be decoded as `T` are rejected with
+    /// [`CloseCode::ProtocolError`].
+    pub fn new<T, F, Fut>(check: F) -> Self
+    where
+        T: DeserializeOwned,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), CloseReason>> + Send + 'static,
+    {
+        Self {
+            check: Arc::new(move |hello| match bincode::deserialize(hello) {
+                Ok(hello) => Box::pin(check(hello)),
+                Err(cause) => {
+                    let reason = protocol_error(format!("invalid handshake: {cause}"));
+                    Box::pin(std::future::ready(Err(reason)))
+                }
+            }),
+            capabilities: Capabilities::default(),
+        }
+    }
+
+    /// Negotiate these capabilities with clients that send theirs
+    ///
+    /// Clients that send capabilities get nothing in common if this is not set.
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Read the hello of a new connection, check it and reply with the capabilities
+    pub(crate) async fn accept(
+        &self,
+        connection: &quinn::Connection,
+    ) -> Result<Handshake, CloseReason> {
+        let read = async {
+            let mut recv = connection.accept_uni().await.ok()?;
+            recv.read_to_end(MAX_HELLO_LEN + MAX_CAPABILITIES_LEN)
+                .await
+                .ok()
+        };
+        let hello = tokio::time::timeout(HELLO_TIMEOUT, read)
+            .await
+            .ok()
+            .flatten()
+            .ok_or_else(|| protocol_error("no handshake".into()))?;
+        let Hello {
+            payload,
+            capabilities,
+        } = bincode::deserialize(&hello)
+            .map_err(|cause| protocol_error(format!("invalid handshake: {cause}")))?;
+        (self.check)(&payload).await?;
+        let negotiated = capabilities.map(|client| self.capabilities.negotiate(&client));
+        if let Some(negotiated) = &negotiated {
+            let reply = async {
+                let mut send = connection.open_uni().await?;
+                send.write_all(&bincode::serialize(negotiated)?).await?;
+                send.finish()?;
+                anyhow::Ok(())
+            };
+            match tokio::time::timeout(HELLO_TIMEOUT, reply).await {
+                Ok(Ok(())) => {}
+                Ok(Err(cause)) => {
+                    let reason = format!("failed to send capabilities: {cause}");
+                    return Err(protocol_error(reason));
+                }
+                Err(_) => return Err(protocol_error("capabilities not accepted".into())),
+            }
+        }
+        Ok(Handshake {
+            hello: payload,
+            negotiated,
+        })
+    }
+}
+
+fn protocol_error(reason: String) -> CloseReason {
+    CloseReason {
+        code: CloseCode::ProtocolError,
+        reason,
+    }
+}
+
+/// The capabilities negotiated on the current connection of a connector
+pub(crate) type SharedNegotiated = Arc<Mutex<Option<Negotiated>>>;
+
+/// Sends the hello of the connector on the current connection, once
+#[derive(Debug)]
+pub(crate) struct HelloSender {
+    config: watch::Receiver<Option<ClientHandshake>>,
+    negotiated: SharedNegotiated,
+    sent: bool,
+}
+
+impl HelloSender {
+    pub fn new(
+        config: watch::Receiver<Option<ClientHandshake>>,
+        negotiated: SharedNegotiated,
+    ) -> Self {
+        Self {
+            config,
+            negotiated,
+            sent: false,
+        }
+    }
+
+    /// Send the hello on a new connection
+    pub fn connected(&mut self, connection: &quinn::Connection) {
+        self.sent = false;
+        *self.negotiated.lock().unwrap() = None;
+        self.send(connection);
+    }
+
+    /// Send the hello on the connection, unless it was sent already or there is none
+    pub fn send(&mut self, connection: &quinn::Connection) {
+        let Some(hello) = self.config.borrow_and_update().clone() else {
+            return;
+        };
+        if std::mem::replace(&mut self.sent, true) {
+            return;
+        }
+        let connection = connection.clone();
+        let negotiated = self.negotiated.clone();
+        // don't hold up requests, the server only serves them after the hello anyway
+        spawn_named(format_args!("quic-rpc handshake"), async move {
+            let handshake = async {
+                let mut send = connection.open_uni().await?;
+                send.write_all(&bincode::serialize(&*hello.0)?).await?;
+                send.finish()?;
+                if hello.0.capabilities.is_none() {
+                    return anyhow::Ok(None);
+                }
+                let mut recv = connection.accept_uni().await?;
+                let reply = recv.read_to_end(MAX_CAPABILITIES_LEN).await?;
+                anyhow::Ok(Some(bincode::deserialize::<Negotiated>(&reply)?))
+            };
+            match tokio::time::timeout(HELLO_TIMEOUT, handshake).await {
+                Ok(Ok(Some(reply))) => {
+                    // a reply from a connection that was replaced already is stale
+                    if connection.close_reason().is_none() {
+                        *negotiated.lock().unwrap() = Some(reply);
+                    }
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(cause)) => tracing::debug!("handshake failed: {cause}"),
+                Err(_) => tracing::debug!("handshake timed out"),
+            }
+        });
+    }
+
+    /// Wait until the handshake is changed
+    ///
+    /// Never completes once the connector is dropped.
+    pub async fn changed(&mut self) {
+        if self.config.changed().await.is_err() {
+            std::future::pending().await
+        }
+    }
+}
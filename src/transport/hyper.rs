@@ -1,13 +1,30 @@
 //! http2 transport using [hyper]
 //!
+//! Channels that stay idle for a long time, e.g. a subscription without updates, can
+//! be cut silently by NATs and proxies on the way. Enable
+//! [`ChannelConfig::keepalive`] on both ends to send keepalive frames on idle
+//! channels, and to fail channels that don't receive anything anymore.
+//!
 //! [hyper]: https://crates.io/crates/hyper/
 use std::{
-    convert::Infallible, error, fmt, io, marker::PhantomData, net::SocketAddr, pin::Pin, result,
-    sync::Arc, task::Poll,
+    any::type_name,
+    convert::Infallible,
+    error, fmt,
+    future::Future,
+    io,
+    marker::PhantomData,
+    net::SocketAddr,
+    pin::Pin,
+    result,
+    sync::Arc,
+    task::{ready, Poll},
+    time::Duration,
 };
 
-use crate::transport::{ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes};
-use crate::RpcMessage;
+use crate::transport::{
+    util::spawn_named, ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes,
+};
+use crate::{error::ErrorKind, RpcMessage};
 use bytes::Bytes;
 use flume::{Receiver, Sender};
 use futures_lite::{Stream, StreamExt};
@@ -18,10 +35,17 @@ use hyper::{
     service::{make_service_fn, service_fn},
     Body, Client, Request, Response, Server, StatusCode, Uri,
 };
+use pin_project::pin_project;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use tokio::time::{Instant, Sleep};
 use tracing::{debug, event, trace, Level};
 
+/// Length prefix of a keepalive frame, which has no payload
+///
+/// Payloads are limited to less than 16 MiB, so this is never the length of a message.
+const KEEPALIVE: [u8; 4] = u32::MAX.to_be_bytes();
+
 struct HyperConnectionInner {
     client: Box<dyn Requester>,
     config: Arc<ChannelConfig>,
@@ -131,6 +155,10 @@ pub struct ChannelConfig {
     /// The maximum frame size to use.
     max_frame_size: u32,
     max_payload_size: usize,
+    accept_buffer: usize,
+    stream_buffer: usize,
+    keepalive_interval: Option<Duration>,
+    keepalive_timeout: Option<Duration>,
 }
 
 impl ChannelConfig {
@@ -151,6 +179,50 @@ impl ChannelConfig {
         self.max_payload_size = value;
         Ok(self)
     }
+
+    /// Set the number of requests that are queued until the server accepts them.
+    ///
+    /// This only applies to server channels.
+    pub fn accept_buffer(mut self, value: usize) -> Self {
+        self.accept_buffer = value;
+        self
+    }
+
+    /// Set the number of messages that are buffered in each direction of a channel.
+    ///
+    /// Larger buffers allow higher throughput for streaming patterns, smaller buffers
+    /// apply backpressure earlier and use less memory.
+    pub fn stream_buffer(mut self, value: usize) -> Self {
+        self.stream_buffer = value;
+        self
+    }
+
+    /// Keep idle channels alive, and fail channels that stopped receiving anything
+    ///
+    /// A keepalive frame is sent on each direction of a channel that nothing was sent
+    /// on for `interval`. Receiving fails with [`RecvError::KeepaliveTimeout`] if
+    /// nothing, not even a keepalive frame, was received for `timeout`, which should be
+    /// a few intervals. Keepalive frames are never seen by the application.
+    ///
+    /// Both ends must be configured with a keepalive, older versions don't understand
+    /// keepalive frames and channels without them time out.
+    pub fn keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self.keepalive_timeout = Some(timeout);
+        self
+    }
+
+    /// The body of one direction of a channel, with keepalive frames if configured
+    fn body(&self, frames: Receiver<io::Result<Bytes>>) -> Body {
+        match self.keepalive_interval {
+            Some(interval) => Body::wrap_stream(KeepaliveBody {
+                inner: frames.into_stream(),
+                interval,
+                idle: tokio::time::sleep(interval),
+            }),
+            None => Body::wrap_stream(frames.into_stream()),
+        }
+    }
 }
 
 impl Default for ChannelConfig {
@@ -158,10 +230,42 @@ impl Default for ChannelConfig {
         Self {
             max_frame_size: 0xFFFFFF,
             max_payload_size: 0xFFFFFF,
+            accept_buffer: 32,
+            stream_buffer: 32,
+            keepalive_interval: None,
+            keepalive_timeout: None,
         }
     }
 }
 
+/// A stream of frames that sends a keepalive frame whenever it was idle for `interval`
+#[pin_project]
+struct KeepaliveBody<S> {
+    #[pin]
+    inner: S,
+    interval: Duration,
+    #[pin]
+    idle: Sleep,
+}
+
+impl<S: Stream<Item = io::Result<Bytes>>> Stream for KeepaliveBody<S> {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if let Poll::Ready(frame) = this.inner.poll_next(cx) {
+            this.idle.as_mut().reset(Instant::now() + *this.interval);
+            return Poll::Ready(frame);
+        }
+        ready!(this.idle.as_mut().poll(cx));
+        this.idle.as_mut().reset(Instant::now() + *this.interval);
+        Poll::Ready(Some(Ok(Bytes::from_static(&KEEPALIVE))))
+    }
+}
+
 /// A listener using a hyper server
 ///
 /// Each request made by the any client connection this channel will yield a `(recv, send)`
@@ -198,7 +302,9 @@ impl<In: RpcMessage, Out: RpcMessage> HyperListener<In, Out> {
 
     /// Creates a server listening on the [`SocketAddr`] with a custom configuration.
     pub fn serve_with_config(addr: &SocketAddr, config: ChannelConfig) -> hyper::Result<Self> {
-        let (accept_tx, accept_rx) = flume::bounded(32);
+        let (accept_tx, accept_rx) = flume::bounded(config.accept_buffer);
+        let config = Arc::new(config);
+        let request_config = config.clone();
 
         // The hyper "MakeService" which is called for each connection that is made to the
         // server.  It creates another Service which handles a single request.
@@ -208,10 +314,11 @@ impl<In: RpcMessage, Out: RpcMessage> HyperListener<In, Out> {
 
             // Need a new accept_tx to move to the future on every call of this FnMut.
             let accept_tx = accept_tx.clone();
+            let config = request_config.clone();
             async move {
                 let one_req_service = service_fn(move |req: Request<Body>| {
                     // This closure is an FnMut as well, so clone accept_tx once more.
-                    Self::handle_one_http2_request(req, accept_tx.clone())
+                    Self::handle_one_http2_request(req, accept_tx.clone(), config.clone())
                 });
                 Ok::<_, Infallible>(one_req_service)
             }
@@ -233,11 +340,14 @@ impl<In: RpcMessage, Out: RpcMessage> HyperListener<In, Out> {
             // If the sender is dropped this will also gracefully terminate the server.
             stop_rx.recv().await;
         });
-        tokio::spawn(server);
+        spawn_named(
+            format_args!("quic-rpc hyper listener {}", type_name::<In>()),
+            server,
+        );
 
         Ok(Self {
             channel: accept_rx,
-            config: Arc::new(config),
+            config,
             stop_tx,
             local_addr: [LocalAddr::Socket(local_addr)],
             _p: PhantomData,
@@ -251,19 +361,21 @@ impl<In: RpcMessage, Out: RpcMessage> HyperListener<In, Out> {
     async fn handle_one_http2_request(
         req: Request<Body>,
         accept_tx: Sender<InternalChannel<In>>,
+        config: Arc<ChannelConfig>,
     ) -> Result<Response<Body>, String> {
-        let (req_tx, req_rx) = flume::bounded::<result::Result<In, RecvError>>(32);
-        let (res_tx, res_rx) = flume::bounded::<io::Result<Bytes>>(32);
+        let stream_buffer = config.stream_buffer;
+        let (req_tx, req_rx) = flume::bounded::<result::Result<In, RecvError>>(stream_buffer);
+        let (res_tx, res_rx) = flume::bounded::<io::Result<Bytes>>(stream_buffer);
         accept_tx
             .send_async((req_rx, res_tx))
             .await
             .map_err(|_e| "unable to send")?;
 
-        spawn_recv_forwarder(req.into_body(), req_tx);
+        spawn_recv_forwarder(req.into_body(), req_tx, config.keepalive_timeout);
         // Create a response with the response body channel as the response body
         let response = Response::builder()
             .status(StatusCode::OK)
-            .body(Body::wrap_stream(res_rx.into_stream()))
+            .body(config.body(res_rx))
             .map_err(|_| "unable to set body")?;
         Ok(response)
     }
@@ -293,7 +405,14 @@ async fn try_forward_all<In: RpcMessage>(
     req_tx: &Sender<Result<In, RecvError>>,
 ) -> result::Result<usize, ()> {
     let mut sent = 0;
-    while let Some(msg) = try_get_length_prefixed(&buffer[sent..]) {
+    loop {
+        if buffer[sent..].starts_with(&KEEPALIVE) {
+            sent += KEEPALIVE.len();
+            continue;
+        }
+        let Some(msg) = try_get_length_prefixed(&buffer[sent..]) else {
+            break;
+        };
         sent += msg.len() + 4;
         let item = bincode::deserialize::<In>(msg).map_err(RecvError::DeserializeError);
         if let Err(_cause) = req_tx.send_async(item).await {
@@ -316,7 +435,8 @@ async fn try_forward_all<In: RpcMessage>(
 /// frames, deserialize those frames, and send the result to the flume channel.
 ///
 /// If there is a network error or the flume channel closes or the request
-/// stream is simply ended this task will terminate.
+/// stream is simply ended this task will terminate. With a keepalive timeout, it
+/// also terminates if nothing was received within the timeout.
 ///
 /// So it is fine to ignore the returned [`JoinHandle`].
 ///
@@ -324,40 +444,61 @@ async fn try_forward_all<In: RpcMessage>(
 fn spawn_recv_forwarder<In: RpcMessage>(
     req: Body,
     req_tx: Sender<result::Result<In, RecvError>>,
+    keepalive_timeout: Option<Duration>,
 ) -> JoinHandle<result::Result<(), ()>> {
-    tokio::spawn(async move {
-        let mut stream = req;
-        let mut buf = Vec::new();
-
-        while let Some(chunk) = stream.next().await {
-            match chunk.as_ref() {
-                Ok(chunk) => {
-                    event!(Level::TRACE, "Server got {} bytes", chunk.len());
-                    if buf.is_empty() {
-                        // try to forward directly from buffer
-                        let sent = try_forward_all(chunk, &req_tx).await?;
-                        // add just the rest, if any
-                        buf.extend_from_slice(&chunk[sent..]);
-                    } else {
-                        // no choice but to add it all
-                        buf.extend_from_slice(chunk);
-                    }
-                }
-                Err(cause) => {
-                    // Indicates that the connection has been closed on the client side.
-                    // This is a normal occurrence, e.g. when the client has raced the RPC
-                    // call with something else and has droppped the future.
-                    debug!("Network error: {}", cause);
+    spawn_named(
+        format_args!("quic-rpc hyper recv {}", type_name::<In>()),
+        async move {
+            let mut stream = req;
+            let mut buf = Vec::new();
+
+            loop {
+                let chunk = match keepalive_timeout {
+                    Some(timeout) => match tokio::time::timeout(timeout, stream.next()).await {
+                        Ok(chunk) => chunk,
+                        Err(_) => {
+                            debug!("Nothing received for {:?}, closing channel", timeout);
+                            req_tx
+                                .send_async(Err(RecvError::KeepaliveTimeout))
+                                .await
+                                .ok();
+                            break;
+                        }
+                    },
+                    None => stream.next().await,
+                };
+                let Some(chunk) = chunk else {
                     break;
-                }
-            };
-            let sent = try_forward_all(&buf, &req_tx).await?;
-            // remove the forwarded bytes.
-            // Frequently this will be the entire buffer, so no memcpy but just set the size to 0
-            buf.drain(..sent);
-        }
-        Ok(())
-    })
+                };
+                match chunk.as_ref() {
+                    Ok(chunk) => {
+                        event!(Level::TRACE, "Server got {} bytes", chunk.len());
+                        if buf.is_empty() {
+                            // try to forward directly from buffer
+                            let sent = try_forward_all(chunk, &req_tx).await?;
+                            // add just the rest, if any
+                            buf.extend_from_slice(&chunk[sent..]);
+                        } else {
+                            // no choice but to add it all
+                            buf.extend_from_slice(chunk);
+                        }
+                    }
+                    Err(cause) => {
+                        // Indicates that the connection has been closed on the client side.
+                        // This is a normal occurrence, e.g. when the client has raced the RPC
+                        // call with something else and has droppped the future.
+                        debug!("Network error: {}", cause);
+                        break;
+                    }
+                };
+                let sent = try_forward_all(&buf, &req_tx).await?;
+                // remove the forwarded bytes.
+                // Frequently this will be the entire buffer, so no memcpy but just set the size to 0
+                buf.drain(..sent);
+            }
+            Ok(())
+        },
+    )
 }
 
 // This does not want or need RpcMessage to be clone but still want to clone the
@@ -467,10 +608,7 @@ impl<Out: RpcMessage> Sink<Out> for SendSink<Out> {
         // figure out what to send and what to return
         let (send, res) = match self.serialize(item) {
             Ok(data) => (Ok(data), Ok(())),
-            Err(cause) => (
-                Err(io::Error::new(io::ErrorKind::Other, cause.to_string())),
-                Err(cause),
-            ),
+            Err(cause) => (Err(io::Error::other(cause.to_string())), Err(cause)),
         };
         // attempt sending
         Pin::new(&mut self.sink)
@@ -524,6 +662,8 @@ pub enum RecvError {
     DeserializeError(bincode::Error),
     /// Hyper network error.
     NetworkError(hyper::Error),
+    /// Nothing was received within the keepalive timeout, see [`ChannelConfig::keepalive`]
+    KeepaliveTimeout,
 }
 
 impl fmt::Display for RecvError {
@@ -591,9 +731,10 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for HyperConnector<In, Out> {
 
 impl<In: RpcMessage, Out: RpcMessage> Connector for HyperConnector<In, Out> {
     async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
-        let (out_tx, out_rx) = flume::bounded::<io::Result<Bytes>>(32);
+        let stream_buffer = self.inner.config.stream_buffer;
+        let (out_tx, out_rx) = flume::bounded::<io::Result<Bytes>>(stream_buffer);
         let req: Request<Body> = Request::post(&self.inner.uri)
-            .body(Body::wrap_stream(out_rx.into_stream()))
+            .body(self.inner.config.body(out_rx))
             .map_err(OpenError::HyperHttp)?;
         let res = self
             .inner
@@ -601,8 +742,8 @@ impl<In: RpcMessage, Out: RpcMessage> Connector for HyperConnector<In, Out> {
             .request(req)
             .await
             .map_err(OpenError::Hyper)?;
-        let (in_tx, in_rx) = flume::bounded::<result::Result<In, RecvError>>(32);
-        spawn_recv_forwarder(res.into_body(), in_tx);
+        let (in_tx, in_rx) = flume::bounded::<result::Result<In, RecvError>>(stream_buffer);
+        spawn_recv_forwarder(res.into_body(), in_tx, self.inner.config.keepalive_timeout);
 
         let out_tx = self::SendSink::new(out_tx, self.inner.config.clone());
         let in_rx = self::RecvStream::new(in_rx);
@@ -641,3 +782,28 @@ impl<In: RpcMessage, Out: RpcMessage> Listener for HyperListener<In, Out> {
         ))
     }
 }
+
+/// The category of an error of this transport, if it is more specific than the default
+pub(crate) fn error_kind(cause: &(dyn error::Error + 'static)) -> Option<ErrorKind> {
+    if let Some(cause) = cause.downcast_ref::<SendError>() {
+        return match cause {
+            SendError::SerializeError(_) => Some(ErrorKind::Decode),
+            SendError::SizeError(_) => None,
+            SendError::ReceiverDropped => Some(ErrorKind::Shutdown),
+        };
+    }
+    if let Some(cause) = cause.downcast_ref::<RecvError>() {
+        return match cause {
+            RecvError::DeserializeError(_) => Some(ErrorKind::Decode),
+            RecvError::NetworkError(_) => None,
+            RecvError::KeepaliveTimeout => Some(ErrorKind::Shutdown),
+        };
+    }
+    if let Some(OpenError::RemoteDropped) = cause.downcast_ref::<OpenError>() {
+        return Some(ErrorKind::Shutdown);
+    }
+    if let Some(AcceptError::RemoteDropped) = cause.downcast_ref::<AcceptError>() {
+        return Some(ErrorKind::Shutdown);
+    }
+    None
+}
@@ -1,11 +1,14 @@
 //! iroh-net transport implementation based on [iroh-net](https://crates.io/crates/iroh-net)
 
 use crate::{
+    error::{CloseCode, CloseReason, ErrorKind},
+    message::Priority,
     transport::{ConnectionErrors, Connector, Listener, LocalAddr},
     RpcMessage,
 };
 
 use std::{
+    any::type_name,
     collections::BTreeSet,
     fmt,
     future::Future,
@@ -16,6 +19,7 @@ use std::{
     pin::{pin, Pin},
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use flume::TryRecvError;
@@ -25,11 +29,20 @@ use iroh_net::{NodeAddr, NodeId};
 use pin_project::pin_project;
 use quinn::Connection;
 use serde::{de::DeserializeOwned, Serialize};
-use tokio::{sync::oneshot, task::yield_now};
+use tokio::{
+    sync::{oneshot, watch},
+    task::yield_now,
+};
 use tracing::{debug_span, Instrument};
 
 use super::{
-    util::{FramedBincodeRead, FramedBincodeWrite},
+    capabilities::Negotiated,
+    connections::{ConnectionHandle, Connections, StreamGuard},
+    filter::RequestFilter,
+    handshake::{ClientHandshake, HelloSender, ServerHandshake, SharedNegotiated},
+    quota::{self, Account, QuotaTracker},
+    util::{spawn_named, spawn_named_on, FlushConfig, FramedBincodeRead, FramedBincodeWrite},
+    warm_up::{WarmStreams, WarmUp},
     StreamTypes,
 };
 
@@ -40,7 +53,10 @@ struct ListenerInner {
     endpoint: Option<iroh_net::Endpoint>,
     task: Option<tokio::task::JoinHandle<()>>,
     local_addr: Vec<LocalAddr>,
-    receiver: flume::Receiver<SocketInner>,
+    receiver: flume::Receiver<Accepted>,
+    connections: Connections,
+    /// The check for new connections, see [`IrohNetListener::handshake`]
+    handshake: watch::Sender<Option<ServerHandshake>>,
 }
 
 impl Drop for ListenerInner {
@@ -50,15 +66,20 @@ impl Drop for ListenerInner {
             if let Ok(handle) = tokio::runtime::Handle::try_current() {
                 // spawn a task to wait for the endpoint to notify peers that it is closing
                 let span = debug_span!("closing listener");
-                handle.spawn(
+                spawn_named_on(
+                    format_args!("quic-rpc iroh-net closing listener"),
                     async move {
                         // iroh-net endpoint's close is async, and internally it waits the
                         // underlying quinn endpoint to be idle.
-                        if let Err(e) = endpoint.close(0u32.into(), b"Listener dropped").await {
+                        if let Err(e) = endpoint
+                            .close(u32::from(CloseCode::Shutdown).into(), b"Listener dropped")
+                            .await
+                        {
                             tracing::warn!(?e, "error closing listener");
                         }
                     }
                     .instrument(span),
+                    &handle,
                 );
             }
         }
@@ -82,6 +103,10 @@ pub enum AccessControl {
 #[derive(Debug)]
 pub struct IrohNetListener<In: RpcMessage, Out: RpcMessage> {
     inner: Arc<ListenerInner>,
+    flush: FlushConfig,
+    priority: Option<fn(&Out) -> i32>,
+    filter: Option<RequestFilter>,
+    quota: Option<QuotaTracker>,
     _p: PhantomData<(In, Out)>,
 }
 
@@ -89,7 +114,28 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
     /// handles RPC requests from a connection
     ///
     /// to cleanly shut down the handler, drop the receiver side of the sender.
-    async fn connection_handler(connection: quinn::Connection, sender: flume::Sender<SocketInner>) {
+    async fn connection_handler(
+        connection: quinn::Connection,
+        sender: flume::Sender<Accepted>,
+        connections: Connections,
+        handshake: watch::Receiver<Option<ServerHandshake>>,
+    ) {
+        let peer = iroh_net::endpoint::get_remote_node_id(&connection)
+            .map(|node_id| node_id.to_string())
+            .unwrap_or_else(|_| connection.remote_address().to_string());
+        let handshake = handshake.borrow().clone();
+        let handshake = match handshake {
+            Some(handshake) => match handshake.accept(&connection).await {
+                Ok(handshake) => Some(handshake),
+                Err(reason) => {
+                    tracing::debug!(%peer, reason = %reason.reason, "Rejecting connection");
+                    connection.close(u32::from(reason.code).into(), reason.reason.as_bytes());
+                    return;
+                }
+            },
+            None => None,
+        };
+        let guard = connections.register(&connection, peer, handshake);
         loop {
             tracing::debug!("Awaiting incoming bidi substream on existing connection...");
             let bidi_stream = match connection.accept_bi().await {
@@ -104,7 +150,12 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
                 }
             };
             tracing::debug!("Sending substream to be handled... {}", bidi_stream.0.id());
-            if sender.send_async(bidi_stream).await.is_err() {
+            let (send, recv) = bidi_stream;
+            if sender
+                .send_async((send, recv, Some(guard.stream())))
+                .await
+                .is_err()
+            {
                 tracing::debug!("Receiver dropped");
                 break;
             }
@@ -113,8 +164,10 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
 
     async fn endpoint_handler(
         endpoint: iroh_net::Endpoint,
-        sender: flume::Sender<SocketInner>,
+        sender: flume::Sender<Accepted>,
+        connections: Connections,
         allowed_node_ids: BTreeSet<NodeId>,
+        handshake: watch::Receiver<Option<ServerHandshake>>,
     ) {
         loop {
             tracing::debug!("Waiting for incoming connection...");
@@ -146,12 +199,18 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
                         )
                     })
                 else {
-                    connection.close(0u32.into(), b"failed to extract iroh-net node id");
+                    connection.close(
+                        u32::from(CloseCode::ProtocolError).into(),
+                        b"failed to extract iroh-net node id",
+                    );
                     continue;
                 };
 
                 if !allowed_node_ids.contains(&client_node_id) {
-                    connection.close(0u32.into(), b"forbidden node id");
+                    connection.close(
+                        u32::from(CloseCode::Unauthorized).into(),
+                        b"forbidden node id",
+                    );
                     continue;
                 }
             }
@@ -162,7 +221,15 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
             );
 
             tracing::debug!("Spawning connection handler...");
-            tokio::spawn(Self::connection_handler(connection, sender.clone()));
+            spawn_named(
+                format_args!("quic-rpc iroh-net connection {}", type_name::<In>()),
+                Self::connection_handler(
+                    connection,
+                    sender.clone(),
+                    connections.clone(),
+                    handshake.clone(),
+                ),
+            );
         }
     }
 
@@ -195,11 +262,18 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
 
         let (ipv4_socket_addr, maybe_ipv6_socket_addr) = endpoint.bound_sockets();
         let (sender, receiver) = flume::bounded(16);
-        let task = tokio::spawn(Self::endpoint_handler(
-            endpoint.clone(),
-            sender,
-            allowed_node_ids,
-        ));
+        let connections = Connections::default();
+        let (handshake, check) = watch::channel(None);
+        let task = spawn_named(
+            format_args!("quic-rpc iroh-net listener {}", type_name::<In>()),
+            Self::endpoint_handler(
+                endpoint.clone(),
+                sender,
+                connections.clone(),
+                allowed_node_ids,
+                check,
+            ),
+        );
 
         Ok(Self {
             inner: Arc::new(ListenerInner {
@@ -209,11 +283,114 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
                     .chain(maybe_ipv6_socket_addr.map(LocalAddr::Socket))
                     .collect(),
                 receiver,
+                connections,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
+            filter: None,
+            quota: None,
             _p: PhantomData,
         })
     }
 
+    /// Create a new server channel by connecting to a client
+    ///
+    /// This reverses the roles of dialer and listener, for servers that can not accept
+    /// incoming connections. The client accepts the connection on its endpoint and wraps
+    /// it in a [`IrohNetConnector::from_connection`], and the server serves all channels
+    /// the client opens on the connection.
+    ///
+    /// Accepting fails once the connection is closed, so connect again to keep serving.
+    pub async fn connect(
+        endpoint: &iroh_net::Endpoint,
+        node_addr: impl Into<NodeAddr>,
+        alpn: &[u8],
+    ) -> anyhow::Result<Self> {
+        let (ipv4_socket_addr, maybe_ipv6_socket_addr) = endpoint.bound_sockets();
+        let connection = endpoint.connect(node_addr, alpn).await?;
+        let (sender, receiver) = flume::bounded(16);
+        let connections = Connections::default();
+        let (handshake, check) = watch::channel(None);
+        let task = spawn_named(
+            format_args!("quic-rpc iroh-net connection {}", type_name::<In>()),
+            Self::connection_handler(connection, sender, connections.clone(), check),
+        );
+        Ok(Self {
+            inner: Arc::new(ListenerInner {
+                endpoint: None,
+                task: Some(task),
+                local_addr: once(LocalAddr::Socket(ipv4_socket_addr))
+                    .chain(maybe_ipv6_socket_addr.map(LocalAddr::Socket))
+                    .collect(),
+                receiver,
+                connections,
+                handshake,
+            }),
+            flush: FlushConfig::default(),
+            priority: None,
+            filter: None,
+            quota: None,
+            _p: PhantomData,
+        })
+    }
+
+    /// Coalesce frames that are sent within `interval` into a single write
+    ///
+    /// By default every frame is written to the stream as soon as it is sent. With a
+    /// flush interval, sending a frame only schedules a write at most `interval` later,
+    /// trading latency for fewer writes when many small frames are sent in a row.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush.interval = Some(interval);
+        self
+    }
+
+    /// Write immediately once `bytes` are buffered, even within the flush interval
+    ///
+    /// Flushing a sink twice without sending in between, e.g. calling
+    /// [`UpdateSink::flush`](crate::client::UpdateSink::flush) after sending, also writes
+    /// immediately. The default is 16 KiB.
+    pub fn flush_threshold(mut self, bytes: usize) -> Self {
+        self.flush.threshold = bytes;
+        self
+    }
+
+    /// Set the priority of each channel from the first message sent on it
+    ///
+    /// See [`Priority`] for details.
+    pub fn prioritized(mut self) -> Self
+    where
+        Out: Priority,
+    {
+        self.priority = Some(Out::priority);
+        self
+    }
+
+    /// Check the first message of each channel with a filter before deserializing it
+    ///
+    /// See [`filter`](super::filter) for details.
+    pub fn request_filter(mut self, filter: RequestFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Account requests and bytes per peer, rejecting requests above the quota
+    ///
+    /// See the [module docs](super::quota) for details.
+    pub fn quota(mut self, quota: QuotaTracker) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Check the hello of each new connection before accepting any channel on it
+    ///
+    /// This applies to all clones of the listener, and to connections that are
+    /// accepted after it is set. See the [module docs](super::handshake) for details.
+    pub fn handshake(self, handshake: ServerHandshake) -> Self {
+        self.inner.handshake.send_replace(Some(handshake));
+        self
+    }
+
     /// Create a new server channel, given just a source of incoming connections
     ///
     /// This is useful if you want to manage the quinn endpoint yourself,
@@ -223,19 +400,41 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
         local_addr: SocketAddr,
     ) -> Self {
         let (sender, receiver) = flume::bounded(16);
-        let task = tokio::spawn(async move {
-            // just grab all connections and spawn a handler for each one
-            while let Ok(connection) = incoming.recv_async().await {
-                tokio::spawn(Self::connection_handler(connection, sender.clone()));
-            }
-        });
+        let connections = Connections::default();
+        let (handshake, check) = watch::channel(None);
+        let task = spawn_named(
+            format_args!("quic-rpc iroh-net listener {}", type_name::<In>()),
+            {
+                let connections = connections.clone();
+                async move {
+                    // just grab all connections and spawn a handler for each one
+                    while let Ok(connection) = incoming.recv_async().await {
+                        spawn_named(
+                            format_args!("quic-rpc iroh-net connection {}", type_name::<In>()),
+                            Self::connection_handler(
+                                connection,
+                                sender.clone(),
+                                connections.clone(),
+                                check.clone(),
+                            ),
+                        );
+                    }
+                }
+            },
+        );
         Self {
             inner: Arc::new(ListenerInner {
                 endpoint: None,
                 task: Some(task),
                 local_addr: vec![LocalAddr::Socket(local_addr)],
                 receiver,
+                connections,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
+            filter: None,
+            quota: None,
             _p: PhantomData,
         }
     }
@@ -244,17 +443,40 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
     ///
     /// This is useful if you want to manage the quinn endpoint yourself,
     /// use multiple endpoints, or use an endpoint for multiple protocols.
+    ///
+    /// The substreams don't belong to a known connection, so
+    /// [`Listener::connections`] returns an empty list.
     pub fn handle_substreams(
-        receiver: flume::Receiver<SocketInner>,
+        incoming: flume::Receiver<SocketInner>,
         local_addr: SocketAddr,
     ) -> Self {
+        let (sender, receiver) = flume::bounded(16);
+        let connections = Connections::default();
+        // the substreams don't belong to a connection, so there is nothing to check
+        let (handshake, _) = watch::channel(None);
+        let task = spawn_named(
+            format_args!("quic-rpc iroh-net listener {}", type_name::<In>()),
+            async move {
+                while let Ok((send, recv)) = incoming.recv_async().await {
+                    if sender.send_async((send, recv, None)).await.is_err() {
+                        break;
+                    }
+                }
+            },
+        );
         Self {
             inner: Arc::new(ListenerInner {
                 endpoint: None,
-                task: None,
+                task: Some(task),
                 local_addr: vec![LocalAddr::Socket(local_addr)],
                 receiver,
+                connections,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
+            filter: None,
+            quota: None,
             _p: PhantomData,
         }
     }
@@ -264,6 +486,10 @@ impl<In: RpcMessage, Out: RpcMessage> Clone for IrohNetListener<In, Out> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            flush: self.flush,
+            priority: self.priority,
+            filter: self.filter.clone(),
+            quota: self.quota.clone(),
             _p: PhantomData,
         }
     }
@@ -281,27 +507,55 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for IrohNetListener<In, Out> {
     type Out = Out;
     type SendSink = SendSink<Out>;
     type RecvStream = RecvStream<In>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        send.0.poll_stopped(cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        recv.0.connection().cloned()
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Listener for IrohNetListener<In, Out> {
     async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), AcceptError> {
-        let (send, recv) = self
-            .inner
-            .receiver
-            .recv_async()
-            .await
-            .map_err(|_| quinn::ConnectionError::LocallyClosed)?;
-
-        Ok((SendSink::new(send), RecvStream::new(recv)))
+        loop {
+            let (send, recv, stream) = self
+                .inner
+                .receiver
+                .recv_async()
+                .await
+                .map_err(|_| quinn::ConnectionError::LocallyClosed)?;
+            let account = match (&self.quota, &stream) {
+                (Some(tracker), Some(stream)) => match tracker.start_request(stream.peer()) {
+                    Ok(account) => Some(account),
+                    Err(_) => {
+                        tracing::debug!("Rejecting channel of {}, quota exceeded", stream.peer());
+                        quota::reject(send, recv);
+                        continue;
+                    }
+                },
+                _ => None,
+            };
+            return Ok((
+                SendSink::new(send, self.flush, account.clone(), self.priority),
+                RecvStream::new(recv, self.filter.clone(), stream, account),
+            ));
+        }
     }
 
     fn local_addr(&self) -> &[LocalAddr] {
         &self.inner.local_addr
     }
+
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        self.inner.connections.list()
+    }
 }
 
 type SocketInner = (quinn::SendStream, quinn::RecvStream);
 
+/// An accepted substream, counted as open on its connection if it has one
+type Accepted = (quinn::SendStream, quinn::RecvStream, Option<StreamGuard>);
+
 #[derive(Debug)]
 struct ClientConnectionInner {
     /// The quinn endpoint, we just keep a clone of this for information
@@ -310,6 +564,12 @@ struct ClientConnectionInner {
     task: Option<tokio::task::JoinHandle<()>>,
     /// The channel to send new received connections
     requests_tx: flume::Sender<oneshot::Sender<anyhow::Result<SocketInner>>>,
+    /// What to do after connecting, see [`IrohNetConnector::warm_up`]
+    warm_up: watch::Sender<WarmUp>,
+    /// The capabilities negotiated on the current connection, if any
+    negotiated: SharedNegotiated,
+    /// The hello sent on each connection, see [`IrohNetConnector::handshake`]
+    handshake: watch::Sender<Option<ClientHandshake>>,
 }
 
 impl Drop for ClientConnectionInner {
@@ -319,7 +579,8 @@ impl Drop for ClientConnectionInner {
             if let Ok(handle) = tokio::runtime::Handle::try_current() {
                 // spawn a task to wait for the endpoint to notify peers that it is closing
                 let span = debug_span!("closing client endpoint");
-                handle.spawn(
+                spawn_named_on(
+                    format_args!("quic-rpc iroh-net closing client endpoint"),
                     async move {
                         // iroh-net endpoint's close is async, and internally it waits the
                         // underlying quinn endpoint to be idle.
@@ -331,6 +592,7 @@ impl Drop for ClientConnectionInner {
                         }
                     }
                     .instrument(span),
+                    &handle,
                 );
             }
         }
@@ -346,6 +608,8 @@ impl Drop for ClientConnectionInner {
 /// A connection using an iroh-net connection
 pub struct IrohNetConnector<In: RpcMessage, Out: RpcMessage> {
     inner: Arc<ClientConnectionInner>,
+    flush: FlushConfig,
+    priority: Option<fn(&Out) -> i32>,
     _p: PhantomData<(In, Out)>,
 }
 
@@ -353,15 +617,36 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetConnector<In, Out> {
     async fn single_connection_handler(
         connection: quinn::Connection,
         requests_rx: flume::Receiver<oneshot::Sender<anyhow::Result<SocketInner>>>,
+        mut warm: WarmStreams,
+        mut hello: HelloSender,
     ) {
+        hello.connected(&connection);
+        warm.prime(&connection);
         loop {
             tracing::debug!("Awaiting request for new bidi substream...");
-            let Ok(request_tx) = requests_rx.recv_async().await else {
+            let request = tokio::select! {
+                request = requests_rx.recv_async() => request,
+                _ = warm.changed() => {
+                    warm.prime(&connection);
+                    continue;
+                }
+                _ = hello.changed() => {
+                    hello.send(&connection);
+                    continue;
+                }
+            };
+            let Ok(request_tx) = request else {
                 tracing::info!("Single connection handler finished");
                 return;
             };
 
             tracing::debug!("Got request for new bidi substream");
+            if let Some(pair) = warm.take(&connection) {
+                if request_tx.send(Ok(pair)).is_err() {
+                    tracing::debug!("requester dropped");
+                }
+                continue;
+            }
             match connection.open_bi().await {
                 Ok(pair) => {
                     tracing::debug!("Bidi substream opened");
@@ -395,6 +680,8 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetConnector<In, Out> {
         node_addr: NodeAddr,
         alpn: Vec<u8>,
         requests_rx: flume::Receiver<oneshot::Sender<anyhow::Result<SocketInner>>>,
+        mut warm: WarmStreams,
+        mut hello: HelloSender,
     ) {
         let mut reconnect = pin!(ReconnectHandler {
             endpoint,
@@ -427,6 +714,8 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetConnector<In, Out> {
                 tracing::trace!("tick: connection result");
                 match reconnect.as_mut().await {
                     Ok(new_connection) => {
+                        hello.connected(&new_connection);
+                        warm.prime(&new_connection);
                         connection = Some(new_connection);
                     }
                     Err(e) => {
@@ -445,7 +734,22 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetConnector<In, Out> {
                 }
                 // If we didn't have a ready request in the channel, we wait for one
             } else if pending_request.is_none() {
-                let Ok(req) = requests_rx.recv_async().await else {
+                let req = tokio::select! {
+                    req = requests_rx.recv_async() => req,
+                    _ = warm.changed() => {
+                        if let Some(connection) = connection.as_ref() {
+                            warm.prime(connection);
+                        }
+                        continue;
+                    }
+                    _ = hello.changed() => {
+                        if let Some(connection) = connection.as_ref() {
+                            hello.send(connection);
+                        }
+                        continue;
+                    }
+                };
+                let Ok(req) = req else {
                     tracing::debug!("client dropped");
                     if let Some(connection) = connection {
                         connection.close(0u32.into(), b"requester dropped");
@@ -460,6 +764,12 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetConnector<In, Out> {
             // If we have a connection and a pending request, we good, just process it
             if let Some(connection) = connection.as_mut() {
                 if let Some(request) = pending_request.take() {
+                    if let Some(pair) = warm.take(connection) {
+                        if request.send(Ok(pair)).is_err() {
+                            tracing::debug!("requester dropped");
+                        }
+                        continue;
+                    }
                     match connection.open_bi().await {
                         Ok(pair) => {
                             tracing::debug!("Bidi substream opened");
@@ -487,21 +797,89 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetConnector<In, Out> {
         addr: NodeAddr,
         alpn: Vec<u8>,
         requests_rx: flume::Receiver<oneshot::Sender<anyhow::Result<SocketInner>>>,
+        warm: WarmStreams,
+        hello: HelloSender,
     ) {
-        Self::reconnect_handler_inner(endpoint, addr, alpn, requests_rx).await;
+        Self::reconnect_handler_inner(endpoint, addr, alpn, requests_rx, warm, hello).await;
         tracing::info!("Reconnect handler finished");
     }
 
+    /// Coalesce frames that are sent within `interval` into a single write
+    ///
+    /// By default every frame is written to the stream as soon as it is sent. With a
+    /// flush interval, sending a frame only schedules a write at most `interval` later,
+    /// trading latency for fewer writes when many small frames are sent in a row.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush.interval = Some(interval);
+        self
+    }
+
+    /// Write immediately once `bytes` are buffered, even within the flush interval
+    ///
+    /// Flushing a sink twice without sending in between, e.g. calling
+    /// [`UpdateSink::flush`](crate::client::UpdateSink::flush) after sending, also writes
+    /// immediately. The default is 16 KiB.
+    pub fn flush_threshold(mut self, bytes: usize) -> Self {
+        self.flush.threshold = bytes;
+        self
+    }
+
+    /// Set the priority of each channel from the first message sent on it
+    ///
+    /// See [`Priority`] for details.
+    pub fn prioritized(mut self) -> Self
+    where
+        Out: Priority,
+    {
+        self.priority = Some(Out::priority);
+        self
+    }
+
+    /// Warm up connections right after they are established
+    ///
+    /// This applies to the current connection, if there is one, and to every
+    /// connection after a reconnect. See [`WarmUp`] for details.
+    pub fn warm_up(self, warm_up: WarmUp) -> Self {
+        self.inner.warm_up.send_replace(warm_up);
+        self
+    }
+
+    /// Send a hello on each new connection, before any channel is opened on it
+    ///
+    /// This applies to the current connection, if the hello was not sent on it yet,
+    /// and to every connection after a reconnect. See the
+    /// [module docs](super::handshake) for details.
+    pub fn handshake(self, handshake: ClientHandshake) -> Self {
+        self.inner.handshake.send_replace(Some(handshake));
+        self
+    }
+
     /// Create a new channel
     pub fn from_connection(connection: quinn::Connection) -> Self {
         let (requests_tx, requests_rx) = flume::bounded(16);
-        let task = tokio::spawn(Self::single_connection_handler(connection, requests_rx));
+        let (warm_up, config) = watch::channel(WarmUp::default());
+        let (handshake, hello) = watch::channel(None);
+        let negotiated = SharedNegotiated::default();
+        let task = spawn_named(
+            format_args!("quic-rpc iroh-net connector {}", type_name::<Out>()),
+            Self::single_connection_handler(
+                connection,
+                requests_rx,
+                WarmStreams::new(config),
+                HelloSender::new(hello, negotiated.clone()),
+            ),
+        );
         Self {
             inner: Arc::new(ClientConnectionInner {
                 endpoint: None,
                 task: Some(task),
                 requests_tx,
+                warm_up,
+                negotiated,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
             _p: PhantomData,
         }
     }
@@ -513,18 +891,31 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetConnector<In, Out> {
         alpn: Vec<u8>,
     ) -> Self {
         let (requests_tx, requests_rx) = flume::bounded(16);
-        let task = tokio::spawn(Self::reconnect_handler(
-            endpoint.clone(),
-            node_addr.into(),
-            alpn,
-            requests_rx,
-        ));
+        let (warm_up, config) = watch::channel(WarmUp::default());
+        let (handshake, hello) = watch::channel(None);
+        let negotiated = SharedNegotiated::default();
+        let task = spawn_named(
+            format_args!("quic-rpc iroh-net connector {}", type_name::<Out>()),
+            Self::reconnect_handler(
+                endpoint.clone(),
+                node_addr.into(),
+                alpn,
+                requests_rx,
+                WarmStreams::new(config),
+                HelloSender::new(hello, negotiated.clone()),
+            ),
+        );
         Self {
             inner: Arc::new(ClientConnectionInner {
                 endpoint: Some(endpoint),
                 task: Some(task),
                 requests_tx,
+                warm_up,
+                negotiated,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
             _p: PhantomData,
         }
     }
@@ -622,6 +1013,8 @@ impl<In: RpcMessage, Out: RpcMessage> Clone for IrohNetConnector<In, Out> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            flush: self.flush,
+            priority: self.priority,
             _p: PhantomData,
         }
     }
@@ -639,6 +1032,9 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for IrohNetConnector<In, Out>
     type Out = Out;
     type SendSink = SendSink<Out>;
     type RecvStream = RecvStream<In>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        send.0.poll_stopped(cx)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Connector for IrohNetConnector<In, Out> {
@@ -655,7 +1051,14 @@ impl<In: RpcMessage, Out: RpcMessage> Connector for IrohNetConnector<In, Out> {
             .await
             .map_err(|_| quinn::ConnectionError::LocallyClosed)??;
 
-        Ok((SendSink::new(send), RecvStream::new(recv)))
+        Ok((
+            SendSink::new(send, self.flush, None, self.priority),
+            RecvStream::new(recv, None, None, None),
+        ))
+    }
+
+    fn capabilities(&self) -> Option<Negotiated> {
+        self.inner.negotiated.lock().unwrap().clone()
     }
 }
 
@@ -664,7 +1067,11 @@ impl<In: RpcMessage, Out: RpcMessage> Connector for IrohNetConnector<In, Out> {
 /// If you want to send bytes directly, use [SendSink::into_inner] to get the
 /// underlying [quinn::SendStream].
 #[pin_project]
-pub struct SendSink<Out>(#[pin] FramedBincodeWrite<quinn::SendStream, Out>);
+pub struct SendSink<Out>(
+    #[pin] pub(crate) FramedBincodeWrite<quinn::SendStream, Out>,
+    /// Sets the priority of the stream from the first message, then taken
+    Option<fn(&Out) -> i32>,
+);
 
 impl<Out> fmt::Debug for SendSink<Out> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -673,9 +1080,16 @@ impl<Out> fmt::Debug for SendSink<Out> {
 }
 
 impl<Out: Serialize> SendSink<Out> {
-    fn new(inner: quinn::SendStream) -> Self {
-        let inner = FramedBincodeWrite::new(inner, MAX_FRAME_LENGTH);
-        Self(inner)
+    fn new(
+        inner: quinn::SendStream,
+        flush: FlushConfig,
+        account: Option<Account>,
+        priority: Option<fn(&Out) -> i32>,
+    ) -> Self {
+        let inner = FramedBincodeWrite::new(inner, MAX_FRAME_LENGTH)
+            .with_flush(flush)
+            .with_account(account);
+        Self(inner, priority)
     }
 }
 
@@ -687,23 +1101,35 @@ impl<Out> SendSink<Out> {
     }
 }
 
-impl<Out: Serialize> Sink<Out> for SendSink<Out> {
+impl<Out: RpcMessage> Sink<Out> for SendSink<Out> {
     type Error = io::Error;
 
     fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_ready(cx)
+        Pin::new(&mut self.project().0)
+            .poll_ready(cx)
+            .map_err(quota::map_error)
     }
 
     fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
-        Pin::new(&mut self.project().0).start_send(item)
+        let this = self.project();
+        if let Some(priority) = this.1.take() {
+            let priority = priority(&item);
+            this.0
+                .with_inner(|stream| stream.set_priority(priority).ok());
+        }
+        this.0.start_send(item).map_err(quota::map_error)
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_flush(cx)
+        Pin::new(&mut self.project().0)
+            .poll_flush(cx)
+            .map_err(quota::map_error)
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_close(cx)
+        Pin::new(&mut self.project().0)
+            .poll_close(cx)
+            .map_err(quota::map_error)
     }
 }
 
@@ -712,7 +1138,7 @@ impl<Out: Serialize> Sink<Out> for SendSink<Out> {
 /// If you want to receive bytes directly, use [RecvStream::into_inner] to get
 /// the underlying [quinn::RecvStream].
 #[pin_project]
-pub struct RecvStream<In>(#[pin] FramedBincodeRead<quinn::RecvStream, In>);
+pub struct RecvStream<In>(#[pin] pub(crate) FramedBincodeRead<quinn::RecvStream, In>);
 
 impl<In> fmt::Debug for RecvStream<In> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -721,8 +1147,16 @@ impl<In> fmt::Debug for RecvStream<In> {
 }
 
 impl<In: DeserializeOwned> RecvStream<In> {
-    fn new(inner: quinn::RecvStream) -> Self {
-        let inner = FramedBincodeRead::new(inner, MAX_FRAME_LENGTH);
+    fn new(
+        inner: quinn::RecvStream,
+        filter: Option<RequestFilter>,
+        stream: Option<StreamGuard>,
+        account: Option<Account>,
+    ) -> Self {
+        let inner = FramedBincodeRead::new(inner, MAX_FRAME_LENGTH)
+            .with_filter(filter)
+            .with_stream_guard(stream)
+            .with_account(account);
         Self(inner)
     }
 }
@@ -735,11 +1169,13 @@ impl<In> RecvStream<In> {
     }
 }
 
-impl<In: DeserializeOwned> Stream for RecvStream<In> {
+impl<In: RpcMessage> Stream for RecvStream<In> {
     type Item = Result<In, io::Error>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.project().0).poll_next(cx)
+        Pin::new(&mut self.project().0)
+            .poll_next(cx)
+            .map_err(quota::map_error)
     }
 }
 
@@ -748,3 +1184,23 @@ pub type OpenBiError = anyhow::Error;
 
 /// Error for accept. Currently just a quinn::ConnectionError
 pub type AcceptError = quinn::ConnectionError;
+
+/// The reason the peer closed the connection, if this error is about it
+pub(crate) fn close_reason(cause: &(dyn std::error::Error + 'static)) -> Option<CloseReason> {
+    match cause.downcast_ref::<quinn::ConnectionError>()? {
+        quinn::ConnectionError::ApplicationClosed(close) => Some(CloseReason {
+            code: u32::try_from(close.error_code.into_inner()).ok()?.into(),
+            reason: String::from_utf8_lossy(&close.reason).into_owned(),
+        }),
+        _ => None,
+    }
+}
+
+/// The category of an error of this transport, if it is more specific than the default
+pub(crate) fn error_kind(cause: &(dyn std::error::Error + 'static)) -> Option<ErrorKind> {
+    match cause.downcast_ref::<quinn::ConnectionError>()? {
+        quinn::ConnectionError::LocallyClosed => Some(ErrorKind::Shutdown),
+        quinn::ConnectionError::TimedOut => Some(ErrorKind::Deadline),
+        _ => None,
+    }
+}
@@ -0,0 +1,887 @@
+//! HTTP long-polling transport, for networks that only allow plain requests
+//!
+//! Some networks, e.g. behind corporate proxies, only let plain HTTP/1.1 requests
+//! and responses through, without HTTP/2 or long lived streaming bodies. This
+//! transport emulates channels with repeated POST requests to a single URI:
+//!
+//! - opening a channel is a request that returns the id of the new channel
+//! - messages to the server are sent in batches, one request per batch
+//! - messages from the server are polled: the server holds each poll request until
+//!   it has messages for the channel, or until [`LongPollConfig::poll_timeout`]
+//!
+//! Poll requests that fail, e.g. because a proxy cut them off, are retried without
+//! losing messages. Send requests are not retried, if one fails the channel fails.
+//!
+//! This takes at least two round trips per request, and a poll request for every
+//! batch of responses, so it is much slower than the other transports. Use it as a
+//! last resort, services work over it without changes.
+//!
+//! [`LongPollConnector::with_connector`] takes any hyper connector, e.g. one that
+//! speaks TLS.
+use std::{
+    any::type_name,
+    collections::BTreeMap,
+    convert::Infallible,
+    error, fmt,
+    hash::{BuildHasher, RandomState},
+    marker::PhantomData,
+    net::SocketAddr,
+    pin::Pin,
+    result,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures_lite::Stream;
+use futures_sink::Sink;
+use hyper::{
+    client::{connect::Connect, HttpConnector, ResponseFuture},
+    header::HeaderValue,
+    service::{make_service_fn, service_fn},
+    Body, Client, HeaderMap, Method, Request, Response, Server, StatusCode, Uri,
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    error::ErrorKind,
+    transport::{util::spawn_named, ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes},
+    RpcMessage,
+};
+
+/// Header with the operation of a request: open, send, poll or close
+const OP_HEADER: &str = "x-quic-rpc-op";
+/// Header with the id of the channel a request is about
+const CHANNEL_HEADER: &str = "x-quic-rpc-channel";
+/// Header with the sequence number of a poll request
+const SEQ_HEADER: &str = "x-quic-rpc-seq";
+/// Header that marks the last batch of messages in either direction
+const END_HEADER: &str = "x-quic-rpc-end";
+
+/// Number of times a failed poll request is retried
+const POLL_RETRIES: usize = 3;
+/// Time to wait before retrying a failed poll request
+const POLL_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Configuration of the long-polling transport
+///
+/// These settings apply to both client and server channels.
+#[derive(Debug, Clone)]
+pub struct LongPollConfig {
+    poll_timeout: Duration,
+    channel_timeout: Duration,
+    max_batch_size: usize,
+    accept_buffer: usize,
+    stream_buffer: usize,
+}
+
+impl LongPollConfig {
+    /// Set how long the server holds a poll request when it has nothing to send
+    ///
+    /// Keep this below the timeouts of proxies between client and server. The
+    /// default is 20 seconds.
+    pub fn poll_timeout(mut self, value: Duration) -> Self {
+        self.poll_timeout = value;
+        self
+    }
+
+    /// Set how long the server keeps a channel that is not polled
+    ///
+    /// This must be longer than the poll timeout. The default is 60 seconds.
+    ///
+    /// This only applies to server channels.
+    pub fn channel_timeout(mut self, value: Duration) -> Self {
+        self.channel_timeout = value;
+        self
+    }
+
+    /// Set the maximum size of a batch of messages sent to the server, in bytes
+    ///
+    /// Sending a larger message fails. The default is 1 MiB.
+    pub fn max_batch_size(mut self, value: usize) -> Self {
+        self.max_batch_size = value;
+        self
+    }
+
+    /// Set the number of channels that are queued until the server accepts them.
+    ///
+    /// This only applies to server channels.
+    pub fn accept_buffer(mut self, value: usize) -> Self {
+        self.accept_buffer = value;
+        self
+    }
+
+    /// Set the number of messages that are buffered in each direction of a channel.
+    pub fn stream_buffer(mut self, value: usize) -> Self {
+        self.stream_buffer = value;
+        self
+    }
+}
+
+impl Default for LongPollConfig {
+    fn default() -> Self {
+        Self {
+            poll_timeout: Duration::from_secs(20),
+            channel_timeout: Duration::from_secs(60),
+            max_batch_size: 1024 * 1024,
+            accept_buffer: 32,
+            stream_buffer: 32,
+        }
+    }
+}
+
+/// Serialize a message as a length prefixed frame
+fn to_frame<T: RpcMessage>(item: &T, max_size: usize) -> result::Result<Bytes, SendError> {
+    let mut data = vec![0u8; 4];
+    bincode::serialize_into(&mut data, item).map_err(SendError::SerializeError)?;
+    if data.len() > max_size {
+        return Err(SendError::SizeError(data.len()));
+    }
+    let len = u32::try_from(data.len() - 4).map_err(|_| SendError::SizeError(data.len()))?;
+    data[0..4].copy_from_slice(&len.to_be_bytes());
+    Ok(data.into())
+}
+
+/// Deserialize the length prefixed frames of a batch
+fn from_frames<T: RpcMessage>(mut batch: &[u8]) -> Vec<result::Result<T, RecvError>> {
+    let mut items = Vec::new();
+    while !batch.is_empty() {
+        let Some(len) = batch.get(..4) else {
+            items.push(Err(RecvError::InvalidBatch));
+            break;
+        };
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        let Some(frame) = batch.get(4..4 + len) else {
+            items.push(Err(RecvError::InvalidBatch));
+            break;
+        };
+        items.push(bincode::deserialize(frame).map_err(RecvError::DeserializeError));
+        batch = &batch[4 + len..];
+    }
+    items
+}
+
+/// Read a request body, or `None` if it is larger than `limit`
+async fn read_body(mut body: Body, limit: usize) -> hyper::Result<Option<Vec<u8>>> {
+    use hyper::body::HttpBody;
+
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if data.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Some(data))
+}
+
+fn header<T: std::str::FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// Trait so we don't have to drag around the hyper internals
+trait Requester: Send + Sync + 'static {
+    fn request(&self, req: Request<Body>) -> ResponseFuture;
+}
+
+impl<C: Connect + Clone + Send + Sync + 'static> Requester for Client<C, Body> {
+    fn request(&self, req: Request<Body>) -> ResponseFuture {
+        self.request(req)
+    }
+}
+
+struct ConnectorInner {
+    client: Box<dyn Requester>,
+    config: Arc<LongPollConfig>,
+    uri: Uri,
+}
+
+impl ConnectorInner {
+    /// Make a request for an operation, returning the response if it succeeded
+    async fn request(
+        &self,
+        op: &'static str,
+        channel: Option<u64>,
+        seq: Option<u64>,
+        end: bool,
+        body: Vec<u8>,
+    ) -> result::Result<Response<Body>, RecvError> {
+        let mut req = Request::post(&self.uri).header(OP_HEADER, op);
+        if let Some(channel) = channel {
+            req = req.header(CHANNEL_HEADER, channel);
+        }
+        if let Some(seq) = seq {
+            req = req.header(SEQ_HEADER, seq);
+        }
+        if end {
+            req = req.header(END_HEADER, "1");
+        }
+        let req = req.body(Body::from(body)).map_err(RecvError::Http)?;
+        let res = self.client.request(req).await.map_err(RecvError::Network)?;
+        if !res.status().is_success() {
+            return Err(RecvError::Status(res.status()));
+        }
+        Ok(res)
+    }
+
+    /// Send batches of frames until the client drops its send side
+    ///
+    /// Errors are reported on the receive side, which is not kept open for that.
+    async fn send_loop<In: RpcMessage>(
+        self: Arc<Self>,
+        channel: u64,
+        frames: flume::Receiver<Bytes>,
+        errors: flume::WeakSender<result::Result<In, RecvError>>,
+    ) {
+        let mut next = None;
+        loop {
+            let first = match next.take() {
+                Some(frame) => frame,
+                None => match frames.recv_async().await {
+                    Ok(frame) => frame,
+                    Err(_) => Bytes::new(),
+                },
+            };
+            let mut batch = first.to_vec();
+            let mut end = first.is_empty();
+            while !end {
+                match frames.try_recv() {
+                    Ok(frame) if batch.len() + frame.len() <= self.config.max_batch_size => {
+                        batch.extend_from_slice(&frame)
+                    }
+                    Ok(frame) => {
+                        next = Some(frame);
+                        break;
+                    }
+                    Err(flume::TryRecvError::Empty) => break,
+                    Err(flume::TryRecvError::Disconnected) => end = true,
+                }
+            }
+            if let Err(cause) = self.request("send", Some(channel), None, end, batch).await {
+                tracing::debug!("sending to long-polling channel {channel} failed: {cause}");
+                if let Some(errors) = errors.upgrade() {
+                    errors.send_async(Err(cause)).await.ok();
+                }
+                return;
+            }
+            if end {
+                return;
+            }
+        }
+    }
+
+    /// Poll batches of frames until the server is done or the client drops its receive side
+    async fn poll_loop<In: RpcMessage>(
+        self: Arc<Self>,
+        channel: u64,
+        items: flume::Sender<result::Result<In, RecvError>>,
+    ) {
+        let mut seq = 0;
+        let mut retries = 0;
+        while !items.is_disconnected() {
+            let res = self.request("poll", Some(channel), Some(seq), false, Vec::new());
+            let res = match res.await {
+                Ok(res) => res,
+                Err(cause) if cause.is_retryable() && retries < POLL_RETRIES => {
+                    tracing::debug!("polling long-polling channel {channel} failed: {cause}");
+                    retries += 1;
+                    tokio::time::sleep(POLL_RETRY_DELAY).await;
+                    continue;
+                }
+                Err(cause) => {
+                    items.send_async(Err(cause)).await.ok();
+                    return;
+                }
+            };
+            let end = res.headers().contains_key(END_HEADER);
+            let batch = match hyper::body::to_bytes(res.into_body()).await {
+                Ok(batch) => batch,
+                Err(cause) if retries < POLL_RETRIES => {
+                    tracing::debug!("polling long-polling channel {channel} failed: {cause}");
+                    retries += 1;
+                    continue;
+                }
+                Err(cause) => {
+                    items.send_async(Err(RecvError::Network(cause))).await.ok();
+                    return;
+                }
+            };
+            retries = 0;
+            seq += 1;
+            for item in from_frames(&batch) {
+                if items.send_async(item).await.is_err() {
+                    break;
+                }
+            }
+            if end {
+                return;
+            }
+        }
+        // the client dropped its receive side, so the server can drop the channel
+        self.request("close", Some(channel), None, false, Vec::new())
+            .await
+            .ok();
+    }
+}
+
+/// Long-polling connection to a server
+pub struct LongPollConnector<In: RpcMessage, Out: RpcMessage> {
+    inner: Arc<ConnectorInner>,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Clone for LongPollConnector<In, Out> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> fmt::Debug for LongPollConnector<In, Out> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LongPollConnector")
+            .field("uri", &self.inner.uri)
+            .field("config", &self.inner.config)
+            .finish()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> LongPollConnector<In, Out> {
+    /// create a client given an uri and the default configuration
+    pub fn new(uri: Uri) -> Self {
+        Self::with_config(uri, LongPollConfig::default())
+    }
+
+    /// create a client given an uri and a custom configuration
+    pub fn with_config(uri: Uri, config: LongPollConfig) -> Self {
+        let mut connector = HttpConnector::new();
+        connector.set_nodelay(true);
+        Self::with_connector(connector, uri, config)
+    }
+
+    /// create a client given a hyper connector, an uri and a custom configuration
+    pub fn with_connector<C: Connect + Clone + Send + Sync + 'static>(
+        connector: C,
+        uri: Uri,
+        config: LongPollConfig,
+    ) -> Self {
+        let client = Client::builder().build(connector);
+        Self {
+            inner: Arc::new(ConnectorInner {
+                client: Box::new(client),
+                config: Arc::new(config),
+                uri,
+            }),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for LongPollConnector<In, Out> {
+    type SendError = self::SendError;
+    type RecvError = self::RecvError;
+    type OpenError = self::RecvError;
+    type AcceptError = self::AcceptError;
+}
+
+impl<In: RpcMessage, Out: RpcMessage> StreamTypes for LongPollConnector<In, Out> {
+    type In = In;
+    type Out = Out;
+    type RecvStream = self::RecvStream<In>;
+    type SendSink = self::SendSink<Out>;
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Connector for LongPollConnector<In, Out> {
+    async fn open(&self) -> result::Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let res = self.inner.request("open", None, None, false, Vec::new());
+        let res = res.await?;
+        let channel =
+            header::<u64>(res.headers(), CHANNEL_HEADER).ok_or(RecvError::InvalidBatch)?;
+        let stream_buffer = self.inner.config.stream_buffer;
+        let (out_tx, out_rx) = flume::bounded(stream_buffer);
+        let (in_tx, in_rx) = flume::bounded(stream_buffer);
+        spawn_named(
+            format_args!("quic-rpc longpoll send {}", type_name::<Out>()),
+            self.inner
+                .clone()
+                .send_loop::<In>(channel, out_rx, in_tx.downgrade()),
+        );
+        spawn_named(
+            format_args!("quic-rpc longpoll poll {}", type_name::<In>()),
+            self.inner.clone().poll_loop(channel, in_tx),
+        );
+        Ok((
+            SendSink::new(out_tx, self.inner.config.clone()),
+            RecvStream::new(in_rx),
+        ))
+    }
+}
+
+/// The next batch of responses of a channel, and the last one in case it is polled again
+struct Responses {
+    frames: flume::Receiver<Bytes>,
+    last: Option<Batch>,
+}
+
+#[derive(Clone)]
+struct Batch {
+    seq: u64,
+    frames: Bytes,
+    end: bool,
+}
+
+impl Responses {
+    /// Wait for frames until the poll timeout, and take what fits into a batch
+    async fn next(&mut self, seq: u64, config: &LongPollConfig) -> Batch {
+        let mut frames = Vec::new();
+        let mut end = false;
+        match tokio::time::timeout(config.poll_timeout, self.frames.recv_async()).await {
+            Ok(Ok(frame)) => frames.extend_from_slice(&frame),
+            Ok(Err(_)) => end = true,
+            Err(_) => {}
+        }
+        // no awaits from here on, so the frames are not lost if the request is dropped
+        while !end && !frames.is_empty() && frames.len() < config.max_batch_size {
+            match self.frames.try_recv() {
+                Ok(frame) => frames.extend_from_slice(&frame),
+                Err(flume::TryRecvError::Empty) => break,
+                Err(flume::TryRecvError::Disconnected) => end = true,
+            }
+        }
+        let batch = Batch {
+            seq,
+            frames: frames.into(),
+            end,
+        };
+        self.last = Some(batch.clone());
+        batch
+    }
+}
+
+struct ServerChannel<In> {
+    requests: Option<flume::Sender<result::Result<In, RecvError>>>,
+    responses: Arc<tokio::sync::Mutex<Responses>>,
+    last_seen: Instant,
+}
+
+type Accepted<In> = (
+    flume::Sender<Bytes>,
+    flume::Receiver<result::Result<In, RecvError>>,
+);
+
+struct ServerState<In> {
+    channels: Mutex<BTreeMap<u64, ServerChannel<In>>>,
+    accept: flume::Sender<Accepted<In>>,
+    config: Arc<LongPollConfig>,
+    /// Hashes a counter into channel ids that can't be guessed by other clients
+    ids: RandomState,
+    next_id: AtomicU64,
+}
+
+impl<In: RpcMessage> ServerState<In> {
+    async fn handle(self: Arc<Self>, req: Request<Body>) -> Response<Body> {
+        if req.method() != Method::POST {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        let headers = req.headers();
+        let channel = header::<u64>(headers, CHANNEL_HEADER);
+        let seq = header::<u64>(headers, SEQ_HEADER);
+        let end = headers.contains_key(END_HEADER);
+        let op = headers.get(OP_HEADER).and_then(|op| op.to_str().ok());
+        match (op, channel, seq) {
+            (Some("open"), _, _) => self.open().await,
+            (Some("send"), Some(channel), _) => self.send(channel, end, req.into_body()).await,
+            (Some("poll"), Some(channel), Some(seq)) => self.poll(channel, seq).await,
+            (Some("close"), Some(channel), _) => {
+                self.channels.lock().unwrap().remove(&channel);
+                status(StatusCode::OK)
+            }
+            _ => status(StatusCode::BAD_REQUEST),
+        }
+    }
+
+    async fn open(&self) -> Response<Body> {
+        let id = self
+            .ids
+            .hash_one(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let (req_tx, req_rx) = flume::bounded(self.config.stream_buffer);
+        let (res_tx, res_rx) = flume::bounded(self.config.stream_buffer);
+        let channel = ServerChannel {
+            requests: Some(req_tx),
+            responses: Arc::new(tokio::sync::Mutex::new(Responses {
+                frames: res_rx,
+                last: None,
+            })),
+            last_seen: Instant::now(),
+        };
+        self.channels.lock().unwrap().insert(id, channel);
+        if self.accept.send_async((res_tx, req_rx)).await.is_err() {
+            self.channels.lock().unwrap().remove(&id);
+            return status(StatusCode::SERVICE_UNAVAILABLE);
+        }
+        let mut res = status(StatusCode::OK);
+        res.headers_mut()
+            .insert(CHANNEL_HEADER, HeaderValue::from(id));
+        res
+    }
+
+    async fn send(&self, channel: u64, end: bool, body: Body) -> Response<Body> {
+        let requests = {
+            let mut channels = self.channels.lock().unwrap();
+            let Some(entry) = channels.get_mut(&channel) else {
+                return status(StatusCode::NOT_FOUND);
+            };
+            entry.last_seen = Instant::now();
+            if end {
+                entry.requests.take()
+            } else {
+                entry.requests.clone()
+            }
+        };
+        let batch = match read_body(body, self.config.max_batch_size).await {
+            Ok(Some(batch)) => batch,
+            Ok(None) => return status(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(cause) => {
+                tracing::debug!("reading long-polling batch failed: {cause}");
+                return status(StatusCode::BAD_REQUEST);
+            }
+        };
+        if let Some(requests) = requests {
+            for item in from_frames(&batch) {
+                if requests.send_async(item).await.is_err() {
+                    // the server is done receiving, this is not an error
+                    break;
+                }
+            }
+        }
+        status(StatusCode::OK)
+    }
+
+    async fn poll(&self, channel: u64, seq: u64) -> Response<Body> {
+        let responses = {
+            let mut channels = self.channels.lock().unwrap();
+            let Some(entry) = channels.get_mut(&channel) else {
+                return status(StatusCode::NOT_FOUND);
+            };
+            entry.last_seen = Instant::now();
+            entry.responses.clone()
+        };
+        let batch = {
+            let mut responses = responses.lock().await;
+            match responses.last.clone() {
+                // the client did not get the last batch, send it again
+                Some(last) if last.seq == seq => last,
+                _ => responses.next(seq, &self.config).await,
+            }
+        };
+        let mut res = Response::new(Body::from(batch.frames));
+        if batch.end {
+            res.headers_mut()
+                .insert(END_HEADER, HeaderValue::from_static("1"));
+        }
+        res
+    }
+
+    /// Drop channels that were not used for the channel timeout
+    async fn expire(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(self.config.channel_timeout / 2);
+        loop {
+            ticker.tick().await;
+            let timeout = self.config.channel_timeout;
+            self.channels
+                .lock()
+                .unwrap()
+                .retain(|_, channel| channel.last_seen.elapsed() < timeout);
+        }
+    }
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = status;
+    res
+}
+
+/// A listener for long-polling clients using a hyper server
+///
+/// Creating this spawns a tokio task which runs the server, once dropped this task is shut
+/// down: no new channels will be accepted and existing channels will stop.
+#[derive(Debug)]
+pub struct LongPollListener<In: RpcMessage, Out: RpcMessage> {
+    channel: flume::Receiver<Accepted<In>>,
+    config: Arc<LongPollConfig>,
+    /// Dropping the last clone of this shuts down the server
+    stop_tx: mpsc::Sender<()>,
+    local_addr: [LocalAddr; 1],
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Clone for LongPollListener<In, Out> {
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel.clone(),
+            config: self.config.clone(),
+            stop_tx: self.stop_tx.clone(),
+            local_addr: self.local_addr.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> LongPollListener<In, Out> {
+    /// Creates a server listening on the [`SocketAddr`], with the default configuration.
+    pub fn serve(addr: &SocketAddr) -> hyper::Result<Self> {
+        Self::serve_with_config(addr, Default::default())
+    }
+
+    /// Creates a server listening on the [`SocketAddr`] with a custom configuration.
+    pub fn serve_with_config(addr: &SocketAddr, config: LongPollConfig) -> hyper::Result<Self> {
+        let config = Arc::new(config);
+        let (accept_tx, accept_rx) = flume::bounded(config.accept_buffer);
+        let state = Arc::new(ServerState {
+            channels: Default::default(),
+            accept: accept_tx,
+            config: config.clone(),
+            ids: RandomState::new(),
+            next_id: Default::default(),
+        });
+        let service = make_service_fn({
+            let state = state.clone();
+            move |_| {
+                let state = state.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req| {
+                        let state = state.clone();
+                        async move { Ok::<_, Infallible>(state.handle(req).await) }
+                    }))
+                }
+            }
+        });
+        let server = Server::try_bind(addr)?.tcp_nodelay(true).serve(service);
+        let local_addr = server.local_addr();
+
+        let (stop_tx, mut stop_rx) = mpsc::channel::<()>(1);
+        let server = server.with_graceful_shutdown(async move {
+            // If the sender is dropped this will also gracefully terminate the server.
+            stop_rx.recv().await;
+        });
+        spawn_named(
+            format_args!("quic-rpc longpoll listener {}", type_name::<In>()),
+            async move {
+                tokio::select! {
+                    res = server => {
+                        if let Err(cause) = res {
+                            tracing::warn!("long-polling server failed: {cause}");
+                        }
+                    }
+                    _ = state.expire() => {}
+                }
+            },
+        );
+
+        Ok(Self {
+            channel: accept_rx,
+            config,
+            stop_tx,
+            local_addr: [LocalAddr::Socket(local_addr)],
+            _p: PhantomData,
+        })
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for LongPollListener<In, Out> {
+    type SendError = self::SendError;
+    type RecvError = self::RecvError;
+    type OpenError = self::AcceptError;
+    type AcceptError = self::AcceptError;
+}
+
+impl<In: RpcMessage, Out: RpcMessage> StreamTypes for LongPollListener<In, Out> {
+    type In = In;
+    type Out = Out;
+    type RecvStream = self::RecvStream<In>;
+    type SendSink = self::SendSink<Out>;
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Listener for LongPollListener<In, Out> {
+    fn local_addr(&self) -> &[LocalAddr] {
+        &self.local_addr
+    }
+
+    async fn accept(&self) -> result::Result<(Self::SendSink, Self::RecvStream), AcceptError> {
+        let (send, recv) = self
+            .channel
+            .recv_async()
+            .await
+            .map_err(|_| AcceptError::RemoteDropped)?;
+        Ok((
+            SendSink::new(send, self.config.clone()),
+            RecvStream::new(recv),
+        ))
+    }
+}
+
+/// Receive stream for long-polling channels
+pub struct RecvStream<In: RpcMessage> {
+    recv: flume::r#async::RecvStream<'static, result::Result<In, RecvError>>,
+}
+
+impl<In: RpcMessage> RecvStream<In> {
+    fn new(recv: flume::Receiver<result::Result<In, RecvError>>) -> Self {
+        Self {
+            recv: recv.into_stream(),
+        }
+    }
+}
+
+impl<In: RpcMessage> Stream for RecvStream<In> {
+    type Item = result::Result<In, RecvError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.recv).poll_next(cx)
+    }
+}
+
+/// Send sink for long-polling channels
+pub struct SendSink<Out: RpcMessage> {
+    sink: flume::r#async::SendSink<'static, Bytes>,
+    config: Arc<LongPollConfig>,
+    _p: PhantomData<Out>,
+}
+
+impl<Out: RpcMessage> SendSink<Out> {
+    fn new(sender: flume::Sender<Bytes>, config: Arc<LongPollConfig>) -> Self {
+        Self {
+            sink: sender.into_sink(),
+            config,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<Out: RpcMessage> Sink<Out> for SendSink<Out> {
+    type Error = SendError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError>> {
+        Pin::new(&mut self.sink)
+            .poll_ready(cx)
+            .map_err(|_| SendError::ReceiverDropped)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Out) -> Result<(), SendError> {
+        let frame = to_frame(&item, self.config.max_batch_size)?;
+        Pin::new(&mut self.sink)
+            .start_send(frame)
+            .map_err(|_| SendError::ReceiverDropped)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError>> {
+        Pin::new(&mut self.sink)
+            .poll_flush(cx)
+            .map_err(|_| SendError::ReceiverDropped)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError>> {
+        Pin::new(&mut self.sink)
+            .poll_close(cx)
+            .map_err(|_| SendError::ReceiverDropped)
+    }
+}
+
+/// Send error for long-polling channels
+#[derive(Debug)]
+pub enum SendError {
+    /// Error when bincode serializing the message.
+    SerializeError(bincode::Error),
+    /// The message is larger than the maximum batch size.
+    SizeError(usize),
+    /// The channel has been closed.
+    ReceiverDropped,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl error::Error for SendError {}
+
+/// Receive error for long-polling channels, also returned when opening a channel fails
+#[derive(Debug)]
+pub enum RecvError {
+    /// Error when bincode deserializing the message.
+    DeserializeError(bincode::Error),
+    /// A batch of messages, or the response to opening a channel, is malformed.
+    InvalidBatch,
+    /// Error when building a request.
+    Http(hyper::http::Error),
+    /// Hyper network error.
+    Network(hyper::Error),
+    /// The server answered with an error status, e.g. 404 for an expired channel.
+    Status(StatusCode),
+}
+
+impl RecvError {
+    /// Whether a poll that failed with this error may succeed when retried
+    fn is_retryable(&self) -> bool {
+        match self {
+            RecvError::Network(_) => true,
+            // proxies answer with e.g. 502 or 504 when they cut off a long poll
+            RecvError::Status(status) => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl error::Error for RecvError {}
+
+/// AcceptError for long-polling channels.
+#[derive(Debug)]
+pub enum AcceptError {
+    /// The server was shut down
+    RemoteDropped,
+}
+
+impl fmt::Display for AcceptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl error::Error for AcceptError {}
+
+/// The category of an error of this transport, if it is more specific than the default
+pub(crate) fn error_kind(cause: &(dyn error::Error + 'static)) -> Option<ErrorKind> {
+    if let Some(cause) = cause.downcast_ref::<SendError>() {
+        return match cause {
+            SendError::SerializeError(_) => Some(ErrorKind::Decode),
+            SendError::SizeError(_) => None,
+            SendError::ReceiverDropped => Some(ErrorKind::Shutdown),
+        };
+    }
+    if let Some(cause) = cause.downcast_ref::<RecvError>() {
+        return match cause {
+            RecvError::DeserializeError(_) | RecvError::InvalidBatch => Some(ErrorKind::Decode),
+            RecvError::Status(StatusCode::SERVICE_UNAVAILABLE) => Some(ErrorKind::Shutdown),
+            _ => None,
+        };
+    }
+    if let Some(AcceptError::RemoteDropped) = cause.downcast_ref::<AcceptError>() {
+        return Some(ErrorKind::Shutdown);
+    }
+    None
+}
@@ -11,7 +11,10 @@ use pin_project::pin_project;
 
 use crate::{RpcError, RpcMessage};
 
-use super::{ConnectionErrors, Connector, StreamTypes};
+use super::{
+    capabilities::Negotiated, connections::ConnectionHandle, ConnectionErrors, Connector, Listener,
+    LocalAddr, StreamTypes,
+};
 
 /// A connection that maps input and output types
 #[derive(Debug)]
@@ -71,6 +74,12 @@ where
     type Out = Out;
     type RecvStream = MappedRecvStream<C::RecvStream, In>;
     type SendSink = MappedSendSink<C::SendSink, Out, C::Out>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        C::poll_closed(&mut send.inner, cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        C::connection(&recv.inner)
+    }
 }
 
 impl<In, Out, C> Connector for MappedConnector<In, Out, C>
@@ -91,6 +100,120 @@ where
             Ok((MappedSendSink::new(send), MappedRecvStream::new(recv)))
         }
     }
+
+    fn open_rpc(
+        &self,
+    ) -> impl std::future::Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::OpenError>>
+           + Send {
+        let inner = self.inner.open_rpc();
+        async move {
+            let (send, recv) = inner.await?;
+            Ok((MappedSendSink::new(send), MappedRecvStream::new(recv)))
+        }
+    }
+
+    fn capabilities(&self) -> Option<Negotiated> {
+        self.inner.capabilities()
+    }
+}
+
+/// A listener that maps input and output types
+///
+/// This is the server side counterpart of [`MappedConnector`]. Every channel
+/// accepted from the inner listener is mapped, so the first message of a channel
+/// must convert into `In` as well, otherwise reading it fails with a conversion error.
+#[derive(Debug)]
+pub struct MappedListener<In, Out, L> {
+    inner: L,
+    _p: std::marker::PhantomData<(In, Out)>,
+}
+
+impl<In, Out, L> MappedListener<In, Out, L>
+where
+    L: Listener,
+    In: TryFrom<L::In>,
+    L::Out: From<Out>,
+{
+    /// Create a new mapped listener
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            _p: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<In, Out, L> Clone for MappedListener<In, Out, L>
+where
+    L: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _p: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<In, Out, L> ConnectionErrors for MappedListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: ConnectionErrors,
+{
+    type RecvError = ErrorOrMapError<L::RecvError>;
+    type SendError = L::SendError;
+    type OpenError = L::OpenError;
+    type AcceptError = L::AcceptError;
+}
+
+impl<In, Out, L> StreamTypes for MappedListener<In, Out, L>
+where
+    L: StreamTypes,
+    In: RpcMessage,
+    Out: RpcMessage,
+    In: TryFrom<L::In>,
+    L::Out: From<Out>,
+{
+    type In = In;
+    type Out = Out;
+    type RecvStream = MappedRecvStream<L::RecvStream, In>;
+    type SendSink = MappedSendSink<L::SendSink, Out, L::Out>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        L::poll_closed(&mut send.inner, cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        L::connection(&recv.inner)
+    }
+}
+
+impl<In, Out, L> Listener for MappedListener<In, Out, L>
+where
+    L: Listener,
+    In: RpcMessage,
+    Out: RpcMessage,
+    In: TryFrom<L::In>,
+    L::Out: From<Out>,
+{
+    fn accept(
+        &self,
+    ) -> impl std::future::Future<
+        Output = Result<(Self::SendSink, Self::RecvStream), Self::AcceptError>,
+    > + Send {
+        let inner = self.inner.accept();
+        async move {
+            let (send, recv) = inner.await?;
+            Ok((MappedSendSink::new(send), MappedRecvStream::new(recv)))
+        }
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        self.inner.local_addr()
+    }
+
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        self.inner.connections()
+    }
 }
 
 /// A combinator that maps a stream of incoming messages to a different type
@@ -115,24 +238,56 @@ impl<S, In> MappedRecvStream<S, In> {
 pub enum ErrorOrMapError<E> {
     /// Error from the inner stream
     Inner(E),
-    /// Conversion error
-    Conversion,
+    /// The message is not a message of the inner service
+    UnexpectedVariant(UnexpectedVariant),
 }
 
-impl<E: Debug + Display> std::error::Error for ErrorOrMapError<E> {}
+impl<E: Debug + Display + 'static> std::error::Error for ErrorOrMapError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ErrorOrMapError::Inner(_) => None,
+            ErrorOrMapError::UnexpectedVariant(e) => Some(e),
+        }
+    }
+}
 
 impl<E: Display> Display for ErrorOrMapError<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ErrorOrMapError::Inner(e) => write!(f, "Inner error: {}", e),
-            ErrorOrMapError::Conversion => write!(f, "Conversion error"),
+            ErrorOrMapError::UnexpectedVariant(e) => Display::fmt(e, f),
         }
     }
 }
 
+/// A message of the outer service that does not convert into the inner service
+///
+/// This usually means that the two sides disagree about which service a channel
+/// belongs to, e.g. because the server dispatched a request to the wrong handler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnexpectedVariant {
+    /// The variant of the message that was received, e.g. `Compute`
+    pub got: String,
+    /// The message type of the inner service, e.g. `my_crate::ComputeResponse`
+    pub expected_service: &'static str,
+}
+
+impl Display for UnexpectedVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unexpected variant {} for {}",
+            self.got, self.expected_service
+        )
+    }
+}
+
+impl std::error::Error for UnexpectedVariant {}
+
 impl<S, In0, In, E> Stream for MappedRecvStream<S, In>
 where
     S: Stream<Item = Result<In0, E>> + Unpin,
+    In0: Debug,
     In: TryFrom<In0>,
     E: RpcError,
 {
@@ -141,7 +296,14 @@ where
     fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
         match self.project().inner.poll_next(cx) {
             Poll::Ready(Some(Ok(item))) => {
-                let item = item.try_into().map_err(|_| ErrorOrMapError::Conversion);
+                // the conversion consumes the message, so get its name up front
+                let got = crate::message::variant_name(&item);
+                let item = item.try_into().map_err(|_| {
+                    ErrorOrMapError::UnexpectedVariant(UnexpectedVariant {
+                        got,
+                        expected_service: std::any::type_name::<In>(),
+                    })
+                });
                 Poll::Ready(Some(item))
             }
             Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(ErrorOrMapError::Inner(e)))),
@@ -243,6 +405,12 @@ where
     type Out = Out;
     type RecvStream = MappedRecvStream<C::RecvStream, In>;
     type SendSink = MappedSendSink<C::SendSink, Out, C::Out>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        C::poll_closed(&mut send.inner, cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        C::connection(&recv.inner)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,319 @@
+//! Mock transport with scripted expectations
+//!
+//! A [`MockConnection`] is a [`Connector`] that does not talk to any server. Instead,
+//! you script which requests are expected, in order, and what the responses should be.
+//! This is useful to unit test code that uses a [`RpcClient`](crate::RpcClient).
+//!
+//! ```ignore
+//! let mock = MockConnection::<ComputeService>::new();
+//! mock.expect_rpc::<Sqr>().returning(SqrResponse(4));
+//! mock.expect_server_streaming::<Fibonacci>()
+//!     .returning_stream([FibonacciResponse(0), FibonacciResponse(1)])
+//!     .returning_error(MockError::new("connection lost"));
+//! let client = RpcClient::<ComputeService, _>::new(mock.clone());
+//! // ... exercise code using the client ...
+//! mock.verify();
+//! ```
+use std::{
+    any::type_name,
+    collections::VecDeque,
+    error, fmt,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use futures_lite::Stream;
+use futures_sink::Sink;
+use tokio::sync::mpsc;
+
+use super::{ConnectionErrors, Connector, StreamTypes};
+use crate::{
+    message::{BidiStreamingMsg, ClientStreamingMsg, Msg, RpcMsg, ServerStreamingMsg},
+    Service,
+};
+
+/// Error produced by a [`MockConnection`]
+///
+/// This is used both for errors injected by the test and for requests that
+/// did not match the script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockError(String);
+
+impl MockError {
+    /// Create a new error with the given message
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for MockError {}
+
+struct Entry<S: Service> {
+    /// Name of the expected request type, for error messages
+    name: &'static str,
+    /// Checks if a request is of the expected type
+    matches: fn(S::Req) -> bool,
+    /// Scripted responses, in order
+    responses: Vec<Result<S::Res, MockError>>,
+}
+
+struct Inner<S: Service> {
+    expectations: Mutex<VecDeque<Arc<Mutex<Entry<S>>>>>,
+    failures: Mutex<Vec<String>>,
+}
+
+/// A connection that answers requests according to a script
+///
+/// This is cheap to clone, all clones share the same script.
+pub struct MockConnection<S: Service> {
+    inner: Arc<Inner<S>>,
+}
+
+impl<S: Service> Clone for MockConnection<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S: Service> fmt::Debug for MockConnection<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockConnection")
+            .field("pending", &self.inner.expectations.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl<S: Service> Default for MockConnection<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Service> MockConnection<S> {
+    /// Create a new mock connection without any expectations
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                expectations: Default::default(),
+                failures: Default::default(),
+            }),
+        }
+    }
+
+    /// Expect a rpc request of type `M`
+    pub fn expect_rpc<M: RpcMsg<S>>(&self) -> Expectation<S, M> {
+        self.expect()
+    }
+
+    /// Expect a server streaming request of type `M`
+    pub fn expect_server_streaming<M: ServerStreamingMsg<S>>(&self) -> Expectation<S, M> {
+        self.expect()
+    }
+
+    /// Expect a client streaming request of type `M`
+    ///
+    /// Updates sent by the client are accepted and ignored.
+    pub fn expect_client_streaming<M: ClientStreamingMsg<S>>(&self) -> Expectation<S, M> {
+        self.expect()
+    }
+
+    /// Expect a bidi streaming request of type `M`
+    ///
+    /// Updates sent by the client are accepted and ignored.
+    pub fn expect_bidi_streaming<M: BidiStreamingMsg<S>>(&self) -> Expectation<S, M> {
+        self.expect()
+    }
+
+    fn expect<M: Msg<S>>(&self) -> Expectation<S, M> {
+        let entry = Arc::new(Mutex::new(Entry {
+            name: type_name::<M>(),
+            matches: |req| M::try_from(req).is_ok(),
+            responses: Vec::new(),
+        }));
+        self.inner
+            .expectations
+            .lock()
+            .unwrap()
+            .push_back(entry.clone());
+        Expectation {
+            entry,
+            _p: PhantomData,
+        }
+    }
+
+    /// Check that all expectations were met and no unexpected requests were made
+    ///
+    /// # Panics
+    ///
+    /// Panics with a description of all problems if verification fails.
+    pub fn verify(&self) {
+        let mut problems = self.inner.failures.lock().unwrap().clone();
+        for entry in self.inner.expectations.lock().unwrap().iter() {
+            problems.push(format!(
+                "expected request {} was never made",
+                entry.lock().unwrap().name
+            ));
+        }
+        if !problems.is_empty() {
+            panic!("mock verification failed:\n{}", problems.join("\n"));
+        }
+    }
+
+    /// Match the first message of a channel against the next expectation
+    fn next_responses(&self, req: S::Req) -> Result<Vec<Result<S::Res, MockError>>, MockError> {
+        let description = format!("{req:?}");
+        let entry = self.inner.expectations.lock().unwrap().pop_front();
+        let message = match entry {
+            Some(entry) => {
+                let mut entry = entry.lock().unwrap();
+                if (entry.matches)(req) {
+                    return Ok(std::mem::take(&mut entry.responses));
+                }
+                format!("expected request {}, got {description}", entry.name)
+            }
+            None => format!("unexpected request {description}"),
+        };
+        self.inner.failures.lock().unwrap().push(message.clone());
+        Err(MockError(message))
+    }
+}
+
+/// A scripted expectation, created by the `expect_*` methods of [`MockConnection`]
+///
+/// The expectation is registered when it is created, the methods on this type only
+/// add responses to it.
+pub struct Expectation<S: Service, M> {
+    entry: Arc<Mutex<Entry<S>>>,
+    _p: PhantomData<M>,
+}
+
+impl<S: Service, M> fmt::Debug for Expectation<S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Expectation")
+            .field("request", &self.entry.lock().unwrap().name)
+            .finish()
+    }
+}
+
+impl<S: Service, M: Msg<S>> Expectation<S, M> {
+    /// Respond with a single message
+    pub fn returning(self, res: impl Into<S::Res>) -> Self {
+        self.push(Ok(res.into()))
+    }
+
+    /// Respond with a stream of messages
+    pub fn returning_stream<R: Into<S::Res>>(self, items: impl IntoIterator<Item = R>) -> Self {
+        items
+            .into_iter()
+            .fold(self, |this, item| this.push(Ok(item.into())))
+    }
+
+    /// Fail receiving with the given error
+    pub fn returning_error(self, err: MockError) -> Self {
+        self.push(Err(err))
+    }
+
+    fn push(self, item: Result<S::Res, MockError>) -> Self {
+        self.entry.lock().unwrap().responses.push(item);
+        self
+    }
+}
+
+impl<S: Service> ConnectionErrors for MockConnection<S> {
+    type SendError = MockError;
+    type RecvError = MockError;
+    type OpenError = MockError;
+    type AcceptError = MockError;
+}
+
+impl<S: Service> StreamTypes for MockConnection<S> {
+    type In = S::Res;
+    type Out = S::Req;
+    type SendSink = SendSink<S>;
+    type RecvStream = RecvStream<S>;
+}
+
+impl<S: Service> Connector for MockConnection<S> {
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let send = SendSink {
+            connection: self.clone(),
+            sender: Some(tx),
+            first: true,
+        };
+        Ok((send, RecvStream(rx)))
+    }
+}
+
+/// Send side of a [`MockConnection`] channel
+pub struct SendSink<S: Service> {
+    connection: MockConnection<S>,
+    sender: Option<mpsc::UnboundedSender<Result<S::Res, MockError>>>,
+    first: bool,
+}
+
+impl<S: Service> fmt::Debug for SendSink<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendSink").finish_non_exhaustive()
+    }
+}
+
+impl<S: Service> Sink<S::Req> for SendSink<S> {
+    type Error = MockError;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: S::Req) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        if !std::mem::replace(&mut this.first, false) {
+            // updates are ignored
+            return Ok(());
+        }
+        // dropping the sender after the responses ends the receive stream
+        let sender = this.sender.take();
+        let responses = this.connection.next_responses(item)?;
+        if let Some(sender) = sender {
+            for res in responses {
+                sender.send(res).ok();
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Receive side of a [`MockConnection`] channel
+pub struct RecvStream<S: Service>(mpsc::UnboundedReceiver<Result<S::Res, MockError>>);
+
+impl<S: Service> fmt::Debug for RecvStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecvStream").finish_non_exhaustive()
+    }
+}
+
+impl<S: Service> Stream for RecvStream<S> {
+    type Item = Result<S::Res, MockError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().0.poll_recv(cx)
+    }
+}
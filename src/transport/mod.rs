@@ -13,38 +13,73 @@
 //! typed channels from any of the currently opened connections to clients, using
 //! [`Listener::accept`].
 //!
+//! Which side opens and which side accepts channels is independent of which side
+//! dialed the underlying connection. E.g. a server behind a NAT can connect to its
+//! client and then accept channels on that connection, see
+//! `QuinnListener::connect` in the quinn transport.
+//!
 //! In both cases, the result is a tuple of a send side and a receive side. These
 //! types are defined by implementing the [`StreamTypes`] trait.
 //!
 //! Errors for both sides are defined by implementing the [`ConnectionErrors`] trait.
 use boxed::{BoxableConnector, BoxableListener, BoxedConnector, BoxedListener};
+use capabilities::Negotiated;
+use connections::ConnectionHandle;
 use futures_lite::{Future, Stream};
 use futures_sink::Sink;
-use mapped::MappedConnector;
+use mapped::{MappedConnector, MappedListener};
 
 use crate::{RpcError, RpcMessage};
 use std::{
     fmt::{self, Debug, Display},
     net::SocketAddr,
+    task::{Context, Poll},
 };
 
 pub mod boxed;
+pub mod capabilities;
+#[cfg(feature = "chaos-transport")]
+pub mod chaos;
 pub mod combined;
+pub mod connections;
+#[cfg(feature = "failover")]
+pub mod failover;
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub mod filter;
 #[cfg(feature = "flume-transport")]
 pub mod flume;
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub mod handshake;
 #[cfg(feature = "hyper-transport")]
 pub mod hyper;
 #[cfg(feature = "iroh-net-transport")]
 pub mod iroh_net;
+#[cfg(feature = "longpoll-transport")]
+pub mod longpoll;
 pub mod mapped;
 pub mod misc;
+#[cfg(feature = "mock-transport")]
+pub mod mock;
+#[cfg(feature = "pooled-transport")]
+pub mod pooled;
 #[cfg(feature = "quinn-transport")]
 pub mod quinn;
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub mod quota;
+#[cfg(any(feature = "chaos-transport", feature = "flume-simulation"))]
+mod rng;
+#[cfg(feature = "stepped-transport")]
+pub mod stepped;
+#[cfg(feature = "quinn-transport")]
+pub mod tenant;
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub mod warm_up;
 
 #[cfg(any(
     feature = "quinn-transport",
     feature = "hyper-transport",
-    feature = "iroh-net-transport"
+    feature = "iroh-net-transport",
+    feature = "longpoll-transport"
 ))]
 mod util;
 
@@ -76,6 +111,27 @@ pub trait StreamTypes: ConnectionErrors {
         + 'static;
     /// Send side of a bidirectional typed channel
     type SendSink: Sink<Self::Out, Error = Self::SendError> + Send + Sync + Unpin + 'static;
+
+    /// Poll whether the remote side of a channel stopped receiving
+    ///
+    /// This resolves once the remote dropped its receive side, e.g. because a client
+    /// dropped the future or stream waiting for the responses. The server uses this
+    /// to cancel handlers that nobody waits for anymore. The default never resolves,
+    /// for transports that can't tell.
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        let _ = (send, cx);
+        Poll::Pending
+    }
+
+    /// The connection a channel that was accepted by a listener belongs to
+    ///
+    /// This gives the server structured information about the client, like its
+    /// remote address, without knowing the transport. The default returns `None`,
+    /// for transports without connections and for channels opened by a connector.
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        let _ = recv;
+        None
+    }
 }
 
 /// A connection to a specific remote machine
@@ -87,6 +143,24 @@ pub trait Connector: StreamTypes {
         &self,
     ) -> impl Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::OpenError>> + Send;
 
+    /// Open a channel for a single request and a single response
+    ///
+    /// This is used for rpc calls. Transports can override this to carry such short
+    /// interactions more cheaply, by default it is the same as [`Connector::open`].
+    fn open_rpc(
+        &self,
+    ) -> impl Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::OpenError>> + Send
+    {
+        self.open()
+    }
+
+    /// The capabilities negotiated with the remote, see [`capabilities`]
+    ///
+    /// The default returns `None`, for transports without a handshake.
+    fn capabilities(&self) -> Option<Negotiated> {
+        None
+    }
+
     /// Map the input and output types of this connection
     fn map<In1, Out1>(self) -> MappedConnector<In1, Out1, Self>
     where
@@ -119,6 +193,22 @@ pub trait Listener: StreamTypes {
     /// The local addresses this endpoint is bound to.
     fn local_addr(&self) -> &[LocalAddr];
 
+    /// The connections this listener currently serves channels on
+    ///
+    /// Transports without connections return an empty list, see [`connections`].
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        Vec::new()
+    }
+
+    /// Map the input and output types of this listener
+    fn map<In1, Out1>(self) -> MappedListener<In1, Out1, Self>
+    where
+        In1: TryFrom<Self::In>,
+        Self::Out: From<Out1>,
+    {
+        MappedListener::new(self)
+    }
+
     /// Box the listener
     fn boxed(self) -> BoxedListener<Self::In, Self::Out>
     where
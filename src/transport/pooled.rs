@@ -0,0 +1,604 @@
+//! Pooled channels for rpc calls
+//!
+//! Opening a channel per rpc call has a cost on transports like quinn, where each
+//! channel is a new stream. A [`PooledConnector`] carries rpc calls, opened with
+//! [`Connector::open_rpc`], over a small pool of persistent channels of the inner
+//! connector, tagging each message with a request id. Streaming interactions still get
+//! a dedicated channel.
+//!
+//! Both sides exchange [`PoolFrame`]s, so the server must wrap its listener in a
+//! [`PooledListener`]:
+//!
+//! ```ignore
+//! let listener = QuinnListener::<PoolFrame<Req>, PoolFrame<Res>>::new(server_endpoint)?;
+//! let server = RpcServer::<MyService, _>::new(PooledListener::new(listener));
+//! let connector = QuinnConnector::<PoolFrame<Res>, PoolFrame<Req>>::new(endpoint, addr, name);
+//! let client = RpcClient::<MyService, _>::new(PooledConnector::new(connector, 4));
+//! ```
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use futures_lite::{Stream, StreamExt};
+use futures_sink::Sink;
+use futures_util::SinkExt;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::AbortHandle};
+
+use super::{
+    connections::ConnectionHandle, ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes,
+};
+use crate::RpcMessage;
+
+/// Message on the channels of a pooled transport
+#[derive(Debug, Serialize, Deserialize)]
+pub enum PoolFrame<T> {
+    /// A message on a dedicated channel
+    Dedicated(T),
+    /// A message of the pooled interaction with the given id
+    Pooled(u64, T),
+    /// The pooled interaction with the given id is done
+    End(u64),
+}
+
+/// Error of a pooled transport
+#[derive(Debug)]
+pub enum PoolError<E> {
+    /// Error from the inner transport
+    Inner(E),
+    /// The pooled channel was closed
+    Closed,
+}
+
+impl<E: Debug + Display> std::error::Error for PoolError<E> {}
+
+impl<E: Display> Display for PoolError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Inner(e) => write!(f, "Inner error: {}", e),
+            PoolError::Closed => write!(f, "Pooled channel closed"),
+        }
+    }
+}
+
+type Writer<T> = mpsc::UnboundedSender<PoolFrame<T>>;
+
+/// Send side of a pooled transport channel
+pub struct SendSink<Out, S>(SendSinkInner<Out, S>);
+
+enum SendSinkInner<Out, S> {
+    Dedicated(S),
+    Pooled { id: u64, writer: Writer<Out> },
+}
+
+impl<Out, S> fmt::Debug for SendSink<Out, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id = match &self.0 {
+            SendSinkInner::Dedicated(_) => None,
+            SendSinkInner::Pooled { id, .. } => Some(id),
+        };
+        f.debug_struct("SendSink").field("pooled_id", &id).finish()
+    }
+}
+
+impl<Out, S> Sink<Out> for SendSink<Out, S>
+where
+    S: Sink<PoolFrame<Out>> + Unpin,
+{
+    type Error = PoolError<S::Error>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.get_mut().0 {
+            SendSinkInner::Dedicated(inner) => {
+                Pin::new(inner).poll_ready(cx).map_err(PoolError::Inner)
+            }
+            SendSinkInner::Pooled { .. } => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
+        match &mut self.get_mut().0 {
+            SendSinkInner::Dedicated(inner) => Pin::new(inner)
+                .start_send(PoolFrame::Dedicated(item))
+                .map_err(PoolError::Inner),
+            SendSinkInner::Pooled { id, writer } => writer
+                .send(PoolFrame::Pooled(*id, item))
+                .map_err(|_| PoolError::Closed),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.get_mut().0 {
+            SendSinkInner::Dedicated(inner) => {
+                Pin::new(inner).poll_flush(cx).map_err(PoolError::Inner)
+            }
+            SendSinkInner::Pooled { .. } => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.get_mut().0 {
+            SendSinkInner::Dedicated(inner) => {
+                Pin::new(inner).poll_close(cx).map_err(PoolError::Inner)
+            }
+            SendSinkInner::Pooled { .. } => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl<Out, S> Drop for SendSink<Out, S> {
+    fn drop(&mut self) {
+        if let SendSinkInner::Pooled { id, writer } = &self.0 {
+            writer.send(PoolFrame::End(*id)).ok();
+        }
+    }
+}
+
+/// Receive side of a pooled transport channel
+pub struct RecvStream<In, S>(RecvStreamInner<In, S>);
+
+enum RecvStreamInner<In, S> {
+    Dedicated { first: Option<In>, inner: S },
+    Pooled(mpsc::UnboundedReceiver<In>),
+}
+
+impl<In, S> fmt::Debug for RecvStream<In, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pooled = matches!(self.0, RecvStreamInner::Pooled(_));
+        f.debug_struct("RecvStream")
+            .field("pooled", &pooled)
+            .finish()
+    }
+}
+
+impl<In, S, E> Stream for RecvStream<In, S>
+where
+    S: Stream<Item = Result<PoolFrame<In>, E>> + Unpin,
+    In: Unpin,
+{
+    type Item = Result<In, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match &mut self.get_mut().0 {
+            RecvStreamInner::Dedicated { first, inner } => {
+                if let Some(item) = first.take() {
+                    return Poll::Ready(Some(Ok(item)));
+                }
+                loop {
+                    return match std::task::ready!(Pin::new(&mut *inner).poll_next(cx)) {
+                        Some(Ok(PoolFrame::Dedicated(item))) => Poll::Ready(Some(Ok(item))),
+                        // not expected on a dedicated channel
+                        Some(Ok(_)) => continue,
+                        Some(Err(cause)) => Poll::Ready(Some(Err(cause))),
+                        None => Poll::Ready(None),
+                    };
+                }
+            }
+            RecvStreamInner::Pooled(recv) => recv.poll_recv(cx).map(|item| item.map(Ok)),
+        }
+    }
+}
+
+/// Senders for the responses of pending pooled interactions, `None` once the channel died
+type Pending<In> = Arc<Mutex<Option<HashMap<u64, mpsc::UnboundedSender<In>>>>>;
+
+/// A persistent channel of the pool
+struct Slot<In, Out> {
+    writer: Writer<Out>,
+    pending: Pending<In>,
+}
+
+impl<In, Out> Clone for Slot<In, Out> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: self.writer.clone(),
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Slot<In, Out> {
+    fn spawn<S, R, E>(send: S, recv: R) -> Self
+    where
+        E: Send + 'static,
+        S: Sink<PoolFrame<Out>> + Send + Unpin + 'static,
+        R: Stream<Item = Result<PoolFrame<In>, E>> + Send + Unpin + 'static,
+    {
+        let (writer, frames) = mpsc::unbounded_channel();
+        let pending: Pending<In> = Arc::new(Mutex::new(Some(HashMap::new())));
+        tokio::spawn(Self::drive(send, recv, frames, pending.clone()));
+        Self { writer, pending }
+    }
+
+    async fn drive<S, R, E>(
+        mut send: S,
+        mut recv: R,
+        mut frames: mpsc::UnboundedReceiver<PoolFrame<Out>>,
+        pending: Pending<In>,
+    ) where
+        S: Sink<PoolFrame<Out>> + Unpin,
+        R: Stream<Item = Result<PoolFrame<In>, E>> + Unpin,
+    {
+        loop {
+            tokio::select! {
+                frame = frames.recv() => match frame {
+                    Some(frame) => if send.send(frame).await.is_err() {
+                        break;
+                    },
+                    None => break,
+                },
+                frame = recv.next() => match frame {
+                    Some(Ok(PoolFrame::Pooled(id, item))) => {
+                        if let Some(sender) = pending.lock().unwrap().as_ref().and_then(|p| p.get(&id)) {
+                            sender.send(item).ok();
+                        }
+                    }
+                    Some(Ok(PoolFrame::End(id))) => {
+                        if let Some(pending) = pending.lock().unwrap().as_mut() {
+                            pending.remove(&id);
+                        }
+                    }
+                    Some(Ok(PoolFrame::Dedicated(_))) => {}
+                    Some(Err(_)) | None => break,
+                },
+            }
+        }
+        // ends the receive streams of all pending interactions
+        pending.lock().unwrap().take();
+    }
+}
+
+struct ConnectorShared<In, Out> {
+    slots: Mutex<Vec<Option<Slot<In, Out>>>>,
+    next_slot: AtomicUsize,
+    next_id: AtomicU64,
+}
+
+/// A connector that carries rpc calls over a pool of persistent channels
+///
+/// Created using [`PooledConnector::new`].
+pub struct PooledConnector<In, Out, C> {
+    inner: C,
+    shared: Arc<ConnectorShared<In, Out>>,
+}
+
+impl<In, Out, C: Clone> Clone for PooledConnector<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<In, Out, C: Debug> fmt::Debug for PooledConnector<In, Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledConnector")
+            .field("inner", &self.inner)
+            .field("pool_size", &self.shared.slots.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl<In, Out, C> PooledConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Connector<In = PoolFrame<In>, Out = PoolFrame<Out>>,
+{
+    /// Wrap a connector, carrying rpc calls over up to `pool_size` persistent channels
+    ///
+    /// Channels of the pool are opened when they are first needed, and reopened if
+    /// they fail.
+    pub fn new(inner: C, pool_size: usize) -> Self {
+        Self {
+            inner,
+            shared: Arc::new(ConnectorShared {
+                slots: Mutex::new(vec![None; pool_size.max(1)]),
+                next_slot: AtomicUsize::new(0),
+                next_id: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Get the inner connector
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<In, Out, C> ConnectionErrors for PooledConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: ConnectionErrors,
+{
+    type SendError = PoolError<C::SendError>;
+    type RecvError = C::RecvError;
+    type OpenError = C::OpenError;
+    type AcceptError = C::AcceptError;
+}
+
+impl<In, Out, C> StreamTypes for PooledConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: StreamTypes<In = PoolFrame<In>, Out = PoolFrame<Out>>,
+{
+    type In = In;
+    type Out = Out;
+    type SendSink = SendSink<Out, C::SendSink>;
+    type RecvStream = RecvStream<In, C::RecvStream>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut send.0 {
+            SendSinkInner::Dedicated(send) => C::poll_closed(send, cx),
+            // pooled channels share the stream, so they can't tell
+            SendSinkInner::Pooled { .. } => Poll::Pending,
+        }
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        match &recv.0 {
+            RecvStreamInner::Dedicated { inner, .. } => C::connection(inner),
+            // pooled channels are demultiplexed from a shared stream
+            RecvStreamInner::Pooled(_) => None,
+        }
+    }
+}
+
+impl<In, Out, C> Connector for PooledConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Connector<In = PoolFrame<In>, Out = PoolFrame<Out>>,
+{
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let (send, recv) = self.inner.open().await?;
+        let send = SendSink(SendSinkInner::Dedicated(send));
+        let recv = RecvStream(RecvStreamInner::Dedicated {
+            first: None,
+            inner: recv,
+        });
+        Ok((send, recv))
+    }
+
+    async fn open_rpc(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let shared = &self.shared;
+        let index = shared.next_slot.fetch_add(1, Ordering::Relaxed) % self.pool_size();
+        let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, recv) = mpsc::unbounded_channel();
+        loop {
+            let slot = shared.slots.lock().unwrap()[index].clone();
+            if let Some(slot) = slot {
+                if let Some(pending) = slot.pending.lock().unwrap().as_mut() {
+                    pending.insert(id, sender);
+                    let send = SendSink(SendSinkInner::Pooled {
+                        id,
+                        writer: slot.writer.clone(),
+                    });
+                    return Ok((send, RecvStream(RecvStreamInner::Pooled(recv))));
+                }
+            }
+            // the slot is empty or its channel died
+            let (send, recv) = self.inner.open().await?;
+            shared.slots.lock().unwrap()[index] = Some(Slot::spawn(send, recv));
+        }
+    }
+}
+
+impl<In, Out, C> PooledConnector<In, Out, C> {
+    fn pool_size(&self) -> usize {
+        self.shared.slots.lock().unwrap().len()
+    }
+}
+
+type Socket<In, Out, L> = (
+    SendSink<Out, <L as StreamTypes>::SendSink>,
+    RecvStream<In, <L as StreamTypes>::RecvStream>,
+);
+
+type AcceptQueue<In, Out, L> =
+    mpsc::UnboundedSender<Result<Socket<In, Out, L>, <L as ConnectionErrors>::AcceptError>>;
+
+struct ListenerShared<In, Out, L: StreamTypes> {
+    #[allow(clippy::type_complexity)]
+    accepted:
+        tokio::sync::Mutex<mpsc::UnboundedReceiver<Result<Socket<In, Out, L>, L::AcceptError>>>,
+    task: AbortHandle,
+}
+
+impl<In, Out, L: StreamTypes> Drop for ListenerShared<In, Out, L> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A listener that accepts both dedicated and pooled channels
+///
+/// Created using [`PooledListener::new`].
+pub struct PooledListener<In, Out, L: StreamTypes> {
+    inner: L,
+    shared: Arc<ListenerShared<In, Out, L>>,
+}
+
+impl<In, Out, L: StreamTypes> Clone for PooledListener<In, Out, L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<In, Out, L: StreamTypes> fmt::Debug for PooledListener<In, Out, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledListener")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<In, Out, L> PooledListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: Listener<In = PoolFrame<In>, Out = PoolFrame<Out>>,
+{
+    /// Wrap a listener, splitting pooled channels into one channel per interaction
+    ///
+    /// This spawns a task that accepts channels of the inner listener, so it must be
+    /// called from within a tokio runtime. The task stops when the inner listener
+    /// fails to accept a channel, or when all clones of this listener are dropped.
+    pub fn new(inner: L) -> Self {
+        let (queue, accepted) = mpsc::unbounded_channel();
+        let task = tokio::spawn(Self::accept_loop(inner.clone(), queue));
+        Self {
+            inner,
+            shared: Arc::new(ListenerShared {
+                accepted: tokio::sync::Mutex::new(accepted),
+                task: task.abort_handle(),
+            }),
+        }
+    }
+
+    async fn accept_loop(listener: L, queue: AcceptQueue<In, Out, L>) {
+        loop {
+            match listener.accept().await {
+                Ok((send, recv)) => {
+                    tokio::spawn(Self::serve_channel(send, recv, queue.clone()));
+                }
+                Err(cause) => {
+                    queue.send(Err(cause)).ok();
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn serve_channel(
+        mut send: L::SendSink,
+        mut recv: L::RecvStream,
+        queue: AcceptQueue<In, Out, L>,
+    ) {
+        let (id, item) = match recv.next().await {
+            Some(Ok(PoolFrame::Dedicated(item))) => {
+                let send = SendSink(SendSinkInner::Dedicated(send));
+                let recv = RecvStream(RecvStreamInner::Dedicated {
+                    first: Some(item),
+                    inner: recv,
+                });
+                queue.send(Ok((send, recv))).ok();
+                return;
+            }
+            Some(Ok(PoolFrame::Pooled(id, item))) => (id, item),
+            _ => return,
+        };
+        let (writer, mut frames) = mpsc::unbounded_channel();
+        // senders for the requests and updates of the interactions on this channel,
+        // the receive side of an interaction ends when the client sends `End`
+        let mut open = HashMap::<u64, mpsc::UnboundedSender<In>>::new();
+        let mut next = Some((id, item));
+        loop {
+            if let Some((id, item)) = next.take() {
+                if let Some(sender) = open.get(&id) {
+                    sender.send(item).ok();
+                } else {
+                    let (sender, recv) = mpsc::unbounded_channel();
+                    sender.send(item).ok();
+                    open.insert(id, sender);
+                    let send = SendSink(SendSinkInner::Pooled {
+                        id,
+                        writer: writer.clone(),
+                    });
+                    let recv = RecvStream(RecvStreamInner::Pooled(recv));
+                    if queue.send(Ok((send, recv))).is_err() {
+                        break;
+                    }
+                }
+            }
+            tokio::select! {
+                frame = frames.recv() => match frame {
+                    Some(frame) => if send.send(frame).await.is_err() {
+                        break;
+                    },
+                    None => break,
+                },
+                frame = recv.next() => match frame {
+                    Some(Ok(PoolFrame::Pooled(id, item))) => next = Some((id, item)),
+                    Some(Ok(PoolFrame::End(id))) => {
+                        open.remove(&id);
+                    }
+                    Some(Ok(PoolFrame::Dedicated(_))) => {}
+                    Some(Err(_)) | None => break,
+                },
+            }
+        }
+    }
+}
+
+impl<In, Out, L> ConnectionErrors for PooledListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: StreamTypes,
+{
+    type SendError = PoolError<L::SendError>;
+    type RecvError = L::RecvError;
+    type OpenError = L::OpenError;
+    type AcceptError = PoolError<L::AcceptError>;
+}
+
+impl<In, Out, L> StreamTypes for PooledListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: StreamTypes<In = PoolFrame<In>, Out = PoolFrame<Out>>,
+{
+    type In = In;
+    type Out = Out;
+    type SendSink = SendSink<Out, L::SendSink>;
+    type RecvStream = RecvStream<In, L::RecvStream>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut send.0 {
+            SendSinkInner::Dedicated(send) => L::poll_closed(send, cx),
+            // pooled channels share the stream, so they can't tell
+            SendSinkInner::Pooled { .. } => Poll::Pending,
+        }
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        match &recv.0 {
+            RecvStreamInner::Dedicated { inner, .. } => L::connection(inner),
+            // pooled channels are demultiplexed from a shared stream
+            RecvStreamInner::Pooled(_) => None,
+        }
+    }
+}
+
+impl<In, Out, L> Listener for PooledListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: Listener<In = PoolFrame<In>, Out = PoolFrame<Out>>,
+{
+    async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::AcceptError> {
+        match self.shared.accepted.lock().await.recv().await {
+            Some(Ok(socket)) => Ok(socket),
+            Some(Err(cause)) => Err(PoolError::Inner(cause)),
+            None => Err(PoolError::Closed),
+        }
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        self.inner.local_addr()
+    }
+
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        self.inner.connections()
+    }
+}
@@ -1,5 +1,7 @@
 //! QUIC transport implementation based on [quinn](https://crates.io/crates/quinn)
 use crate::{
+    error::{CloseCode, CloseReason, ErrorKind},
+    message::Priority,
     transport::{ConnectionErrors, Connector, Listener, LocalAddr},
     RpcMessage,
 };
@@ -7,48 +9,406 @@ use futures_lite::{Future, Stream, StreamExt};
 use futures_sink::Sink;
 use futures_util::FutureExt;
 use pin_project::pin_project;
+use quinn::rustls::{
+    self,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct,
+};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
-use std::{fmt, io, marker::PhantomData, pin::Pin, result};
-use tokio::sync::oneshot;
+use std::time::Duration;
+use std::{any::type_name, fmt, io, marker::PhantomData, pin::Pin, result};
+use tokio::sync::{oneshot, watch};
 use tracing::{debug_span, Instrument};
 
 use super::{
-    util::{FramedBincodeRead, FramedBincodeWrite},
+    capabilities::Negotiated,
+    connections::{ConnectionHandle, Connections, StreamGuard},
+    filter::RequestFilter,
+    handshake::{ClientHandshake, HelloSender, ServerHandshake, SharedNegotiated},
+    quota::{self, Account, QuotaTracker},
+    util::{spawn_named, spawn_named_on, FlushConfig, FramedBincodeRead, FramedBincodeWrite},
+    warm_up::{WarmStreams, WarmUp},
     StreamTypes,
 };
 
 const MAX_FRAME_LENGTH: usize = 1024 * 1024 * 16;
 
+/// Congestion controller for the connections of an endpoint, see [`EndpointBuilder`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CongestionController {
+    /// The classic loss based controller
+    NewReno,
+    /// Loss based, with faster window growth on high bandwidth paths
+    ///
+    /// This is the quinn default.
+    #[default]
+    Cubic,
+    /// Model based, keeps the throughput up on lossy paths, e.g. for bulk streaming
+    Bbr,
+}
+
+/// Builds quinn endpoints with the transport settings that matter for rpc traffic
+///
+/// Settings that are not set keep the quinn defaults. The transport config of the
+/// server or client config passed to [`EndpointBuilder::server`] or
+/// [`EndpointBuilder::client`] is replaced. For other endpoints, e.g. iroh-net ones,
+/// use [`EndpointBuilder::transport_config`].
+///
+/// Endpoints can also be created from rustls configs, with [`EndpointBuilder::server_tls`]
+/// and [`EndpointBuilder::client_tls`]. Only those can log their TLS secrets for
+/// debugging, see [`EndpointBuilder::key_log`].
+/// Clients with a custom server certificate verifier, e.g. for certificate pinning,
+/// are created with [`EndpointBuilder::client_with_verifier`]. Reconnects can skip
+/// the full handshake with [`EndpointBuilder::session_resumption`].
+#[derive(Debug, Clone, Default)]
+pub struct EndpointBuilder {
+    congestion_controller: CongestionController,
+    initial_window: Option<u64>,
+    key_log: bool,
+    session_resumption: bool,
+    session_store: Option<Arc<dyn rustls::client::ClientSessionStore>>,
+}
+
+impl EndpointBuilder {
+    /// Create a builder with the quinn defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the congestion controller of the connections
+    pub fn congestion_controller(mut self, controller: CongestionController) -> Self {
+        self.congestion_controller = controller;
+        self
+    }
+
+    /// Set the initial congestion window of the connections, in bytes
+    pub fn initial_window(mut self, bytes: u64) -> Self {
+        self.initial_window = Some(bytes);
+        self
+    }
+
+    /// Log the TLS secrets of all connections to the file in `SSLKEYLOGFILE`
+    ///
+    /// This lets tools like Wireshark decrypt captured traffic. Nothing is logged if
+    /// the environment variable is not set. Never enable this in production, anyone
+    /// who can read the file can decrypt the traffic.
+    pub fn key_log(mut self) -> Self {
+        self.key_log = true;
+        self
+    }
+
+    /// Resume TLS sessions, so reconnects skip the full handshake
+    ///
+    /// Clients keep the session tickets they get from servers in memory, see
+    /// [`EndpointBuilder::session_store`] to keep them elsewhere, and servers accept
+    /// 0-RTT. This is what lets a [`QuinnConnector`] tell whether its connection was
+    /// resumed, see [`QuinnConnector::resumed`]. Its requests are never sent as 0-RTT
+    /// data, but servers accept 0-RTT data from other clients, which can be replayed
+    /// by an attacker.
+    ///
+    /// This applies to endpoints created from rustls configs, e.g. with
+    /// [`EndpointBuilder::server_tls`] and [`EndpointBuilder::client_tls`].
+    pub fn session_resumption(mut self) -> Self {
+        self.session_resumption = true;
+        self
+    }
+
+    /// Keep the session tickets of clients in `store`, e.g. to resume after a restart
+    ///
+    /// This enables [`EndpointBuilder::session_resumption`].
+    pub fn session_store(mut self, store: Arc<dyn rustls::client::ClientSessionStore>) -> Self {
+        self.session_store = Some(store);
+        self.session_resumption()
+    }
+
+    /// The quinn transport config with the settings of this builder
+    pub fn transport_config(&self) -> quinn::TransportConfig {
+        use quinn::congestion::{BbrConfig, CubicConfig, NewRenoConfig};
+
+        let mut transport = quinn::TransportConfig::default();
+        let factory: Arc<dyn quinn::congestion::ControllerFactory + Send + Sync> =
+            match self.congestion_controller {
+                CongestionController::NewReno => {
+                    let mut config = NewRenoConfig::default();
+                    if let Some(window) = self.initial_window {
+                        config.initial_window(window);
+                    }
+                    Arc::new(config)
+                }
+                CongestionController::Cubic => {
+                    let mut config = CubicConfig::default();
+                    if let Some(window) = self.initial_window {
+                        config.initial_window(window);
+                    }
+                    Arc::new(config)
+                }
+                CongestionController::Bbr => {
+                    let mut config = BbrConfig::default();
+                    if let Some(window) = self.initial_window {
+                        config.initial_window(window);
+                    }
+                    Arc::new(config)
+                }
+            };
+        transport.congestion_controller_factory(factory);
+        transport
+    }
+
+    /// Create a server endpoint bound to `addr`
+    pub fn server(
+        &self,
+        mut config: quinn::ServerConfig,
+        addr: SocketAddr,
+    ) -> io::Result<quinn::Endpoint> {
+        config.transport_config(Arc::new(self.transport_config()));
+        quinn::Endpoint::server(config, addr)
+    }
+
+    /// Create server endpoints bound to each of `addrs`, e.g. one per interface
+    ///
+    /// IPv6 sockets are bound to only accept IPv6, so an IPv4 and an IPv6 address can
+    /// share a port. Serve all endpoints with [`QuinnListener::from_endpoints`].
+    pub fn servers(
+        &self,
+        mut config: quinn::ServerConfig,
+        addrs: impl IntoIterator<Item = SocketAddr>,
+    ) -> io::Result<Vec<quinn::Endpoint>> {
+        config.transport_config(Arc::new(self.transport_config()));
+        let runtime =
+            quinn::default_runtime().ok_or_else(|| io::Error::other("no async runtime found"))?;
+        addrs
+            .into_iter()
+            .map(|addr| {
+                quinn::Endpoint::new(
+                    Default::default(),
+                    Some(config.clone()),
+                    bind_socket(addr)?,
+                    runtime.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Create server endpoints for IPv4 and IPv6 on all interfaces
+    ///
+    /// With port 0, the IPv6 endpoint is bound to the ephemeral port that was picked
+    /// for the IPv4 endpoint, so clients can use the same port for both.
+    pub fn server_dual_stack(
+        &self,
+        config: quinn::ServerConfig,
+        port: u16,
+    ) -> io::Result<Vec<quinn::Endpoint>> {
+        let v4 = self.servers(config.clone(), [(Ipv4Addr::UNSPECIFIED, port).into()])?;
+        let port = v4[0].local_addr()?.port();
+        let v6 = self.servers(config, [(Ipv6Addr::UNSPECIFIED, port).into()])?;
+        Ok(v4.into_iter().chain(v6).collect())
+    }
+
+    /// Create a client endpoint bound to `addr`, connecting with `config` by default
+    pub fn client(
+        &self,
+        mut config: quinn::ClientConfig,
+        addr: SocketAddr,
+    ) -> io::Result<quinn::Endpoint> {
+        config.transport_config(Arc::new(self.transport_config()));
+        let mut endpoint = quinn::Endpoint::client(addr)?;
+        endpoint.set_default_client_config(config);
+        Ok(endpoint)
+    }
+
+    /// Create a server endpoint bound to `addr` from a rustls config
+    ///
+    /// The config must support TLS 1.3 with a cipher suite usable for QUIC.
+    pub fn server_tls(
+        &self,
+        mut crypto: rustls::ServerConfig,
+        addr: SocketAddr,
+    ) -> io::Result<quinn::Endpoint> {
+        if self.key_log {
+            crypto.key_log = Arc::new(rustls::KeyLogFile::new());
+        }
+        if self.session_resumption {
+            // quinn only accepts 0-RTT with an unlimited amount of early data
+            crypto.max_early_data_size = u32::MAX;
+        }
+        let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(crypto)
+            .map_err(|cause| io::Error::new(io::ErrorKind::InvalidInput, cause))?;
+        self.server(quinn::ServerConfig::with_crypto(Arc::new(crypto)), addr)
+    }
+
+    /// Create a client endpoint bound to `addr` from a rustls config
+    ///
+    /// The config must support TLS 1.3 with a cipher suite usable for QUIC.
+    pub fn client_tls(
+        &self,
+        mut crypto: rustls::ClientConfig,
+        addr: SocketAddr,
+    ) -> io::Result<quinn::Endpoint> {
+        if self.key_log {
+            crypto.key_log = Arc::new(rustls::KeyLogFile::new());
+        }
+        if self.session_resumption {
+            crypto.enable_early_data = true;
+            if let Some(store) = self.session_store.clone() {
+                crypto.resumption = rustls::client::Resumption::store(store);
+            }
+        }
+        let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(crypto)
+            .map_err(|cause| io::Error::new(io::ErrorKind::InvalidInput, cause))?;
+        self.client(quinn::ClientConfig::new(Arc::new(crypto)), addr)
+    }
+
+    /// Create a client endpoint bound to `addr` that checks server certificates with `verifier`
+    ///
+    /// This is for private PKIs and pinning policies, e.g. with [`SpkiPins`], without
+    /// building the rustls config manually. The config uses TLS 1.3 with the ring
+    /// crypto provider, and no client certificate.
+    pub fn client_with_verifier(
+        &self,
+        verifier: Arc<dyn ServerCertVerifier>,
+        addr: SocketAddr,
+    ) -> io::Result<quinn::Endpoint> {
+        let crypto = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(|cause| io::Error::new(io::ErrorKind::InvalidInput, cause))?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier)
+        .with_no_client_auth();
+        self.client_tls(crypto, addr)
+    }
+}
+
+/// Bind a UDP socket, only for IPv6 if it is an IPv6 address
+fn bind_socket(addr: SocketAddr) -> io::Result<std::net::UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.bind(&addr.into())?;
+    Ok(socket.into())
+}
+
+/// A server certificate verifier that accepts servers by the hash of their public key
+///
+/// A server is accepted if the SHA-256 hash of the subject public key info (SPKI) of
+/// its certificate is one of the pins, see [`SpkiPins::pin`]. Pinning the key instead
+/// of the certificate allows renewing certificates without updating the clients, as
+/// long as the key stays the same.
+///
+/// Only the key is checked: the certificate chain, the server name and the validity
+/// period are ignored, since the pins are the trust anchors. Add the pin of the next
+/// key before rotating keys.
+#[derive(Debug)]
+pub struct SpkiPins {
+    pins: Vec<[u8; 32]>,
+    algorithms: rustls::crypto::WebPkiSupportedAlgorithms,
+}
+
+impl SpkiPins {
+    /// Accept servers whose key has one of the given pins
+    pub fn new(pins: impl IntoIterator<Item = [u8; 32]>) -> Self {
+        Self {
+            pins: pins.into_iter().collect(),
+            algorithms: rustls::crypto::ring::default_provider().signature_verification_algorithms,
+        }
+    }
+
+    /// The pin of a DER encoded certificate, the SHA-256 hash of its SPKI
+    pub fn pin(cert: &CertificateDer<'_>) -> Result<[u8; 32], rustls::Error> {
+        let cert = webpki::EndEntityCert::try_from(cert).map_err(|_| {
+            rustls::Error::InvalidCertificate(rustls::CertificateError::BadEncoding)
+        })?;
+        let spki = cert.subject_public_key_info();
+        let digest = ring::digest::digest(&ring::digest::SHA256, spki.as_ref());
+        let mut pin = [0u8; 32];
+        pin.copy_from_slice(digest.as_ref());
+        Ok(pin)
+    }
+}
+
+impl ServerCertVerifier for SpkiPins {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if self.pins.contains(&Self::pin(end_entity)?) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
 #[derive(Debug)]
 struct ListenerInner {
-    endpoint: Option<quinn::Endpoint>,
-    task: Option<tokio::task::JoinHandle<()>>,
-    local_addr: [LocalAddr; 1],
-    receiver: flume::Receiver<SocketInner>,
+    endpoints: Vec<quinn::Endpoint>,
+    tasks: Vec<tokio::task::JoinHandle<()>>,
+    local_addr: Vec<LocalAddr>,
+    receiver: flume::Receiver<Accepted>,
+    connections: Connections,
+    /// The check for new connections, see [`QuinnListener::handshake`]
+    handshake: watch::Sender<Option<ServerHandshake>>,
 }
 
 impl Drop for ListenerInner {
     fn drop(&mut self) {
         tracing::debug!("Dropping listener");
-        if let Some(endpoint) = self.endpoint.take() {
-            endpoint.close(0u32.into(), b"Listener dropped");
+        for endpoint in self.endpoints.drain(..) {
+            endpoint.close(u32::from(CloseCode::Shutdown).into(), b"Listener dropped");
 
             if let Ok(handle) = tokio::runtime::Handle::try_current() {
                 // spawn a task to wait for the endpoint to notify peers that it is closing
                 let span = debug_span!("closing listener");
-                handle.spawn(
+                spawn_named_on(
+                    format_args!("quic-rpc quinn closing listener"),
                     async move {
                         endpoint.wait_idle().await;
                     }
                     .instrument(span),
+                    &handle,
                 );
             }
         }
-        if let Some(task) = self.task.take() {
+        for task in self.tasks.drain(..) {
             task.abort()
         }
     }
@@ -58,6 +418,10 @@ impl Drop for ListenerInner {
 #[derive(Debug)]
 pub struct QuinnListener<In: RpcMessage, Out: RpcMessage> {
     inner: Arc<ListenerInner>,
+    flush: FlushConfig,
+    priority: Option<fn(&Out) -> i32>,
+    filter: Option<RequestFilter>,
+    quota: Option<QuotaTracker>,
     _p: PhantomData<(In, Out)>,
 }
 
@@ -65,7 +429,26 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
     /// handles RPC requests from a connection
     ///
     /// to cleanly shutdown the handler, drop the receiver side of the sender.
-    async fn connection_handler(connection: quinn::Connection, sender: flume::Sender<SocketInner>) {
+    async fn connection_handler(
+        connection: quinn::Connection,
+        sender: flume::Sender<Accepted>,
+        connections: Connections,
+        handshake: watch::Receiver<Option<ServerHandshake>>,
+    ) {
+        let peer = connection.remote_address().to_string();
+        let handshake = handshake.borrow().clone();
+        let handshake = match handshake {
+            Some(handshake) => match handshake.accept(&connection).await {
+                Ok(handshake) => Some(handshake),
+                Err(reason) => {
+                    tracing::debug!("Rejecting connection from {peer}: {}", reason.reason);
+                    connection.close(u32::from(reason.code).into(), reason.reason.as_bytes());
+                    return;
+                }
+            },
+            None => None,
+        };
+        let guard = connections.register(&connection, peer, handshake);
         loop {
             tracing::debug!("Awaiting incoming bidi substream on existing connection...");
             let bidi_stream = match connection.accept_bi().await {
@@ -80,14 +463,24 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
                 }
             };
             tracing::debug!("Sending substream to be handled... {}", bidi_stream.0.id());
-            if sender.send_async(bidi_stream).await.is_err() {
+            let (send, recv) = bidi_stream;
+            if sender
+                .send_async((send, recv, Some(guard.stream())))
+                .await
+                .is_err()
+            {
                 tracing::debug!("Receiver dropped");
                 break;
             }
         }
     }
 
-    async fn endpoint_handler(endpoint: quinn::Endpoint, sender: flume::Sender<SocketInner>) {
+    async fn endpoint_handler(
+        endpoint: quinn::Endpoint,
+        sender: flume::Sender<Accepted>,
+        connections: Connections,
+        handshake: watch::Receiver<Option<ServerHandshake>>,
+    ) {
         loop {
             tracing::debug!("Waiting for incoming connection...");
             let connecting = match endpoint.accept().await {
@@ -107,7 +500,15 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
                 conection.remote_address()
             );
             tracing::debug!("Spawning connection handler...");
-            tokio::spawn(Self::connection_handler(conection, sender.clone()));
+            spawn_named(
+                format_args!("quic-rpc quinn connection {}", type_name::<In>()),
+                Self::connection_handler(
+                    conection,
+                    sender.clone(),
+                    connections.clone(),
+                    handshake.clone(),
+                ),
+            );
         }
     }
 
@@ -118,20 +519,166 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
     /// The server channel will take care of listening on the endpoint and spawning
     /// handlers for new connections.
     pub fn new(endpoint: quinn::Endpoint) -> io::Result<Self> {
+        Self::from_endpoints([endpoint])
+    }
+
+    /// Create a new server channel that accepts connections on several endpoints
+    ///
+    /// This serves e.g. an IPv4 and an IPv6 endpoint, or one endpoint per interface,
+    /// with a single [`RpcServer`](crate::RpcServer). See
+    /// [`EndpointBuilder::servers`] for creating the endpoints.
+    pub fn from_endpoints(
+        endpoints: impl IntoIterator<Item = quinn::Endpoint>,
+    ) -> io::Result<Self> {
+        let endpoints = endpoints.into_iter().collect::<Vec<_>>();
+        let local_addr = endpoints
+            .iter()
+            .map(|endpoint| Ok(LocalAddr::Socket(endpoint.local_addr()?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let (sender, receiver) = flume::bounded(16);
+        let connections = Connections::default();
+        let (handshake, check) = watch::channel(None);
+        let tasks = endpoints
+            .iter()
+            .map(|endpoint| {
+                spawn_named(
+                    format_args!("quic-rpc quinn listener {}", type_name::<In>()),
+                    Self::endpoint_handler(
+                        endpoint.clone(),
+                        sender.clone(),
+                        connections.clone(),
+                        check.clone(),
+                    ),
+                )
+            })
+            .collect();
+        Ok(Self {
+            inner: Arc::new(ListenerInner {
+                endpoints,
+                tasks,
+                local_addr,
+                receiver,
+                connections,
+                handshake,
+            }),
+            flush: FlushConfig::default(),
+            priority: None,
+            filter: None,
+            quota: None,
+            _p: PhantomData,
+        })
+    }
+
+    /// The socket addresses the endpoints of this listener are bound to
+    ///
+    /// This is the same as [`Listener::local_addr`], e.g. to find out which
+    /// ephemeral ports were picked.
+    pub fn socket_addrs(&self) -> Vec<SocketAddr> {
+        self.inner
+            .local_addr
+            .iter()
+            .filter_map(|addr| match addr {
+                LocalAddr::Socket(addr) => Some(*addr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Create a new server channel by connecting to a client
+    ///
+    /// This reverses the roles of dialer and listener, for servers that can not accept
+    /// incoming connections, e.g. because they are behind a NAT. The client accepts the
+    /// connection on its endpoint and wraps it in a [`QuinnConnector::from_connection`],
+    /// and the server serves all channels the client opens on the connection.
+    ///
+    /// Accepting fails once the connection is closed, so connect again to keep serving.
+    pub async fn connect(
+        endpoint: &quinn::Endpoint,
+        addr: SocketAddr,
+        server_name: &str,
+    ) -> Result<Self, CreateChannelError> {
         let local_addr = endpoint.local_addr()?;
+        let connection = endpoint.connect(addr, server_name)?.await?;
         let (sender, receiver) = flume::bounded(16);
-        let task = tokio::spawn(Self::endpoint_handler(endpoint.clone(), sender));
+        let connections = Connections::default();
+        let (handshake, check) = watch::channel(None);
+        let task = spawn_named(
+            format_args!("quic-rpc quinn connection {}", type_name::<In>()),
+            Self::connection_handler(connection, sender, connections.clone(), check),
+        );
         Ok(Self {
             inner: Arc::new(ListenerInner {
-                endpoint: Some(endpoint),
-                task: Some(task),
-                local_addr: [LocalAddr::Socket(local_addr)],
+                endpoints: Vec::new(),
+                tasks: vec![task],
+                local_addr: vec![LocalAddr::Socket(local_addr)],
                 receiver,
+                connections,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
+            filter: None,
+            quota: None,
             _p: PhantomData,
         })
     }
 
+    /// Coalesce frames that are sent within `interval` into a single write
+    ///
+    /// By default every frame is written to the stream as soon as it is sent. With a
+    /// flush interval, sending a frame only schedules a write at most `interval` later,
+    /// trading latency for fewer writes when many small frames are sent in a row.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush.interval = Some(interval);
+        self
+    }
+
+    /// Write immediately once `bytes` are buffered, even within the flush interval
+    ///
+    /// Flushing a sink twice without sending in between, e.g. calling
+    /// [`UpdateSink::flush`](crate::client::UpdateSink::flush) after sending, also writes
+    /// immediately. The default is 16 KiB.
+    pub fn flush_threshold(mut self, bytes: usize) -> Self {
+        self.flush.threshold = bytes;
+        self
+    }
+
+    /// Set the priority of each channel from the first message sent on it
+    ///
+    /// See [`Priority`] for details.
+    pub fn prioritized(mut self) -> Self
+    where
+        Out: Priority,
+    {
+        self.priority = Some(Out::priority);
+        self
+    }
+
+    /// Check the first message of each channel with a filter before deserializing it
+    ///
+    /// See [`filter`](super::filter) for details.
+    pub fn request_filter(mut self, filter: RequestFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Account requests and bytes per peer, rejecting requests above the quota
+    ///
+    /// See the [module docs](super::quota) for details.
+    pub fn quota(mut self, quota: QuotaTracker) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Check the hello of each new connection before accepting any channel on it
+    ///
+    /// This applies to all clones of the listener, and to connections that are
+    /// accepted after it is set. See the [module docs](super::handshake) for details.
+    pub fn handshake(self, handshake: ServerHandshake) -> Self {
+        self.inner.handshake.send_replace(Some(handshake));
+        self
+    }
+
     /// Create a new server channel, given just a source of incoming connections
     ///
     /// This is useful if you want to manage the quinn endpoint yourself,
@@ -141,19 +688,41 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
         local_addr: SocketAddr,
     ) -> Self {
         let (sender, receiver) = flume::bounded(16);
-        let task = tokio::spawn(async move {
-            // just grab all connections and spawn a handler for each one
-            while let Ok(connection) = incoming.recv_async().await {
-                tokio::spawn(Self::connection_handler(connection, sender.clone()));
-            }
-        });
+        let connections = Connections::default();
+        let (handshake, check) = watch::channel(None);
+        let task = spawn_named(
+            format_args!("quic-rpc quinn listener {}", type_name::<In>()),
+            {
+                let connections = connections.clone();
+                async move {
+                    // just grab all connections and spawn a handler for each one
+                    while let Ok(connection) = incoming.recv_async().await {
+                        spawn_named(
+                            format_args!("quic-rpc quinn connection {}", type_name::<In>()),
+                            Self::connection_handler(
+                                connection,
+                                sender.clone(),
+                                connections.clone(),
+                                check.clone(),
+                            ),
+                        );
+                    }
+                }
+            },
+        );
         Self {
             inner: Arc::new(ListenerInner {
-                endpoint: None,
-                task: Some(task),
-                local_addr: [LocalAddr::Socket(local_addr)],
+                endpoints: Vec::new(),
+                tasks: vec![task],
+                local_addr: vec![LocalAddr::Socket(local_addr)],
                 receiver,
+                connections,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
+            filter: None,
+            quota: None,
             _p: PhantomData,
         }
     }
@@ -162,17 +731,40 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
     ///
     /// This is useful if you want to manage the quinn endpoint yourself,
     /// use multiple endpoints, or use an endpoint for multiple protocols.
+    ///
+    /// The substreams don't belong to a known connection, so
+    /// [`Listener::connections`] returns an empty list.
     pub fn handle_substreams(
-        receiver: flume::Receiver<SocketInner>,
+        incoming: flume::Receiver<SocketInner>,
         local_addr: SocketAddr,
     ) -> Self {
+        let (sender, receiver) = flume::bounded(16);
+        let connections = Connections::default();
+        // the substreams don't belong to a connection, so there is nothing to check
+        let (handshake, _) = watch::channel(None);
+        let task = spawn_named(
+            format_args!("quic-rpc quinn listener {}", type_name::<In>()),
+            async move {
+                while let Ok((send, recv)) = incoming.recv_async().await {
+                    if sender.send_async((send, recv, None)).await.is_err() {
+                        break;
+                    }
+                }
+            },
+        );
         Self {
             inner: Arc::new(ListenerInner {
-                endpoint: None,
-                task: None,
-                local_addr: [LocalAddr::Socket(local_addr)],
+                endpoints: Vec::new(),
+                tasks: vec![task],
+                local_addr: vec![LocalAddr::Socket(local_addr)],
                 receiver,
+                connections,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
+            filter: None,
+            quota: None,
             _p: PhantomData,
         }
     }
@@ -182,6 +774,10 @@ impl<In: RpcMessage, Out: RpcMessage> Clone for QuinnListener<In, Out> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            flush: self.flush,
+            priority: self.priority,
+            filter: self.filter.clone(),
+            quota: self.quota.clone(),
             _p: PhantomData,
         }
     }
@@ -199,26 +795,55 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for QuinnListener<In, Out> {
     type Out = Out;
     type SendSink = self::SendSink<Out>;
     type RecvStream = self::RecvStream<In>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        send.0.poll_stopped(cx)
+    }
+    fn connection(recv: &Self::RecvStream) -> Option<ConnectionHandle> {
+        recv.0.connection().cloned()
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Listener for QuinnListener<In, Out> {
     async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), AcceptError> {
-        let (send, recv) = self
-            .inner
-            .receiver
-            .recv_async()
-            .await
-            .map_err(|_| quinn::ConnectionError::LocallyClosed)?;
-        Ok((SendSink::new(send), RecvStream::new(recv)))
+        loop {
+            let (send, recv, stream) = self
+                .inner
+                .receiver
+                .recv_async()
+                .await
+                .map_err(|_| quinn::ConnectionError::LocallyClosed)?;
+            let account = match (&self.quota, &stream) {
+                (Some(tracker), Some(stream)) => match tracker.start_request(stream.peer()) {
+                    Ok(account) => Some(account),
+                    Err(_) => {
+                        tracing::debug!("Rejecting channel of {}, quota exceeded", stream.peer());
+                        quota::reject(send, recv);
+                        continue;
+                    }
+                },
+                _ => None,
+            };
+            return Ok((
+                SendSink::new(send, self.flush, account.clone(), self.priority),
+                RecvStream::new(recv, self.filter.clone(), stream, account),
+            ));
+        }
     }
 
     fn local_addr(&self) -> &[LocalAddr] {
         &self.inner.local_addr
     }
+
+    fn connections(&self) -> Vec<ConnectionHandle> {
+        self.inner.connections.list()
+    }
 }
 
 type SocketInner = (quinn::SendStream, quinn::RecvStream);
 
+/// An accepted substream, counted as open on its connection if it has one
+type Accepted = (quinn::SendStream, quinn::RecvStream, Option<StreamGuard>);
+
 #[derive(Debug)]
 struct ClientConnectionInner {
     /// The quinn endpoint, we just keep a clone of this for information
@@ -227,6 +852,16 @@ struct ClientConnectionInner {
     task: Option<tokio::task::JoinHandle<()>>,
     /// The channel to receive new connections
     sender: flume::Sender<oneshot::Sender<Result<SocketInner, quinn::ConnectionError>>>,
+    /// What to do after connecting, see [`QuinnConnector::warm_up`]
+    warm_up: watch::Sender<WarmUp>,
+    /// Notifies the connection handler that the endpoint was rebound
+    rebound: watch::Sender<()>,
+    /// Whether the current connection resumed a TLS session, if known
+    resumed: Arc<Mutex<Option<bool>>>,
+    /// The capabilities negotiated on the current connection, if any
+    negotiated: SharedNegotiated,
+    /// The hello sent on each connection, see [`QuinnConnector::handshake`]
+    handshake: watch::Sender<Option<ClientHandshake>>,
 }
 
 impl Drop for ClientConnectionInner {
@@ -237,11 +872,13 @@ impl Drop for ClientConnectionInner {
             if let Ok(handle) = tokio::runtime::Handle::try_current() {
                 // spawn a task to wait for the endpoint to notify peers that it is closing
                 let span = debug_span!("closing client endpoint");
-                handle.spawn(
+                spawn_named_on(
+                    format_args!("quic-rpc quinn closing client endpoint"),
                     async move {
                         endpoint.wait_idle().await;
                     }
                     .instrument(span),
+                    &handle,
                 );
             }
         }
@@ -257,6 +894,8 @@ impl Drop for ClientConnectionInner {
 /// A connection using a quinn connection
 pub struct QuinnConnector<In: RpcMessage, Out: RpcMessage> {
     inner: Arc<ClientConnectionInner>,
+    flush: FlushConfig,
+    priority: Option<fn(&Out) -> i32>,
     _p: PhantomData<(In, Out)>,
 }
 
@@ -264,11 +903,31 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
     async fn single_connection_handler_inner(
         connection: quinn::Connection,
         requests: flume::Receiver<oneshot::Sender<Result<SocketInner, quinn::ConnectionError>>>,
+        mut warm: WarmStreams,
+        mut hello: HelloSender,
     ) -> result::Result<(), flume::RecvError> {
+        hello.connected(&connection);
+        warm.prime(&connection);
         loop {
             tracing::debug!("Awaiting request for new bidi substream...");
-            let request = requests.recv_async().await?;
+            let request = tokio::select! {
+                request = requests.recv_async() => request?,
+                _ = warm.changed() => {
+                    warm.prime(&connection);
+                    continue;
+                }
+                _ = hello.changed() => {
+                    hello.send(&connection);
+                    continue;
+                }
+            };
             tracing::debug!("Got request for new bidi substream");
+            if let Some(pair) = warm.take(&connection) {
+                if request.send(Ok(pair)).is_err() {
+                    tracing::debug!("requester dropped");
+                }
+                continue;
+            }
             match connection.open_bi().await {
                 Ok(pair) => {
                     tracing::debug!("Bidi substream opened");
@@ -289,8 +948,10 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
     async fn single_connection_handler(
         connection: quinn::Connection,
         requests: flume::Receiver<oneshot::Sender<Result<SocketInner, quinn::ConnectionError>>>,
+        warm: WarmStreams,
+        hello: HelloSender,
     ) {
-        if Self::single_connection_handler_inner(connection, requests)
+        if Self::single_connection_handler_inner(connection, requests, warm, hello)
             .await
             .is_err()
         {
@@ -306,17 +967,12 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
     /// All other errors are logged and handled internally.
     /// It will try to keep a connection open at all times.
     async fn reconnect_handler_inner(
-        endpoint: quinn::Endpoint,
-        addr: SocketAddr,
-        name: String,
+        reconnect: ReconnectHandler,
         requests: flume::Receiver<oneshot::Sender<Result<SocketInner, quinn::ConnectionError>>>,
+        mut warm: WarmStreams,
+        mut hello: HelloSender,
+        mut rebound: watch::Receiver<()>,
     ) {
-        let reconnect = ReconnectHandler {
-            endpoint,
-            state: ConnectionState::NotConnected,
-            addr,
-            name,
-        };
         tokio::pin!(reconnect);
 
         let mut receiver = Receiver::new(&requests);
@@ -351,13 +1007,35 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
                 conn_result = Some(reconnect.as_mut().await);
             } else if pending_request.is_none() {
                 // there is a connection, just need a request
-                chann_result = Some(receiver.next().await);
+                tokio::select! {
+                    request = receiver.next() => chann_result = Some(request),
+                    _ = warm.changed() => {
+                        if let Some(connection) = connection.as_ref() {
+                            warm.prime(connection);
+                        }
+                    }
+                    _ = hello.changed() => {
+                        if let Some(connection) = connection.as_ref() {
+                            hello.send(connection);
+                        }
+                    }
+                    Ok(()) = rebound.changed() => {
+                        // the connection migrates to the new socket if it is still
+                        // alive, otherwise don't wait for the next request to reconnect
+                        if connection.as_ref().is_some_and(|c| c.close_reason().is_some()) {
+                            tracing::debug!("endpoint rebound, reconnecting");
+                            reconnect.set_not_connected();
+                        }
+                    }
+                }
             }
 
             if let Some(conn_result) = conn_result {
                 tracing::trace!("tick: connection result");
                 match conn_result {
                     Ok(new_connection) => {
+                        hello.connected(&new_connection);
+                        warm.prime(&new_connection);
                         connection = Some(new_connection);
                     }
                     Err(e) => {
@@ -400,6 +1078,12 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
 
             if let Some(connection) = connection.as_mut() {
                 if let Some(request) = pending_request.take() {
+                    if let Some(pair) = warm.take(connection) {
+                        if request.send(Ok(pair)).is_err() {
+                            tracing::debug!("requester dropped");
+                        }
+                        continue;
+                    }
                     match connection.open_bi().await {
                         Ok(pair) => {
                             tracing::debug!("Bidi substream opened");
@@ -423,25 +1107,124 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
     }
 
     async fn reconnect_handler(
-        endpoint: quinn::Endpoint,
-        addr: SocketAddr,
-        name: String,
+        reconnect: ReconnectHandler,
         requests: flume::Receiver<oneshot::Sender<Result<SocketInner, quinn::ConnectionError>>>,
+        warm: WarmStreams,
+        hello: HelloSender,
+        rebound: watch::Receiver<()>,
     ) {
-        Self::reconnect_handler_inner(endpoint, addr, name, requests).await;
+        Self::reconnect_handler_inner(reconnect, requests, warm, hello, rebound).await;
         tracing::info!("Reconnect handler finished");
     }
 
+    /// Coalesce frames that are sent within `interval` into a single write
+    ///
+    /// By default every frame is written to the stream as soon as it is sent. With a
+    /// flush interval, sending a frame only schedules a write at most `interval` later,
+    /// trading latency for fewer writes when many small frames are sent in a row.
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush.interval = Some(interval);
+        self
+    }
+
+    /// Write immediately once `bytes` are buffered, even within the flush interval
+    ///
+    /// Flushing a sink twice without sending in between, e.g. calling
+    /// [`UpdateSink::flush`](crate::client::UpdateSink::flush) after sending, also writes
+    /// immediately. The default is 16 KiB.
+    pub fn flush_threshold(mut self, bytes: usize) -> Self {
+        self.flush.threshold = bytes;
+        self
+    }
+
+    /// Set the priority of each channel from the first message sent on it
+    ///
+    /// See [`Priority`] for details.
+    pub fn prioritized(mut self) -> Self
+    where
+        Out: Priority,
+    {
+        self.priority = Some(Out::priority);
+        self
+    }
+
+    /// Warm up connections right after they are established
+    ///
+    /// This applies to the current connection, if there is one, and to every
+    /// connection after a reconnect. See [`WarmUp`] for details.
+    pub fn warm_up(self, warm_up: WarmUp) -> Self {
+        self.inner.warm_up.send_replace(warm_up);
+        self
+    }
+
+    /// Rebind the endpoint to a new UDP socket, e.g. after the network changed
+    ///
+    /// The current connection migrates to the new socket, so channels that are open
+    /// stay open. If the connection was lost already, e.g. while there was no network,
+    /// a new one is established right away instead of on the next request.
+    ///
+    /// Fails for connectors created with [`QuinnConnector::from_connection`], since
+    /// they don't know their endpoint.
+    pub fn rebind(&self, socket: std::net::UdpSocket) -> io::Result<()> {
+        let Some(endpoint) = self.inner.endpoint.as_ref() else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "connector has no endpoint to rebind",
+            ));
+        };
+        endpoint.rebind(socket)?;
+        self.inner.rebound.send_replace(());
+        Ok(())
+    }
+
+    /// Whether the current connection resumed a TLS session
+    ///
+    /// Returns `None` if there is no connection yet, or if the connector was created
+    /// with [`QuinnConnector::from_connection`]. Resumption is only detected if both
+    /// sides enabled [`EndpointBuilder::session_resumption`], otherwise this is
+    /// always false.
+    pub fn resumed(&self) -> Option<bool> {
+        *self.inner.resumed.lock().unwrap()
+    }
+
+    /// Send a hello on each new connection, before any channel is opened on it
+    ///
+    /// This applies to the current connection, if the hello was not sent on it yet,
+    /// and to every connection after a reconnect. See the
+    /// [module docs](super::handshake) for details.
+    pub fn handshake(self, handshake: ClientHandshake) -> Self {
+        self.inner.handshake.send_replace(Some(handshake));
+        self
+    }
+
     /// Create a new channel
     pub fn from_connection(connection: quinn::Connection) -> Self {
         let (sender, receiver) = flume::bounded(16);
-        let task = tokio::spawn(Self::single_connection_handler(connection, receiver));
+        let (warm_up, config) = watch::channel(WarmUp::default());
+        let (handshake, hello) = watch::channel(None);
+        let negotiated = SharedNegotiated::default();
+        let task = spawn_named(
+            format_args!("quic-rpc quinn connector {}", type_name::<Out>()),
+            Self::single_connection_handler(
+                connection,
+                receiver,
+                WarmStreams::new(config),
+                HelloSender::new(hello, negotiated.clone()),
+            ),
+        );
         Self {
             inner: Arc::new(ClientConnectionInner {
                 endpoint: None,
                 task: Some(task),
                 sender,
+                warm_up,
+                rebound: watch::channel(()).0,
+                resumed: Default::default(),
+                negotiated,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
             _p: PhantomData,
         }
     }
@@ -449,18 +1232,41 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
     /// Create a new channel
     pub fn new(endpoint: quinn::Endpoint, addr: SocketAddr, name: String) -> Self {
         let (sender, receiver) = flume::bounded(16);
-        let task = tokio::spawn(Self::reconnect_handler(
-            endpoint.clone(),
+        let (warm_up, config) = watch::channel(WarmUp::default());
+        let (rebound, rebound_rx) = watch::channel(());
+        let (handshake, hello) = watch::channel(None);
+        let negotiated = SharedNegotiated::default();
+        let resumed = Arc::new(Mutex::new(None));
+        let reconnect = ReconnectHandler {
+            endpoint: endpoint.clone(),
+            state: ConnectionState::NotConnected,
             addr,
             name,
-            receiver,
-        ));
+            resumed: resumed.clone(),
+        };
+        let task = spawn_named(
+            format_args!("quic-rpc quinn connector {}", type_name::<Out>()),
+            Self::reconnect_handler(
+                reconnect,
+                receiver,
+                WarmStreams::new(config),
+                HelloSender::new(hello, negotiated.clone()),
+                rebound_rx,
+            ),
+        );
         Self {
             inner: Arc::new(ClientConnectionInner {
                 endpoint: Some(endpoint),
                 task: Some(task),
                 sender,
+                warm_up,
+                rebound,
+                resumed,
+                negotiated,
+                handshake,
             }),
+            flush: FlushConfig::default(),
+            priority: None,
             _p: PhantomData,
         }
     }
@@ -471,6 +1277,7 @@ struct ReconnectHandler {
     state: ConnectionState,
     addr: SocketAddr,
     name: String,
+    resumed: Arc<Mutex<Option<bool>>>,
 }
 
 impl ReconnectHandler {
@@ -488,6 +1295,10 @@ enum ConnectionState {
     NotConnected,
     /// Connecting to the remote.
     Connecting(quinn::Connecting),
+    /// Connecting to the remote with a session ticket, until the handshake completes.
+    ///
+    /// Nothing is sent as 0-RTT data, this is just to find out if the ticket was accepted.
+    Resuming(quinn::Connection, quinn::ZeroRttAccepted),
     /// A connection is already established. In this state, no more connection attempts are made.
     Connected(quinn::Connection),
     /// Intermediate state while processing.
@@ -516,7 +1327,12 @@ impl Future for ReconnectHandler {
         match self.state.poison() {
             ConnectionState::NotConnected => match self.endpoint.connect(self.addr, &self.name) {
                 Ok(connecting) => {
-                    self.state = ConnectionState::Connecting(connecting);
+                    self.state = match connecting.into_0rtt() {
+                        Ok((connection, accepted)) => {
+                            ConnectionState::Resuming(connection, accepted)
+                        }
+                        Err(connecting) => ConnectionState::Connecting(connecting),
+                    };
                     self.poll(cx)
                 }
                 Err(e) => {
@@ -528,6 +1344,7 @@ impl Future for ReconnectHandler {
             {
                 Poll::Ready(res) => match res {
                     Ok(connection) => {
+                        *self.resumed.lock().unwrap() = Some(false);
                         self.state = ConnectionState::Connected(connection.clone());
                         Poll::Ready(Ok(connection))
                     }
@@ -541,6 +1358,23 @@ impl Future for ReconnectHandler {
                     Poll::Pending
                 }
             },
+            ConnectionState::Resuming(connection, mut accepted) => {
+                match Pin::new(&mut accepted).poll(cx) {
+                    Poll::Ready(resumed) => {
+                        if let Some(e) = connection.close_reason() {
+                            self.state = ConnectionState::NotConnected;
+                            return Poll::Ready(Err(ReconnectErr::Connection(e)));
+                        }
+                        *self.resumed.lock().unwrap() = Some(resumed);
+                        self.state = ConnectionState::Connected(connection.clone());
+                        Poll::Ready(Ok(connection))
+                    }
+                    Poll::Pending => {
+                        self.state = ConnectionState::Resuming(connection, accepted);
+                        Poll::Pending
+                    }
+                }
+            }
             ConnectionState::Connected(connection) => {
                 self.state = ConnectionState::Connected(connection.clone());
                 Poll::Ready(Ok(connection))
@@ -614,6 +1448,8 @@ impl<In: RpcMessage, Out: RpcMessage> Clone for QuinnConnector<In, Out> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            flush: self.flush,
+            priority: self.priority,
             _p: PhantomData,
         }
     }
@@ -631,6 +1467,9 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for QuinnConnector<In, Out> {
     type Out = Out;
     type SendSink = self::SendSink<Out>;
     type RecvStream = self::RecvStream<In>;
+    fn poll_closed(send: &mut Self::SendSink, cx: &mut Context<'_>) -> Poll<()> {
+        send.0.poll_stopped(cx)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Connector for QuinnConnector<In, Out> {
@@ -644,7 +1483,14 @@ impl<In: RpcMessage, Out: RpcMessage> Connector for QuinnConnector<In, Out> {
         let (send, recv) = receiver
             .await
             .map_err(|_| quinn::ConnectionError::LocallyClosed)??;
-        Ok((SendSink::new(send), RecvStream::new(recv)))
+        Ok((
+            SendSink::new(send, self.flush, None, self.priority),
+            RecvStream::new(recv, None, None, None),
+        ))
+    }
+
+    fn capabilities(&self) -> Option<Negotiated> {
+        self.inner.negotiated.lock().unwrap().clone()
     }
 }
 
@@ -653,7 +1499,11 @@ impl<In: RpcMessage, Out: RpcMessage> Connector for QuinnConnector<In, Out> {
 /// If you want to send bytes directly, use [SendSink::into_inner] to get the
 /// underlying [quinn::SendStream].
 #[pin_project]
-pub struct SendSink<Out>(#[pin] FramedBincodeWrite<quinn::SendStream, Out>);
+pub struct SendSink<Out>(
+    #[pin] pub(crate) FramedBincodeWrite<quinn::SendStream, Out>,
+    /// Sets the priority of the stream from the first message, then taken
+    Option<fn(&Out) -> i32>,
+);
 
 impl<Out> fmt::Debug for SendSink<Out> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -662,9 +1512,16 @@ impl<Out> fmt::Debug for SendSink<Out> {
 }
 
 impl<Out: Serialize> SendSink<Out> {
-    fn new(inner: quinn::SendStream) -> Self {
-        let inner = FramedBincodeWrite::new(inner, MAX_FRAME_LENGTH);
-        Self(inner)
+    fn new(
+        inner: quinn::SendStream,
+        flush: FlushConfig,
+        account: Option<Account>,
+        priority: Option<fn(&Out) -> i32>,
+    ) -> Self {
+        let inner = FramedBincodeWrite::new(inner, MAX_FRAME_LENGTH)
+            .with_flush(flush)
+            .with_account(account);
+        Self(inner, priority)
     }
 }
 
@@ -676,32 +1533,44 @@ impl<Out> SendSink<Out> {
     }
 }
 
-impl<Out: Serialize> Sink<Out> for SendSink<Out> {
+impl<Out: RpcMessage> Sink<Out> for SendSink<Out> {
     type Error = io::Error;
 
     fn poll_ready(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_ready(cx)
+        Pin::new(&mut self.project().0)
+            .poll_ready(cx)
+            .map_err(quota::map_error)
     }
 
     fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
-        Pin::new(&mut self.project().0).start_send(item)
+        let this = self.project();
+        if let Some(priority) = this.1.take() {
+            let priority = priority(&item);
+            this.0
+                .with_inner(|stream| stream.set_priority(priority).ok());
+        }
+        this.0.start_send(item).map_err(quota::map_error)
     }
 
     fn poll_flush(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_flush(cx)
+        Pin::new(&mut self.project().0)
+            .poll_flush(cx)
+            .map_err(quota::map_error)
     }
 
     fn poll_close(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_close(cx)
+        Pin::new(&mut self.project().0)
+            .poll_close(cx)
+            .map_err(quota::map_error)
     }
 }
 
@@ -710,7 +1579,7 @@ impl<Out: Serialize> Sink<Out> for SendSink<Out> {
 /// If you want to receive bytes directly, use [RecvStream::into_inner] to get
 /// the underlying [quinn::RecvStream].
 #[pin_project]
-pub struct RecvStream<In>(#[pin] FramedBincodeRead<quinn::RecvStream, In>);
+pub struct RecvStream<In>(#[pin] pub(crate) FramedBincodeRead<quinn::RecvStream, In>);
 
 impl<In> fmt::Debug for RecvStream<In> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -719,8 +1588,16 @@ impl<In> fmt::Debug for RecvStream<In> {
 }
 
 impl<In: DeserializeOwned> RecvStream<In> {
-    fn new(inner: quinn::RecvStream) -> Self {
-        let inner = FramedBincodeRead::new(inner, MAX_FRAME_LENGTH);
+    fn new(
+        inner: quinn::RecvStream,
+        filter: Option<RequestFilter>,
+        stream: Option<StreamGuard>,
+        account: Option<Account>,
+    ) -> Self {
+        let inner = FramedBincodeRead::new(inner, MAX_FRAME_LENGTH)
+            .with_filter(filter)
+            .with_stream_guard(stream)
+            .with_account(account);
         Self(inner)
     }
 }
@@ -733,14 +1610,16 @@ impl<In> RecvStream<In> {
     }
 }
 
-impl<In: DeserializeOwned> Stream for RecvStream<In> {
+impl<In: RpcMessage> Stream for RecvStream<In> {
     type Item = result::Result<In, io::Error>;
 
     fn poll_next(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        Pin::new(&mut self.project().0).poll_next(cx)
+        Pin::new(&mut self.project().0)
+            .poll_next(cx)
+            .map_err(quota::map_error)
     }
 }
 
@@ -798,3 +1677,23 @@ pub fn get_handshake_data(
         server_name: tls_connection.server_name.clone(),
     })
 }
+
+/// The reason the peer closed the connection, if this error is about it
+pub(crate) fn close_reason(cause: &(dyn std::error::Error + 'static)) -> Option<CloseReason> {
+    match cause.downcast_ref::<quinn::ConnectionError>()? {
+        quinn::ConnectionError::ApplicationClosed(close) => Some(CloseReason {
+            code: u32::try_from(close.error_code.into_inner()).ok()?.into(),
+            reason: String::from_utf8_lossy(&close.reason).into_owned(),
+        }),
+        _ => None,
+    }
+}
+
+/// The category of an error of this transport, if it is more specific than the default
+pub(crate) fn error_kind(cause: &(dyn std::error::Error + 'static)) -> Option<ErrorKind> {
+    match cause.downcast_ref::<quinn::ConnectionError>()? {
+        quinn::ConnectionError::LocallyClosed => Some(ErrorKind::Shutdown),
+        quinn::ConnectionError::TimedOut => Some(ErrorKind::Deadline),
+        _ => None,
+    }
+}
@@ -0,0 +1,216 @@
+//! Usage accounting and quotas per peer
+//!
+//! A [`QuotaTracker`] set on a quinn or iroh-net listener counts the requests and the
+//! bytes transferred for each peer identity, i.e. the
+//! [`peer`](super::connections::ConnectionHandle::peer) of the connection: the remote
+//! address for quinn and the node id for iroh-net. [`QuotaTracker::usage`] reads the
+//! current numbers, e.g. for billing.
+//!
+//! With a [`Quota`], new requests of a peer that used up its quota are rejected before
+//! they reach the server. The client sees receiving the response fail with an
+//! [`io::Error`] of kind [`io::ErrorKind::PermissionDenied`], which wraps
+//! [`QuotaExceeded`]. Requests that were accepted before run to completion.
+//!
+//! Channels that don't belong to a known connection, e.g. from
+//! `QuinnListener::handle_substreams`, are not accounted.
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Application error code used to reset channels that exceed their quota
+pub(crate) const QUOTA_EXCEEDED_CODE: u32 = 0x71_75_6f_74;
+
+/// Hard limits per peer, unlimited by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    max_requests: Option<u64>,
+    max_bytes: Option<u64>,
+}
+
+impl Quota {
+    /// Reject requests once a peer made `max` requests
+    pub fn max_requests(mut self, max: u64) -> Self {
+        self.max_requests = Some(max);
+        self
+    }
+
+    /// Reject requests once a peer sent and received `max` bytes in total
+    pub fn max_bytes(mut self, max: u64) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    fn exceeded(&self, usage: &Usage) -> bool {
+        self.max_requests.is_some_and(|max| usage.requests >= max)
+            || self.max_bytes.is_some_and(|max| usage.bytes() >= max)
+    }
+}
+
+/// Requests and bytes of a peer, since it was first seen or last reset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Requests that were accepted
+    pub requests: u64,
+    /// Requests that were rejected because the quota was exceeded
+    pub rejected: u64,
+    /// Bytes received from the peer, including framing
+    pub bytes_received: u64,
+    /// Bytes sent to the peer, including framing
+    pub bytes_sent: u64,
+}
+
+impl Usage {
+    /// Bytes sent and received
+    pub fn bytes(&self) -> u64 {
+        self.bytes_received + self.bytes_sent
+    }
+}
+
+/// Error when a request is rejected because the peer used up its quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaExceeded;
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("quota exceeded")
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+impl From<QuotaExceeded> for io::Error {
+    fn from(value: QuotaExceeded) -> Self {
+        io::Error::new(io::ErrorKind::PermissionDenied, value)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    requests: AtomicU64,
+    rejected: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+impl Counters {
+    fn usage(&self) -> Usage {
+        Usage {
+            requests: self.requests.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Usage of all peers of a listener, and the quota that applies to each of them
+///
+/// This is cheap to clone, all clones share the same numbers.
+#[derive(Debug, Clone, Default)]
+pub struct QuotaTracker {
+    quota: Quota,
+    peers: Arc<Mutex<BTreeMap<String, Arc<Counters>>>>,
+}
+
+impl QuotaTracker {
+    /// Create a tracker that enforces `quota` for each peer
+    ///
+    /// Use `Quota::default()` to only account usage.
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            peers: Default::default(),
+        }
+    }
+
+    /// The quota for each peer
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// Current usage of a peer, zero if it was never seen
+    pub fn usage(&self, peer: &str) -> Usage {
+        let peers = self.peers.lock().unwrap();
+        peers.get(peer).map(|c| c.usage()).unwrap_or_default()
+    }
+
+    /// Current usage of all peers that were seen
+    pub fn all_usage(&self) -> Vec<(String, Usage)> {
+        let peers = self.peers.lock().unwrap();
+        peers
+            .iter()
+            .map(|(peer, c)| (peer.clone(), c.usage()))
+            .collect()
+    }
+
+    /// Start accounting a peer from zero, e.g. at the start of a billing period
+    ///
+    /// Returns the usage up to now. Requests that are still running keep counting
+    /// their bytes towards the old usage.
+    pub fn reset(&self, peer: &str) -> Usage {
+        let mut peers = self.peers.lock().unwrap();
+        peers.remove(peer).map(|c| c.usage()).unwrap_or_default()
+    }
+
+    /// Account a new request of a peer, unless it exceeded its quota
+    pub(crate) fn start_request(&self, peer: &str) -> Result<Account, QuotaExceeded> {
+        let mut peers = self.peers.lock().unwrap();
+        let counters = peers.entry(peer.to_string()).or_default().clone();
+        if self.quota.exceeded(&counters.usage()) {
+            counters.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(QuotaExceeded);
+        }
+        counters.requests.fetch_add(1, Ordering::Relaxed);
+        Ok(Account(counters))
+    }
+}
+
+/// Counts the bytes of one channel towards the usage of its peer
+#[derive(Debug, Clone)]
+pub(crate) struct Account(Arc<Counters>);
+
+impl Account {
+    pub fn received(&self, bytes: usize) {
+        self.0
+            .bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self, bytes: usize) {
+        self.0.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Reject a channel of a peer that exceeded its quota
+pub(crate) fn reject(mut send: quinn::SendStream, mut recv: quinn::RecvStream) {
+    let code = quinn::VarInt::from_u32(QUOTA_EXCEEDED_CODE);
+    send.reset(code).ok();
+    recv.stop(code).ok();
+}
+
+/// Turn the error of a channel that was rejected by [`reject`] into [`QuotaExceeded`]
+pub(crate) fn map_error(cause: io::Error) -> io::Error {
+    let code = quinn::VarInt::from_u32(QUOTA_EXCEEDED_CODE);
+    let rejected = match cause.get_ref() {
+        Some(inner) => match (
+            inner.downcast_ref::<quinn::ReadError>(),
+            inner.downcast_ref::<quinn::WriteError>(),
+        ) {
+            (Some(quinn::ReadError::Reset(c)), _) | (_, Some(quinn::WriteError::Stopped(c))) => {
+                *c == code
+            }
+            _ => false,
+        },
+        None => false,
+    };
+    if rejected {
+        QuotaExceeded.into()
+    } else {
+        cause
+    }
+}
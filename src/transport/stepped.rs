@@ -0,0 +1,407 @@
+//! Deterministic memory transport with manual message delivery
+//!
+//! Unlike the flume transport, frames sent on this transport are not delivered to
+//! the other side until a [`Controller`] says so. There are no timers or hidden
+//! sleeps involved, so this works well together with `tokio::time::pause` when
+//! testing timeouts: nothing happens unless the test steps delivery.
+//!
+//! Channels are opened and accepted immediately. Each sent frame, and the end of
+//! each send side, is queued as a pending *event*. [`Controller::step`] delivers the
+//! oldest pending event, [`Controller::deliver_all`] delivers everything that is
+//! currently pending. With [`Controller::set_auto_deliver`] events are delivered as
+//! soon as they are sent.
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    error, fmt,
+    future::{poll_fn, Future},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use futures_lite::Stream;
+use futures_sink::Sink;
+
+use super::{ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes};
+use crate::RpcMessage;
+
+/// A pending event, delivering a frame or closing a receive side
+type Event = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct ControllerState {
+    pending: VecDeque<Event>,
+    auto_deliver: bool,
+    /// Wakers of tasks waiting for pending events
+    waiting: Vec<Waker>,
+}
+
+/// Controls delivery of frames for a [`channel`]
+///
+/// This is cheap to clone, all clones control the same transport.
+#[derive(Clone, Default)]
+pub struct Controller(Arc<Mutex<ControllerState>>);
+
+impl fmt::Debug for Controller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Controller")
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+impl Controller {
+    /// Number of events that are waiting to be delivered
+    pub fn pending(&self) -> usize {
+        self.0.lock().unwrap().pending.len()
+    }
+
+    /// Deliver the oldest pending event
+    ///
+    /// Returns false if there was nothing to deliver.
+    pub fn step(&self) -> bool {
+        // release the lock before delivering, delivery wakes up other tasks
+        let event = self.0.lock().unwrap().pending.pop_front();
+        match event {
+            Some(event) => {
+                event();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deliver all currently pending events, returning how many were delivered
+    ///
+    /// Events that are queued while delivering are not delivered.
+    pub fn deliver_all(&self) -> usize {
+        let n = self.pending();
+        for _ in 0..n {
+            self.step();
+        }
+        n
+    }
+
+    /// Drop the oldest pending event without delivering it
+    ///
+    /// Returns false if there was nothing to drop.
+    pub fn drop_next(&self) -> bool {
+        self.0.lock().unwrap().pending.pop_front().is_some()
+    }
+
+    /// Deliver events as soon as they are sent
+    ///
+    /// Enabling this delivers all currently pending events.
+    pub fn set_auto_deliver(&self, value: bool) {
+        self.0.lock().unwrap().auto_deliver = value;
+        if value {
+            while self.step() {}
+        }
+    }
+
+    /// Wait until there is at least one pending event
+    pub async fn wait_pending(&self) {
+        poll_fn(|cx| {
+            let mut state = self.0.lock().unwrap();
+            if state.pending.is_empty() {
+                state.waiting.push(cx.waker().clone());
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+        .await
+    }
+
+    fn push(&self, event: Event) {
+        let mut state = self.0.lock().unwrap();
+        if state.auto_deliver {
+            drop(state);
+            event();
+        } else {
+            state.pending.push_back(event);
+            state.waiting.drain(..).for_each(Waker::wake);
+        }
+    }
+}
+
+struct InboxState<T> {
+    items: VecDeque<T>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// Receiving end of a queue, shared between the sender and the receiver
+struct Inbox<T>(Arc<Mutex<InboxState<T>>>);
+
+impl<T> Clone for Inbox<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Inbox<T> {
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(InboxState {
+            items: VecDeque::new(),
+            closed: false,
+            waker: None,
+        })))
+    }
+
+    fn push(&self, item: T) {
+        let mut state = self.0.lock().unwrap();
+        state.items.push_back(item);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.0.lock().unwrap();
+        if let Some(item) = state.items.pop_front() {
+            Poll::Ready(Some(item))
+        } else if state.closed {
+            Poll::Ready(None)
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Send side of a stepped channel
+pub struct SendSink<T: Send + 'static> {
+    inbox: Inbox<T>,
+    controller: Controller,
+    closed: bool,
+}
+
+impl<T: Send + 'static> fmt::Debug for SendSink<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendSink")
+            .field("closed", &self.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> SendSink<T> {
+    /// Queue the end of the stream, once
+    fn close(&mut self) {
+        if !std::mem::replace(&mut self.closed, true) {
+            let inbox = self.inbox.clone();
+            self.controller.push(Box::new(move || inbox.close()));
+        }
+    }
+}
+
+impl<T: RpcMessage> Sink<T> for SendSink<T> {
+    type Error = Infallible;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let inbox = self.inbox.clone();
+        self.controller.push(Box::new(move || inbox.push(item)));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: Send + 'static> Drop for SendSink<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Receive side of a stepped channel
+pub struct RecvStream<T>(Inbox<T>);
+
+impl<T> fmt::Debug for RecvStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecvStream").finish_non_exhaustive()
+    }
+}
+
+impl<T> Stream for RecvStream<T> {
+    type Item = Result<T, Infallible>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_next(cx).map(|item| item.map(Ok))
+    }
+}
+
+type Socket<In, Out> = (SendSink<Out>, RecvStream<In>);
+
+/// Keeps the accept queue open while any connector is alive
+struct ConnectorGuard<T>(Inbox<T>);
+
+impl<T> Drop for ConnectorGuard<T> {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// A stepped listener
+///
+/// Created using [channel].
+pub struct StepListener<In: RpcMessage, Out: RpcMessage> {
+    accept: Inbox<Socket<In, Out>>,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Clone for StepListener<In, Out> {
+    fn clone(&self) -> Self {
+        Self {
+            accept: self.accept.clone(),
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> fmt::Debug for StepListener<In, Out> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StepListener").finish_non_exhaustive()
+    }
+}
+
+/// A stepped connector
+///
+/// Created using [channel].
+pub struct StepConnector<In: RpcMessage, Out: RpcMessage> {
+    accept: Arc<ConnectorGuard<Socket<Out, In>>>,
+    controller: Controller,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Clone for StepConnector<In, Out> {
+    fn clone(&self) -> Self {
+        Self {
+            accept: self.accept.clone(),
+            controller: self.controller.clone(),
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> fmt::Debug for StepConnector<In, Out> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StepConnector").finish_non_exhaustive()
+    }
+}
+
+/// AcceptError for stepped channels.
+#[derive(Debug)]
+pub enum AcceptError {
+    /// All connectors were dropped
+    RemoteDropped,
+}
+
+impl fmt::Display for AcceptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl error::Error for AcceptError {}
+
+impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for StepListener<In, Out> {
+    type SendError = Infallible;
+    type RecvError = Infallible;
+    type OpenError = Infallible;
+    type AcceptError = AcceptError;
+}
+
+impl<In: RpcMessage, Out: RpcMessage> StreamTypes for StepListener<In, Out> {
+    type In = In;
+    type Out = Out;
+    type SendSink = SendSink<Out>;
+    type RecvStream = RecvStream<In>;
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Listener for StepListener<In, Out> {
+    fn accept(
+        &self,
+    ) -> impl Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::AcceptError>> + Send
+    {
+        let accept = self.accept.clone();
+        async move {
+            poll_fn(|cx| accept.poll_next(cx))
+                .await
+                .ok_or(AcceptError::RemoteDropped)
+        }
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        &[LocalAddr::Mem]
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for StepConnector<In, Out> {
+    type SendError = Infallible;
+    type RecvError = Infallible;
+    type OpenError = Infallible;
+    type AcceptError = AcceptError;
+}
+
+impl<In: RpcMessage, Out: RpcMessage> StreamTypes for StepConnector<In, Out> {
+    type In = In;
+    type Out = Out;
+    type SendSink = SendSink<Out>;
+    type RecvStream = RecvStream<In>;
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Connector for StepConnector<In, Out> {
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let local = Inbox::new();
+        let remote = Inbox::new();
+        let remote_chan = (
+            SendSink {
+                inbox: local.clone(),
+                controller: self.controller.clone(),
+                closed: false,
+            },
+            RecvStream(remote.clone()),
+        );
+        self.accept.0.push(remote_chan);
+        let local_chan = (
+            SendSink {
+                inbox: remote,
+                controller: self.controller.clone(),
+                closed: false,
+            },
+            RecvStream(local),
+        );
+        Ok(local_chan)
+    }
+}
+
+/// Create a stepped listener, a connected connector, and the controller for both.
+pub fn channel<Req: RpcMessage, Res: RpcMessage>(
+) -> (StepListener<Req, Res>, StepConnector<Res, Req>, Controller) {
+    let controller = Controller::default();
+    let accept = Inbox::new();
+    let listener = StepListener {
+        accept: accept.clone(),
+    };
+    let connector = StepConnector {
+        accept: Arc::new(ConnectorGuard(accept)),
+        controller: controller.clone(),
+    };
+    (listener, connector, controller)
+}
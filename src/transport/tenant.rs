@@ -0,0 +1,241 @@
+//! Route the connections of one endpoint to several tenants
+//!
+//! A [`TenantRouter`] accepts the connections of a quinn endpoint and hands each of
+//! them to the listener of one tenant, so a single endpoint can serve many isolated
+//! customers. Every tenant gets its own [`QuinnListener`], which is served by its own
+//! [`RpcServer`](crate::RpcServer) and handler, and only ever sees the connections
+//! that were routed to it.
+//!
+//! The tenant of a connection is picked by the [`Route`] of the router: the TLS server
+//! name the client asked for, the negotiated ALPN protocol, or a token the client
+//! presents with [`present_token`] right after connecting. Connections for an unknown
+//! tenant, or for a tenant that is at its [`TenantLimits`], are closed with
+//! [`TENANT_REJECTED_CODE`].
+//!
+//! Requests and bytes can be limited per tenant as well, by setting a
+//! [`QuotaTracker`](super::quota::QuotaTracker) on the listener of the tenant.
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use super::{
+    quinn::{get_handshake_data, QuinnListener},
+    util::spawn_named,
+};
+use crate::RpcMessage;
+
+/// Application error code used to close connections that can't be routed to a tenant
+pub const TENANT_REJECTED_CODE: u32 = 0x74_65_6e_74;
+
+/// Maximum size of a token presented with [`present_token`]
+const MAX_TOKEN_LEN: usize = 1024;
+
+/// Time a client has to present its token after connecting
+const TOKEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the tenant of a connection is picked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// The server name the client sent in the TLS handshake (SNI)
+    ServerName,
+    /// The ALPN protocol negotiated in the TLS handshake
+    ///
+    /// The protocols of all tenants must be set in the crypto config of the endpoint.
+    Alpn,
+    /// A token the client sends with [`present_token`] right after connecting
+    ///
+    /// The endpoint must allow at least one incoming unidirectional stream per
+    /// connection.
+    Token,
+}
+
+impl Route {
+    /// The tenant key of a connection, if it has one
+    async fn key(self, connection: &quinn::Connection) -> Option<String> {
+        match self {
+            Route::ServerName => get_handshake_data(connection)?.server_name,
+            Route::Alpn => {
+                let protocol = get_handshake_data(connection)?.protocol?;
+                String::from_utf8(protocol).ok()
+            }
+            Route::Token => {
+                let read = async {
+                    let mut recv = connection.accept_uni().await.ok()?;
+                    recv.read_to_end(MAX_TOKEN_LEN).await.ok()
+                };
+                let token = tokio::time::timeout(TOKEN_TIMEOUT, read).await.ok()??;
+                String::from_utf8(token).ok()
+            }
+        }
+    }
+}
+
+/// Present a token to a [`TenantRouter`] that routes by [`Route::Token`]
+///
+/// Call this right after connecting, before opening any channels.
+pub async fn present_token(connection: &quinn::Connection, token: &str) -> io::Result<()> {
+    let mut send = connection.open_uni().await?;
+    send.write_all(token.as_bytes()).await?;
+    send.finish()?;
+    Ok(())
+}
+
+/// Limits for the connections of one tenant, unlimited by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TenantLimits {
+    max_connections: Option<usize>,
+}
+
+impl TenantLimits {
+    /// Reject new connections once the tenant has `max` open connections
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+}
+
+struct Tenant {
+    sender: flume::Sender<quinn::Connection>,
+    limits: TenantLimits,
+    active: Arc<AtomicUsize>,
+}
+
+type Tenants = Arc<Mutex<BTreeMap<String, Tenant>>>;
+
+/// Routes the connections of an endpoint to tenants, see the [module docs](self)
+///
+/// Dropping the router stops accepting connections, the listeners of the tenants
+/// fail to accept once their connections are gone.
+pub struct TenantRouter {
+    local_addr: SocketAddr,
+    tenants: Tenants,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl fmt::Debug for TenantRouter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tenants = self.tenants.lock().unwrap();
+        f.debug_struct("TenantRouter")
+            .field("local_addr", &self.local_addr)
+            .field("tenants", &tenants.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl TenantRouter {
+    /// Route the connections of a server endpoint
+    pub fn new(endpoint: quinn::Endpoint, route: Route) -> io::Result<Self> {
+        let local_addr = endpoint.local_addr()?;
+        let tenants = Tenants::default();
+        let task = spawn_named(
+            format_args!("quic-rpc quinn tenant router"),
+            Self::endpoint_handler(endpoint, route, tenants.clone()),
+        );
+        Ok(Self {
+            local_addr,
+            tenants,
+            task,
+        })
+    }
+
+    /// Add a tenant and return the listener for its connections
+    ///
+    /// Adding a tenant with the same key again replaces it. The old listener keeps
+    /// serving the connections it already has, new ones go to the new listener.
+    pub fn tenant<In: RpcMessage, Out: RpcMessage>(
+        &self,
+        key: impl Into<String>,
+        limits: TenantLimits,
+    ) -> QuinnListener<In, Out> {
+        let (sender, receiver) = flume::bounded(16);
+        let tenant = Tenant {
+            sender,
+            limits,
+            active: Default::default(),
+        };
+        self.tenants.lock().unwrap().insert(key.into(), tenant);
+        QuinnListener::handle_connections(receiver, self.local_addr)
+    }
+
+    /// Remove a tenant, new connections for it are rejected
+    ///
+    /// Returns false if there was no such tenant.
+    pub fn remove(&self, key: &str) -> bool {
+        self.tenants.lock().unwrap().remove(key).is_some()
+    }
+
+    /// Number of open connections of a tenant
+    pub fn active_connections(&self, key: &str) -> usize {
+        let tenants = self.tenants.lock().unwrap();
+        tenants
+            .get(key)
+            .map(|tenant| tenant.active.load(Ordering::Relaxed))
+            .unwrap_or_default()
+    }
+
+    async fn endpoint_handler(endpoint: quinn::Endpoint, route: Route, tenants: Tenants) {
+        while let Some(incoming) = endpoint.accept().await {
+            // routing may have to wait for a token, so don't hold up other connections
+            spawn_named(
+                format_args!("quic-rpc quinn tenant routing"),
+                Self::route(incoming, route, tenants.clone()),
+            );
+        }
+    }
+
+    async fn route(incoming: quinn::Incoming, route: Route, tenants: Tenants) {
+        let connection = match incoming.await {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::warn!("Error accepting connection: {}", e);
+                return;
+            }
+        };
+        let reject = |reason: &str| {
+            tracing::debug!(
+                "Rejecting connection from {}: {reason}",
+                connection.remote_address()
+            );
+            connection.close(TENANT_REJECTED_CODE.into(), reason.as_bytes());
+        };
+        let Some(key) = route.key(&connection).await else {
+            return reject("no tenant");
+        };
+        let (sender, active) = {
+            let tenants = tenants.lock().unwrap();
+            let Some(tenant) = tenants.get(&key) else {
+                return reject("unknown tenant");
+            };
+            let active = tenant.active.load(Ordering::Relaxed);
+            if tenant
+                .limits
+                .max_connections
+                .is_some_and(|max| active >= max)
+            {
+                return reject("tenant at capacity");
+            }
+            tenant.active.fetch_add(1, Ordering::Relaxed);
+            (tenant.sender.clone(), tenant.active.clone())
+        };
+        if sender.send_async(connection.clone()).await.is_ok() {
+            // count the connection against the limits of the tenant while it is open
+            connection.closed().await;
+        } else {
+            reject("tenant not listening");
+        }
+        active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for TenantRouter {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
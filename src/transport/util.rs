@@ -1,29 +1,139 @@
 use std::{
+    fmt,
+    future::Future,
+    io,
+    marker::PhantomData,
     pin::Pin,
-    task::{self, Poll},
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    task::{self, ready, Poll},
+    time::Duration,
 };
 
+use crate::RpcMessage;
 use bincode::Options;
 use futures_lite::Stream;
 use futures_sink::Sink;
 use pin_project::pin_project;
 use serde::{de::DeserializeOwned, Serialize};
-use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_util::codec::LengthDelimitedCodec;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    runtime::Handle,
+    task::JoinHandle,
+};
+use tokio_util::{
+    bytes::{BufMut, Bytes, BytesMut},
+    codec::{Encoder, LengthDelimitedCodec},
+};
 
 type BincodeEncoding =
     bincode::config::WithOtherIntEncoding<bincode::DefaultOptions, bincode::config::FixintEncoding>;
+
+fn bincode_options() -> BincodeEncoding {
+    bincode::DefaultOptions::new().with_fixint_encoding()
+}
+
+fn invalid_data(cause: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, cause)
+}
+
+/// An encoded message, followed by raw byte sections
+///
+/// Sections are only produced with the `zero-copy` feature, see [`crate::blob`].
+struct Frame {
+    message: Vec<u8>,
+    sections: Vec<Bytes>,
+}
+
+impl Frame {
+    fn encode<T: Serialize>(item: &T) -> io::Result<Self> {
+        // `serialize` runs the serializer twice to compute the size first, which
+        // would collect every section twice
+        #[cfg(feature = "zero-copy")]
+        let (message, sections) = crate::blob::encode_sections(|| {
+            let mut message = Vec::new();
+            bincode_options()
+                .serialize_into(&mut message, item)
+                .map(|_| message)
+        });
+        #[cfg(not(feature = "zero-copy"))]
+        let (message, sections) = (bincode_options().serialize(item), Vec::new());
+        Ok(Self {
+            message: message.map_err(invalid_data)?,
+            sections,
+        })
+    }
+
+    fn decode<T: DeserializeOwned>(frame: BytesMut) -> io::Result<T> {
+        #[cfg(feature = "zero-copy")]
+        {
+            let frame = frame.freeze();
+            let mut reader = &frame[..];
+            let (item, sections_start) = crate::blob::decode_sections(frame.clone(), || {
+                bincode_options().deserialize_from(&mut reader)
+            });
+            let item = item.map_err(invalid_data)?;
+            if frame.len() - reader.len() != sections_start {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "frame length does not match message",
+                ));
+            }
+            Ok(item)
+        }
+        #[cfg(not(feature = "zero-copy"))]
+        bincode_options().deserialize(&frame).map_err(invalid_data)
+    }
+
+    fn len(&self) -> usize {
+        self.message.len() + self.sections.iter().map(Bytes::len).sum::<usize>()
+    }
+}
+
+/// Writes frames with the same length prefix that [`LengthDelimitedCodec`] reads
+///
+/// The message and the sections are written directly into the write buffer.
+struct FrameEncoder {
+    max_frame_length: usize,
+}
+
+impl Encoder<Frame> for FrameEncoder {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> io::Result<()> {
+        let len = frame.len();
+        if len > self.max_frame_length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame size too big",
+            ));
+        }
+        dst.reserve(4 + len);
+        dst.put_u32(len as u32);
+        dst.extend_from_slice(&frame.message);
+        for section in &frame.sections {
+            dst.extend_from_slice(section);
+        }
+        Ok(())
+    }
+}
+
 /// Wrapper that wraps a bidirectional binary stream in a length delimited codec and bincode with fast fixint encoding
 /// to get a bidirectional stream of rpc Messages
 #[pin_project]
-pub struct FramedBincodeRead<T, In>(
+pub struct FramedBincodeRead<T, In> {
     #[pin]
-    tokio_serde::SymmetricallyFramed<
-        tokio_util::codec::FramedRead<T, tokio_util::codec::LengthDelimitedCodec>,
-        In,
-        tokio_serde::formats::SymmetricalBincode<In, BincodeEncoding>,
-    >,
-);
+    framed: tokio_util::codec::FramedRead<T, LengthDelimitedCodec>,
+    /// Filter for the first frame, taken when the first frame is received
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    filter: Option<super::filter::RequestFilter>,
+    /// Keeps the channel counted as open on its connection
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    stream: Option<super::connections::StreamGuard>,
+    /// Counts received bytes towards the usage of the peer
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    account: Option<super::quota::Account>,
+    _p: PhantomData<fn() -> In>,
+}
 
 impl<T: AsyncRead, In: DeserializeOwned> FramedBincodeRead<T, In> {
     /// Wrap a socket in a length delimited codec and bincode with fast fixint encoding
@@ -32,15 +142,50 @@ impl<T: AsyncRead, In: DeserializeOwned> FramedBincodeRead<T, In> {
         let framing = LengthDelimitedCodec::builder()
             .max_frame_length(max_frame_length)
             .new_codec();
-        // create the actual framing. This turns the AsyncRead/AsyncWrite into a Stream/Sink of Bytes/BytesMut
+        // create the actual framing. This turns the AsyncRead/AsyncWrite into a Stream of BytesMut
         let framed = tokio_util::codec::FramedRead::new(inner, framing);
-        // configure bincode with fixint encoding
-        let bincode_options: BincodeEncoding =
-            bincode::DefaultOptions::new().with_fixint_encoding();
-        let bincode = tokio_serde::formats::Bincode::from(bincode_options);
-        // create the actual framing. This turns the Stream/Sink of Bytes/BytesMut into a Stream/Sink of In/Out
-        let framed = tokio_serde::Framed::new(framed, bincode);
-        Self(framed)
+        Self {
+            framed,
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            filter: None,
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            stream: None,
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            account: None,
+            _p: PhantomData,
+        }
+    }
+
+    /// Check the first frame with a filter before deserializing it
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub(crate) fn with_filter(mut self, filter: Option<super::filter::RequestFilter>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Count the channel as open on its connection while this is alive
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub(crate) fn with_stream_guard(
+        mut self,
+        stream: Option<super::connections::StreamGuard>,
+    ) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// The connection the channel belongs to, if it was accepted on one
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub(crate) fn connection(&self) -> Option<&super::connections::ConnectionHandle> {
+        self.stream
+            .as_ref()
+            .map(super::connections::StreamGuard::connection)
+    }
+
+    /// Count received bytes towards the usage of the peer
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub(crate) fn with_account(mut self, account: Option<super::quota::Account>) -> Self {
+        self.account = account;
+        self
     }
 }
 
@@ -50,46 +195,168 @@ impl<T, In> FramedBincodeRead<T, In> {
     /// This can be useful if you want to drop the framing and use the underlying stream directly
     /// after exchanging some messages.
     pub fn into_inner(self) -> T {
-        self.0.into_inner().into_inner()
+        self.framed.into_inner()
     }
 }
 
-impl<T: AsyncRead, In: DeserializeOwned> Stream for FramedBincodeRead<T, In> {
+impl<T: AsyncRead, In: RpcMessage> Stream for FramedBincodeRead<T, In> {
     type Item = Result<In, std::io::Error>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.project().0).poll_next(cx)
+        let this = self.project();
+        let frame = ready!(this.framed.poll_next(cx));
+        #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+        if let (Some(Ok(frame)), Some(account)) = (&frame, this.account.as_ref()) {
+            account.received(4 + frame.len());
+        }
+        let res = match frame {
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            Some(Ok(frame)) if !check_filter(this.filter.take(), &frame) => {
+                Some(Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "request rejected by filter",
+                )))
+            }
+            Some(Ok(frame)) => Some(Frame::decode(frame)),
+            Some(Err(cause)) => Some(Err(cause)),
+            None => None,
+        };
+        #[cfg(feature = "debug-frames")]
+        if let Some(Ok(item)) = &res {
+            log_frame("recv", item);
+        }
+        Poll::Ready(res)
+    }
+}
+
+/// Check a frame against an optional filter, frames that are too short are rejected
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+fn check_filter(filter: Option<super::filter::RequestFilter>, frame: &[u8]) -> bool {
+    let Some(filter) = filter else {
+        return true;
+    };
+    super::filter::RequestPeek::new(frame).is_some_and(|peek| filter.accepts(&peek))
+}
+
+struct WriteState<T> {
+    framed: tokio_util::codec::FramedWrite<T, FrameEncoder>,
+    /// A delayed flush is scheduled
+    flush_scheduled: bool,
+    /// Error of a delayed flush, reported by the next operation
+    error: Option<io::Error>,
+    /// Tasks waiting for the underlying stream
+    ///
+    /// The stream only remembers the last waker, but both the sink and a delayed flush
+    /// can be waiting for it.
+    waiting: Vec<task::Waker>,
+}
+
+impl<T: AsyncWrite + Unpin> WriteState<T> {
+    fn poll<R>(
+        &mut self,
+        cx: &mut task::Context<'_>,
+        f: impl FnOnce(
+            Pin<&mut tokio_util::codec::FramedWrite<T, FrameEncoder>>,
+            &mut task::Context<'_>,
+        ) -> Poll<io::Result<R>>,
+    ) -> Poll<io::Result<R>> {
+        if let Some(cause) = self.error.take() {
+            return Poll::Ready(Err(cause));
+        }
+        let res = f(Pin::new(&mut self.framed), cx);
+        if res.is_pending() {
+            if !self.waiting.iter().any(|w| w.will_wake(cx.waker())) {
+                self.waiting.push(cx.waker().clone());
+            }
+        } else {
+            self.waiting.drain(..).for_each(task::Waker::wake);
+        }
+        res
+    }
+}
+
+type SharedWriteState<T> = Arc<Mutex<WriteState<T>>>;
+
+/// When to write buffered frames of a [`FramedBincodeWrite`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FlushConfig {
+    /// Delay writes by up to this long, `None` to write on every flush
+    pub interval: Option<Duration>,
+    /// Buffered bytes above which a delayed write is done immediately
+    pub threshold: usize,
+}
+
+impl Default for FlushConfig {
+    fn default() -> Self {
+        Self {
+            interval: None,
+            threshold: 16 * 1024,
+        }
     }
 }
 
 /// Wrapper that wraps a bidirectional binary stream in a length delimited codec and bincode with fast fixint encoding
 /// to get a bidirectional stream of rpc Messages
-#[pin_project]
-pub struct FramedBincodeWrite<T, Out>(
-    #[pin]
-    tokio_serde::SymmetricallyFramed<
-        tokio_util::codec::FramedWrite<T, tokio_util::codec::LengthDelimitedCodec>,
-        Out,
-        tokio_serde::formats::SymmetricalBincode<Out, BincodeEncoding>,
-    >,
-);
-
-impl<T: AsyncWrite, Out: Serialize> FramedBincodeWrite<T, Out> {
+///
+/// Each frame is encoded into a single write buffer together with its length prefix.
+/// By default the buffer is written to the stream whenever the sink is flushed. With a
+/// flush interval, flushing only schedules a write at most that long after, so frames
+/// sent in quick succession are written together. The buffer is written immediately
+/// if it exceeds the flush threshold, or if the sink is flushed again without sending
+/// a frame in between.
+pub struct FramedBincodeWrite<T, Out> {
+    /// Only `None` after [`FramedBincodeWrite::into_inner`]
+    state: Option<SharedWriteState<T>>,
+    flush: FlushConfig,
+    /// A frame was sent since the last flush
+    unflushed: bool,
+    /// Writes what is left in the buffer when the sink is dropped
+    ///
+    /// This is a function pointer so `Drop` does not need the bounds for spawning.
+    flush_on_drop: fn(SharedWriteState<T>),
+    /// Counts sent bytes towards the usage of the peer
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    account: Option<super::quota::Account>,
+    _p: PhantomData<fn(Out)>,
+}
+
+impl<T, Out> FramedBincodeWrite<T, Out>
+where
+    T: AsyncWrite + Unpin + Send + 'static,
+    Out: Serialize,
+{
     /// Wrap a socket in a length delimited codec and bincode with fast fixint encoding
     pub fn new(inner: T, max_frame_length: usize) -> Self {
-        // configure length delimited codec with max frame length
-        let framing = LengthDelimitedCodec::builder()
-            .max_frame_length(max_frame_length)
-            .new_codec();
-        // create the actual framing. This turns the AsyncRead/AsyncWrite into a Stream/Sink of Bytes/BytesMut
+        let framing = FrameEncoder { max_frame_length };
+        // create the actual framing. This turns the AsyncRead/AsyncWrite into a Sink of frames
         let framed = tokio_util::codec::FramedWrite::new(inner, framing);
-        // configure bincode with fixint encoding
-        let bincode_options: BincodeEncoding =
-            bincode::DefaultOptions::new().with_fixint_encoding();
-        let bincode = tokio_serde::formats::SymmetricalBincode::from(bincode_options);
-        // create the actual framing. This turns the Stream/Sink of Bytes/BytesMut into a Stream/Sink of In/Out
-        let framed = tokio_serde::SymmetricallyFramed::new(framed, bincode);
-        Self(framed)
+        Self {
+            state: Some(Arc::new(Mutex::new(WriteState {
+                framed,
+                flush_scheduled: false,
+                error: None,
+                waiting: Vec::new(),
+            }))),
+            flush: FlushConfig::default(),
+            unflushed: false,
+            flush_on_drop: Self::flush_on_drop,
+            #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+            account: None,
+            _p: PhantomData,
+        }
+    }
+
+    /// Set when buffered frames are written, see [`FramedBincodeWrite`]
+    pub(crate) fn with_flush(mut self, config: FlushConfig) -> Self {
+        self.flush = config;
+        self
+    }
+
+    /// Count sent bytes towards the usage of the peer
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub(crate) fn with_account(mut self, account: Option<super::quota::Account>) -> Self {
+        self.account = account;
+        self
     }
 }
 
@@ -98,39 +365,220 @@ impl<T, Out> FramedBincodeWrite<T, Out> {
     ///
     /// This can be useful if you want to drop the framing and use the underlying stream directly
     /// after exchanging some messages.
-    pub fn into_inner(self) -> T {
-        self.0.into_inner().into_inner()
+    ///
+    /// Frames that have not been written yet are discarded.
+    pub fn into_inner(mut self) -> T {
+        let state = self.state.take().expect("state is present");
+        // delayed flushes only hold a weak reference
+        let state = Arc::into_inner(state).expect("no other strong references");
+        state.into_inner().unwrap().framed.into_inner()
+    }
+
+    /// Run `f` with the underlying stream
+    #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+    pub(crate) fn with_inner<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(self.lock().framed.get_mut())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, WriteState<T>> {
+        self.state
+            .as_ref()
+            .expect("state is present")
+            .lock()
+            .unwrap()
     }
 }
 
-impl<T: AsyncWrite, Out: Serialize> Sink<Out> for FramedBincodeWrite<T, Out> {
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+impl<Out> FramedBincodeWrite<quinn::SendStream, Out> {
+    /// Poll whether the peer stopped reading the stream, or read all of it
+    pub(crate) fn poll_stopped(&self, cx: &mut task::Context<'_>) -> Poll<()> {
+        self.with_inner(|stream| stream.poll_stopped(cx).map(|_| ()))
+    }
+}
+
+impl<T, Out> FramedBincodeWrite<T, Out>
+where
+    T: AsyncWrite + Unpin + Send + 'static,
+{
+    fn schedule_flush(state: &SharedWriteState<T>, interval: Duration) {
+        let state = Arc::downgrade(state);
+        tokio::spawn(async move {
+            tokio::time::sleep(interval).await;
+            Self::flush_weak(state).await;
+        });
+    }
+
+    fn flush_on_drop(state: SharedWriteState<T>) {
+        if state.lock().unwrap().framed.write_buffer().is_empty() {
+            return;
+        }
+        if let Ok(handle) = Handle::try_current() {
+            handle.spawn(async move {
+                std::future::poll_fn(|cx| {
+                    let mut state = state.lock().unwrap();
+                    let res = ready!(state.poll(cx, |framed, cx| framed.poll_flush(cx)));
+                    Poll::Ready(res.ok())
+                })
+                .await;
+            });
+        }
+    }
+
+    /// Flush if the sink is still alive, a dropped sink flushes on its own
+    async fn flush_weak(state: Weak<Mutex<WriteState<T>>>) {
+        std::future::poll_fn(|cx| {
+            let Some(state) = state.upgrade() else {
+                return Poll::Ready(());
+            };
+            let mut state = state.lock().unwrap();
+            let res = ready!(state.poll(cx, |framed, cx| framed.poll_flush(cx)));
+            state.flush_scheduled = false;
+            state.error = res.err();
+            Poll::Ready(())
+        })
+        .await
+    }
+}
+
+impl<T, Out> Sink<Out> for FramedBincodeWrite<T, Out>
+where
+    T: AsyncWrite + Unpin + Send + 'static,
+    Out: RpcMessage,
+{
     type Error = std::io::Error;
 
     fn poll_ready(
         self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_ready(cx)
+        // this writes the buffer if it is above the backpressure boundary
+        self.lock().poll(cx, |framed, cx| framed.poll_ready(cx))
     }
 
-    fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
-        Pin::new(&mut self.project().0).start_send(item)
+    fn start_send(mut self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
+        #[cfg(feature = "debug-frames")]
+        log_frame("send", &item);
+        let frame = Frame::encode(&item)?;
+        #[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+        if let Some(account) = &self.account {
+            account.sent(4 + frame.len());
+        }
+        self.unflushed = true;
+        Pin::new(&mut self.lock().framed).start_send(frame)
     }
 
     fn poll_flush(
-        self: Pin<&mut Self>,
+        mut self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_flush(cx)
+        let unflushed = std::mem::take(&mut self.unflushed);
+        let Some(interval) = self.flush.interval else {
+            return self.lock().poll(cx, |framed, cx| framed.poll_flush(cx));
+        };
+        let mut state = self.lock();
+        if !unflushed || state.framed.write_buffer().len() >= self.flush.threshold {
+            // an explicit flush, or enough data to be worth a write on its own
+            return state.poll(cx, |framed, cx| framed.poll_flush(cx));
+        }
+        if let Some(cause) = state.error.take() {
+            return Poll::Ready(Err(cause));
+        }
+        if !state.framed.write_buffer().is_empty() && !state.flush_scheduled {
+            state.flush_scheduled = true;
+            drop(state);
+            Self::schedule_flush(self.state.as_ref().expect("state is present"), interval);
+        }
+        Poll::Ready(Ok(()))
     }
 
     fn poll_close(
         self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_close(cx)
+        self.lock().poll(cx, |framed, cx| framed.poll_close(cx))
+    }
+}
+
+impl<T, Out> Drop for FramedBincodeWrite<T, Out> {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.take() {
+            (self.flush_on_drop)(state);
+        }
     }
 }
 
 // fn assert_sink<T>(_: &impl Sink<T>) {}
 // fn assert_stream<T>(_: &impl Stream<Item = T>) {}
+
+/// Log an encoded frame at trace level
+///
+/// Frames are logged to the `quic_rpc::frames` target. The hex encoded payload is
+/// additionally logged if the `quic_rpc::frames::payload` target is enabled.
+#[cfg(feature = "debug-frames")]
+fn log_frame(direction: &str, item: &(impl Serialize + fmt::Debug)) {
+    if !tracing::enabled!(target: "quic_rpc::frames", tracing::Level::TRACE) {
+        return;
+    }
+    // bincode encoding is deterministic, so this gives the same bytes as on the wire
+    let Ok(bytes) = bincode_options().serialize(item) else {
+        return;
+    };
+    let variant = crate::message::variant_name(item);
+    tracing::trace!(target: "quic_rpc::frames", direction, len = bytes.len(), variant);
+    if tracing::enabled!(target: "quic_rpc::frames::payload", tracing::Level::TRACE) {
+        tracing::trace!(
+            target: "quic_rpc::frames::payload",
+            direction,
+            payload = hex::encode(&bytes)
+        );
+    }
+}
+
+/// Spawn a task on the current runtime, giving it a name.
+///
+/// The name is only used when the `tokio-console` feature is enabled and the crate is
+/// compiled with `--cfg tokio_unstable`, so tasks show up with a useful name in
+/// tokio-console. Otherwise this is just [`tokio::spawn`].
+pub(crate) fn spawn_named<F>(name: fmt::Arguments<'_>, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(all(tokio_unstable, feature = "tokio-console"))]
+    {
+        tokio::task::Builder::new()
+            .name(&name.to_string())
+            .spawn(future)
+            .expect("failed to spawn task")
+    }
+    #[cfg(not(all(tokio_unstable, feature = "tokio-console")))]
+    {
+        let _ = name;
+        tokio::spawn(future)
+    }
+}
+
+/// Like [`spawn_named`], but spawns on the given runtime handle.
+pub(crate) fn spawn_named_on<F>(
+    name: fmt::Arguments<'_>,
+    future: F,
+    handle: &Handle,
+) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(all(tokio_unstable, feature = "tokio-console"))]
+    {
+        tokio::task::Builder::new()
+            .name(&name.to_string())
+            .spawn_on(future, handle)
+            .expect("failed to spawn task")
+    }
+    #[cfg(not(all(tokio_unstable, feature = "tokio-console")))]
+    {
+        let _ = name;
+        handle.spawn(future)
+    }
+}
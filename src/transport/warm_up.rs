@@ -0,0 +1,99 @@
+//! Warm up client connections right after connecting
+//!
+//! The first request on a fresh connection pays for more than the request itself: it
+//! may have to wait for stream credit from the server, and the congestion window
+//! starts small and without an RTT estimate. For latency critical requests, e.g. the
+//! first ones after a reconnect, the quinn and iroh-net connectors can do some of this
+//! work up front, see [`QuinnConnector::warm_up`](super::quinn::QuinnConnector::warm_up).
+//!
+//! [`WarmUp::streams`] opens streams as soon as a connection is established, which
+//! are then used for the next channels. [`WarmUp::ping`] sends an empty datagram, so
+//! the connection gets an RTT sample before the first request. Peers ignore the
+//! datagram, but it is only sent if the peer accepts datagrams at all.
+use std::collections::VecDeque;
+
+use futures_util::FutureExt;
+use tokio::sync::watch;
+
+/// What to do right after a connection is established, nothing by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WarmUp {
+    streams: usize,
+    ping: bool,
+}
+
+impl WarmUp {
+    /// Open up to `n` streams ahead of time, to be used by the next channels
+    ///
+    /// Fewer streams are opened if the server does not allow that many. Streams are
+    /// only opened again after a reconnect, not when they are used up.
+    pub fn streams(mut self, n: usize) -> Self {
+        self.streams = n;
+        self
+    }
+
+    /// Send a ping to prime the path
+    pub fn ping(mut self) -> Self {
+        self.ping = true;
+        self
+    }
+}
+
+type SocketInner = (quinn::SendStream, quinn::RecvStream);
+
+/// Streams of the current connection that were opened by a [`WarmUp`]
+#[derive(Debug)]
+pub(crate) struct WarmStreams {
+    config: watch::Receiver<WarmUp>,
+    streams: VecDeque<SocketInner>,
+}
+
+impl WarmStreams {
+    pub fn new(config: watch::Receiver<WarmUp>) -> Self {
+        Self {
+            config,
+            streams: VecDeque::new(),
+        }
+    }
+
+    /// Warm up a new connection, dropping the streams of the previous one
+    pub fn prime(&mut self, connection: &quinn::Connection) {
+        self.streams.clear();
+        let warm_up = *self.config.borrow_and_update();
+        if warm_up.ping && connection.max_datagram_size().is_some() {
+            if let Err(cause) = connection.send_datagram(Default::default()) {
+                tracing::debug!("failed to send warm-up ping: {cause}");
+            }
+        }
+        for _ in 0..warm_up.streams {
+            // don't wait for stream credit, that is what the first request would do
+            match connection.open_bi().now_or_never() {
+                Some(Ok(pair)) => self.streams.push_back(pair),
+                Some(Err(cause)) => {
+                    tracing::debug!("failed to open warm-up stream: {cause}");
+                    break;
+                }
+                None => break,
+            }
+        }
+        tracing::debug!("warmed up connection with {} streams", self.streams.len());
+    }
+
+    /// Wait until the warm-up config is changed
+    ///
+    /// Never completes once the connector is dropped.
+    pub async fn changed(&mut self) {
+        if self.config.changed().await.is_err() {
+            std::future::pending().await
+        }
+    }
+
+    /// Take a stream that was opened ahead of time on `connection`, if there is one left
+    pub fn take(&mut self, connection: &quinn::Connection) -> Option<SocketInner> {
+        if connection.close_reason().is_some() {
+            // let the caller find out about the closed connection by opening a stream
+            self.streams.clear();
+        }
+        self.streams.pop_front()
+    }
+}
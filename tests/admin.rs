@@ -0,0 +1,65 @@
+#![cfg(all(feature = "admin", feature = "flume-transport"))]
+use quic_rpc::{
+    admin::{AdminService, ConnectionsRequest, InFlightRequest, Introspection},
+    transport::flume,
+    RpcClient, RpcServer,
+};
+
+#[derive(Debug)]
+#[allow(dead_code)]
+enum Request {
+    Add(u64, u64),
+    Watch { path: String },
+}
+
+#[tokio::test]
+async fn admin_lists_in_flight_requests() -> anyhow::Result<()> {
+    let introspection = Introspection::new();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<AdminService, _>::new(server);
+    let handle = tokio::task::spawn({
+        let introspection = introspection.clone();
+        async move {
+            while let Ok(accepting) = server.accept().await {
+                let (req, chan) = accepting.read_first().await?;
+                introspection.clone().handle_rpc_request(req, chan).await?;
+            }
+            anyhow::Ok(())
+        }
+    });
+    let client = RpcClient::<AdminService, _>::new(client);
+
+    let add = introspection.request(&Request::Add(1, 2), None);
+    let watch = introspection.request(
+        &Request::Watch {
+            path: "/".to_string(),
+        },
+        Some("peer".to_string()),
+    );
+    watch.item_sent();
+    watch.item_sent();
+    let conn = introspection.connection("127.0.0.1:1234");
+
+    let in_flight = client.rpc(InFlightRequest).await?.0;
+    assert_eq!(in_flight.len(), 2);
+    assert_eq!(in_flight[0].variant, "Add");
+    assert_eq!(in_flight[0].items_sent, 0);
+    assert_eq!(in_flight[1].variant, "Watch");
+    assert_eq!(in_flight[1].peer.as_deref(), Some("peer"));
+    assert_eq!(in_flight[1].items_sent, 2);
+    let connections = client.rpc(ConnectionsRequest).await?.0;
+    assert_eq!(connections.len(), 1);
+    assert_eq!(connections[0].peer, "127.0.0.1:1234");
+
+    // dropping the guards removes the entries
+    drop(add);
+    drop(conn);
+    let in_flight = client.rpc(InFlightRequest).await?.0;
+    assert_eq!(in_flight.len(), 1);
+    assert_eq!(in_flight[0].variant, "Watch");
+    assert!(client.rpc(ConnectionsRequest).await?.0.is_empty());
+
+    drop(client);
+    handle.await??;
+    Ok(())
+}
@@ -0,0 +1,34 @@
+#![cfg(all(feature = "bench", feature = "flume-transport"))]
+use quic_rpc::{
+    bench::{self, EchoService, LoadConfig, Pattern},
+    transport::flume,
+    RpcClient, RpcServer,
+};
+
+#[tokio::test]
+async fn bench_all_patterns() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<EchoService, _>::new(server);
+    let handle = tokio::task::spawn(bench::serve(server));
+    let client = RpcClient::<EchoService, _>::new(client);
+
+    for pattern in [
+        Pattern::Rpc,
+        Pattern::ServerStreaming,
+        Pattern::BidiStreaming,
+    ] {
+        let config = LoadConfig::new(pattern)
+            .payload_size(100)
+            .items(3)
+            .requests(20)
+            .concurrency(4);
+        let report = bench::run(client.clone(), config).await?;
+        assert_eq!(report.requests(), 20);
+        let items = if pattern == Pattern::Rpc { 1 } else { 3 };
+        assert_eq!(report.bytes, 20 * items * 100);
+        assert!(report.latency(0.5) <= report.latency(0.99));
+    }
+
+    handle.abort();
+    Ok(())
+}
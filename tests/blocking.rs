@@ -0,0 +1,56 @@
+#![cfg(all(feature = "flume-transport", feature = "tokio-runtime"))]
+use quic_rpc::{
+    server::{RpcChannel, RpcServerError},
+    transport::flume,
+    RpcServer,
+};
+
+mod math;
+use math::*;
+
+/// The compute service, written as synchronous code
+async fn handle_blocking(
+    req: ComputeRequest,
+    chan: RpcChannel<ComputeService, flume::FlumeListener<ComputeRequest, ComputeResponse>>,
+) -> Result<(), RpcServerError<flume::FlumeListener<ComputeRequest, ComputeResponse>>> {
+    match req {
+        ComputeRequest::Sqr(msg) => {
+            chan.rpc_blocking(msg, (), |_, Sqr(n)| SqrResponse(n as u128 * n as u128))
+                .await
+        }
+        ComputeRequest::Sum(msg) => {
+            chan.client_streaming_blocking(msg, (), |_, _, updates| {
+                SumResponse(updates.map(|SumUpdate(n)| n as u128).sum())
+            })
+            .await
+        }
+        ComputeRequest::Fibonacci(msg) => {
+            chan.server_streaming_blocking(msg, (), |_, Fibonacci(n)| {
+                let fib = std::iter::successors(Some((0u128, 1u128)), |(a, b)| Some((*b, a + b)));
+                fib.take(n as usize).map(|(a, _)| FibonacciResponse(a))
+            })
+            .await
+        }
+        ComputeRequest::Multiply(msg) => {
+            chan.bidi_streaming_blocking(msg, (), |_, Multiply(factor), updates| {
+                updates.map(move |MultiplyUpdate(n)| MultiplyResponse(factor as u128 * n as u128))
+            })
+            .await
+        }
+        _ => Err(RpcServerError::UnexpectedStartMessage),
+    }
+}
+
+#[tokio::test]
+async fn blocking_handlers() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::task::spawn(async move {
+        while let Ok(accepting) = server.accept().await {
+            let (req, chan) = accepting.read_first().await?;
+            tokio::task::spawn(handle_blocking(req, chan));
+        }
+        anyhow::Ok(())
+    });
+    smoke_test(client).await
+}
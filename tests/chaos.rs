@@ -0,0 +1,92 @@
+#![cfg(all(feature = "chaos-transport", feature = "flume-transport"))]
+#![allow(non_local_definitions)]
+mod math;
+use std::time::{Duration, Instant};
+
+use futures_lite::StreamExt;
+use math::*;
+use quic_rpc::{
+    pattern::{rpc, server_streaming},
+    transport::{
+        chaos::{ChaosConfig, ChaosConnection, ChaosError},
+        flume,
+    },
+    RpcClient, RpcServer,
+};
+
+fn chaos_client(
+    config: ChaosConfig,
+) -> RpcClient<
+    ComputeService,
+    ChaosConnection<flume::FlumeConnector<ComputeResponse, ComputeRequest>>,
+> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::task::spawn(ComputeService::server(server));
+    RpcClient::new(ChaosConnection::new(client, config))
+}
+
+async fn fibonacci(config: ChaosConfig) -> Vec<u128> {
+    chaos_client(config)
+        .server_streaming(Fibonacci(20))
+        .await
+        .unwrap()
+        .map(|res| res.unwrap().0)
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn chaos_no_faults() -> anyhow::Result<()> {
+    let client = chaos_client(ChaosConfig::default());
+    assert_eq!(client.rpc(Sqr(3)).await?.0, 9);
+    Ok(())
+}
+
+#[tokio::test]
+async fn chaos_latency() -> anyhow::Result<()> {
+    let config = ChaosConfig::default().latency(Duration::from_millis(50), Duration::ZERO);
+    let client = chaos_client(config);
+    let start = Instant::now();
+    assert_eq!(client.rpc(Sqr(3)).await?.0, 9);
+    // one delay for opening, one for receiving the response
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    Ok(())
+}
+
+#[tokio::test]
+async fn chaos_reset() -> anyhow::Result<()> {
+    let client = chaos_client(ChaosConfig::default().reset_probability(1.0));
+    let res = client.rpc(Sqr(3)).await;
+    assert!(matches!(res, Err(rpc::Error::RecvError(ChaosError::Reset))));
+    let mut items = client.server_streaming(Fibonacci(10)).await?;
+    assert!(matches!(
+        items.next().await,
+        Some(Err(server_streaming::ItemError::RecvError(
+            ChaosError::Reset
+        )))
+    ));
+    assert!(items.next().await.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn chaos_drop_and_reorder_are_deterministic() -> anyhow::Result<()> {
+    let expected = fibonacci(ChaosConfig::default()).await;
+    assert_eq!(expected.len(), 20);
+
+    let config = ChaosConfig::default().seed(42).reorder_probability(0.5);
+    let reordered = fibonacci(config.clone()).await;
+    assert_ne!(reordered, expected);
+    assert_eq!(reordered, fibonacci(config).await);
+    let mut sorted = reordered.clone();
+    sorted.sort();
+    assert_eq!(sorted, expected);
+
+    let config = ChaosConfig::default().seed(42).drop_probability(0.5);
+    let dropped = fibonacci(config.clone()).await;
+    assert!(dropped.len() < expected.len());
+    assert!(dropped.iter().all(|item| expected.contains(item)));
+    assert_eq!(dropped, fibonacci(config).await);
+    Ok(())
+}
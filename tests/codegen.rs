@@ -0,0 +1,103 @@
+#![cfg(all(feature = "codegen", feature = "flume-transport"))]
+use futures_lite::StreamExt;
+use futures_util::SinkExt;
+use quic_rpc::{server::RpcServerError, transport::flume, Listener, RpcClient, RpcServer};
+
+mod compute {
+    include!("codegen/compute.rs");
+}
+
+use compute::*;
+
+#[test]
+fn generated_code_is_up_to_date() {
+    let code = quic_rpc::codegen::generate(include_str!("codegen/compute.rpc")).unwrap();
+    assert_eq!(code, include_str!("codegen/compute.rs"));
+}
+
+#[test]
+fn generated_typescript_is_up_to_date() {
+    let code = quic_rpc::codegen::generate_typescript(include_str!("codegen/compute.rpc")).unwrap();
+    assert_eq!(code, include_str!("codegen/compute.ts"));
+}
+
+#[test]
+fn parse_errors() {
+    let err = quic_rpc::codegen::generate("message A;\nservice S {\n  rpc a(A) A;\n}").unwrap_err();
+    assert_eq!(err.line, 3);
+    let err = quic_rpc::codegen::generate("service S {\n  oneway a(A) -> A;\n}").unwrap_err();
+    assert_eq!(err.line, 2);
+}
+
+async fn server<C: Listener<ComputeService>>(
+    server: RpcServer<ComputeService, C>,
+) -> Result<(), RpcServerError<C>> {
+    #[derive(Clone)]
+    struct Handler;
+    loop {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        match req {
+            ComputeRequest::Sqr(req) => {
+                chan.rpc(req, Handler, |_, Sqr(x)| async move {
+                    SqrResponse(x as u128 * x as u128)
+                })
+                .await?
+            }
+            ComputeRequest::Sum(req) => {
+                chan.client_streaming(req, Handler, |_, _, updates| async move {
+                    let total = updates.fold(0, |acc, SumUpdate(x)| acc + x as u128).await;
+                    SumResponse { total }
+                })
+                .await?
+            }
+            ComputeRequest::Fibonacci(req) => {
+                chan.server_streaming(req, Handler, |_, Fibonacci(n)| {
+                    futures_lite::stream::iter((0..n as u128).map(FibonacciResponse))
+                })
+                .await?
+            }
+            ComputeRequest::Multiply(req) => {
+                chan.bidi_streaming(req, Handler, |_, Multiply(x), updates| {
+                    updates
+                        .map(move |MultiplyUpdate(y)| MultiplyResponse(vec![x as u128 * y as u128]))
+                })
+                .await?
+            }
+            ComputeRequest::SumUpdate(_) | ComputeRequest::MultiplyUpdate(_) => {
+                return Err(RpcServerError::UnexpectedStartMessage)
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn generated_client() -> anyhow::Result<()> {
+    let (s, c) = flume::channel(1);
+    let server_handle = tokio::task::spawn(server(RpcServer::new(s)));
+    let client = ComputeClient::new(RpcClient::new(c));
+    assert_eq!(client.sqr(Sqr(4)).await?.0, 16);
+    let (mut sink, res) = client.sum(Sum).await?;
+    sink.send(SumUpdate(1)).await?;
+    sink.send(SumUpdate(2)).await?;
+    drop(sink);
+    assert_eq!(res.await?.total, 3);
+    let items = client
+        .fibonacci(Fibonacci(3))
+        .await?
+        .try_collect::<_, _, Vec<_>>()
+        .await?;
+    assert_eq!(items.len(), 3);
+    let (mut sink, mut res) = client.multiply(Multiply(2)).await?;
+    sink.send(MultiplyUpdate(3)).await?;
+    assert_eq!(res.next().await.unwrap()?.0, vec![6]);
+    // end the updates and wait for the responses to end, so the handler completes
+    // instead of being cancelled
+    drop(sink);
+    assert!(res.next().await.is_none());
+    drop((res, client));
+    assert!(matches!(
+        server_handle.await?,
+        Err(RpcServerError::Accept(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,298 @@
+// Generated by quic_rpc::codegen. Do not edit.
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct Sqr(pub u64);
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct SqrResponse(pub u128);
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct Sum;
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct SumUpdate(pub u64);
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct SumResponse {
+    pub total: u128,
+}
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct Fibonacci(pub u64);
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct FibonacciResponse(pub u128);
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct Multiply(pub u64);
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct MultiplyUpdate(pub u64);
+
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub struct MultiplyResponse(pub Vec<u128>);
+
+#[doc = "Request messages for ComputeService"]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub enum ComputeRequest {
+    Sqr(Sqr),
+    Sum(Sum),
+    SumUpdate(SumUpdate),
+    Fibonacci(Fibonacci),
+    Multiply(Multiply),
+    MultiplyUpdate(MultiplyUpdate),
+}
+
+impl From<Sqr> for ComputeRequest {
+    fn from(value: Sqr) -> Self {
+        Self::Sqr(value)
+    }
+}
+
+impl TryFrom<ComputeRequest> for Sqr {
+    type Error = ComputeRequest;
+
+    fn try_from(value: ComputeRequest) -> Result<Self, Self::Error> {
+        match value {
+            ComputeRequest::Sqr(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<Sum> for ComputeRequest {
+    fn from(value: Sum) -> Self {
+        Self::Sum(value)
+    }
+}
+
+impl TryFrom<ComputeRequest> for Sum {
+    type Error = ComputeRequest;
+
+    fn try_from(value: ComputeRequest) -> Result<Self, Self::Error> {
+        match value {
+            ComputeRequest::Sum(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<SumUpdate> for ComputeRequest {
+    fn from(value: SumUpdate) -> Self {
+        Self::SumUpdate(value)
+    }
+}
+
+impl TryFrom<ComputeRequest> for SumUpdate {
+    type Error = ComputeRequest;
+
+    fn try_from(value: ComputeRequest) -> Result<Self, Self::Error> {
+        match value {
+            ComputeRequest::SumUpdate(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<Fibonacci> for ComputeRequest {
+    fn from(value: Fibonacci) -> Self {
+        Self::Fibonacci(value)
+    }
+}
+
+impl TryFrom<ComputeRequest> for Fibonacci {
+    type Error = ComputeRequest;
+
+    fn try_from(value: ComputeRequest) -> Result<Self, Self::Error> {
+        match value {
+            ComputeRequest::Fibonacci(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<Multiply> for ComputeRequest {
+    fn from(value: Multiply) -> Self {
+        Self::Multiply(value)
+    }
+}
+
+impl TryFrom<ComputeRequest> for Multiply {
+    type Error = ComputeRequest;
+
+    fn try_from(value: ComputeRequest) -> Result<Self, Self::Error> {
+        match value {
+            ComputeRequest::Multiply(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<MultiplyUpdate> for ComputeRequest {
+    fn from(value: MultiplyUpdate) -> Self {
+        Self::MultiplyUpdate(value)
+    }
+}
+
+impl TryFrom<ComputeRequest> for MultiplyUpdate {
+    type Error = ComputeRequest;
+
+    fn try_from(value: ComputeRequest) -> Result<Self, Self::Error> {
+        match value {
+            ComputeRequest::MultiplyUpdate(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+#[doc = "Response messages for ComputeService"]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, ::serde::Serialize, ::serde::Deserialize)]
+pub enum ComputeResponse {
+    SqrResponse(SqrResponse),
+    SumResponse(SumResponse),
+    FibonacciResponse(FibonacciResponse),
+    MultiplyResponse(MultiplyResponse),
+}
+
+impl From<SqrResponse> for ComputeResponse {
+    fn from(value: SqrResponse) -> Self {
+        Self::SqrResponse(value)
+    }
+}
+
+impl TryFrom<ComputeResponse> for SqrResponse {
+    type Error = ComputeResponse;
+
+    fn try_from(value: ComputeResponse) -> Result<Self, Self::Error> {
+        match value {
+            ComputeResponse::SqrResponse(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<SumResponse> for ComputeResponse {
+    fn from(value: SumResponse) -> Self {
+        Self::SumResponse(value)
+    }
+}
+
+impl TryFrom<ComputeResponse> for SumResponse {
+    type Error = ComputeResponse;
+
+    fn try_from(value: ComputeResponse) -> Result<Self, Self::Error> {
+        match value {
+            ComputeResponse::SumResponse(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<FibonacciResponse> for ComputeResponse {
+    fn from(value: FibonacciResponse) -> Self {
+        Self::FibonacciResponse(value)
+    }
+}
+
+impl TryFrom<ComputeResponse> for FibonacciResponse {
+    type Error = ComputeResponse;
+
+    fn try_from(value: ComputeResponse) -> Result<Self, Self::Error> {
+        match value {
+            ComputeResponse::FibonacciResponse(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+impl From<MultiplyResponse> for ComputeResponse {
+    fn from(value: MultiplyResponse) -> Self {
+        Self::MultiplyResponse(value)
+    }
+}
+
+impl TryFrom<ComputeResponse> for MultiplyResponse {
+    type Error = ComputeResponse;
+
+    fn try_from(value: ComputeResponse) -> Result<Self, Self::Error> {
+        match value {
+            ComputeResponse::MultiplyResponse(value) => Ok(value),
+            other => Err(other),
+        }
+    }
+}
+
+/// RPC service Compute
+#[derive(Debug, Clone, Copy)]
+pub struct ComputeService;
+
+impl ::quic_rpc::Service for ComputeService {
+    type Req = ComputeRequest;
+    type Res = ComputeResponse;
+}
+
+impl ::quic_rpc::message::RpcMsg<ComputeService> for Sqr {
+    type Response = SqrResponse;
+}
+
+impl ::quic_rpc::message::Msg<ComputeService> for Sum {
+    type Pattern = ::quic_rpc::message::ClientStreaming;
+}
+
+impl ::quic_rpc::message::ClientStreamingMsg<ComputeService> for Sum {
+    type Update = SumUpdate;
+    type Response = SumResponse;
+}
+
+impl ::quic_rpc::message::Msg<ComputeService> for Fibonacci {
+    type Pattern = ::quic_rpc::message::ServerStreaming;
+}
+
+impl ::quic_rpc::message::ServerStreamingMsg<ComputeService> for Fibonacci {
+    type Response = FibonacciResponse;
+}
+
+impl ::quic_rpc::message::Msg<ComputeService> for Multiply {
+    type Pattern = ::quic_rpc::message::BidiStreaming;
+}
+
+impl ::quic_rpc::message::BidiStreamingMsg<ComputeService> for Multiply {
+    type Update = MultiplyUpdate;
+    type Response = MultiplyResponse;
+}
+
+/// Client for ComputeService
+#[derive(Debug, Clone)]
+pub struct ComputeClient<C = ::quic_rpc::client::BoxedConnector<ComputeService>> {
+    /// The underlying rpc client
+    pub rpc: ::quic_rpc::RpcClient<ComputeService, C>,
+}
+
+impl<C: ::quic_rpc::Connector<ComputeService>> ComputeClient<C> {
+    /// Create a new client from an rpc client
+    pub fn new(rpc: ::quic_rpc::RpcClient<ComputeService, C>) -> Self {
+        Self { rpc }
+    }
+
+    /// Call `sqr` on the service
+    pub async fn sqr(&self, req: Sqr) -> Result<SqrResponse, ::quic_rpc::pattern::rpc::Error<C>> {
+        self.rpc.rpc(req).await
+    }
+
+    /// Call `sum` on the service
+    pub async fn sum(&self, req: Sum) -> Result<(::quic_rpc::client::UpdateSink<C, SumUpdate>, ::std::pin::Pin<Box<dyn ::std::future::Future<Output = Result<SumResponse, ::quic_rpc::pattern::client_streaming::ItemError<C>>> + Send + 'static>>), ::quic_rpc::pattern::client_streaming::Error<C>> {
+        self.rpc.client_streaming(req).await
+    }
+
+    /// Call `fibonacci` on the service
+    pub async fn fibonacci(&self, req: Fibonacci) -> Result<::quic_rpc::client::BoxStreamSync<'static, Result<FibonacciResponse, ::quic_rpc::pattern::server_streaming::ItemError<C>>>, ::quic_rpc::pattern::server_streaming::Error<C>> {
+        self.rpc.server_streaming(req).await
+    }
+
+    /// Call `multiply` on the service
+    pub async fn multiply(&self, req: Multiply) -> Result<(::quic_rpc::client::UpdateSink<C, MultiplyUpdate>, ::quic_rpc::client::BoxStreamSync<'static, Result<MultiplyResponse, ::quic_rpc::pattern::bidi_streaming::ItemError<C>>>), ::quic_rpc::pattern::bidi_streaming::Error<C>> {
+        self.rpc.bidi(req).await
+    }
+}
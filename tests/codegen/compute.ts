@@ -0,0 +1,65 @@
+// Generated by quic_rpc::codegen. Do not edit.
+
+export type Sqr = number;
+
+export type SqrResponse = number;
+
+export type Sum = null;
+
+export type SumUpdate = number;
+
+export interface SumResponse {
+  total: number;
+}
+
+export type Fibonacci = number;
+
+export type FibonacciResponse = number;
+
+export type Multiply = number;
+
+export type MultiplyUpdate = number;
+
+export type MultiplyResponse = number[];
+
+export type ComputeRequest =
+  | { Sqr: Sqr }
+  | { Sum: Sum }
+  | { SumUpdate: SumUpdate }
+  | { Fibonacci: Fibonacci }
+  | { Multiply: Multiply }
+  | { MultiplyUpdate: MultiplyUpdate };
+
+export type ComputeResponse =
+  | { SqrResponse: SqrResponse }
+  | { SumResponse: SumResponse }
+  | { FibonacciResponse: FibonacciResponse }
+  | { MultiplyResponse: MultiplyResponse };
+
+/** Transport used by ComputeClient, sending JSON encoded messages. */
+export interface ComputeTransport {
+  rpc(req: ComputeRequest): Promise<ComputeResponse>;
+  serverStreaming(req: ComputeRequest): AsyncIterable<ComputeResponse>;
+}
+
+/** Client for ComputeService. */
+export class ComputeClient {
+  constructor(private readonly transport: ComputeTransport) {}
+
+  async sqr(req: Sqr): Promise<SqrResponse> {
+    const res = await this.transport.rpc({ Sqr: req });
+    if (!("SqrResponse" in res)) {
+      throw new Error("unexpected response");
+    }
+    return res.SqrResponse;
+  }
+
+  async *fibonacci(req: Fibonacci): AsyncIterable<FibonacciResponse> {
+    for await (const res of this.transport.serverStreaming({ Fibonacci: req })) {
+      if (!("FibonacciResponse" in res)) {
+        throw new Error("unexpected response");
+      }
+      yield res.FibonacciResponse;
+    }
+  }
+}
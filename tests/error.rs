@@ -0,0 +1,71 @@
+#![cfg(feature = "flume-transport")]
+mod math;
+use math::*;
+use quic_rpc::{
+    error::{CallError, Context, Error, ErrorKind, ResultExt},
+    transport::flume,
+    RpcClient, RpcServer,
+};
+
+#[tokio::test]
+async fn error_kind_shutdown() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    drop(server);
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let err: Error = client.rpc(Sqr(2)).await.unwrap_err().into();
+    assert_eq!(err.kind(), ErrorKind::Shutdown);
+    assert!(err.is_connection_lost());
+    assert!(err.is_retryable());
+    assert!(!err.is_temporary());
+    Ok(())
+}
+
+#[tokio::test]
+async fn error_kind_cancelled() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let server_handle = tokio::task::spawn(async move {
+        // drop the channel without answering
+        let (_req, _chan) = server.accept().await?.read_first().await?;
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let err: Error = client.rpc(Sqr(2)).await.unwrap_err().into();
+    assert_eq!(err.kind(), ErrorKind::Cancelled);
+    assert!(!err.is_retryable());
+    server_handle.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn error_kind_server() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    drop(client);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let Err(err) = server.accept().await else {
+        panic!("accepted a channel without a client");
+    };
+    let err: Error = err.into();
+    assert_eq!(err.kind(), ErrorKind::Shutdown);
+    Ok(())
+}
+
+#[tokio::test]
+async fn error_context() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    drop(server);
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let msg = Sqr(2);
+    let context = Context::new(&msg);
+    let id = context.id();
+    assert_ne!(Context::new(&msg).id(), id);
+    let err: CallError = client.rpc(msg).await.context(context).unwrap_err();
+    assert_eq!(err.context().request(), "Sqr");
+    assert_eq!(err.context().id(), id);
+    assert_eq!(err.kind(), ErrorKind::Shutdown);
+    assert!(err.to_string().starts_with(&format!("Sqr #{id}: ")));
+
+    let context = Context::with_id(&ComputeRequest::Sqr(Sqr(2)), 7);
+    assert_eq!(context.to_string(), "Sqr #7");
+    Ok(())
+}
@@ -0,0 +1,134 @@
+#![cfg(all(feature = "failover", feature = "flume-transport"))]
+use std::time::Duration;
+
+use derive_more::{From, TryInto};
+use futures_lite::StreamExt;
+use quic_rpc::{
+    message::{Msg, RpcMsg, ServerStreaming, ServerStreamingMsg},
+    transport::{
+        failover::{FailoverConnector, FailoverEvent},
+        flume::{self, FlumeConnector},
+    },
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::{JoinHandle, JoinSet};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Subscribe;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Whoami;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Request {
+    Subscribe(Subscribe),
+    Whoami(Whoami),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Response {
+    Replica(usize),
+}
+
+#[derive(Debug, Clone)]
+struct ReplicaService;
+
+impl Service for ReplicaService {
+    type Req = Request;
+    type Res = Response;
+}
+
+impl Msg<ReplicaService> for Subscribe {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<ReplicaService> for Subscribe {
+    type Response = usize;
+}
+
+impl RpcMsg<ReplicaService> for Whoami {
+    type Response = usize;
+}
+
+type Connector = FlumeConnector<Response, Request>;
+
+/// start a replica that answers with its id, returns the task serving it
+fn replica(id: usize) -> (JoinHandle<()>, Connector) {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ReplicaService, _>::new(server);
+    let task = tokio::task::spawn(async move {
+        // handlers are owned by this task, so aborting it kills the whole replica
+        let mut handlers = JoinSet::new();
+        loop {
+            // channels opened by probes are dropped without a request
+            let Ok((req, chan)) = server.accept().await.unwrap().read_first().await else {
+                continue;
+            };
+            handlers.spawn(async move {
+                match req {
+                    Request::Subscribe(req) => {
+                        let ids = futures_lite::stream::repeat(id).then(|id| async move {
+                            tokio::time::sleep(Duration::from_millis(10)).await;
+                            id
+                        });
+                        chan.server_streaming(req, (), move |(), _| ids).await.ok();
+                    }
+                    Request::Whoami(req) => {
+                        chan.rpc(req, (), move |(), _| async move { id }).await.ok();
+                    }
+                }
+            });
+        }
+    });
+    (task, client)
+}
+
+/// a dead replica is skipped, and the subscription moves to the next one
+#[tokio::test]
+async fn failover_restarts_subscription() -> anyhow::Result<()> {
+    let (replica0, connector0) = replica(0);
+    let (_replica1, connector1) = replica(1);
+    let connector =
+        FailoverConnector::new([connector0, connector1]).health_check(Duration::from_millis(50));
+    let mut events = connector.events();
+    let client = RpcClient::<ReplicaService, _>::new(connector.clone());
+    assert_eq!(client.rpc(Whoami).await?, 0);
+
+    let mut ids = client.subscribe_with_failover(Subscribe).await?;
+    assert_eq!(ids.next().await.transpose()?, Some(0));
+    replica0.abort();
+    // responses that were in flight may still arrive
+    let mut id = 0;
+    while id == 0 {
+        id = ids
+            .next()
+            .await
+            .transpose()?
+            .expect("subscription restarted");
+    }
+    assert_eq!(id, 1);
+    assert_eq!(events.recv().await?, FailoverEvent { from: 0, to: 1 });
+    assert_eq!(connector.current(), 1);
+    assert!(!connector.is_healthy(0));
+    assert_eq!(client.rpc(Whoami).await?, 1);
+    Ok(())
+}
+
+/// the health check notices a dead replica before any request fails
+#[tokio::test]
+async fn failover_health_check() -> anyhow::Result<()> {
+    let (replica0, connector0) = replica(0);
+    let (_replica1, connector1) = replica(1);
+    let connector =
+        FailoverConnector::new([connector0, connector1]).health_check(Duration::from_millis(50));
+    let mut events = connector.events();
+    replica0.abort();
+    let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await??;
+    assert_eq!(event, FailoverEvent { from: 0, to: 1 });
+    assert!(connector.is_healthy(1));
+    // channels are opened on the healthy replica right away
+    let client = RpcClient::<ReplicaService, _>::new(connector);
+    assert_eq!(client.rpc(Whoami).await?, 1);
+    Ok(())
+}
@@ -94,6 +94,99 @@ async fn flume_channel_mapped_bench() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// a whole listener can be mapped to a sub-service, and boxed afterwards
+#[tokio::test]
+async fn flume_channel_mapped_listener() -> anyhow::Result<()> {
+    use derive_more::{From, TryInto};
+    use quic_rpc::transport::{Connector, Listener};
+    use serde::{Deserialize, Serialize};
+
+    tracing_subscriber::fmt::try_init().ok();
+
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum OuterRequest {
+        Compute(ComputeRequest),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum OuterResponse {
+        Compute(ComputeResponse),
+    }
+    #[derive(Debug, Clone)]
+    struct OuterService;
+    impl Service for OuterService {
+        type Req = OuterRequest;
+        type Res = OuterResponse;
+    }
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<OuterService, _>::new(server).map::<ComputeService>();
+    let server_handle = tokio::task::spawn(ComputeService::server(server));
+    let client = client.map::<ComputeResponse, ComputeRequest>();
+    smoke_test(client).await?;
+    // dropping the client will cause the server to terminate
+    match server_handle.await? {
+        Err(RpcServerError::Accept(_)) => {}
+        e => panic!("unexpected termination result {e:?}"),
+    }
+
+    let (server, client) = flume::channel::<OuterRequest, OuterResponse>(1);
+    let server: RpcServer<ComputeService> =
+        RpcServer::new(server.map::<ComputeRequest, ComputeResponse>().boxed());
+    let server_handle = tokio::task::spawn(ComputeService::server(server));
+    let client = client.map::<ComputeResponse, ComputeRequest>();
+    smoke_test(client).await?;
+    match server_handle.await? {
+        Err(RpcServerError::Accept(_)) => {}
+        e => panic!("unexpected termination result {e:?}"),
+    }
+    Ok(())
+}
+
+/// a response that doesn't belong to the mapped service is reported with its variant
+#[tokio::test]
+async fn flume_channel_mapped_unexpected_variant() -> anyhow::Result<()> {
+    use derive_more::{From, TryInto};
+    use futures_util::SinkExt;
+    use quic_rpc::{
+        pattern::rpc,
+        transport::{
+            mapped::{ErrorOrMapError, UnexpectedVariant},
+            Connector, Listener,
+        },
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum OuterRequest {
+        Compute(ComputeRequest),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum OuterResponse {
+        Compute(ComputeResponse),
+        Other(String),
+    }
+    let (server, client) = flume::channel::<OuterRequest, OuterResponse>(1);
+    tokio::task::spawn(async move {
+        let (mut send, _recv) = server.accept().await?;
+        send.send(OuterResponse::Other("hello".into())).await?;
+        anyhow::Ok(())
+    });
+    let client =
+        RpcClient::<ComputeService, _>::new(client.map::<ComputeResponse, ComputeRequest>());
+    let res = client.rpc(Sqr(2)).await;
+    let Err(rpc::Error::RecvError(ErrorOrMapError::UnexpectedVariant(cause))) = res else {
+        panic!("unexpected result {res:?}");
+    };
+    assert_eq!(
+        cause,
+        UnexpectedVariant {
+            got: "Other".into(),
+            expected_service: std::any::type_name::<ComputeResponse>(),
+        }
+    );
+    Ok(())
+}
+
 /// simple happy path test for all 4 patterns
 #[tokio::test]
 async fn flume_channel_smoke() -> anyhow::Result<()> {
@@ -111,3 +204,187 @@ async fn flume_channel_smoke() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+/// all 4 patterns still work when channels buffer a single message
+#[tokio::test]
+async fn flume_channel_stream_buffer() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let client = client.stream_buffer(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let server_handle = tokio::task::spawn(ComputeService::server(server));
+    smoke_test(client).await?;
+
+    // dropping the client will cause the server to terminate
+    match server_handle.await? {
+        Err(RpcServerError::Accept(_)) => {}
+        e => panic!("unexpected termination result {e:?}"),
+    }
+    Ok(())
+}
+
+/// messages are moved through the channel without being serialized
+#[tokio::test]
+async fn flume_channel_no_serialization() -> anyhow::Result<()> {
+    use derive_more::{From, TryInto};
+    use quic_rpc::message::RpcMsg;
+    use serde::{Deserialize, Serialize};
+
+    /// fails to serialize and to deserialize
+    #[derive(Debug)]
+    struct Opaque(u64);
+
+    impl Serialize for Opaque {
+        fn serialize<S: serde::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("serialized a local message"))
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Opaque {
+        fn deserialize<D: serde::Deserializer<'de>>(_deserializer: D) -> Result<Self, D::Error> {
+            Err(serde::de::Error::custom("deserialized a local message"))
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Request {
+        Opaque(Opaque),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Response {
+        Opaque(Opaque),
+    }
+    #[derive(Debug, Clone)]
+    struct OpaqueService;
+    impl Service for OpaqueService {
+        type Req = Request;
+        type Res = Response;
+    }
+    impl RpcMsg<OpaqueService> for Opaque {
+        type Response = Opaque;
+    }
+
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<OpaqueService, _>::new(server);
+    let server_handle = tokio::task::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let Request::Opaque(msg) = req;
+        chan.rpc(msg, (), |(), Opaque(n)| async move { Opaque(n + 1) })
+            .await?;
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<OpaqueService, _>::new(client);
+    assert_eq!(client.rpc(Opaque(1)).await?.0, 2);
+    server_handle.await??;
+    Ok(())
+}
+
+/// dropping the call on the client cancels the handler on the server
+#[tokio::test]
+async fn flume_channel_client_drop_cancels_handler() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+    let (dropped_tx, dropped_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::task::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Sqr(msg) = req else {
+            anyhow::bail!("unexpected request");
+        };
+        let res = chan
+            .rpc(msg, (), |(), _req| async move {
+                // the sender is dropped along with the handler future
+                let _dropped = dropped_tx;
+                started_tx.send(()).ok();
+                std::future::pending::<SqrResponse>().await
+            })
+            .await;
+        anyhow::ensure!(matches!(res, Err(RpcServerError::Cancelled)), "{res:?}");
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let call = tokio::task::spawn(async move { client.rpc(Sqr(2)).await });
+    started_rx.await?;
+    call.abort();
+    tokio::time::timeout(std::time::Duration::from_secs(1), dropped_rx)
+        .await?
+        .ok();
+    server_handle.await??;
+    Ok(())
+}
+
+/// updates can be sent from a stream and the last one closes the sink
+#[tokio::test]
+async fn flume_channel_update_sink_helpers() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::task::spawn(ComputeService::server(server));
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let (mut send, recv) = client.client_streaming(Sum).await?;
+    tokio::task::spawn(async move {
+        send.send_all(futures_lite::stream::iter((1..=3).map(SumUpdate)))
+            .await?;
+        send.feed(SumUpdate(4)).await?;
+        send.flush().await?;
+        send.close_with(SumUpdate(5)).await
+    });
+    assert_eq!(recv.await?, SumResponse(15));
+    Ok(())
+}
+
+/// dropping a subscription cancels the handler, closing it is explicit
+#[tokio::test]
+async fn flume_channel_subscription_drop_cancels_handler() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let (cancelled_tx, mut cancelled_rx) = tokio::sync::mpsc::channel(2);
+    tokio::task::spawn(async move {
+        loop {
+            let (req, chan) = server.accept().await?.read_first().await?;
+            let ComputeRequest::Fibonacci(msg) = req else {
+                anyhow::bail!("unexpected request");
+            };
+            let cancelled_tx = cancelled_tx.clone();
+            tokio::task::spawn(async move {
+                let res = chan
+                    .server_streaming(msg, (), |(), _req| {
+                        // an endless subscription
+                        futures_lite::stream::repeat_with(|| FibonacciResponse(1))
+                    })
+                    .await;
+                // the handler is cancelled, or fails to send if it was sending just then
+                let stopped = matches!(
+                    res,
+                    Err(RpcServerError::Cancelled | RpcServerError::SendError(_))
+                );
+                cancelled_tx.send(stopped).await.ok();
+            });
+        }
+        #[allow(unreachable_code)]
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let mut first = client.subscribe(Fibonacci(1)).await?;
+    let second = client.subscribe(Fibonacci(1)).await?;
+    assert_ne!(first.id(), second.id());
+    let item = futures_lite::StreamExt::next(&mut first)
+        .await
+        .transpose()?;
+    assert!(matches!(item, Some(FibonacciResponse(1))));
+    let timeout = std::time::Duration::from_secs(1);
+    drop(first);
+    assert_eq!(
+        tokio::time::timeout(timeout, cancelled_rx.recv()).await?,
+        Some(true)
+    );
+    second.close().await?;
+    assert_eq!(
+        tokio::time::timeout(timeout, cancelled_rx.recv()).await?,
+        Some(true)
+    );
+    Ok(())
+}
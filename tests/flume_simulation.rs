@@ -0,0 +1,71 @@
+#![cfg(feature = "flume-simulation")]
+#![allow(non_local_definitions)]
+mod math;
+use std::time::{Duration, Instant};
+
+use futures_lite::StreamExt;
+use math::*;
+use quic_rpc::{
+    transport::flume::{self, LinkConditions},
+    RpcClient, RpcServer,
+};
+
+fn simulated_client(
+    to_server: LinkConditions,
+    to_client: LinkConditions,
+) -> RpcClient<ComputeService, flume::FlumeConnector<ComputeResponse, ComputeRequest>> {
+    let (server, client) = flume::channel_with_conditions(1, to_server, to_client);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::task::spawn(ComputeService::server(server));
+    RpcClient::new(client)
+}
+
+async fn fibonacci(to_client: LinkConditions) -> Vec<u128> {
+    simulated_client(LinkConditions::default(), to_client)
+        .server_streaming(Fibonacci(20))
+        .await
+        .unwrap()
+        .map(|res| res.unwrap().0)
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn simulation_perfect_link() -> anyhow::Result<()> {
+    let client = simulated_client(LinkConditions::default(), LinkConditions::default());
+    assert_eq!(client.rpc(Sqr(3)).await?.0, 9);
+    assert_eq!(fibonacci(LinkConditions::default()).await.len(), 20);
+    Ok(())
+}
+
+#[tokio::test]
+async fn simulation_latency() -> anyhow::Result<()> {
+    let client = simulated_client(
+        LinkConditions::default().latency(Duration::from_millis(30)),
+        LinkConditions::default().latency(Duration::from_millis(20)),
+    );
+    let start = Instant::now();
+    assert_eq!(client.rpc(Sqr(3)).await?.0, 9);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    Ok(())
+}
+
+#[tokio::test]
+async fn simulation_bandwidth() -> anyhow::Result<()> {
+    // 20 responses of 20 bytes each at 2000 bytes per second
+    let start = Instant::now();
+    let items = fibonacci(LinkConditions::default().bandwidth(2000)).await;
+    assert_eq!(items.len(), 20);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    Ok(())
+}
+
+#[tokio::test]
+async fn simulation_drop_is_deterministic() -> anyhow::Result<()> {
+    let conditions = LinkConditions::default().seed(7).drop_probability(0.5);
+    let a = fibonacci(conditions.clone()).await;
+    let b = fibonacci(conditions).await;
+    assert_eq!(a, b);
+    assert!(!a.is_empty() && a.len() < 20);
+    Ok(())
+}
@@ -0,0 +1,114 @@
+#![cfg(all(feature = "grpc-bridge", feature = "flume-transport"))]
+use std::net::SocketAddr;
+
+use hyper::{body::HttpBody, client::HttpConnector, Body, Client, Request};
+use quic_rpc::{
+    bridge::grpc::{from_payload, to_payload, GrpcBridge},
+    transport::flume,
+    RpcServer,
+};
+
+mod math;
+use math::*;
+
+/// Encode messages as length prefixed `Payload` messages
+fn encode(msgs: &[ComputeRequest]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for msg in msgs {
+        let data = to_payload(msg).unwrap();
+        // the test messages are small enough for a single byte varint
+        assert!(data.len() < 0x80);
+        body.push(0);
+        body.extend_from_slice(&(data.len() as u32 + 2).to_be_bytes());
+        body.extend_from_slice(&[0x0A, data.len() as u8]);
+        body.extend_from_slice(&data);
+    }
+    body
+}
+
+/// Perform a call, returning the responses and the grpc-status
+async fn call(
+    client: &Client<HttpConnector>,
+    addr: SocketAddr,
+    method: &str,
+    msgs: &[ComputeRequest],
+) -> anyhow::Result<(Vec<ComputeResponse>, String)> {
+    let req = Request::post(format!("http://{addr}/quic_rpc.Bridge/{method}"))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(Body::from(encode(msgs)))?;
+    let mut res = client.request(req).await?;
+    if let Some(status) = res.headers().get("grpc-status") {
+        // trailers-only response
+        return Ok((vec![], status.to_str()?.to_string()));
+    }
+    let mut buf = Vec::new();
+    while let Some(chunk) = res.data().await {
+        buf.extend_from_slice(&chunk?);
+    }
+    let mut responses = Vec::new();
+    let mut rest = &buf[..];
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest[1..5].try_into()?) as usize;
+        let (msg, tail) = rest[5..].split_at(len);
+        // skip the field key and the single byte length
+        responses.push(from_payload(&msg[2..])?);
+        rest = tail;
+    }
+    let trailers = res.trailers().await?.expect("trailers");
+    let status = trailers["grpc-status"].to_str()?.to_string();
+    Ok((responses, status))
+}
+
+#[tokio::test]
+async fn grpc_bridge() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::spawn(ComputeService::server(server));
+    let addr: SocketAddr = "127.0.0.1:3010".parse()?;
+    tokio::spawn(GrpcBridge::<ComputeService, _>::new(client).serve(addr));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let client = Client::builder().http2_only(true).build_http();
+
+    let (res, status) = call(&client, addr, "Unary", &[Sqr(4).into()]).await?;
+    assert_eq!(status, "0");
+    assert!(matches!(
+        res[..],
+        [ComputeResponse::SqrResponse(SqrResponse(16))]
+    ));
+
+    let (res, status) = call(&client, addr, "ServerStreaming", &[Fibonacci(5).into()]).await?;
+    assert_eq!(status, "0");
+    assert_eq!(res.len(), 5);
+
+    let msgs = [Sum.into(), SumUpdate(1).into(), SumUpdate(2).into()];
+    let (res, status) = call(&client, addr, "ClientStreaming", &msgs).await?;
+    assert_eq!(status, "0");
+    assert!(matches!(
+        res[..],
+        [ComputeResponse::SumResponse(SumResponse(3))]
+    ));
+
+    let msgs = [
+        Multiply(2).into(),
+        MultiplyUpdate(3).into(),
+        MultiplyUpdate(4).into(),
+    ];
+    let (res, status) = call(&client, addr, "BidiStreaming", &msgs).await?;
+    assert_eq!(status, "0");
+    assert_eq!(res.len(), 2);
+
+    // payload that is not a request
+    let req = Request::post(format!("http://{addr}/quic_rpc.Bridge/Unary"))
+        .header("content-type", "application/grpc")
+        .body(Body::from(vec![0, 0, 0, 0, 3, 0x0A, 1, 0xFF]))?;
+    let mut res = client.request(req).await?;
+    while res.data().await.is_some() {}
+    let trailers = res.trailers().await?.expect("trailers");
+    assert_eq!(trailers["grpc-status"], "3");
+
+    // unknown method
+    let (_, status) = call(&client, addr, "Nope", &[Sqr(4).into()]).await?;
+    assert_eq!(status, "12");
+    Ok(())
+}
@@ -155,6 +155,22 @@ async fn hyper_channel_smoke() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// the hyper transport can be chosen at runtime, behind the boxed types
+#[tokio::test]
+async fn hyper_channel_boxed() -> anyhow::Result<()> {
+    use quic_rpc::transport::{Connector, Listener};
+
+    let addr: SocketAddr = "127.0.0.1:3013".parse()?;
+    let uri: Uri = "http://127.0.0.1:3013".parse()?;
+    let server: RpcServer<ComputeService> = RpcServer::new(HyperListener::serve(&addr)?.boxed());
+    let server_handle = tokio::spawn(ComputeService::server(server));
+    let client = HyperConnector::new(uri).boxed();
+    smoke_test(client).await?;
+    server_handle.abort();
+    let _ = server_handle.await;
+    Ok(())
+}
+
 declare_rpc!(TestService, BigRequest, ());
 declare_rpc!(TestService, NoSerRequest, ());
 declare_rpc!(TestService, NoDeserRequest, ());
@@ -305,3 +321,51 @@ async fn hyper_channel_errors() -> anyhow::Result<()> {
     let _ = server_handle.await;
     Ok(())
 }
+
+/// idle channels stay open with a keepalive, and fail if the other side sends none
+#[tokio::test]
+async fn hyper_keepalive() -> anyhow::Result<()> {
+    use std::time::Duration;
+
+    use futures_lite::StreamExt;
+    use futures_util::SinkExt;
+    use quic_rpc::pattern::bidi_streaming::ItemError;
+
+    let config = || {
+        hyper::ChannelConfig::default()
+            .keepalive(Duration::from_millis(20), Duration::from_millis(100))
+    };
+    let addr: SocketAddr = "127.0.0.1:3014".parse()?;
+    let uri: Uri = "http://127.0.0.1:3014".parse()?;
+    let listener = HyperListener::serve_with_config(&addr, config())?;
+    let server_handle = tokio::spawn(ComputeService::server(RpcServer::new(listener)));
+
+    // both sides send keepalives, so the channel survives several timeouts
+    let client =
+        RpcClient::<ComputeService, _>::new(HyperConnector::with_config(uri.clone(), config()));
+    let (mut send, mut recv) = client.bidi(Multiply(2)).await?;
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    send.send(MultiplyUpdate(3)).await?;
+    let MultiplyResponse(product) = recv.next().await.expect("a response")?;
+    assert_eq!(product, 6);
+    drop(send);
+
+    // a server without keepalive never sends anything on an idle channel
+    let addr: SocketAddr = "127.0.0.1:3015".parse()?;
+    let uri: Uri = "http://127.0.0.1:3015".parse()?;
+    let server_handle_2 = run_server(&addr);
+    let client = RpcClient::<ComputeService, _>::new(HyperConnector::with_config(uri, config()));
+    let (_send, mut recv) = client.bidi(Multiply(2)).await?;
+    let err = recv
+        .next()
+        .await
+        .expect("an error, not the end of the stream");
+    assert!(matches!(
+        err,
+        Err(ItemError::RecvError(RecvError::KeepaliveTimeout))
+    ));
+
+    server_handle.abort();
+    server_handle_2.abort();
+    Ok(())
+}
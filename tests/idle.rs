@@ -0,0 +1,86 @@
+#![cfg(all(feature = "flume-transport", feature = "tokio-runtime"))]
+use std::time::Duration;
+
+use derive_more::{From, TryInto};
+use futures_lite::StreamExt;
+use quic_rpc::{
+    client::{IdleError, IdleTimeout},
+    message::{Heartbeat, Msg, ServerStreaming, ServerStreamingMsg},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Subscribe;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Tick {
+    Heartbeat,
+    Value(u64),
+}
+
+impl Heartbeat for Tick {
+    fn is_heartbeat(&self) -> bool {
+        matches!(self, Tick::Heartbeat)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Request {
+    Subscribe(Subscribe),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Response {
+    Tick(Tick),
+}
+
+#[derive(Debug, Clone)]
+struct TickService;
+
+impl Service for TickService {
+    type Req = Request;
+    type Res = Response;
+}
+
+impl Msg<TickService> for Subscribe {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<TickService> for Subscribe {
+    type Response = Tick;
+}
+
+/// heartbeats are dropped but keep the subscription alive, silence is reported
+#[tokio::test(start_paused = true)]
+async fn idle_timeout_heartbeats() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<TickService, _>::new(server);
+    tokio::task::spawn(async move {
+        let (Request::Subscribe(req), chan) = server.accept().await?.read_first().await?;
+        chan.server_streaming(req, (), |(), _req| {
+            async_stream::stream! {
+                yield Tick::Value(1);
+                for _ in 0..5 {
+                    tokio::time::sleep(Duration::from_millis(60)).await;
+                    yield Tick::Heartbeat;
+                }
+                yield Tick::Value(2);
+                std::future::pending::<()>().await;
+            }
+        })
+        .await?;
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<TickService, _>::new(client);
+    let window = Duration::from_millis(100);
+    let stream = IdleTimeout::new(client.server_streaming(Subscribe).await?, window);
+    tokio::pin!(stream);
+    assert_eq!(stream.next().await.unwrap()?, Tick::Value(1));
+    // the heartbeats come more often than the window, so the next item is the value
+    assert_eq!(stream.next().await.unwrap()?, Tick::Value(2));
+    let res = stream.next().await.unwrap();
+    assert!(matches!(res, Err(IdleError::Idle(w)) if w == window));
+    Ok(())
+}
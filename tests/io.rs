@@ -0,0 +1,89 @@
+#![cfg(feature = "flume-transport")]
+use derive_more::{From, TryInto};
+use futures_lite::StreamExt;
+use quic_rpc::{
+    io::{SinkWriter, StreamReader},
+    message::{BidiStreaming, BidiStreamingMsg, Msg},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Echo the uploaded bytes in upper case
+#[derive(Debug, Serialize, Deserialize)]
+struct Upper;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Chunk(Vec<u8>);
+
+impl From<Vec<u8>> for Chunk {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Chunk> for Vec<u8> {
+    fn from(value: Chunk) -> Self {
+        value.0
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Request {
+    Upper(Upper),
+    Chunk(Chunk),
+}
+
+#[derive(Debug, Clone)]
+struct PipeService;
+
+impl Service for PipeService {
+    type Req = Request;
+    type Res = Chunk;
+}
+
+impl Msg<PipeService> for Upper {
+    type Pattern = BidiStreaming;
+}
+
+impl BidiStreamingMsg<PipeService> for Upper {
+    type Update = Chunk;
+    type Response = Chunk;
+}
+
+#[tokio::test]
+async fn bidi_as_async_io() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<PipeService, _>::new(server);
+    tokio::task::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let Request::Upper(req) = req else {
+            anyhow::bail!("unexpected request");
+        };
+        chan.bidi_streaming(req, (), |_, _, updates| {
+            updates.map(|Chunk(bytes)| Chunk(bytes.to_ascii_uppercase()))
+        })
+        .await?;
+        anyhow::Ok(())
+    });
+
+    let client = RpcClient::<PipeService, _>::new(client);
+    let (updates, responses) = client.bidi(Upper).await?;
+    let mut writer = SinkWriter::new(updates).with_max_chunk_size(4);
+    let mut reader = StreamReader::new(responses);
+    let data = b"hello async world".repeat(10);
+    let upload = async move {
+        writer.write_all(&data).await?;
+        writer.shutdown().await?;
+        anyhow::Ok(())
+    };
+    let download = async move {
+        let mut res = Vec::new();
+        reader.read_to_end(&mut res).await?;
+        anyhow::Ok(res)
+    };
+    let ((), res) = tokio::try_join!(upload, download)?;
+    assert_eq!(res, b"HELLO ASYNC WORLD".repeat(10));
+    Ok(())
+}
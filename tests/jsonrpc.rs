@@ -0,0 +1,77 @@
+#![cfg(all(feature = "jsonrpc-bridge", feature = "flume-transport"))]
+use std::net::SocketAddr;
+
+use hyper::{client::HttpConnector, Body, Client, Request, StatusCode};
+use quic_rpc::{bridge::jsonrpc::JsonRpcBridge, transport::flume, RpcServer};
+use serde_json::{json, Value};
+
+mod math;
+use math::*;
+
+async fn post(
+    client: &Client<HttpConnector>,
+    addr: SocketAddr,
+    body: &str,
+) -> anyhow::Result<Option<Value>> {
+    let req = Request::post(format!("http://{addr}/"))
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))?;
+    let res = client.request(req).await?;
+    if res.status() == StatusCode::NO_CONTENT {
+        return Ok(None);
+    }
+    let body = hyper::body::to_bytes(res.into_body()).await?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+#[tokio::test]
+async fn jsonrpc_bridge() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::spawn(ComputeService::server(server));
+    let addr: SocketAddr = "127.0.0.1:3011".parse()?;
+    let bridge = JsonRpcBridge::<ComputeService, _>::new(client).server_streaming("Fibonacci");
+    tokio::spawn(bridge.serve(addr));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let client = Client::new();
+
+    let res = post(
+        &client,
+        addr,
+        r#"{"jsonrpc":"2.0","method":"Sqr","params":4,"id":1}"#,
+    )
+    .await?;
+    assert_eq!(res, Some(json!({"jsonrpc": "2.0", "result": 16, "id": 1})));
+
+    let call = r#"{"jsonrpc":"2.0","method":"Fibonacci","params":5,"id":"fib"}"#;
+    let res = post(&client, addr, call).await?;
+    assert_eq!(
+        res,
+        Some(json!({"jsonrpc": "2.0", "result": [0, 1, 1, 2, 3], "id": "fib"}))
+    );
+
+    // batch with a notification, an unknown method and invalid params
+    let batch = r#"[
+        {"jsonrpc":"2.0","method":"Sqr","params":2},
+        {"jsonrpc":"2.0","method":"Nope","id":2},
+        {"jsonrpc":"2.0","method":"Sqr","params":"four","id":3}
+    ]"#;
+    let res = post(&client, addr, batch).await?.unwrap();
+    assert_eq!(res[0]["error"]["code"], -32601);
+    assert_eq!(res[0]["id"], 2);
+    assert_eq!(res[1]["error"]["code"], -32602);
+    assert_eq!(res[1]["id"], 3);
+
+    // only notifications
+    let res = post(
+        &client,
+        addr,
+        r#"{"jsonrpc":"2.0","method":"Sqr","params":2}"#,
+    )
+    .await?;
+    assert_eq!(res, None);
+
+    let res = post(&client, addr, "{").await?.unwrap();
+    assert_eq!(res["error"]["code"], -32700);
+    Ok(())
+}
@@ -0,0 +1,110 @@
+#![cfg(feature = "longpoll-transport")]
+use std::{net::SocketAddr, time::Duration};
+
+use ::hyper::Uri;
+use futures_lite::StreamExt;
+use quic_rpc::{
+    transport::{
+        longpoll::{LongPollConfig, LongPollConnector, LongPollListener},
+        Connector, Listener, LocalAddr,
+    },
+    RpcClient, RpcServer,
+};
+
+mod math;
+use math::*;
+mod util;
+
+type Server = LongPollListener<ComputeRequest, ComputeResponse>;
+type Client = LongPollConnector<ComputeResponse, ComputeRequest>;
+
+/// Serve the compute service on a random port, returning the uri to connect to
+fn serve(config: LongPollConfig) -> anyhow::Result<(tokio::task::JoinHandle<()>, Uri)> {
+    let listener = Server::serve_with_config(&"127.0.0.1:0".parse()?, config)?;
+    let [LocalAddr::Socket(addr)] = listener.local_addr() else {
+        anyhow::bail!("unexpected local addr");
+    };
+    let uri = format!("http://{addr}/rpc").parse()?;
+    let server = RpcServer::new(listener);
+    let handle = tokio::spawn(async move {
+        loop {
+            ComputeService::server(server.clone()).await.ok();
+        }
+    });
+    Ok((handle, uri))
+}
+
+#[tokio::test]
+async fn longpoll_channel_smoke() -> anyhow::Result<()> {
+    let (server_handle, uri) = serve(LongPollConfig::default())?;
+    smoke_test(Client::new(uri)).await?;
+    server_handle.abort();
+    Ok(())
+}
+
+/// responses that take longer than the poll timeout arrive with later polls
+#[tokio::test]
+async fn longpoll_slow_responses() -> anyhow::Result<()> {
+    let config = LongPollConfig::default().poll_timeout(Duration::from_millis(20));
+    let listener = Server::serve_with_config(&"127.0.0.1:0".parse::<SocketAddr>()?, config)?;
+    let [LocalAddr::Socket(addr)] = *listener.local_addr() else {
+        anyhow::bail!("unexpected local addr");
+    };
+    let server_handle = tokio::spawn(async move {
+        let server = RpcServer::<ComputeService, _>::new(listener);
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Sqr(Sqr(x)) = req else {
+            anyhow::bail!("unexpected request");
+        };
+        chan.rpc(Sqr(x), (), |_, Sqr(x)| async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            SqrResponse(u128::from(x) * u128::from(x))
+        })
+        .await?;
+        anyhow::Ok(())
+    });
+    let client =
+        RpcClient::<ComputeService, _>::new(Client::new(format!("http://{addr}/rpc").parse()?));
+    assert_eq!(client.rpc(Sqr(12)).await?, SqrResponse(144));
+    server_handle.await??;
+    Ok(())
+}
+
+/// channels that are not polled are dropped by the server, polled ones are kept
+#[tokio::test]
+async fn longpoll_channel_timeout() -> anyhow::Result<()> {
+    let config = LongPollConfig::default()
+        .poll_timeout(Duration::from_millis(20))
+        .channel_timeout(Duration::from_millis(100));
+    let listener = Server::serve_with_config(&"127.0.0.1:0".parse::<SocketAddr>()?, config)?;
+    let [LocalAddr::Socket(addr)] = *listener.local_addr() else {
+        anyhow::bail!("unexpected local addr");
+    };
+    let uri: Uri = format!("http://{addr}/rpc").parse()?;
+    let (_send, mut recv) = Client::new(uri.clone()).open().await?;
+    let _polled = listener.accept().await?;
+
+    // open a channel by hand and never poll it
+    let http = ::hyper::Client::new();
+    let request = |op: &str, channel: Option<&str>| {
+        let mut req = ::hyper::Request::post(uri.clone()).header("x-quic-rpc-op", op);
+        if let Some(channel) = channel {
+            req = req
+                .header("x-quic-rpc-channel", channel)
+                .header("x-quic-rpc-seq", "0");
+        }
+        req.body(::hyper::Body::empty())
+    };
+    let res = http.request(request("open", None)?).await?;
+    let channel = res.headers()["x-quic-rpc-channel"].to_str()?.to_owned();
+    let (_unpolled_send, mut unpolled_recv) = listener.accept().await?;
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let res = http.request(request("poll", Some(&channel))?).await?;
+    assert_eq!(res.status(), ::hyper::StatusCode::NOT_FOUND);
+    // dropping the channel ends the requests on the server side
+    assert!(unpolled_recv.next().await.is_none());
+    // the client kept polling its channel, so it is still open
+    assert!(futures_lite::future::poll_once(recv.next()).await.is_none());
+    Ok(())
+}
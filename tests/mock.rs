@@ -0,0 +1,75 @@
+#![cfg(feature = "mock-transport")]
+#![allow(non_local_definitions)]
+mod math;
+use futures_lite::StreamExt;
+use futures_util::SinkExt;
+use math::*;
+use quic_rpc::{
+    pattern::{rpc, server_streaming},
+    transport::mock::{MockConnection, MockError},
+    RpcClient,
+};
+
+#[tokio::test]
+async fn mock_scripted_responses() -> anyhow::Result<()> {
+    let mock = MockConnection::<ComputeService>::new();
+    mock.expect_rpc::<Sqr>().returning(SqrResponse(4));
+    mock.expect_server_streaming::<Fibonacci>()
+        .returning_stream([FibonacciResponse(0), FibonacciResponse(1)]);
+    mock.expect_client_streaming::<Sum>()
+        .returning(SumResponse(3));
+    let client = RpcClient::<ComputeService, _>::new(mock.clone());
+
+    assert_eq!(client.rpc(Sqr(2)).await?.0, 4);
+    let items = client
+        .server_streaming(Fibonacci(2))
+        .await?
+        .map(|res| res.map(|res| res.0))
+        .try_collect::<_, _, Vec<_>>()
+        .await?;
+    assert_eq!(items, vec![0, 1]);
+    let (mut updates, res) = client.client_streaming(Sum).await?;
+    updates.send(SumUpdate(1)).await?;
+    updates.send(SumUpdate(2)).await?;
+    drop(updates);
+    assert_eq!(res.await?.0, 3);
+
+    mock.verify();
+    Ok(())
+}
+
+#[tokio::test]
+async fn mock_injected_errors() -> anyhow::Result<()> {
+    let mock = MockConnection::<ComputeService>::new();
+    mock.expect_server_streaming::<Fibonacci>()
+        .returning(FibonacciResponse(0))
+        .returning_error(MockError::new("connection lost"));
+    mock.expect_rpc::<Sqr>();
+    let client = RpcClient::<ComputeService, _>::new(mock.clone());
+
+    let mut items = client.server_streaming(Fibonacci(10)).await?;
+    assert_eq!(items.next().await.unwrap()?.0, 0);
+    assert!(matches!(
+        items.next().await,
+        Some(Err(server_streaming::ItemError::RecvError(_)))
+    ));
+    // no response scripted, so the server closes early
+    assert!(matches!(
+        client.rpc(Sqr(2)).await,
+        Err(rpc::Error::EarlyClose)
+    ));
+
+    mock.verify();
+    Ok(())
+}
+
+#[tokio::test]
+#[should_panic(expected = "got Fibonacci(Fibonacci(1))")]
+async fn mock_verify_unexpected() {
+    let mock = MockConnection::<ComputeService>::new();
+    mock.expect_rpc::<Sqr>().returning(SqrResponse(4));
+    mock.expect_rpc::<Sqr>().returning(SqrResponse(9));
+    let client = RpcClient::<ComputeService, _>::new(mock.clone());
+    assert!(client.server_streaming(Fibonacci(1)).await.is_err());
+    mock.verify();
+}
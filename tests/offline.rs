@@ -0,0 +1,80 @@
+#![cfg(all(feature = "offline-queue", feature = "flume-transport"))]
+use quic_rpc::{
+    offline::{Error, OfflineQueue, QueueLimits},
+    transport::flume,
+    RpcClient, RpcServer,
+};
+
+mod math;
+use math::*;
+
+/// Serve requests, reporting the numbers that get squared
+fn spawn_server() -> (
+    flume::FlumeConnector<ComputeResponse, ComputeRequest>,
+    tokio::sync::mpsc::UnboundedReceiver<u64>,
+) {
+    let (server, client) = flume::channel(1);
+    let (squared, recv_squared) = tokio::sync::mpsc::unbounded_channel();
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::task::spawn(async move {
+        while let Ok(accepting) = server.accept().await {
+            let (req, chan) = accepting.read_first().await?;
+            if let ComputeRequest::Sqr(Sqr(n)) = &req {
+                squared.send(*n).ok();
+            }
+            ComputeService::handle_rpc_request(ComputeService, req, chan).await?;
+        }
+        anyhow::Ok(())
+    });
+    (client, recv_squared)
+}
+
+/// requests issued while offline survive a restart and are sent in order
+#[tokio::test]
+async fn offline_queue() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("queue");
+    let limits = QueueLimits::default().max_requests(3);
+
+    // nobody is listening, so everything is queued
+    let (server, client) = flume::channel::<ComputeRequest, ComputeResponse>(1);
+    drop(server);
+    let queue = OfflineQueue::open(RpcClient::new(client), &path, limits)?;
+    for n in 1..=3 {
+        queue.submit(Sqr(n)).await?;
+    }
+    assert!(matches!(queue.submit(Sqr(4)).await, Err(Error::Full)));
+    assert!(queue.flush().await.is_err());
+    assert_eq!(queue.len(), 3);
+    drop(queue);
+
+    // after a restart, the queued requests are sent before new ones
+    let (client, mut squared) = spawn_server();
+    let limits = limits.max_requests(4);
+    let queue = OfflineQueue::open(RpcClient::new(client), &path, limits)?;
+    assert_eq!(queue.len(), 3);
+    queue.enqueue(Sqr(4))?;
+    assert_eq!(queue.flush().await?, 4);
+    assert!(queue.is_empty());
+    queue.submit(Sqr(5)).await?;
+    for n in 1..=5 {
+        assert_eq!(squared.recv().await, Some(n));
+    }
+    Ok(())
+}
+
+/// requests that are too old are dropped instead of sent
+#[tokio::test]
+async fn offline_queue_max_age() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("queue");
+    let limits = QueueLimits::default().max_age(std::time::Duration::from_millis(10));
+    let (client, mut squared) = spawn_server();
+    let queue = OfflineQueue::open(RpcClient::new(client), &path, limits)?;
+    queue.enqueue(Sqr(1))?;
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    queue.enqueue(Sqr(2))?;
+    assert_eq!(queue.flush().await?, 1);
+    assert_eq!(squared.recv().await, Some(2));
+    Ok(())
+}
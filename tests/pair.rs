@@ -0,0 +1,37 @@
+#![cfg(feature = "test-utils")]
+#![allow(non_local_definitions)]
+mod math;
+use futures_lite::StreamExt;
+use math::*;
+use quic_rpc::test;
+
+#[tokio::test]
+async fn pair_serves_requests() -> anyhow::Result<()> {
+    let (client, guard) =
+        test::pair(|req, chan| ComputeService::handle_rpc_request(ComputeService, req, chan));
+    assert_eq!(client.rpc(Sqr(4)).await?.0, 16);
+    let items = client
+        .server_streaming(Fibonacci(5))
+        .await?
+        .map(|res| res.unwrap().0)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(items, vec![0, 1, 1, 2, 3]);
+    // requests are handled concurrently
+    let (a, b) = tokio::join!(client.rpc(Sqr(2)), client.rpc(Sqr(3)));
+    assert_eq!((a?.0, b?.0), (4, 9));
+    // dropping the client stops the server
+    drop(client);
+    guard.join().await;
+    Ok(())
+}
+
+#[tokio::test]
+async fn pair_shutdown() -> anyhow::Result<()> {
+    let (client, guard) =
+        test::pair(|req, chan| ComputeService::handle_rpc_request(ComputeService, req, chan));
+    assert_eq!(client.rpc(Sqr(4)).await?.0, 16);
+    guard.shutdown().await;
+    assert!(client.rpc(Sqr(4)).await.is_err());
+    Ok(())
+}
@@ -0,0 +1,62 @@
+#![cfg(all(feature = "ping", feature = "flume-transport"))]
+use derive_more::{From, TryInto};
+use quic_rpc::{
+    ping::{PingRequest, PingResponse, PingService},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[tokio::test]
+async fn ping_standalone() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<PingService, _>::new(server);
+    tokio::task::spawn(async move {
+        while let Ok(accepting) = server.accept().await {
+            let (req, chan) = accepting.read_first().await?;
+            PingService.handle_rpc_request(req, chan).await?;
+        }
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<PingService, _>::new(client);
+    let rtt = client.ping().await?;
+    assert!(rtt < std::time::Duration::from_secs(5));
+    Ok(())
+}
+
+/// A service that has ping nested in it
+#[derive(Debug, Clone)]
+struct AppService;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum AppRequest {
+    Ping(PingRequest),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum AppResponse {
+    Ping(PingResponse),
+}
+
+impl Service for AppService {
+    type Req = AppRequest;
+    type Res = AppResponse;
+}
+
+#[tokio::test]
+async fn ping_nested() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<AppService, _>::new(server);
+    tokio::task::spawn(async move {
+        while let Ok(accepting) = server.accept().await {
+            let (req, chan) = accepting.read_first().await?;
+            match req {
+                AppRequest::Ping(req) => PingService.handle_rpc_request(req, chan.map()).await?,
+            }
+        }
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<AppService, _>::new(client);
+    client.clone().map::<PingService>().ping().await?;
+    Ok(())
+}
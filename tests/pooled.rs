@@ -0,0 +1,65 @@
+#![cfg(feature = "pooled-transport")]
+#![allow(non_local_definitions)]
+mod math;
+use futures_buffered::BufferedStreamExt;
+use futures_lite::StreamExt;
+use math::*;
+use quic_rpc::{
+    transport::{
+        flume,
+        pooled::{PoolFrame, PooledConnector, PooledListener},
+    },
+    RpcClient, RpcServer,
+};
+
+type Connector = PooledConnector<
+    ComputeResponse,
+    ComputeRequest,
+    flume::FlumeConnector<PoolFrame<ComputeResponse>, PoolFrame<ComputeRequest>>,
+>;
+
+fn pooled_client(pool_size: usize) -> RpcClient<ComputeService, Connector> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(PooledListener::new(server));
+    tokio::task::spawn(ComputeService::server(server));
+    RpcClient::new(PooledConnector::new(client, pool_size))
+}
+
+#[tokio::test]
+async fn pooled_rpc() -> anyhow::Result<()> {
+    let client = pooled_client(2);
+    let results = futures_lite::stream::iter(0..100u64)
+        .map(|i| {
+            let client = client.clone();
+            async move { client.rpc(Sqr(i)).await.map(|res| (i, res.0)) }
+        })
+        .buffered_unordered(32)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(results.len(), 100);
+    for res in results {
+        let (i, sqr) = res?;
+        assert_eq!(sqr, u128::from(i) * u128::from(i));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn pooled_streaming_is_dedicated() -> anyhow::Result<()> {
+    let client = pooled_client(1);
+    let items: Vec<u128> = client
+        .server_streaming(Fibonacci(10))
+        .await?
+        .map(|res| res.unwrap().0)
+        .collect()
+        .await;
+    assert_eq!(items, [0, 1, 1, 2, 3, 5, 8, 13, 21, 34]);
+    // rpc calls still work while a dedicated channel is open
+    let (mut send, recv) = client.bidi(Multiply(2)).await?;
+    assert_eq!(client.rpc(Sqr(3)).await?.0, 9);
+    futures_util::SinkExt::send(&mut send, MultiplyUpdate(4)).await?;
+    drop(send);
+    let items: Vec<u128> = recv.map(|res| res.unwrap().0).collect().await;
+    assert_eq!(items, [8]);
+    Ok(())
+}
@@ -1,7 +1,8 @@
 #![cfg(feature = "quinn-transport")]
 use std::{
-    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4},
     sync::Arc,
+    time::Duration,
 };
 
 use quic_rpc::{transport, RpcClient, RpcServer};
@@ -45,25 +46,48 @@ pub fn make_server_endpoint(bind_addr: SocketAddr) -> anyhow::Result<(Endpoint,
     Ok((endpoint, server_cert))
 }
 
-/// Builds default quinn client config and trusts given certificates.
-///
-/// ## Args
-///
-/// - server_certs: a list of trusted certificates in DER format.
-fn configure_client(server_certs: &[&[u8]]) -> anyhow::Result<ClientConfig> {
+/// Builds a rustls server config with a self-signed certificate for localhost.
+fn configure_server_crypto() -> anyhow::Result<(rustls::ServerConfig, Vec<u8>)> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let cert_der = cert.serialize_der()?;
+    let priv_key = cert.serialize_private_key_der();
+    let priv_key = rustls::pki_types::PrivatePkcs8KeyDer::from(priv_key);
+    let cert_chain = vec![rustls::pki_types::CertificateDer::from(cert_der.clone())];
+
+    let crypto_server_config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])
+    .expect("valid versions")
+    .with_no_client_auth()
+    .with_single_cert(cert_chain, priv_key.into())?;
+    Ok((crypto_server_config, cert_der))
+}
+
+/// Builds default rustls client config and trusts given certificates.
+fn configure_client_crypto(server_certs: &[&[u8]]) -> anyhow::Result<rustls::ClientConfig> {
     let mut certs = rustls::RootCertStore::empty();
     for cert in server_certs {
         let cert = rustls::pki_types::CertificateDer::from(cert.to_vec());
         certs.add(cert)?;
     }
 
-    let crypto_client_config = rustls::ClientConfig::builder_with_provider(Arc::new(
+    Ok(rustls::ClientConfig::builder_with_provider(Arc::new(
         rustls::crypto::ring::default_provider(),
     ))
     .with_protocol_versions(&[&rustls::version::TLS13])
     .expect("valid versions")
     .with_root_certificates(certs)
-    .with_no_client_auth();
+    .with_no_client_auth())
+}
+
+/// Builds default quinn client config and trusts given certificates.
+///
+/// ## Args
+///
+/// - server_certs: a list of trusted certificates in DER format.
+fn configure_client(server_certs: &[&[u8]]) -> anyhow::Result<ClientConfig> {
+    let crypto_client_config = configure_client_crypto(server_certs)?;
     let quic_client_config = QuicClientConfig::try_from(crypto_client_config)?;
 
     Ok(ClientConfig::new(Arc::new(quic_client_config)))
@@ -72,19 +96,7 @@ fn configure_client(server_certs: &[&[u8]]) -> anyhow::Result<ClientConfig> {
 /// Returns default server configuration along with its certificate.
 #[allow(clippy::field_reassign_with_default)] // https://github.com/rust-lang/rust-clippy/issues/6527
 fn configure_server() -> anyhow::Result<(ServerConfig, Vec<u8>)> {
-    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
-    let cert_der = cert.serialize_der()?;
-    let priv_key = cert.serialize_private_key_der();
-    let priv_key = rustls::pki_types::PrivatePkcs8KeyDer::from(priv_key);
-    let cert_chain = vec![rustls::pki_types::CertificateDer::from(cert_der.clone())];
-
-    let crypto_server_config = rustls::ServerConfig::builder_with_provider(Arc::new(
-        rustls::crypto::ring::default_provider(),
-    ))
-    .with_protocol_versions(&[&rustls::version::TLS13])
-    .expect("valid versions")
-    .with_no_client_auth()
-    .with_single_cert(cert_chain, priv_key.into())?;
+    let (crypto_server_config, cert_der) = configure_server_crypto()?;
     let quic_server_config = QuicServerConfig::try_from(crypto_server_config)?;
     let mut server_config = ServerConfig::with_crypto(Arc::new(quic_server_config));
 
@@ -157,6 +169,144 @@ async fn quinn_channel_smoke() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn quinn_flush_interval() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12350)?;
+    let server =
+        transport::quinn::QuinnListener::new(server)?.flush_interval(Duration::from_millis(1));
+    let server_handle = tokio::task::spawn(ComputeService::server(RpcServer::new(server)));
+    let client_connection =
+        transport::quinn::QuinnConnector::new(client, server_addr, "localhost".into())
+            .flush_interval(Duration::from_millis(1));
+    smoke_test(client_connection).await?;
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn quinn_boxed() -> anyhow::Result<()> {
+    use quic_rpc::transport::{Connector, Listener};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12351)?;
+    let server = transport::quinn::QuinnListener::new(server)?.boxed();
+    let server_handle = tokio::task::spawn(ComputeService::server(RpcServer::new(server)));
+    let client_connection =
+        transport::quinn::QuinnConnector::new(client, server_addr, "localhost".into()).boxed();
+    smoke_test(client_connection).await?;
+    server_handle.abort();
+    Ok(())
+}
+
+/// An explicit flush writes updates that are held back by a long flush interval
+#[tokio::test]
+async fn quinn_explicit_flush() -> anyhow::Result<()> {
+    use futures_lite::StreamExt;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12352)?;
+    let server_handle = run_server(server);
+    let client_connection =
+        transport::quinn::QuinnConnector::new(client, server_addr, "localhost".into())
+            .flush_interval(Duration::from_secs(60));
+    let client = RpcClient::<ComputeService, _>::new(client_connection);
+    let (mut send, mut recv) = client.bidi(Multiply(2)).await?;
+    send.feed(MultiplyUpdate(3)).await?;
+    send.feed(MultiplyUpdate(4)).await?;
+    send.flush().await?;
+    let timeout = Duration::from_secs(5);
+    let first = tokio::time::timeout(timeout, recv.next()).await?;
+    assert_eq!(first.unwrap()?.0, 6);
+    let second = tokio::time::timeout(timeout, recv.next()).await?;
+    assert_eq!(second.unwrap()?.0, 8);
+    server_handle.abort();
+    Ok(())
+}
+
+/// Requests rejected by a filter fail without stopping the server
+#[tokio::test]
+async fn quinn_request_filter() -> anyhow::Result<()> {
+    use futures_lite::StreamExt;
+    use quic_rpc::transport::filter::RequestFilter;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12353)?;
+    // reject ComputeRequest::Sqr, the first variant
+    let filter = RequestFilter::new(|peek| peek.variant != 0);
+    let server = transport::quinn::QuinnListener::new(server)?.request_filter(filter);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let server_handle = tokio::task::spawn(async move {
+        loop {
+            let Ok((req, chan)) = server.accept().await?.read_first().await else {
+                continue;
+            };
+            tokio::spawn(ComputeService::handle_rpc_request(
+                ComputeService,
+                req,
+                chan,
+            ));
+        }
+        #[allow(unreachable_code)]
+        anyhow::Ok(())
+    });
+    let client_connection =
+        transport::quinn::QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(client_connection);
+    assert!(client.rpc(Sqr(2)).await.is_err());
+    let res: Vec<_> = client
+        .server_streaming(Fibonacci(3))
+        .await?
+        .map(|x| x.map(|x| x.0))
+        .try_collect()
+        .await?;
+    assert_eq!(res, vec![0, 1, 1]);
+    server_handle.abort();
+    Ok(())
+}
+
+/// The server dials out to the client, and then serves the channels the client opens
+#[tokio::test]
+async fn quinn_reverse_connection() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client: dialer,
+        server: rendezvous,
+        server_addr,
+    } = make_endpoints(12354)?;
+    let accept = tokio::task::spawn(async move {
+        let connection = rendezvous.accept().await.expect("endpoint open").await?;
+        anyhow::Ok((rendezvous, connection))
+    });
+    let server =
+        transport::quinn::QuinnListener::connect(&dialer, server_addr, "localhost").await?;
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let server_handle = tokio::task::spawn(ComputeService::server(server));
+    let (_rendezvous, connection) = accept.await??;
+    smoke_test(transport::quinn::QuinnConnector::from_connection(
+        connection,
+    ))
+    .await?;
+    server_handle.abort();
+    Ok(())
+}
+
 /// Test that using the client after the server goes away and comes back behaves as if the server
 /// had never gone away in the first place.
 ///
@@ -206,3 +356,820 @@ async fn server_away_and_back() -> anyhow::Result<()> {
     server_handle.abort();
     Ok(())
 }
+
+#[cfg(feature = "zero-copy")]
+#[tokio::test]
+async fn quinn_zero_copy_blob() -> anyhow::Result<()> {
+    use quic_rpc::{message::RpcMsg, Blob, Service};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone)]
+    struct BlobService;
+
+    impl Service for BlobService {
+        type Req = Echo;
+        type Res = Echo;
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Echo {
+        before: String,
+        blobs: Vec<Blob>,
+        after: u32,
+    }
+
+    impl RpcMsg<BlobService> for Echo {
+        type Response = Echo;
+    }
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12348)?;
+    let server_handle = tokio::task::spawn(async move {
+        let server =
+            RpcServer::<BlobService, _>::new(transport::quinn::QuinnListener::new(server)?);
+        loop {
+            let (req, chan) = server.accept().await.unwrap().read_first().await.unwrap();
+            chan.rpc(req, (), |_, req| async move { req })
+                .await
+                .unwrap();
+        }
+        #[allow(unreachable_code)]
+        anyhow::Ok(())
+    });
+    let client = transport::quinn::QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<BlobService, _>::new(client);
+    let blobs = vec![
+        Blob::new(vec![1u8; 100_000]),
+        Blob::default(),
+        Blob::new(&b"hello"[..]),
+    ];
+    let res = client
+        .rpc(Echo {
+            before: "before".into(),
+            blobs: blobs.clone(),
+            after: 42,
+        })
+        .await?;
+    assert_eq!(res.before, "before");
+    assert_eq!(res.blobs, blobs);
+    assert_eq!(res.after, 42);
+    server_handle.abort();
+    Ok(())
+}
+
+#[cfg(feature = "pooled-transport")]
+#[tokio::test]
+async fn quinn_pooled_rpc() -> anyhow::Result<()> {
+    use quic_rpc::transport::pooled::{PoolFrame, PooledConnector, PooledListener};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12349)?;
+    let listener = transport::quinn::QuinnListener::<
+        PoolFrame<ComputeRequest>,
+        PoolFrame<ComputeResponse>,
+    >::new(server)?;
+    let server = RpcServer::new(PooledListener::new(listener));
+    let server_handle = tokio::task::spawn(ComputeService::server(server));
+    let connector = transport::quinn::QuinnConnector::<
+        PoolFrame<ComputeResponse>,
+        PoolFrame<ComputeRequest>,
+    >::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(PooledConnector::new(connector, 2));
+    for i in 0..10u64 {
+        assert_eq!(client.rpc(Sqr(i)).await?.0, u128::from(i * i));
+    }
+    smoke_test(client.into_inner()).await?;
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn quinn_server_connections() -> anyhow::Result<()> {
+    use futures_lite::StreamExt;
+    use futures_util::SinkExt;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12355)?;
+    let server = RpcServer::<ComputeService, _>::new(transport::quinn::QuinnListener::new(server)?);
+    let server_handle = tokio::task::spawn(ComputeService::server(server.clone()));
+    assert!(server.connections().is_empty());
+
+    let client = transport::quinn::QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(client);
+    assert_eq!(client.rpc(Sqr(2)).await?, SqrResponse(4));
+    let connections = server.connections();
+    assert_eq!(connections.len(), 1);
+    let connection = connections[0].clone();
+    assert!(connection.peer().starts_with("127.0.0.1:"));
+
+    // a bidi call keeps its stream open until the client is done
+    let (mut send, mut recv) = client.bidi(Multiply(2)).await?;
+    send.send(MultiplyUpdate(3)).await?;
+    assert_eq!(recv.next().await.transpose()?.map(|res| res.0), Some(6));
+    assert_eq!(connection.open_streams(), 1);
+
+    // evicting the client fails the call
+    connection.close(42, b"evicted");
+    assert!(recv.next().await.transpose().is_err());
+    server_handle.abort();
+    Ok(())
+}
+
+/// The math service, described in the codegen language
+#[cfg(feature = "dyn-client")]
+const COMPUTE_SCHEMA: &str = "
+message Sqr(u64);
+message SqrResponse(u128);
+message Sum;
+message SumUpdate(u64);
+message SumResponse(u128);
+message Fibonacci(u64);
+message FibonacciResponse(u128);
+message Multiply(u64);
+message MultiplyUpdate(u64);
+message MultiplyResponse(u128);
+
+service Compute {
+    rpc sqr(Sqr) -> SqrResponse;
+    client_streaming sum(Sum, SumUpdate) -> SumResponse;
+    server_streaming fibonacci(Fibonacci) -> FibonacciResponse;
+    bidi_streaming multiply(Multiply, MultiplyUpdate) -> MultiplyResponse;
+}
+";
+
+#[cfg(feature = "dyn-client")]
+#[tokio::test]
+async fn quinn_dyn_client() -> anyhow::Result<()> {
+    use futures_lite::StreamExt;
+    use quic_rpc::{
+        codegen::Schema,
+        dyn_client::{self, DynClient},
+    };
+    use serde_json::json;
+
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12356)?;
+    let server_handle = run_server(server);
+    let connection = client.connect(server_addr, "localhost")?.await?;
+    let schema = Schema::parse(COMPUTE_SCHEMA)?;
+    assert_eq!(schema.services().collect::<Vec<_>>(), ["Compute"]);
+    let client = DynClient::new(schema, "Compute", connection)?;
+
+    assert_eq!(client.rpc("sqr", json!(4)).await?, json!(16));
+    let res = client
+        .server_streaming("fibonacci", json!(5))
+        .await?
+        .try_collect::<_, _, Vec<_>>()
+        .await?;
+    assert_eq!(res, [json!(0), json!(1), json!(1), json!(2), json!(3)]);
+
+    let mut chan = client.open("sum", json!(null)).await?;
+    chan.send(json!(1)).await?;
+    chan.send(json!(2)).await?;
+    chan.finish().await?;
+    assert_eq!(chan.recv().await.transpose()?, Some(json!(3)));
+
+    let mut chan = client.open("multiply", json!(2)).await?;
+    chan.send(json!(3)).await?;
+    assert_eq!(chan.recv().await.transpose()?, Some(json!(6)));
+
+    // values that don't match the schema are rejected before sending
+    assert!(matches!(
+        client.rpc("sqr", json!("four")).await,
+        Err(dyn_client::Error::Encode(_))
+    ));
+    assert!(matches!(
+        client.rpc("cube", json!(4)).await,
+        Err(dyn_client::Error::UnknownMethod(_))
+    ));
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn quinn_quota() -> anyhow::Result<()> {
+    use quic_rpc::{
+        pattern::rpc,
+        transport::quota::{Quota, QuotaExceeded, QuotaTracker},
+    };
+
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12357)?;
+    let quota = QuotaTracker::new(Quota::default().max_requests(2));
+    let listener = transport::quinn::QuinnListener::new(server)?.quota(quota.clone());
+    let server_handle = tokio::task::spawn(ComputeService::server(RpcServer::new(listener)));
+    let client = transport::quinn::QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(client);
+    assert_eq!(client.rpc(Sqr(2)).await?, SqrResponse(4));
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+
+    // the third request is over the quota
+    let Err(rpc::Error::RecvError(cause)) = client.rpc(Sqr(4)).await else {
+        panic!("request should be rejected");
+    };
+    assert_eq!(cause.kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(cause.get_ref().unwrap().is::<QuotaExceeded>());
+
+    let usage = quota.all_usage();
+    assert_eq!(usage.len(), 1);
+    let (peer, usage) = &usage[0];
+    assert_eq!((usage.requests, usage.rejected), (2, 1));
+    assert!(usage.bytes_received > 0 && usage.bytes_sent > 0);
+
+    // a new billing period
+    quota.reset(peer);
+    assert_eq!(client.rpc(Sqr(4)).await?, SqrResponse(16));
+    server_handle.abort();
+    Ok(())
+}
+
+impl quic_rpc::message::Priority for ComputeRequest {
+    fn priority(&self) -> i32 {
+        match self {
+            // streams of numbers are bulk transfers
+            ComputeRequest::Sum(_) | ComputeRequest::Multiply(_) => -1,
+            _ => 1,
+        }
+    }
+}
+
+#[tokio::test]
+async fn quinn_stream_priority() -> anyhow::Result<()> {
+    use futures_util::SinkExt;
+    use quic_rpc::transport::Connector;
+
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12358)?;
+    let server_handle = run_server(server);
+    let client = transport::quinn::QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    )
+    .prioritized();
+    for (req, priority) in [(ComputeRequest::from(Sqr(2)), 1), (Sum.into(), -1)] {
+        let (mut send, _recv) = client.open().await?;
+        send.send(req).await?;
+        assert_eq!(send.into_inner().priority()?, priority);
+    }
+
+    // calls work as usual
+    let client = RpcClient::<ComputeService, _>::new(client);
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+    server_handle.abort();
+    Ok(())
+}
+
+/// connections are routed to the tenant named by the token they present
+#[tokio::test]
+async fn quinn_tenant_routing() -> anyhow::Result<()> {
+    use quic_rpc::transport::{
+        quinn::QuinnConnector,
+        tenant::{self, Route, TenantLimits, TenantRouter},
+        Listener,
+    };
+
+    let server_addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12359));
+    let (mut server_config, server_cert) = configure_server()?;
+    // the token is sent on a unidirectional stream
+    Arc::get_mut(&mut server_config.transport)
+        .unwrap()
+        .max_concurrent_uni_streams(1_u8.into());
+    let server = Endpoint::server(server_config, server_addr)?;
+    let client = make_client_endpoint("0.0.0.0:0".parse()?, &[&server_cert])?;
+    let router = TenantRouter::new(server, Route::Token)?;
+    let a = router.tenant::<ComputeRequest, ComputeResponse>("a", TenantLimits::default());
+    let b = router
+        .tenant::<ComputeRequest, ComputeResponse>("b", TenantLimits::default().max_connections(1));
+    tokio::task::spawn(ComputeService::server(RpcServer::new(a.clone())));
+    tokio::task::spawn(ComputeService::server(RpcServer::new(b.clone())));
+
+    let sqr = |token: &'static str, x: u64| {
+        let client = client.clone();
+        async move {
+            let connection = client.connect(server_addr, "localhost")?.await?;
+            tenant::present_token(&connection, token).await?;
+            let client = RpcClient::<ComputeService, _>::new(QuinnConnector::<
+                ComputeResponse,
+                ComputeRequest,
+            >::from_connection(
+                connection.clone()
+            ));
+            let res = client.rpc(Sqr(x)).await?;
+            anyhow::Ok((res, connection))
+        }
+    };
+    let (res, _a) = sqr("a", 3).await?;
+    assert_eq!(res, SqrResponse(9));
+    let (res, _b) = sqr("b", 4).await?;
+    assert_eq!(res, SqrResponse(16));
+    // each tenant only sees its own connections
+    assert_eq!(a.connections().len(), 1);
+    assert_eq!(b.connections().len(), 1);
+    assert_eq!(router.active_connections("b"), 1);
+
+    // b is at its limit, and there is no tenant c
+    assert!(sqr("b", 5).await.is_err());
+    assert!(sqr("c", 5).await.is_err());
+    Ok(())
+}
+
+/// the server sees where a channel comes from without knowing the transport
+#[tokio::test]
+async fn quinn_channel_connection_info() -> anyhow::Result<()> {
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12360)?;
+    let client_port = client.local_addr()?.port();
+    let server = RpcServer::<ComputeService, _>::new(transport::quinn::QuinnListener::<
+        ComputeRequest,
+        ComputeResponse,
+    >::new(server)?)
+    .boxed();
+    let server_handle = tokio::task::spawn(async move {
+        let accepting = server.accept().await?;
+        let connection = accepting
+            .connection()
+            .expect("quinn channels have a connection");
+        let (req, chan) = accepting.read_first().await?;
+        anyhow::ensure!(chan.connection().map(|c| c.id()) == Some(connection.id()));
+        let ComputeRequest::Sqr(req) = req else {
+            anyhow::bail!("unexpected request");
+        };
+        chan.rpc(req, (), |(), Sqr(x)| async move {
+            SqrResponse(x as u128 * x as u128)
+        })
+        .await?;
+        // keep the listener alive until the client got the response
+        anyhow::Ok((connection, server))
+    });
+    let client =
+        RpcClient::<ComputeService, _>::new(transport::quinn::QuinnConnector::<
+            ComputeResponse,
+            ComputeRequest,
+        >::new(client, server_addr, "localhost".into()));
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+    let (connection, _server) = server_handle.await??;
+    assert_eq!(connection.remote_addr().port(), client_port);
+    assert_eq!(connection.alpn(), None);
+    assert!(connection.established() <= std::time::SystemTime::now());
+    Ok(())
+}
+
+#[tokio::test]
+async fn quinn_close_reason() -> anyhow::Result<()> {
+    use quic_rpc::{
+        error::{CloseCode, Error, ErrorKind},
+        transport::Listener,
+    };
+
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12361)?;
+    let listener = transport::quinn::QuinnListener::<ComputeRequest, ComputeResponse>::new(server)?;
+    let server = RpcServer::<ComputeService, _>::new(listener.clone());
+    tokio::task::spawn(async move {
+        loop {
+            let (ComputeRequest::Sqr(req), chan) = server.accept().await?.read_first().await?
+            else {
+                anyhow::bail!("unexpected request");
+            };
+            chan.rpc(req, (), |(), Sqr(x)| async move {
+                SqrResponse(x as u128 * x as u128)
+            })
+            .await?;
+        }
+        #[allow(unreachable_code)]
+        anyhow::Ok(())
+    });
+    let connection = client.connect(server_addr, "localhost")?.await?;
+    let client = RpcClient::<ComputeService, _>::new(transport::quinn::QuinnConnector::<
+        ComputeResponse,
+        ComputeRequest,
+    >::from_connection(connection.clone()));
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+
+    let [handle] = listener.connections().try_into().expect("one connection");
+    handle.close_with(CloseCode::Overloaded, "too busy");
+    connection.closed().await;
+    let err: Error = client.rpc(Sqr(4)).await.unwrap_err().into();
+    assert_eq!(err.kind(), ErrorKind::Closed);
+    let reason = err.close_reason().expect("closed on purpose");
+    assert_eq!(reason.code, CloseCode::Overloaded);
+    assert_eq!(reason.reason, "too busy");
+    assert!(err.is_retryable());
+    assert!(err.is_connection_lost());
+    Ok(())
+}
+
+#[tokio::test]
+async fn quinn_warm_up() -> anyhow::Result<()> {
+    use quic_rpc::transport::warm_up::WarmUp;
+
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12362)?;
+    let server = RpcServer::<ComputeService, _>::new(transport::quinn::QuinnListener::<
+        ComputeRequest,
+        ComputeResponse,
+    >::new(server)?);
+    tokio::task::spawn(async move {
+        loop {
+            let (ComputeRequest::Sqr(req), chan) = server.accept().await?.read_first().await?
+            else {
+                anyhow::bail!("unexpected request");
+            };
+            chan.rpc(req, (), |(), Sqr(x)| async move {
+                SqrResponse(x as u128 * x as u128)
+            })
+            .await?;
+        }
+        #[allow(unreachable_code)]
+        anyhow::Ok(())
+    });
+    let connection = client.connect(server_addr, "localhost")?.await?;
+    let connector =
+        transport::quinn::QuinnConnector::<ComputeResponse, ComputeRequest>::from_connection(
+            connection.clone(),
+        )
+        .warm_up(WarmUp::default().streams(2).ping());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while connection.stats().frame_tx.datagram == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    // the first two use the warm streams, the third one opens a new stream
+    for x in 1..=3 {
+        assert_eq!(
+            client.rpc(Sqr(x)).await?,
+            SqrResponse(x as u128 * x as u128)
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn quinn_endpoint_builder_congestion_controller() -> anyhow::Result<()> {
+    use transport::quinn::{CongestionController, EndpointBuilder};
+
+    let builder = EndpointBuilder::new()
+        .congestion_controller(CongestionController::Bbr)
+        .initial_window(64 * 1024);
+    let server_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12363));
+    let (server_config, server_cert) = configure_server()?;
+    let server = builder.server(server_config, server_addr)?;
+    let client_config = configure_client(&[&server_cert])?;
+    let client = builder.client(client_config, "0.0.0.0:0".parse()?)?;
+    let server_handle = run_server(server);
+
+    let connection = client.connect(server_addr, "localhost")?.await?;
+    let congestion = connection.congestion_state();
+    assert_eq!(congestion.initial_window(), 64 * 1024);
+    assert!(congestion
+        .into_any()
+        .downcast::<quinn::congestion::Bbr>()
+        .is_ok());
+    let client = RpcClient::<ComputeService, _>::new(transport::quinn::QuinnConnector::<
+        ComputeResponse,
+        ComputeRequest,
+    >::from_connection(connection));
+    assert_eq!(client.rpc(Sqr(4)).await?, SqrResponse(16));
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn quinn_endpoint_builder_key_log() -> anyhow::Result<()> {
+    use transport::quinn::EndpointBuilder;
+
+    let dir = tempfile::tempdir()?;
+    let key_log = dir.path().join("keys.log");
+    // only this test enables the key log, so setting the variable does not affect others
+    std::env::set_var("SSLKEYLOGFILE", &key_log);
+    let builder = EndpointBuilder::new().key_log();
+    let server_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12364));
+    let (server_crypto, server_cert) = configure_server_crypto()?;
+    let server = builder.server_tls(server_crypto, server_addr)?;
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(rustls::pki_types::CertificateDer::from(server_cert))?;
+    let client_crypto = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])
+    .expect("valid versions")
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    let client = builder.client_tls(client_crypto, "0.0.0.0:0".parse()?)?;
+    let server_handle = run_server(server);
+
+    let client =
+        RpcClient::<ComputeService, _>::new(transport::quinn::QuinnConnector::<
+            ComputeResponse,
+            ComputeRequest,
+        >::new(client, server_addr, "localhost".into()));
+    assert_eq!(client.rpc(Sqr(5)).await?, SqrResponse(25));
+    let keys = std::fs::read_to_string(&key_log)?;
+    assert!(keys.contains("CLIENT_HANDSHAKE_TRAFFIC_SECRET"));
+    assert!(keys.contains("SERVER_TRAFFIC_SECRET_0"));
+    server_handle.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn quinn_spki_pinning() -> anyhow::Result<()> {
+    use transport::quinn::{EndpointBuilder, SpkiPins};
+
+    let builder = EndpointBuilder::new();
+    let server_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12365));
+    let (server_crypto, server_cert) = configure_server_crypto()?;
+    let server = builder.server_tls(server_crypto, server_addr)?;
+    let server_handle = run_server(server);
+    let pin = SpkiPins::pin(&rustls::pki_types::CertificateDer::from(server_cert))?;
+
+    // the certificate is self-signed, so only the pin makes the client trust it
+    let pinned =
+        builder.client_with_verifier(Arc::new(SpkiPins::new([pin])), "0.0.0.0:0".parse()?)?;
+    let connection = pinned.connect(server_addr, "localhost")?.await?;
+    let client = RpcClient::<ComputeService, _>::new(transport::quinn::QuinnConnector::<
+        ComputeResponse,
+        ComputeRequest,
+    >::from_connection(connection));
+    assert_eq!(client.rpc(Sqr(6)).await?, SqrResponse(36));
+
+    let other =
+        builder.client_with_verifier(Arc::new(SpkiPins::new([[0u8; 32]])), "0.0.0.0:0".parse()?)?;
+    assert!(other.connect(server_addr, "localhost")?.await.is_err());
+    server_handle.abort();
+    Ok(())
+}
+
+/// one server accepts on an IPv4 and an IPv6 endpoint on the same port
+#[tokio::test]
+async fn quinn_dual_stack() -> anyhow::Result<()> {
+    use transport::quinn::{EndpointBuilder, QuinnConnector, QuinnListener};
+
+    let (server_config, server_cert) = configure_server()?;
+    let endpoints = EndpointBuilder::new().server_dual_stack(server_config, 0)?;
+    let listener = QuinnListener::<ComputeRequest, ComputeResponse>::from_endpoints(endpoints)?;
+    let addrs = listener.socket_addrs();
+    assert_eq!(addrs.len(), 2);
+    assert_eq!(addrs[0].port(), addrs[1].port());
+    let port = addrs[0].port();
+    let server_handle = tokio::task::spawn(ComputeService::server(RpcServer::new(listener)));
+
+    for (bind_addr, server_addr) in [
+        ("0.0.0.0:0", SocketAddr::from((Ipv4Addr::LOCALHOST, port))),
+        ("[::]:0", SocketAddr::from((Ipv6Addr::LOCALHOST, port))),
+    ] {
+        let endpoint = make_client_endpoint(bind_addr.parse()?, &[&server_cert])?;
+        let client = RpcClient::<ComputeService, _>::new(QuinnConnector::<
+            ComputeResponse,
+            ComputeRequest,
+        >::new(
+            endpoint,
+            server_addr,
+            "localhost".into(),
+        ));
+        assert_eq!(client.rpc(Sqr(7)).await?, SqrResponse(49));
+    }
+    server_handle.abort();
+    Ok(())
+}
+
+/// a channel that is open while the client endpoint is rebound keeps working
+#[tokio::test]
+async fn quinn_rebind() -> anyhow::Result<()> {
+    use futures_util::SinkExt;
+
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12366)?;
+    let server_handle = run_server(server);
+    let old_addr = client.local_addr()?;
+    let connector = transport::quinn::QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client.clone(),
+        server_addr,
+        "localhost".into(),
+    );
+    let client_rpc = RpcClient::<ComputeService, _>::new(connector.clone());
+    let (mut send, recv) = client_rpc.client_streaming(Sum).await?;
+    send.send(SumUpdate(1)).await?;
+    send.send(SumUpdate(2)).await?;
+
+    connector.rebind(std::net::UdpSocket::bind("127.0.0.1:0")?)?;
+    assert_ne!(client.local_addr()?, old_addr);
+    send.send(SumUpdate(3)).await?;
+    drop(send);
+    assert_eq!(recv.await?, SumResponse(6));
+    assert_eq!(client_rpc.rpc(Sqr(3)).await?, SqrResponse(9));
+    server_handle.abort();
+    Ok(())
+}
+
+/// a second connection to the same server resumes the session of the first one
+#[tokio::test]
+async fn quinn_session_resumption() -> anyhow::Result<()> {
+    use transport::quinn::{EndpointBuilder, QuinnConnector};
+
+    let builder = EndpointBuilder::new().session_resumption();
+    let server_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12367));
+    let (server_crypto, server_cert) = configure_server_crypto()?;
+    let server = builder.server_tls(server_crypto, server_addr)?;
+    let server_handle = run_server(server);
+    let client_crypto = configure_client_crypto(&[&server_cert])?;
+    let client = builder.client_tls(client_crypto, "0.0.0.0:0".parse()?)?;
+
+    let connect = || {
+        QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+            client.clone(),
+            server_addr,
+            "localhost".into(),
+        )
+    };
+    let first = connect();
+    assert_eq!(first.resumed(), None);
+    let rpc = RpcClient::<ComputeService, _>::new(first.clone());
+    assert_eq!(rpc.rpc(Sqr(2)).await?, SqrResponse(4));
+    assert_eq!(first.resumed(), Some(false));
+
+    let second = connect();
+    let rpc = RpcClient::<ComputeService, _>::new(second.clone());
+    assert_eq!(rpc.rpc(Sqr(3)).await?, SqrResponse(9));
+    assert_eq!(second.resumed(), Some(true));
+    server_handle.abort();
+    Ok(())
+}
+
+/// the server only serves clients whose hello passes its check
+#[tokio::test]
+async fn quinn_handshake() -> anyhow::Result<()> {
+    use quic_rpc::{
+        error::{CloseCode, CloseReason, Error, ErrorKind},
+        transport::{
+            handshake::{ClientHandshake, ServerHandshake},
+            quinn::{QuinnConnector, QuinnListener},
+            Listener,
+        },
+    };
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Hello {
+        token: String,
+    }
+
+    let server_addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12368));
+    let (mut server_config, server_cert) = configure_server()?;
+    // the hello is sent on a unidirectional stream
+    Arc::get_mut(&mut server_config.transport)
+        .unwrap()
+        .max_concurrent_uni_streams(1_u8.into());
+    let server = Endpoint::server(server_config, server_addr)?;
+    let client = make_client_endpoint("0.0.0.0:0".parse()?, &[&server_cert])?;
+    let listener = QuinnListener::<ComputeRequest, ComputeResponse>::new(server)?.handshake(
+        ServerHandshake::new(|hello: Hello| async move {
+            if hello.token == "secret" {
+                Ok(())
+            } else {
+                Err(CloseReason {
+                    code: CloseCode::Unauthorized,
+                    reason: "bad token".into(),
+                })
+            }
+        }),
+    );
+    tokio::task::spawn(ComputeService::server(RpcServer::new(listener.clone())));
+
+    let hello = |token: &str| {
+        ClientHandshake::new(&Hello {
+            token: token.into(),
+        })
+    };
+    let good = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client.clone(),
+        server_addr,
+        "localhost".into(),
+    )
+    .handshake(hello("secret")?);
+    let good = RpcClient::<ComputeService, _>::new(good);
+    assert_eq!(good.rpc(Sqr(3)).await?, SqrResponse(9));
+    let [handle] = listener.connections().try_into().expect("one connection");
+    assert_eq!(
+        handle.handshake::<Hello>(),
+        Some(Hello {
+            token: "secret".into()
+        })
+    );
+
+    let connection = client.connect(server_addr, "localhost")?.await?;
+    let bad =
+        QuinnConnector::<ComputeResponse, ComputeRequest>::from_connection(connection.clone())
+            .handshake(hello("guess")?);
+    let bad = RpcClient::<ComputeService, _>::new(bad);
+    connection.closed().await;
+    let err: Error = bad.rpc(Sqr(4)).await.unwrap_err().into();
+    assert_eq!(err.kind(), ErrorKind::Closed);
+    let reason = err.close_reason().expect("rejected by the handshake");
+    assert_eq!(reason.code, CloseCode::Unauthorized);
+    assert_eq!(reason.reason, "bad token");
+    assert_eq!(listener.connections().len(), 1);
+    Ok(())
+}
+
+/// client and server agree on what both support, and both can look it up
+#[tokio::test]
+async fn quinn_capabilities() -> anyhow::Result<()> {
+    use quic_rpc::transport::{
+        capabilities::Capabilities,
+        handshake::{ClientHandshake, ServerHandshake},
+        quinn::{QuinnConnector, QuinnListener},
+        Listener,
+    };
+
+    let server_addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12369));
+    let (mut server_config, server_cert) = configure_server()?;
+    // the hello is sent on a unidirectional stream
+    Arc::get_mut(&mut server_config.transport)
+        .unwrap()
+        .max_concurrent_uni_streams(1_u8.into());
+    let server = Endpoint::server(server_config, server_addr)?;
+    let client = make_client_endpoint("0.0.0.0:0".parse()?, &[&server_cert])?;
+    let listener = QuinnListener::<ComputeRequest, ComputeResponse>::new(server)?.handshake(
+        ServerHandshake::default().capabilities(
+            Capabilities::default()
+                .version(1)
+                .version(2)
+                .compression("zstd")
+                .compression("lz4")
+                .max_message_size(1 << 20)
+                .feature("batching"),
+        ),
+    );
+    tokio::task::spawn(ComputeService::server(RpcServer::new(listener.clone())));
+
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    )
+    .handshake(
+        ClientHandshake::default().capabilities(
+            Capabilities::default()
+                .version(2)
+                .version(3)
+                .compression("lz4")
+                .compression("zstd")
+                .feature("batching")
+                .feature("tracing"),
+        ),
+    );
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    assert_eq!(client.capabilities(), None);
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+
+    let [handle] = listener.connections().try_into().expect("one connection");
+    let negotiated = handle.capabilities().expect("client sent capabilities");
+    assert_eq!(negotiated.version(), Some(2));
+    assert_eq!(negotiated.compression(), Some("lz4"));
+    assert_eq!(negotiated.max_message_size(), Some(1 << 20));
+    assert!(negotiated.supports("batching"));
+    assert!(!negotiated.supports("tracing"));
+
+    // the reply may arrive after the first response
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while client.capabilities().is_none() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(client.capabilities().as_ref(), Some(negotiated));
+    Ok(())
+}
@@ -0,0 +1,88 @@
+#![cfg(all(feature = "relay", feature = "flume-transport"))]
+mod math;
+use math::*;
+use quic_rpc::{
+    message::RpcMsg,
+    relay::{Relay, Shadow},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+/// all 4 patterns work through a relay
+#[tokio::test]
+async fn relay_smoke() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, upstream) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::task::spawn(ComputeService::server(server));
+
+    let (listener, client) = flume::channel(1);
+    let relay = tokio::task::spawn(Relay::<ComputeService, _, _>::new(listener, upstream).run());
+    smoke_test(client).await?;
+
+    // dropping the client will cause the relay to terminate
+    assert!(relay.await?.is_err());
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+struct EchoService;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Echo(String);
+
+impl Service for EchoService {
+    type Req = Echo;
+    type Res = Echo;
+}
+
+impl RpcMsg<EchoService> for Echo {
+    type Response = Echo;
+}
+
+/// Echo server, the new version of which shouts
+fn spawn_echo(uppercase: bool) -> flume::FlumeConnector<Echo, Echo> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<EchoService, _>::new(server);
+    tokio::task::spawn(async move {
+        while let Ok(accepting) = server.accept().await {
+            let (req, chan) = accepting.read_first().await?;
+            chan.rpc(req, (), move |_, Echo(text)| async move {
+                match uppercase {
+                    true => Echo(text.to_uppercase()),
+                    false => Echo(text),
+                }
+            })
+            .await?;
+        }
+        anyhow::Ok(())
+    });
+    client
+}
+
+/// half of the channels are mirrored, and differing responses are reported
+#[tokio::test]
+async fn relay_shadow() -> anyhow::Result<()> {
+    let (divergences, mut recv_divergences) = tokio::sync::mpsc::unbounded_channel();
+    let shadow = Shadow::new(spawn_echo(true), 0.5).on_divergence(move |divergence| {
+        divergences.send(divergence).ok();
+    });
+    let (listener, client) = flume::channel(1);
+    let relay = Relay::<EchoService, _, _>::new(listener, spawn_echo(false)).with_shadow(shadow);
+    tokio::task::spawn(relay.run());
+
+    let client = RpcClient::<EchoService, _>::new(client);
+    for text in ["a", "b", "c", "D"] {
+        // clients only see the responses of the upstream server
+        assert_eq!(client.rpc(Echo(text.into())).await?, Echo(text.into()));
+    }
+    // "b" and "D" were mirrored, but only "b" got a different response
+    let divergence = recv_divergences.recv().await.unwrap();
+    assert_eq!(divergence.requests, vec![Echo("b".into())]);
+    assert_eq!(divergence.upstream, vec![Echo("b".into())]);
+    assert_eq!(divergence.shadow?, vec![Echo("B".into())]);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(recv_divergences.try_recv().is_err());
+    Ok(())
+}
@@ -0,0 +1,106 @@
+#![cfg(all(feature = "reliable", feature = "flume-transport"))]
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use derive_more::{From, TryInto};
+use futures_lite::StreamExt;
+use quic_rpc::{
+    message::{BidiStreaming, BidiStreamingMsg, Msg},
+    reliable::{self, ReliableBidi, Sessions},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sync;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Request {
+    Sync(Sync),
+    Update(reliable::Update<u64>),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Response {
+    Sync(reliable::Response<u64>),
+}
+
+#[derive(Debug, Clone)]
+struct SyncService;
+
+impl Service for SyncService {
+    type Req = Request;
+    type Res = Response;
+}
+
+impl Msg<SyncService> for Sync {
+    type Pattern = BidiStreaming;
+}
+
+impl BidiStreamingMsg<SyncService> for Sync {
+    type Update = reliable::Update<u64>;
+    type Response = reliable::Response<u64>;
+}
+
+/// updates that were not acked are replayed after the channel breaks, once
+#[tokio::test]
+async fn reliable_bidi_replay() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<SyncService, _>::new(server);
+    let sessions = Sessions::new();
+    let handled = Arc::new(Mutex::new(Vec::new()));
+    let (kill_tx, mut kill_rx) = tokio::sync::mpsc::channel::<()>(1);
+    let sessions2 = sessions.clone();
+    let handled2 = handled.clone();
+    tokio::task::spawn(async move {
+        loop {
+            let (Request::Sync(req), chan) = server.accept().await?.read_first().await? else {
+                anyhow::bail!("unexpected request");
+            };
+            let handled = handled2.clone();
+            let serve =
+                chan.bidi_streaming(req, sessions2.clone(), move |sessions, _req, updates| {
+                    sessions.handle(updates, move |updates| {
+                        updates.map(move |n| {
+                            handled.lock().unwrap().push(n);
+                            n * 10
+                        })
+                    })
+                });
+            // serve channels one after the other, until the test breaks the channel
+            tokio::select! {
+                res = serve => res?,
+                _ = kill_rx.recv() => {}
+            }
+        }
+        #[allow(unreachable_code)]
+        anyhow::Ok(())
+    });
+
+    let client = RpcClient::<SyncService, _>::new(client);
+    let mut session = ReliableBidi::connect(client, Sync).await?;
+    session.send(1).await?;
+    session.send(2).await?;
+    assert_eq!(session.recv().await.transpose()?, Some(10));
+    assert_eq!(session.recv().await.transpose()?, Some(20));
+    // give the handler time to ask for the next update, which marks 2 as handled
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    kill_tx.send(()).await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // sending reconnects, the server skips the updates it already handled
+    session.send(3).await?;
+    session.send(4).await?;
+    assert_eq!(session.recv().await.transpose()?, Some(30));
+    assert_eq!(session.recv().await.transpose()?, Some(40));
+    assert_eq!(*handled.lock().unwrap(), vec![1, 2, 3, 4]);
+
+    assert_eq!(sessions.len(), 1);
+    session.close().await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(sessions.is_empty());
+    Ok(())
+}
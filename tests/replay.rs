@@ -0,0 +1,87 @@
+#![cfg(all(feature = "replay-protection", feature = "flume-transport"))]
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use derive_more::{From, TryInto};
+use quic_rpc::{
+    message::RpcMsg,
+    replay::{Guarded, ReplayError, ReplayWindow},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Withdraw(u64);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Request {
+    Withdraw(Guarded<Withdraw>),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Response {
+    Withdraw(Result<u64, ReplayError>),
+}
+
+#[derive(Debug, Clone)]
+struct BankService;
+
+impl Service for BankService {
+    type Req = Request;
+    type Res = Response;
+}
+
+impl RpcMsg<BankService> for Guarded<Withdraw> {
+    type Response = Result<u64, ReplayError>;
+}
+
+/// a request is handled at most once, and only within the window
+#[tokio::test]
+async fn replay_rejected() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<BankService, _>::new(server);
+    let window = ReplayWindow::new(Duration::from_secs(60));
+    let withdrawn = Arc::new(AtomicU64::new(0));
+    let withdrawn2 = withdrawn.clone();
+    tokio::task::spawn(async move {
+        loop {
+            let (Request::Withdraw(req), chan) = server.accept().await?.read_first().await?;
+            let withdrawn = withdrawn2.clone();
+            chan.rpc(req, window.clone(), move |window, req| async move {
+                window
+                    .handle(req, |Withdraw(amount)| async move {
+                        withdrawn.fetch_add(amount, Ordering::SeqCst) + amount
+                    })
+                    .await
+            })
+            .await?;
+        }
+        #[allow(unreachable_code)]
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<BankService, _>::new(client);
+
+    let req = Guarded::new(Withdraw(10));
+    assert_eq!(client.rpc(req.clone()).await?, Ok(10));
+    // a retry of the same request, or a recorded copy, is not handled again
+    assert_eq!(client.rpc(req).await?, Err(ReplayError::Replayed));
+    // a new request for the same amount is
+    assert_eq!(client.rpc(Guarded::new(Withdraw(10))).await?, Ok(20));
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let mut old = Guarded::new(Withdraw(10));
+    old.timestamp = now - 120_000;
+    assert_eq!(client.rpc(old).await?, Err(ReplayError::Expired));
+    let mut future = Guarded::new(Withdraw(10));
+    future.timestamp = now + 120_000;
+    assert_eq!(client.rpc(future).await?, Err(ReplayError::Expired));
+
+    assert_eq!(withdrawn.load(Ordering::SeqCst), 20);
+    Ok(())
+}
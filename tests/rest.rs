@@ -0,0 +1,54 @@
+#![cfg(all(feature = "rest-gateway", feature = "flume-transport"))]
+use std::net::SocketAddr;
+
+use hyper::{client::HttpConnector, Body, Client, Request, StatusCode};
+use quic_rpc::{bridge::rest::RestGateway, transport::flume, RpcServer};
+
+mod math;
+use math::*;
+
+async fn post(
+    client: &Client<HttpConnector>,
+    url: &str,
+    accept: &str,
+    body: &'static str,
+) -> anyhow::Result<(StatusCode, String)> {
+    let req = Request::post(url)
+        .header("accept", accept)
+        .body(Body::from(body))?;
+    let res = client.request(req).await?;
+    let status = res.status();
+    let body = hyper::body::to_bytes(res.into_body()).await?;
+    Ok((status, String::from_utf8(body.to_vec())?))
+}
+
+#[tokio::test]
+async fn rest_gateway() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::spawn(ComputeService::server(server));
+    let addr: SocketAddr = "127.0.0.1:3012".parse()?;
+    let gateway =
+        RestGateway::<ComputeService, _>::new("compute", client).server_streaming("Fibonacci");
+    tokio::spawn(gateway.serve(addr));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let client = Client::new();
+    let url = |method: &str| format!("http://{addr}/compute/{method}");
+
+    let res = post(&client, &url("Sqr"), "*/*", "4").await?;
+    assert_eq!(res, (StatusCode::OK, "16".to_string()));
+
+    let res = post(&client, &url("Fibonacci"), "*/*", "4").await?;
+    assert_eq!(res, (StatusCode::OK, "0\n1\n1\n2\n".to_string()));
+
+    let (status, body) = post(&client, &url("Fibonacci"), "text/event-stream", "3").await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "data:0\n\ndata:1\n\ndata:1\n\n");
+
+    let (status, _) = post(&client, &url("Nope"), "*/*", "").await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = post(&client, &url("Sqr"), "*/*", "\"four\"").await?;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    Ok(())
+}
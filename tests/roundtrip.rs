@@ -0,0 +1,107 @@
+#![cfg(feature = "test-utils")]
+use quic_rpc::{
+    test::roundtrip::{self, Codec, RoundtripError},
+    Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Request {
+    Get(u64),
+    Put { key: String, value: Vec<u8> },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Response {
+    Value(Option<Vec<u8>>),
+    Done,
+}
+
+#[derive(Debug, Clone)]
+struct StoreService;
+
+impl Service for StoreService {
+    type Req = Request;
+    type Res = Response;
+}
+
+/// skipping a field is fine for self describing formats, but not for bincode
+#[derive(Debug, Serialize, Deserialize)]
+struct Asymmetric {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    a: Option<u32>,
+    b: u32,
+}
+
+#[test]
+fn roundtrip_ok() {
+    roundtrip::request::<StoreService>(&Request::Get(1)).unwrap();
+    roundtrip::request::<StoreService>(&Request::Put {
+        key: "key".into(),
+        value: vec![1, 2, 3],
+    })
+    .unwrap();
+    roundtrip::response::<StoreService>(&Response::Value(None)).unwrap();
+    roundtrip::response::<StoreService>(&Response::Done).unwrap();
+}
+
+#[test]
+fn roundtrip_asymmetric() {
+    let value = Asymmetric { a: None, b: 1 };
+    assert!(matches!(
+        roundtrip::roundtrip_with(Codec::FramedBincode, &value),
+        Err(RoundtripError::Decode {
+            codec: Codec::FramedBincode,
+            ..
+        })
+    ));
+    assert!(roundtrip::roundtrip(&Asymmetric { a: Some(1), b: 1 }).is_ok());
+}
+
+#[test]
+fn roundtrip_frame_too_large() {
+    let value = vec![0u8; roundtrip::MAX_FRAME_LENGTH + 1];
+    assert!(matches!(
+        roundtrip::roundtrip(&value),
+        Err(RoundtripError::FrameTooLarge { .. })
+    ));
+}
+
+#[cfg(feature = "proptest")]
+mod arbitrary {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    impl Arbitrary for Request {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            prop_oneof![
+                any::<u64>().prop_map(Request::Get),
+                (any::<String>(), any::<Vec<u8>>())
+                    .prop_map(|(key, value)| Request::Put { key, value }),
+            ]
+            .boxed()
+        }
+    }
+
+    impl Arbitrary for Response {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            prop_oneof![
+                any::<Option<Vec<u8>>>().prop_map(Response::Value),
+                Just(()).prop_map(|_| Response::Done),
+            ]
+            .boxed()
+        }
+    }
+
+    #[test]
+    fn roundtrip_arbitrary() {
+        roundtrip::check_service::<StoreService>();
+    }
+}
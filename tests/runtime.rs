@@ -0,0 +1,87 @@
+#![cfg(all(
+    feature = "relay",
+    feature = "chaos-transport",
+    feature = "flume-transport"
+))]
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use futures_lite::{future::block_on, StreamExt};
+use quic_rpc::{
+    relay::Relay,
+    runtime::{BoxFuture, Sleep},
+    transport::{
+        chaos::{ChaosConfig, ChaosConnection},
+        flume,
+    },
+    RpcClient, RpcServer,
+};
+
+mod math;
+use math::*;
+
+/// A minimal executor that runs every task on its own thread
+fn spawn(future: impl Future<Output = ()> + Send + 'static) {
+    std::thread::spawn(move || block_on(future));
+}
+
+fn sleep(duration: Duration) -> Sleep {
+    let (send, recv) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        send.send(()).ok();
+    });
+    Box::pin(async move {
+        recv.await.ok();
+    })
+}
+
+/// Serve requests one after the other, without spawning
+fn spawn_server() -> flume::FlumeConnector<ComputeResponse, ComputeRequest> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    spawn(async move {
+        while let Ok(accepting) = server.accept().await {
+            if let Ok((req, chan)) = accepting.read_first().await {
+                ComputeService::handle_rpc_request(ComputeService, req, chan)
+                    .await
+                    .ok();
+            }
+        }
+    });
+    client
+}
+
+/// relay and chaos transport work without a tokio runtime
+#[test]
+fn runtime_without_tokio() -> anyhow::Result<()> {
+    block_on(async {
+        let (listener, client) = flume::channel(1);
+        let relay = Relay::<ComputeService, _, _>::new(listener, spawn_server())
+            .with_spawner(|future: BoxFuture<()>| spawn(future));
+        spawn(async move {
+            relay.run().await.ok();
+        });
+        let client = RpcClient::<ComputeService, _>::new(client);
+        assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+        let res: Vec<_> = client
+            .server_streaming(Fibonacci(5))
+            .await?
+            .map(|res| res.unwrap().0)
+            .collect()
+            .await;
+        assert_eq!(res, vec![0, 1, 1, 2, 3]);
+
+        let latency = Duration::from_millis(20);
+        let config = ChaosConfig::default().latency(latency, Duration::ZERO);
+        let client = ChaosConnection::new(spawn_server(), config).with_timer(sleep);
+        let client = RpcClient::<ComputeService, _>::new(client);
+        let t0 = Instant::now();
+        assert_eq!(client.rpc(Sqr(4)).await?, SqrResponse(16));
+        // one delay for opening the channel and one for the response
+        assert!(t0.elapsed() >= latency * 2);
+        anyhow::Ok(())
+    })
+}
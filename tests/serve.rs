@@ -0,0 +1,242 @@
+#![cfg(all(feature = "flume-transport", feature = "tokio-runtime"))]
+use std::time::{Duration, Instant};
+
+use quic_rpc::{runtime::BlockingPool, transport::flume, RpcClient, RpcServer};
+
+mod math;
+use math::*;
+
+#[tokio::test]
+async fn serve_smoke() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    tokio::task::spawn(async move {
+        server
+            .serve(ComputeService, |chan, req, service| {
+                ComputeService::handle_rpc_request(service, req, chan)
+            })
+            .await
+    });
+    smoke_test(client).await
+}
+
+/// handlers run on the runtime given to the server
+#[tokio::test]
+async fn serve_on_runtime() -> anyhow::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("rpc-handler")
+        .build()?;
+    let (threads, mut recv_threads) = tokio::sync::mpsc::unbounded_channel();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server).with_spawner(runtime.handle().clone());
+    tokio::task::spawn(async move {
+        server
+            .serve(ComputeService, move |chan, req, service| {
+                let name = std::thread::current().name().map(String::from);
+                threads.send(name).ok();
+                ComputeService::handle_rpc_request(service, req, chan)
+            })
+            .await
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+    assert_eq!(recv_threads.recv().await, Some(Some("rpc-handler".into())));
+    runtime.shutdown_background();
+    Ok(())
+}
+
+/// blocking handlers don't stall each other or the accept loop
+#[tokio::test]
+async fn serve_blocking() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server).with_spawner(BlockingPool::current());
+    tokio::task::spawn(async move {
+        server
+            .serve(ComputeService, |chan, req, service| {
+                std::thread::sleep(Duration::from_millis(200));
+                ComputeService::handle_rpc_request(service, req, chan)
+            })
+            .await
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let t0 = Instant::now();
+    let (a, b) = tokio::join!(client.rpc(Sqr(2)), client.rpc(Sqr(3)));
+    assert_eq!((a?, b?), (SqrResponse(4), SqrResponse(9)));
+    assert!(t0.elapsed() < Duration::from_millis(400));
+    Ok(())
+}
+
+/// requests whose deadline passed are answered without running the handler
+#[tokio::test]
+async fn serve_drop_expired() -> anyhow::Result<()> {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    use derive_more::{From, TryInto};
+    use quic_rpc::{
+        message::{Deadline, RpcMsg},
+        Service,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Work {
+        deadline: u64,
+    }
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    enum WorkResponse {
+        Done,
+        DeadlineExceeded,
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Request {
+        Work(Work),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Response {
+        Work(WorkResponse),
+    }
+    impl Deadline for Request {
+        fn deadline(&self) -> Option<u64> {
+            match self {
+                Request::Work(work) => Some(work.deadline),
+            }
+        }
+    }
+    #[derive(Debug, Clone)]
+    struct WorkService;
+    impl Service for WorkService {
+        type Req = Request;
+        type Res = Response;
+    }
+    impl RpcMsg<WorkService> for Work {
+        type Response = WorkResponse;
+    }
+
+    let handled = Arc::new(AtomicUsize::new(0));
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<WorkService, _>::new(server)
+        .drop_expired(|_req| WorkResponse::DeadlineExceeded.into());
+    let handled2 = handled.clone();
+    tokio::task::spawn(async move {
+        server
+            .serve((), move |chan, req, ()| {
+                let handled = handled2.clone();
+                async move {
+                    let Request::Work(work) = req;
+                    chan.rpc(work, (), |(), _work| async move {
+                        handled.fetch_add(1, Ordering::SeqCst);
+                        WorkResponse::Done
+                    })
+                    .await
+                }
+            })
+            .await
+    });
+    let client = RpcClient::<WorkService, _>::new(client);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let res = client
+        .rpc(Work {
+            deadline: now - 1000,
+        })
+        .await?;
+    assert_eq!(res, WorkResponse::DeadlineExceeded);
+    assert_eq!(handled.load(Ordering::SeqCst), 0);
+    let res = client
+        .rpc(Work {
+            deadline: now + 60_000,
+        })
+        .await?;
+    assert_eq!(res, WorkResponse::Done);
+    assert_eq!(handled.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+/// requests of a variant at its limit wait, requests of other variants go ahead
+#[tokio::test]
+async fn serve_concurrency_limits() -> anyhow::Result<()> {
+    use std::sync::Arc;
+
+    use futures_lite::StreamExt;
+    use quic_rpc::server::ConcurrencyLimits;
+    use tokio::sync::Semaphore;
+
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server)
+        .concurrency_limits(ConcurrencyLimits::default().max_variant("Fibonacci", 1));
+    let (started_tx, mut started_rx) = tokio::sync::mpsc::unbounded_channel();
+    let release = Arc::new(Semaphore::new(0));
+    let release2 = release.clone();
+    tokio::task::spawn(async move {
+        server
+            .serve(ComputeService, move |chan, req, service| {
+                let started_tx = started_tx.clone();
+                let release = release2.clone();
+                async move {
+                    if let ComputeRequest::Fibonacci(Fibonacci(n)) = &req {
+                        started_tx.send(*n).ok();
+                        release.acquire().await.unwrap().forget();
+                    }
+                    ComputeService::handle_rpc_request(service, req, chan).await
+                }
+            })
+            .await
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let first = client.server_streaming(Fibonacci(1)).await?;
+    let second = client.server_streaming(Fibonacci(2)).await?;
+    assert!(started_rx.recv().await.is_some());
+    // rpc calls are not limited
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+    // the second handler only starts once the first one is done
+    assert!(started_rx.try_recv().is_err());
+    release.add_permits(1);
+    assert!(started_rx.recv().await.is_some());
+    release.add_permits(1);
+    assert_eq!(first.count().await + second.count().await, 3);
+    Ok(())
+}
+
+/// every handled request is recorded, with redacted payloads
+#[cfg(feature = "audit")]
+#[tokio::test]
+async fn serve_audit() -> anyhow::Result<()> {
+    use quic_rpc::audit::{AuditLog, ChannelSink, Outcome};
+
+    let (records_tx, mut records) = tokio::sync::mpsc::channel(16);
+    let log =
+        AuditLog::<ComputeService>::new(ChannelSink::new(records_tx)).redact(|req| match req {
+            ComputeRequest::Sqr(_) => "Sqr(<redacted>)".into(),
+            req => format!("{req:?}"),
+        });
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server).audit(log);
+    tokio::task::spawn(async move {
+        server
+            .serve(ComputeService, |chan, req, service| {
+                ComputeService::handle_rpc_request(service, req, chan)
+            })
+            .await
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+    let record = records.recv().await.unwrap();
+    assert_eq!(record.variant, "Sqr");
+    assert_eq!(record.request, "Sqr(<redacted>)");
+    assert_eq!(record.outcome, Outcome::Ok);
+    assert_eq!(record.peer, None);
+
+    // the client goes away before the update, so the handler fails
+    let (_send, recv) = client.client_streaming(Sum).await?;
+    drop(recv);
+    let record = records.recv().await.unwrap();
+    assert_eq!(record.variant, "Sum");
+    assert!(matches!(record.outcome, Outcome::Failed(_)));
+    Ok(())
+}
@@ -0,0 +1,83 @@
+#![cfg(all(feature = "request-signing", feature = "flume-transport"))]
+use std::sync::{Arc, Mutex};
+
+use derive_more::{From, TryInto};
+use quic_rpc::{
+    message::RpcMsg,
+    signing::{RequestSigner, SignatureError, SignatureVerifier, Signed},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct DropTable(String);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Request {
+    DropTable(Signed<DropTable>),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Response {
+    DropTable(Result<(), SignatureError>),
+}
+
+#[derive(Debug, Clone)]
+struct AdminService;
+
+impl Service for AdminService {
+    type Req = Request;
+    type Res = Response;
+}
+
+impl RpcMsg<AdminService> for Signed<DropTable> {
+    type Response = Result<(), SignatureError>;
+}
+
+/// only requests signed with a trusted key are handled, and each one is recorded
+#[tokio::test]
+async fn signed_requests() -> anyhow::Result<()> {
+    let signer = RequestSigner::from_pkcs8(&RequestSigner::generate_pkcs8()?)?;
+    let stranger = RequestSigner::from_pkcs8(&RequestSigner::generate_pkcs8()?)?;
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<AdminService, _>::new(server);
+    let records = Arc::new(Mutex::new(Vec::new()));
+    let records2 = records.clone();
+    let verifier = SignatureVerifier::new(move |record| records2.lock().unwrap().push(record))
+        .trust(signer.public_key());
+    tokio::task::spawn(async move {
+        loop {
+            let (Request::DropTable(req), chan) = server.accept().await?.read_first().await?;
+            chan.rpc(req, verifier.clone(), |verifier, req| async move {
+                verifier.handle(req, |_table| async {}).await
+            })
+            .await?;
+        }
+        #[allow(unreachable_code)]
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<AdminService, _>::new(client);
+
+    let table = DropTable("users".into());
+    assert_eq!(client.rpc(signer.sign(&table)?).await?, Ok(()));
+    assert_eq!(
+        client.rpc(stranger.sign(&table)?).await?,
+        Err(SignatureError::UntrustedKey)
+    );
+    // a request that was tampered with on the way is rejected
+    let mut tampered = bincode::serialize(&signer.sign(&table)?)?;
+    *tampered.last_mut().unwrap() ^= 1;
+    let tampered: Signed<DropTable> = bincode::deserialize(&tampered)?;
+    assert_eq!(
+        client.rpc(tampered).await?,
+        Err(SignatureError::InvalidSignature)
+    );
+
+    let records = records.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    assert!(records[0].verify());
+    assert_eq!(records[0].key, signer.public_key());
+    assert_eq!(records[0].request::<DropTable>()?, table);
+    Ok(())
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "stepped-transport")]
+#![allow(non_local_definitions)]
+mod math;
+use std::time::Duration;
+
+use math::*;
+use quic_rpc::{transport::stepped, RpcClient, RpcServer};
+
+#[tokio::test(start_paused = true)]
+async fn stepped_timeout_with_paused_time() -> anyhow::Result<()> {
+    let (server, client, controller) = stepped::channel();
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let _server_handle = tokio::task::spawn(ComputeService::server(server));
+    let client = RpcClient::<ComputeService, _>::new(client);
+
+    // nothing is delivered, so the request times out in virtual time
+    let res = tokio::time::timeout(Duration::from_secs(10), client.rpc(Sqr(2))).await;
+    assert!(res.is_err());
+    // the request frame and the end of the send side are still pending
+    assert_eq!(controller.pending(), 2);
+    assert_eq!(controller.deliver_all(), 2);
+
+    let request = tokio::task::spawn({
+        let client = client.clone();
+        async move { client.rpc(Sqr(3)).await }
+    });
+    // step delivery until the request is done
+    while !request.is_finished() {
+        controller.wait_pending().await;
+        controller.step();
+    }
+    assert_eq!(request.await??.0, 9);
+    Ok(())
+}
+
+#[tokio::test]
+async fn stepped_auto_deliver() -> anyhow::Result<()> {
+    let (server, client, controller) = stepped::channel();
+    controller.set_auto_deliver(true);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let server_handle = tokio::task::spawn(ComputeService::server(server));
+    let client = RpcClient::<ComputeService, _>::new(client);
+    bench(client, 100).await?;
+    // dropping the client will cause the server to terminate
+    assert!(server_handle.await?.is_err());
+    Ok(())
+}